@@ -15,6 +15,7 @@ import Data.Serialize
 import Data.Serialize.Put as P
 import qualified Data.Aeson as AE
 import Data.Foldable(forM_)
+import Data.IORef
 import Text.Read(readMaybe)
 import Control.Exception
 import Control.Monad(unless)
@@ -47,7 +48,7 @@ import Concordium.Runner
 import Concordium.Skov hiding (receiveTransaction, MessageType, getCatchUpStatus, getBlocksAtHeight)
 import qualified Concordium.Skov as Skov
 import Concordium.Afgjort.Finalize.Types(getExactVersionedFPM)
-import Concordium.GlobalState.Persistent.LMDB (addDatabaseVersion)
+import Concordium.GlobalState.Persistent.LMDB (addDatabaseVersion, readDatabaseVersion, isSupportedDatabaseVersion)
 import Concordium.Logger
 import Concordium.TimeMonad
 import Concordium.TimerMonad (ThreadTimer)
@@ -765,6 +766,46 @@ getInstances cptr blockcstr = do
       logm External LLTrace $ "Replying with the list: " ++ show istances
       jsonValueToCString istances
 
+-- |Get a page of the list of accounts in the given block. The block must be
+-- given as a null-terminated base16 encoding of the block hash. The page starts
+-- at the account whose address is given as a null-terminated Base58 string, or
+-- at the first account if that string is empty, and contains at most the given
+-- number of addresses. The return value is a null-terminated JSON object with
+-- the list of addresses under "items" and the address starting the next page
+-- under "next" (null if this is the last page).
+-- The returned string should be freed by calling 'freeCStr'.
+getAccountListPage :: StablePtr ConsensusRunner -> CString -> CString -> Word64 -> IO CString
+getAccountListPage cptr blockcstr cursorcstr limit = do
+    c <- deRefStablePtr cptr
+    let logm = consensusLogMethod c
+    logm External LLDebug "Received account list page request."
+    cursor <- BS.packCString cursorcstr
+    let withCursor k
+          | BS.null cursor = k Nothing
+          | otherwise = withAccountAddress cursorcstr (logm External LLDebug) (k . Just)
+    withCursor $ \start ->
+      withBlockHash blockcstr (logm External LLDebug) $ \hash -> do
+        page <- runConsensusQuery c (Get.getAccountListPage hash start limit)
+        logm External LLTrace $ "Replying with the page: " ++ show page
+        jsonValueToCString page
+
+-- |Get a page of the list of contract instances in the given block. The block
+-- must be given as a null-terminated base16 encoding of the block hash. The page
+-- starts at the given contract index and contains at most the given number of
+-- addresses. The return value is a null-terminated JSON object with the list of
+-- addresses under "items" and the index starting the next page under "next"
+-- (null if this is the last page).
+-- The returned string should be freed by calling 'freeCStr'.
+getInstancesPage :: StablePtr ConsensusRunner -> CString -> Word64 -> Word64 -> IO CString
+getInstancesPage cptr blockcstr start limit = do
+    c <- deRefStablePtr cptr
+    let logm = consensusLogMethod c
+    logm External LLDebug "Received instance list page request."
+    withBlockHash blockcstr (logm External LLDebug) $ \hash -> do
+      page <- runConsensusQuery c (Get.getInstancesPage hash (fromIntegral start) limit)
+      logm External LLTrace $ "Replying with the page: " ++ show page
+      jsonValueToCString page
+
 withAccountAddress :: CString -> (String -> IO ()) -> (AccountAddress -> IO CString) -> IO CString
 withAccountAddress cstr logm k = do
   bs <- BS.packCString cstr
@@ -880,7 +921,7 @@ bakerIdBestBlock cptr = do
           return (-1)
     let active :: SyncRunner (ActiveConfig a) -> IO Int64
         active s = do
-          let bid = syncBakerIdentity s
+          bid <- readIORef (syncBakerIdentity s)
           status <- runConsensusQuery c (Get.bakerStatusBestBlock bid)
           let r = case status of
                   Get.ActiveBaker -> fromIntegral $ bakerId bid
@@ -1059,6 +1100,26 @@ getBlocksAtHeight cptr height = do
     logm External LLTrace $ "Replying with: " ++ show blocks
     jsonValueToCString blocks
 
+-- |Get the finalized block at the given height, serialized as in the files read
+-- by 'importBlocks'. The return value is the length of the block as a 32-bit
+-- big-endian integer followed by the block; the length is 0 if there is no
+-- finalized block at that height, or if the height is 0, since the genesis block
+-- can't be imported.
+-- The returned string should be freed by calling 'freeCStr'.
+getFinalizedBlockAtHeight :: StablePtr ConsensusRunner -> Word64 -> IO CString
+getFinalizedBlockAtHeight cptr height = do
+    c <- deRefStablePtr cptr
+    let logm = consensusLogMethod c
+    logm External LLDebug "Received finalized block at height request."
+    mblock <- runConsensusQuery c Get.getFinalizedBlockAtHeight (fromIntegral height)
+    case mblock of
+      Nothing -> do
+        logm External LLTrace "No finalized block at that height."
+        byteStringToCString BS.empty
+      Just block -> do
+        logm External LLTrace $ "Replying with data size = " ++ show (BS.length block)
+        byteStringToCString block
+
 getAllIdentityProviders :: StablePtr ConsensusRunner -> CString -> IO CString
 getAllIdentityProviders cptr blockcstr = do
     c <- deRefStablePtr cptr
@@ -1188,6 +1249,94 @@ importBlocks cptr cstr len = do
   logm External LLDebug "Done importing file."
   return ret
 
+-- |Write a consistent copy of the global state to the given directory while consensus is
+-- running. The directory is created if it does not exist.
+-- Returns 0 on success and 1 otherwise, in which case the error is logged.
+snapshotDatabase :: StablePtr ConsensusRunner
+                 -> CString
+                 -> Int64
+                 -> IO Int64
+snapshotDatabase cptr cstr len = do
+  c <- deRefStablePtr cptr
+  let logm = consensusLogMethod c
+  dir <- BS.unpack <$> BS.packCStringLen (cstr, fromIntegral len)
+  logm External LLInfo $ "Writing a snapshot of the global state to: " ++ dir
+  res <- try $ do
+    createDirectoryIfMissing True dir
+    case c of
+      BakerRunner{..} -> syncSnapshot bakerSyncRunner dir
+      PassiveRunner{..} -> syncPassiveSnapshot passiveSyncRunner dir
+      BakerRunnerWithLog{..} -> syncSnapshot bakerSyncRunnerWithLog dir
+      PassiveRunnerWithLog{..} -> syncPassiveSnapshot passiveSyncRunnerWithLog dir
+  case res of
+    Left (ex :: SomeException) -> do
+      logm External LLError $ "Could not write the snapshot: " ++ displayException ex
+      return 1
+    Right () -> do
+      logm External LLInfo "Done writing the snapshot."
+      return 0
+
+-- |Compute the hash of the genesis block from serialized genesis data.
+-- This does not require consensus to be running.
+-- The result is empty if the genesis data cannot be deserialized.
+computeGenesisBlockHash :: CString -> Int64 -> IO CString
+computeGenesisBlockHash gdataC gdataLenC = do
+  gdata <- BS.packCStringLen (gdataC, fromIntegral gdataLenC)
+  byteStringToCString $ case runGet getVersionedGenesisData gdata of
+    Left _ -> BS.empty
+    Right (genData :: GenesisData 'P1) -> Hash.hashToByteString (genesisBlockHash genData)
+
+-- |Read the version of the tree state database in the given directory, which need not be used by
+-- a running consensus, e.g., one unpacked from a snapshot.
+-- The result is a JSON object with the description of the stored version and whether this node
+-- supports it, or null if the directory holds no database with a readable version.
+treeStateVersion :: CString -> Int64 -> IO CString
+treeStateVersion dirC dirLenC = do
+  dir <- BS.unpack <$> BS.packCStringLen (dirC, fromIntegral dirLenC)
+  res <- try (readDatabaseVersion dir)
+  jsonValueToCString $ case res of
+    Right (Just vm) -> AE.object ["version" AE..= show vm, "supported" AE..= isSupportedDatabaseVersion vm]
+    Right Nothing -> Null
+    Left (_ :: SomeException) -> Null
+
+-- |Replace the baker credentials of a running baker node with the given
+-- JSON-encoded baker identity, which must be consistent with the chain.
+-- Baking is stopped while the credentials are replaced and restarted
+-- afterwards if it was running.
+-- Returns 0 on success.
+-- Returns 1 if the credentials cannot be parsed.
+-- Returns 2 if the node was started without baker credentials.
+-- Returns 3 if the keys do not match the baker's public keys on the chain.
+setBakerCredentials :: StablePtr ConsensusRunner -> CString -> Int64 -> IO Int64
+setBakerCredentials cptr bidC bidLenC = do
+    c <- deRefStablePtr cptr
+    let logm = consensusLogMethod c
+    bdata <- BS.packCStringLen (bidC, fromIntegral bidLenC)
+    case AE.eitherDecodeStrict bdata of
+      Left err -> do
+        logm External LLError $ "Failed to decode baker identity data: " ++ err
+        return 1
+      Right bid -> do
+        let passive = do
+              logm External LLError "Cannot set baker credentials: consensus was started without baker credentials."
+              return 2
+        let active :: SyncRunner (ActiveConfig a) -> IO Int64
+            active s = do
+              status <- runConsensusQuery c (Get.bakerStatusBestBlock bid)
+              case status of
+                Get.BadKeys -> do
+                  logm External LLError $ "Cannot set baker credentials: the keys do not match the keys of baker " ++ show (bakerId bid) ++ " on the chain."
+                  return 3
+                _ -> do
+                  let finInst = FinalizationInstance (bakerSignKey bid) (bakerElectionKey bid) (bakerAggregationKey bid)
+                  setSyncBakerIdentity s bid (\ctx -> ctx{scFinContext = finInst})
+                  return 0
+        case c of
+          PassiveRunner _ -> passive
+          PassiveRunnerWithLog _ -> passive
+          BakerRunner s -> active s
+          BakerRunnerWithLog s -> active s
+
 foreign export ccall startConsensus :: Word64 -> Word64 -> Word64 -> Word64 -> Word64 -> Word64 -> CString -> Int64 -> CString -> Int64 -> FunPtr BroadcastCallback -> FunPtr CatchUpStatusCallback -> Ptr () -> FunPtr (Ptr () -> IO ()) -> FunPtr RegenesisCallback -> Word8 -> FunPtr LogCallback -> CString -> Int64 -> CString -> Int64 -> Ptr (StablePtr ConsensusRunner) -> IO Int64
 foreign export ccall startConsensusPassive :: Word64 -> Word64 -> Word64 -> Word64 -> Word64 -> Word64 -> CString -> Int64 -> FunPtr CatchUpStatusCallback -> Ptr () -> FunPtr (Ptr () -> IO ()) -> FunPtr RegenesisCallback -> Word8 -> FunPtr LogCallback -> CString -> Int64 ->CString -> Int64 -> Ptr (StablePtr ConsensusRunner) -> IO Int64
 foreign export ccall stopConsensus :: StablePtr ConsensusRunner -> IO ()
@@ -1210,6 +1359,8 @@ foreign export ccall receiveCatchUpStatus :: StablePtr ConsensusRunner -> PeerID
 -- state is handled better
 foreign export ccall getAccountList :: StablePtr ConsensusRunner -> CString -> IO CString
 foreign export ccall getInstances :: StablePtr ConsensusRunner -> CString -> IO CString
+foreign export ccall getAccountListPage :: StablePtr ConsensusRunner -> CString -> CString -> Word64 -> IO CString
+foreign export ccall getInstancesPage :: StablePtr ConsensusRunner -> CString -> Word64 -> Word64 -> IO CString
 foreign export ccall getAccountInfo :: StablePtr ConsensusRunner -> CString -> CString -> IO CString
 foreign export ccall getInstanceInfo :: StablePtr ConsensusRunner -> CString -> CString -> IO CString
 foreign export ccall getRewardStatus :: StablePtr ConsensusRunner -> CString -> IO CString
@@ -1222,6 +1373,7 @@ foreign export ccall getAccountNonFinalizedTransactions :: StablePtr ConsensusRu
 foreign export ccall getBlockSummary :: StablePtr ConsensusRunner -> CString -> IO CString
 foreign export ccall getNextAccountNonce :: StablePtr ConsensusRunner -> CString -> IO CString
 foreign export ccall getBlocksAtHeight :: StablePtr ConsensusRunner -> Word64 -> IO CString
+foreign export ccall getFinalizedBlockAtHeight :: StablePtr ConsensusRunner -> Word64 -> IO CString
 foreign export ccall getAllIdentityProviders :: StablePtr ConsensusRunner -> CString -> IO CString
 foreign export ccall getAllAnonymityRevokers :: StablePtr ConsensusRunner -> CString -> IO CString
 foreign export ccall getCryptographicParameters :: StablePtr ConsensusRunner -> CString -> IO CString
@@ -1234,3 +1386,7 @@ foreign export ccall checkIfWeAreFinalizer :: StablePtr ConsensusRunner -> IO Wo
 foreign export ccall freeCStr :: CString -> IO ()
 
 foreign export ccall importBlocks :: StablePtr ConsensusRunner -> CString -> Int64 -> IO Int64
+foreign export ccall snapshotDatabase :: StablePtr ConsensusRunner -> CString -> Int64 -> IO Int64
+foreign export ccall computeGenesisBlockHash :: CString -> Int64 -> IO CString
+foreign export ccall treeStateVersion :: CString -> Int64 -> IO CString
+foreign export ccall setBakerCredentials :: StablePtr ConsensusRunner -> CString -> Int64 -> IO Int64
@@ -2,6 +2,7 @@
 {-# LANGUAGE DataKinds #-}
 {-# LANGUAGE OverloadedStrings #-}
 {-# LANGUAGE ScopedTypeVariables #-}
+{-# LANGUAGE TypeApplications #-}
 {-# LANGUAGE TypeFamilies #-}
 {-# LANGUAGE UndecidableInstances #-}
 module Concordium.Getters where
@@ -34,6 +35,7 @@ import Concordium.Types.HashableTo
 import Concordium.GlobalState.Instance
 import Concordium.GlobalState.Finalization
 import Concordium.Types.SeedState
+import Concordium.Types.Updates (ProtocolUpdate(..))
 
 import Concordium.Afgjort.Finalize(FinalizationStateLenses(..), FinalizationCurrentRound(..))
 import Concordium.Afgjort.Finalize.Types
@@ -45,6 +47,8 @@ import Data.IORef
 import Text.Read hiding (get, String)
 import qualified Data.Map as Map
 import Data.Aeson
+import qualified Data.ByteString as ByteString
+import Data.Serialize (runPut)
 import Data.Aeson.Types (Pair)
 import qualified Data.Text as T
 import qualified Data.Set as S
@@ -205,6 +209,37 @@ getInstances hash sfsRef = runStateQuery sfsRef $
   ilist <- BS.getContractInstanceList st
   return $ toJSON (map iaddress ilist)
 
+-- |Split the items fetched for a page of at most the given number of items.
+-- One more item than fits on the page is fetched, to determine whether there
+-- is a next page. The result is an object with the items of the page under
+-- "items", and the cursor of the first item of the next page under "next", or
+-- null if there are no more items.
+paginate :: ToJSON a => (a -> Value) -> Word64 -> [a] -> Value
+paginate cursorOf limit items = object ["items" .= page, "next" .= next]
+  where (page, rest) = splitAt (fromIntegral limit) items
+        next = case rest of
+                 [] -> Null
+                 (item:_) -> cursorOf item
+
+-- |Get a page of the list of accounts in the given block, starting at the given
+-- account address, or at the beginning if no address is given. Accounts are
+-- listed in ascending order of their address. Only the accounts on the page are
+-- visited.
+getAccountListPage :: SkovStateQueryable z m => BlockHash -> Maybe AccountAddress -> Word64 -> z -> IO Value
+getAccountListPage hash cursor limit sfsRef = runStateQuery sfsRef $
+  withBlockStateJSON hash $ \st -> do
+  alist <- BS.getAccountListFrom st cursor (limit + 1)
+  return $ paginate toJSON limit alist
+
+-- |Get a page of the list of contract instances in the given block, starting at
+-- the given contract index. Instances are listed in ascending order of their
+-- index. Only the instances on the page are visited.
+getInstancesPage :: SkovStateQueryable z m => BlockHash -> ContractIndex -> Word64 -> z -> IO Value
+getInstancesPage hash start limit sfsRef = runStateQuery sfsRef $
+  withBlockStateJSON hash $ \st -> do
+  ilist <- BS.getContractInstanceListFrom st start (limit + 1)
+  return $ paginate (toJSON . contractIndex) limit (map iaddress ilist)
+
 -- |Get the account information for the given account.
 -- The account can be given either via an address, or via a credential registration id.
 -- In the latter case we lookup the account the credential is associated with, even if it was
@@ -326,6 +361,7 @@ getConsensusStatus sfsRef = runStateQuery sfsRef $ do
         genesis <- genesisBlock
         stats <- TS.getConsensusStatistics
         genData <- TS.getGenesisData
+        puStatus <- Skov.getProtocolUpdateStatus
         let -- for now we'll use the genesis epoch length even though that is a bit less
             -- than optimal with respect to future changes.
             -- When all of these parameters are dynamic we need to revisit.
@@ -360,7 +396,22 @@ getConsensusStatus sfsRef = runStateQuery sfsRef $ do
                 "finalizationCount" .= (stats ^. Stat.finalizationCount),
                 "lastFinalizedTime" .= (stats ^. Stat.lastFinalizedTime),
                 "finalizationPeriodEMA" .= (stats ^. Stat.finalizationPeriodEMA),
-                "finalizationPeriodEMSD" .= (sqrt <$> (stats ^. Stat.finalizationPeriodEMVar))
+                "finalizationPeriodEMSD" .= (sqrt <$> (stats ^. Stat.finalizationPeriodEMVar)),
+                -- the protocol update that has taken effect, or else the next one
+                -- scheduled, as of the last finalized block
+                "protocolUpdate" .= protocolUpdateJSON puStatus
+            ]
+    where
+        protocolUpdateJSON (Left pu) = object $ "status" .= ("enacted" :: String) : protocolUpdateFields pu
+        protocolUpdateJSON (Right []) = Null
+        protocolUpdateJSON (Right ((ts, pu):_)) = object $
+            "status" .= ("scheduled" :: String)
+            : "effectiveTime" .= timestampToUTCTime (transactionTimeToTimestamp ts)
+            : protocolUpdateFields pu
+        protocolUpdateFields pu = [
+                "message" .= puMessage pu,
+                "specificationURL" .= puSpecificationURL pu,
+                "specificationHash" .= show (puSpecificationHash pu)
             ]
 
 getCryptographicParameters :: (SkovStateQueryable z m) => BlockHash -> z -> IO (Maybe (Versioned Parameters.CryptographicParameters))
@@ -407,6 +458,19 @@ getBlocksAtHeight :: (SkovStateQueryable z m, HashableTo BlockHash (BlockPointer
 getBlocksAtHeight sfsRef height = runStateQuery sfsRef $
     toJSONList . map hsh <$> Skov.getBlocksAtHeight height
 
+-- |Get the finalized block at the given height, serialized as in the files read
+-- by 'TS.readBlocksV2', i.e., without a version header. Such files don't contain
+-- the genesis block, so 'Nothing' is returned for height 0 as well as for the
+-- heights that are not finalized yet.
+getFinalizedBlockAtHeight :: forall z m. SkovStateQueryable z m => z -> BlockHeight -> IO (Maybe ByteString.ByteString)
+getFinalizedBlockAtHeight sfsRef height = runStateQuery sfsRef $ do
+    lastFin <- lastFinalizedBlock
+    if height == 0 || height > bpHeight lastFin
+      then return Nothing
+      else
+        (\case [bp] -> Just $! runPut (putBlock (protocolVersion @(SkovStateProtocolVersion z)) bp)
+                _ -> Nothing) <$> Skov.getBlocksAtHeight height
+
 getAncestors :: (SkovStateQueryable z m, BlockPointerMonad m, HashableTo BlockHash (BlockPointerType m))
              => z -> String -> BlockHeight -> IO Value
 getAncestors sfsRef blockHash count = case readMaybe blockHash of
@@ -349,6 +349,11 @@ class GlobalStateConfig c where
     -- |Shutdown the global state.
     shutdownGlobalState :: Proxy c -> GSContext c -> GSState c -> GSLogContext c -> IO ()
 
+    -- |Write a consistent copy of the global state to the given directory, while it is in use.
+    -- Only configurations that keep the whole state on disk support this.
+    snapshotGlobalState :: Proxy c -> GSContext c -> GSState c -> FilePath -> IO ()
+    snapshotGlobalState _ _ _ _ = throwIO $ userError "The global state configuration does not support snapshots."
+
 instance (IsProtocolVersion pv) => GlobalStateConfig (MemoryTreeMemoryBlockConfig pv) where
     type GSContext (MemoryTreeMemoryBlockConfig pv) = ()
     type GSState (MemoryTreeMemoryBlockConfig pv) = SkovData pv (BS.HashedBlockState pv)
@@ -417,6 +422,8 @@ instance (IsProtocolVersion pv) => GlobalStateConfig (DiskTreeDiskBlockConfig pv
     shutdownGlobalState _ (PersistentBlockStateContext{..}) st _ = do
         closeBlobStore pbscBlobStore
         closeSkovPersistentData st
+    snapshotGlobalState _ (PersistentBlockStateContext{..}) st dir =
+        snapshotSkovPersistentData pbscBlobStore st dir
 
 instance (IsProtocolVersion pv) => GlobalStateConfig (DiskTreeDiskBlockWithLogConfig pv) where
     type GSState (DiskTreeDiskBlockWithLogConfig pv) = SkovPersistentData pv DiskDump (HashedPersistentBlockState pv)
@@ -459,3 +466,6 @@ instance (IsProtocolVersion pv) => GlobalStateConfig (DiskTreeDiskBlockWithLogCo
         closeBlobStore pbscBlobStore
         destroyAllResources dbHandle
         closeSkovPersistentData st
+    -- The transaction log is kept in an external database, which is not part of the snapshot.
+    snapshotGlobalState _ (PersistentBlockStateContext{..}) st dir =
+        snapshotSkovPersistentData pbscBlobStore st dir
@@ -370,6 +370,15 @@ instance (IsProtocolVersion pv, Monad m) => BS.BlockStateQuery (PureBlockStateMo
     getAccountList bs =
       return $ Map.keys (Accounts.accountMap (bs ^. blockAccounts))
 
+    {-# INLINE getAccountListFrom #-}
+    getAccountListFrom bs start limit =
+      return $ take (fromIntegral limit) $ Map.keys $
+        maybe id (\addr -> Map.dropWhileAntitone (< addr)) start (Accounts.accountMap (bs ^. blockAccounts))
+
+    {-# INLINE getContractInstanceListFrom #-}
+    getContractInstanceListFrom bs start limit =
+      return $ take (fromIntegral limit) (Instances.instancesFrom start (bs ^. blockInstances))
+
     getSeedState = return . view (blockBirkParameters . birkSeedState)
 
     getCurrentEpochBakers = return . epochToFullBakers . view (blockBirkParameters . birkCurrentEpochBakers . unhashed)
@@ -62,6 +62,23 @@ foldIT up (Branch _ _ _ _ l r) = foldIT up l <> foldIT up r
 foldIT up t@(Leaf i) = t <$ up (Right i)
 foldIT up t@(VacantLeaf si) = t <$ up (Left si)
 
+-- |The instances in an 'InstanceTable' with at least the given index, in
+-- ascending order of their index. Subtrees holding only lower indices are
+-- skipped, and the list is produced lazily.
+instanceTableFrom :: ContractIndex -> InstanceTable -> [Instance]
+instanceTableFrom _ Empty = []
+instanceTableFrom start (Tree _ t0) = go 0 t0
+    where
+        go lowIndex (Branch h _ _ _ l r)
+            | start >= midIndex = go midIndex r
+            | otherwise = go lowIndex l ++ go midIndex r
+            where
+                midIndex = lowIndex + 2^h
+        go lowIndex (Leaf i)
+            | lowIndex >= start = [i]
+            | otherwise = []
+        go _ VacantLeaf{} = []
+
 type instance Index IT = ContractIndex
 type instance IxValue IT = Instance
 
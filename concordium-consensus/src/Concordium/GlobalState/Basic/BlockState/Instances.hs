@@ -12,6 +12,7 @@ module Concordium.GlobalState.Basic.BlockState.Instances(
     createInstance,
     deleteInstance,
     foldInstances,
+    instancesFrom,
     instanceCount,
     -- * Serialization
     putInstancesV0,
@@ -60,6 +61,11 @@ foldInstances :: SimpleFold Instances Instance
 foldInstances _ is@(Instances Empty) = is <$ mempty
 foldInstances f is@(Instances (Tree _ t)) = is <$ (foldIT . _Right) f t
 
+-- |The smart contract instances with at least the given index, in ascending
+-- order of their index. The list is produced lazily.
+instancesFrom :: ContractIndex -> Instances -> [Instance]
+instancesFrom start (Instances iss) = instanceTableFrom start iss
+
 instanceCount :: Instances -> Word64
 instanceCount (Instances Empty) = 0
 instanceCount (Instances (Tree c _)) = c
@@ -203,6 +203,13 @@ class AccountOperations m => BlockStateQuery m where
     getAccountList :: BlockState m -> m [AccountAddress]
     -- |Get the list of contract instances existing in the given block state.
     getContractInstanceList :: BlockState m -> m [Instance]
+    -- |Get at most the given number of account addresses existing in the given
+    -- block state, in ascending order, starting at the given address, or at the
+    -- first one if no address is given.
+    getAccountListFrom :: BlockState m -> Maybe AccountAddress -> Word64 -> m [AccountAddress]
+    -- |Get at most the given number of contract instances existing in the given
+    -- block state, in ascending order of their index, starting at the given index.
+    getContractInstanceListFrom :: BlockState m -> ContractIndex -> Word64 -> m [Instance]
 
     -- |Get the seed state, from which the leadership election nonce
     -- is derived.
@@ -609,6 +616,8 @@ instance (Monad (t m), MonadTrans t, BlockStateQuery m) => BlockStateQuery (MGST
   getModuleList = lift . getModuleList
   getAccountList = lift . getAccountList
   getContractInstanceList = lift . getContractInstanceList
+  getAccountListFrom s start = lift . getAccountListFrom s start
+  getContractInstanceListFrom s start = lift . getContractInstanceListFrom s start
   getSeedState = lift . getSeedState
   getCurrentEpochBakers = lift . getCurrentEpochBakers
   getSlotBakers s = lift . getSlotBakers s
@@ -634,6 +643,8 @@ instance (Monad (t m), MonadTrans t, BlockStateQuery m) => BlockStateQuery (MGST
   {-# INLINE getModuleList #-}
   {-# INLINE getAccountList #-}
   {-# INLINE getContractInstanceList #-}
+  {-# INLINE getAccountListFrom #-}
+  {-# INLINE getContractInstanceListFrom #-}
   {-# INLINE getSeedState #-}
   {-# INLINE getCurrentEpochBakers #-}
   {-# INLINE getSlotBakers #-}
@@ -226,6 +226,14 @@ instance (Monad m, C.HasGlobalStateContext (PairGSContext lc rc) r, BlockStateQu
         a1 <- coerceBSML (getContractInstanceList ls)
         a2 <- coerceBSMR (getContractInstanceList rs)
         assert (((==) `on` fmap instanceHash) a1 a2) $ return a1
+    getAccountListFrom (ls, rs) start limit = do
+        a1 <- coerceBSML (getAccountListFrom ls start limit)
+        a2 <- coerceBSMR (getAccountListFrom rs start limit)
+        assert (a1 == a2) $ return a1
+    getContractInstanceListFrom (ls, rs) start limit = do
+        a1 <- coerceBSML (getContractInstanceListFrom ls start limit)
+        a2 <- coerceBSMR (getContractInstanceListFrom rs start limit)
+        assert (((==) `on` fmap instanceHash) a1 a2) $ return a1
     getSeedState (ls, rs) = do
         ss1 <- coerceBSML (getSeedState ls)
         ss2 <- coerceBSMR (getSeedState rs)
@@ -12,6 +12,7 @@ import Lens.Micro.Platform
 import Data.Serialize
 import GHC.Generics
 import Data.Maybe
+import Data.Word
 import qualified Data.Map.Strict as Map
 
 import Concordium.Types
@@ -291,6 +292,11 @@ updateAccount !upd !acc = do
 accountAddresses :: MonadBlobStore m => Accounts pv -> m [AccountAddress]
 accountAddresses = Trie.keys . accountMap
 
+-- |Get at most the given number of account addresses in ascending order,
+-- starting at the given address, or at the first one if none is given.
+accountAddressesFrom :: MonadBlobStore m => Maybe AccountAddress -> Word64 -> Accounts pv -> m [AccountAddress]
+accountAddressesFrom start limit = Trie.keysFrom start limit . accountMap
+
 -- |Serialize accounts in V0 format.
 serializeAccounts :: (MonadBlobStore m, MonadPut m, IsProtocolVersion pv) => GlobalContext -> Accounts pv -> m ()
 serializeAccounts cryptoParams accts = do
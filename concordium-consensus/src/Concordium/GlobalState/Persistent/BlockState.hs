@@ -951,6 +951,17 @@ doGetInstance pbs caddr = do
         minst <- Instances.lookupContractInstance caddr (bspInstances bsp)
         forM minst Instances.fromPersistentInstance
 
+doAccountListFrom :: (IsProtocolVersion pv, MonadBlobStore m) => PersistentBlockState pv -> Maybe AccountAddress -> Word64 -> m [AccountAddress]
+doAccountListFrom pbs start limit = do
+        bsp <- loadPBS pbs
+        Accounts.accountAddressesFrom start limit (bspAccounts bsp)
+
+doContractInstanceListFrom :: (IsProtocolVersion pv, MonadBlobStore m) => PersistentBlockState pv -> ContractIndex -> Word64 -> m [Instance]
+doContractInstanceListFrom pbs start limit = do
+        bsp <- loadPBS pbs
+        insts <- Instances.instancesFrom start limit (bspInstances bsp)
+        mapM Instances.fromPersistentInstance insts
+
 doContractInstanceList :: (IsProtocolVersion pv, MonadBlobStore m) => PersistentBlockState pv -> m [Instance]
 doContractInstanceList pbs = do
         bsp <- loadPBS pbs
@@ -1214,6 +1225,8 @@ instance (IsProtocolVersion pv, PersistentState r m) => BlockStateQuery (Persist
     getModuleList = doGetModuleList . hpbsPointers
     getAccountList = doAccountList . hpbsPointers
     getContractInstanceList = doContractInstanceList . hpbsPointers
+    getAccountListFrom = doAccountListFrom . hpbsPointers
+    getContractInstanceListFrom = doContractInstanceListFrom . hpbsPointers
     getSeedState = doGetSeedState . hpbsPointers
     getCurrentEpochBakers = doGetCurrentEpochBakers . hpbsPointers
     getSlotBakers = doGetSlotBakers . hpbsPointers
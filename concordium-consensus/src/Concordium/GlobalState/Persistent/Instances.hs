@@ -442,6 +442,30 @@ allInstances (InstancesTree _ it) = mapReduceIT mfun it
         mfun (Left _) = return mempty
         mfun (Right inst) = return [inst]
 
+-- |Get at most the given number of instances with at least the given index, in
+-- ascending order of their index. Only the part of the tree holding the
+-- returned instances is traversed.
+instancesFrom :: forall m. MonadBlobStore m => ContractIndex -> Word64 -> Instances -> m [PersistentInstance]
+instancesFrom _ _ InstancesEmpty = return []
+instancesFrom start limit (InstancesTree _ it0) = fmap fst . go 0 limit =<< mproject it0
+    where
+        go _ 0 _ = return ([], 0)
+        go lowIndex n (Branch h _ _ _ l r)
+            | start >= midIndex = go midIndex n =<< mproject r
+            | otherwise = do
+                (ls, n') <- go lowIndex n =<< mproject l
+                if n' == 0 then
+                    return (ls, 0)
+                else do
+                    (rs, n'') <- go midIndex n' =<< mproject r
+                    return (ls ++ rs, n'')
+            where
+                midIndex = setBit lowIndex (fromIntegral h)
+        go lowIndex n (Leaf inst)
+            | lowIndex >= start = return ([inst], n - 1)
+            | otherwise = return ([], n)
+        go _ n VacantLeaf{} = return ([], n)
+
 makePersistent :: forall m. MonadBlobStore m => Modules.Modules -> Transient.Instances -> m Instances
 makePersistent _ (Transient.Instances Transient.Empty) = return InstancesEmpty
 makePersistent mods (Transient.Instances (Transient.Tree s t)) = InstancesTree s <$> conv t
@@ -23,7 +23,11 @@ module Concordium.GlobalState.Persistent.LMDB (
   , initializeDatabase
   , VersionDatabaseHandlers(..)
   , openReadOnlyDatabase
+  , VersionMetadata(..)
+  , readDatabaseVersion
+  , isSupportedDatabaseVersion
   , closeDatabase
+  , copyDatabase
   , addDatabaseVersion
   , checkDatabaseVersion
   , resizeOnResized
@@ -55,7 +59,7 @@ import qualified Concordium.GlobalState.TransactionTable as T
 import Concordium.Crypto.SHA256
 import Concordium.Types.HashableTo
 import Control.Concurrent (runInBoundThread)
-import Control.Monad.Catch (tryJust, handleJust, MonadCatch)
+import Control.Monad.Catch (tryJust, handleJust, MonadCatch, bracket)
 import Control.Monad.IO.Class
 import Control.Monad
 import Control.Monad.State
@@ -326,6 +330,27 @@ openReadOnlyDatabase treeStateDir = do
                         return (Just (VersionDatabaseHandlers @pv DatabaseHandlers{..}))
         _ -> Nothing <$ mdb_env_close _storeEnv
 
+-- |Read the version record of an existing database, e.g., a copy that is not used by a running node,
+-- without opening its other stores.
+-- The result is 'Nothing' if the database has no version record or it cannot be deserialized.
+readDatabaseVersion
+  :: FilePath
+  -- ^Path of database
+  -> IO (Maybe VersionMetadata)
+readDatabaseVersion treeStateDir = bracket mdb_env_create mdb_env_close $ \env -> do
+  mdb_env_set_mapsize env dbInitSize
+  mdb_env_set_maxdbs env databaseCount
+  mdb_env_open env treeStateDir [MDB_RDONLY]
+  mversion <- transaction env True $ \txn -> do
+    metadataStore' <- MetadataStore <$> mdb_dbi_open' txn (Just metadataStoreName) []
+    loadRecord txn metadataStore' versionMetadata
+  return $ mversion >>= either (const Nothing) Just . S.decode
+
+-- |Whether this version of the node can open a database with the given version.
+-- As in 'checkDatabaseVersion', the protocol version is not checked here.
+isSupportedDatabaseVersion :: VersionMetadata -> Bool
+isSupportedDatabaseVersion vm = vmDatabaseVersion vm == 0
+
 
 -- |Initialize the database handlers creating the databases if needed and writing the genesis block and its finalization record into the disk
 initializeDatabase :: forall pv st ati bs. (IsProtocolVersion pv, S.Serialize st) =>
@@ -402,6 +427,11 @@ checkDatabaseVersion db = checkVersion <$> transaction (db ^. storeEnv) True
 closeDatabase :: DatabaseHandlers pv st -> IO ()
 closeDatabase db = runInBoundThread $ mdb_env_close (db ^. storeEnv)
 
+-- |Write a consistent copy of the database to the given directory, which must exist.
+-- The copy is made in a read transaction, so writers are not blocked while it is made.
+copyDatabase :: DatabaseHandlers pv st -> FilePath -> IO ()
+copyDatabase db dir = runInBoundThread $ mdb_env_copy (db ^. storeEnv) dir
+
 -- |Resize the LMDB map if the file size has changed.
 -- This is used to allow a secondary process that is reading the database
 -- to handle resizes to the database that are made by the writer.
@@ -21,6 +21,7 @@ import Concordium.GlobalState.Parameters
 import Concordium.GlobalState.Persistent.Account
 import Concordium.GlobalState.Persistent.BlockPointer as PB
 import Concordium.GlobalState.Persistent.LMDB
+import Concordium.GlobalState.Persistent.BlobStore (BlobStore(..), flushBlobStore)
 import Concordium.GlobalState.Statistics
 import Concordium.GlobalState.AccountTransactionIndex
 import Concordium.GlobalState.TransactionTable
@@ -339,6 +340,18 @@ loadSkovPersistentData rp _genesisData pbsc atiPair = do
 closeSkovPersistentData :: SkovPersistentData pv ati bs -> IO ()
 closeSkovPersistentData = closeDatabase . _db
 
+-- |Write a consistent copy of the tree state and the block state to the given directory,
+-- using the same names as the originals.
+-- The tree state is copied first. Since the block state file is only ever appended to,
+-- a copy of it that is made afterwards contains everything the copied tree state refers to.
+snapshotSkovPersistentData :: BlobStore -> SkovPersistentData pv ati bs -> FilePath -> IO ()
+snapshotSkovPersistentData blobStore skovData dir = do
+    let treeStateDir = dir </> takeFileName (rpTreeStateDir (skovData ^. runtimeParameters))
+    createDirectoryIfMissing True treeStateDir
+    copyDatabase (skovData ^. db) treeStateDir
+    flushBlobStore blobStore
+    copyFile (blobStoreFilePath blobStore) (dir </> takeFileName (blobStoreFilePath blobStore))
+
 -- |Newtype wrapper that provides an implementation of the TreeStateMonad using a persistent tree state.
 -- The underlying Monad must provide instances for:
 --
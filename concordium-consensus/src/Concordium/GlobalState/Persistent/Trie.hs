@@ -60,6 +60,9 @@ instance FixedTrieKey IDTypes.CredentialRegistrationID -- FIXME: this is not the
 class (Ord a, FixedTrieKey a) => OrdFixedTrieKey a
 instance OrdFixedTrieKey Word64
 instance OrdFixedTrieKey Word32
+-- Account addresses are serialized as their raw bytes, which are also compared
+-- byte-wise.
+instance OrdFixedTrieKey AccountAddress
 deriving via Word64 instance OrdFixedTrieKey BakerId
 
 -- |Trie with keys all of same fixed length treated as lists of bytes.
@@ -211,6 +214,38 @@ mapReduceF mfun = mr [] <=< mproject
 mapReduceAscF :: (MRecursive m t, Base t ~ TrieF k v, OrdFixedTrieKey k, Monoid a) => (k -> v -> m a) -> t -> m a
 mapReduceAscF = mapReduceF
 
+-- |Get at most the given number of keys, from lowest to highest in their
+-- byte-wise representation, that are at least the given key, if any. Only the
+-- part of the trie holding the returned keys is traversed.
+keysFromF :: forall m t k v. (MRecursive m t, Base t ~ TrieF k v, FixedTrieKey k) => Maybe k -> Word64 -> t -> m [k]
+keysFromF start limit = fmap fst . go [] (unpackKey <$> start) limit <=< mproject
+    where
+        -- The second argument is the remainder of the lower bound on the key,
+        -- if the keys so far are equal to it.
+        go :: [Word8] -> Maybe [Word8] -> Word64 -> TrieF k v t -> m ([k], Word64)
+        go _ _ 0 _ = return ([], 0)
+        go keyPrefix _ n (Tip _) = return ([packKey keyPrefix], n - 1)
+        go keyPrefix Nothing n (Stem pref r) = go (keyPrefix ++ pref) Nothing n =<< mproject r
+        go keyPrefix (Just bound) n (Stem pref r) = case compare pref boundPref of
+                LT -> return ([], n)
+                EQ -> go (keyPrefix ++ pref) (Just boundRest) n =<< mproject r
+                GT -> go (keyPrefix ++ pref) Nothing n =<< mproject r
+            where
+                (boundPref, boundRest) = splitAt (length pref) bound
+        go keyPrefix bound n (Branch vec) = branches (drop lowest (zip [0..] (V.toList vec))) n []
+            where
+                (lowest, childBound) = case bound of
+                    Just (b:bs) -> (fromIntegral b, Just bs)
+                    _ -> (0, Nothing)
+                branches :: [(Int, Nullable t)] -> Word64 -> [[k]] -> m ([k], Word64)
+                branches [] n' acc = return (concat (reverse acc), n')
+                branches _ 0 acc = return (concat (reverse acc), 0)
+                branches ((_, Null):rest) n' acc = branches rest n' acc
+                branches ((i, Some r):rest) n' acc = do
+                    let bound' = if i == lowest then childBound else Nothing
+                    (ks, n'') <- go (keyPrefix ++ [fromIntegral i]) bound' n' =<< mproject r
+                    branches rest n'' (ks:acc)
+
 -- |Compute the common prefix and distinct suffixes of two lists.
 commonPrefix :: (Eq a) => [a] -> [a] -> ([a], [a], [a])
 commonPrefix [] [] = ([], [], [])
@@ -406,6 +441,12 @@ keys (TrieN _ t) = mapReduceF (\k _ -> pure [k]) t
 keysAsc :: (MRecursive m (fix (TrieF k v)), Base (fix (TrieF k v)) ~ TrieF k v, OrdFixedTrieKey k) => TrieN fix k v -> m [k]
 keysAsc = keys
 
+-- |Get at most the given number of keys of a trie in ascending order, starting
+-- at the given key, or at the lowest key if none is given.
+keysFrom :: (MRecursive m (fix (TrieF k v)), Base (fix (TrieF k v)) ~ TrieF k v, OrdFixedTrieKey k) => Maybe k -> Word64 -> TrieN fix k v -> m [k]
+keysFrom _ _ EmptyTrieN = return []
+keysFrom start limit (TrieN _ t) = keysFromF start limit t
+
 -- |Convert from a trie using 'Fix' (i.e. direct unrolling) to a trie using a different fixpoint combinator.
 fromTrie :: forall m fix k v. (MCorecursive m (fix (TrieF k v)), Base (fix (TrieF k v)) ~ TrieF k v) => TrieN Fix k v -> m (TrieN fix k v)
 fromTrie EmptyTrieN = return EmptyTrieN
@@ -57,13 +57,17 @@ data SimpleOutMessage
     | SOMsgFinalizationRecord !BS.ByteString
 
 data SyncRunner c = SyncRunner {
-    syncBakerIdentity :: !BakerIdentity,
+    -- |The identity used for baking. It can be replaced while consensus is
+    -- running with 'setSyncBakerIdentity'.
+    syncBakerIdentity :: !(IORef BakerIdentity),
     syncState :: !(MVar (SkovState c)),
     syncBakerThread :: !(MVar ThreadId),
     syncLogMethod :: LogMethod IO,
     syncCallback :: SimpleOutMessage -> IO (),
     syncFinalizationCatchUpActive :: MVar (Maybe (IORef Bool)),
-    syncContext :: !(SkovContext c),
+    -- |The context is only replaced while holding the lock on 'syncState',
+    -- when the baker identity changes.
+    syncContext :: !(IORef (SkovContext c)),
     syncHandlePendingLive :: !(IO ()),
     syncTransactionPurgingThread :: !(MVar ThreadId),
     -- |Genesis block hashes will be used to check whether we are compatible
@@ -76,7 +80,8 @@ instance (SkovQueryMonad (SkovProtocolVersion c) (SkovT () c LogIO)) => SkovStat
     type SkovStateProtocolVersion (SyncRunner c) = SkovProtocolVersion c
     runStateQuery sr a = do
         s <- readMVar (syncState sr)
-        runLoggerT (evalSkovT a () (syncContext sr) s) (syncLogMethod sr)
+        ctx <- readIORef (syncContext sr)
+        runLoggerT (evalSkovT a () ctx s) (syncLogMethod sr)
 
 bufferedHandlePendingLive :: IO () -> MVar (Maybe (UTCTime, UTCTime)) -> IO ()
 bufferedHandlePendingLive hpl bufferMVar = do
@@ -112,8 +117,10 @@ makeSyncRunner :: (SkovConfiguration c, SkovQueryMonad (SkovProtocolVersion c) (
                   (CatchUpStatus -> IO ()) ->
                   (BlockHash -> IO ()) ->
                   IO (SyncRunner c)
-makeSyncRunner syncLogMethod syncBakerIdentity config syncCallback cusCallback syncRegenesisCallback = do
-        (syncContext, st0) <- runLoggerT (initialiseSkov config) syncLogMethod
+makeSyncRunner syncLogMethod bid config syncCallback cusCallback syncRegenesisCallback = do
+        (ctx0, st0) <- runLoggerT (initialiseSkov config) syncLogMethod
+        syncBakerIdentity <- newIORef bid
+        syncContext <- newIORef ctx0
         syncState <- newMVar st0
         syncTransactionPurgingThread <- newEmptyMVar
         syncBakerThread <- newEmptyMVar
@@ -140,7 +147,9 @@ runWithStateLog mvState logm a = bracketOnError (takeMVar mvState) (tryPutMVar m
 
 runSkovTransaction :: SyncRunner c -> SkovTLogIO c a -> IO a
 {-# INLINE runSkovTransaction #-}
-runSkovTransaction sr@SyncRunner{..} a = runWithStateLog syncState syncLogMethod (runSkovT a (syncSkovHandlers sr) syncContext)
+runSkovTransaction sr@SyncRunner{..} a = runWithStateLog syncState syncLogMethod $ \s -> do
+        ctx <- liftIO $ readIORef syncContext
+        runSkovT a (syncSkovHandlers sr) ctx s
 
 syncSkovHandlers :: forall c. SyncRunner c -> SkovHandlers ThreadTimer c LogIO
 syncSkovHandlers sr@SyncRunner{..} = SkovHandlers{
@@ -180,8 +189,10 @@ startSyncRunner sr@SyncRunner{..} = do
     bakerLoop :: Slot -> IO ()
     bakerLoop nextSlot = do
 
-        res <- runWithStateLog syncState syncLogMethod
-            (runSkovT (tryBake syncBakerIdentity nextSlot) (syncSkovHandlers sr) syncContext)
+        res <- runWithStateLog syncState syncLogMethod $ \s -> do
+            bid <- liftIO $ readIORef syncBakerIdentity
+            ctx <- liftIO $ readIORef syncContext
+            runSkovT (tryBake bid nextSlot) (syncSkovHandlers sr) ctx s
         case res of
             BakeSuccess slot' block -> do
                 syncCallback (SOMsgNewBlock block)
@@ -218,7 +229,39 @@ stopSyncRunner SyncRunner{..} = do
 shutdownSyncRunner :: (SkovConfiguration c) => SyncRunner c -> IO ()
 shutdownSyncRunner sr@SyncRunner{..} = do
         stopSyncRunner sr
-        takeMVar syncState >>= flip runLoggerT syncLogMethod . shutdownSkov syncContext
+        ctx <- readIORef syncContext
+        takeMVar syncState >>= flip runLoggerT syncLogMethod . shutdownSkov ctx
+
+-- |Write a consistent copy of the global state to the given directory.
+-- Consensus keeps running while the copy is made.
+syncSnapshot :: (SkovConfiguration c) => SyncRunner c -> FilePath -> IO ()
+syncSnapshot SyncRunner{..} dir = do
+        s <- readMVar syncState
+        ctx <- readIORef syncContext
+        runLoggerT (snapshotSkov ctx s dir) syncLogMethod
+
+-- |Replace the baker identity of a 'SyncRunner'. The context is updated with
+-- the given function, which should install the keys of the new identity for
+-- finalization. The baker thread is stopped while the identity is replaced and
+-- restarted afterwards if it was running. Since the current finalization round
+-- keeps its keys, the new keys are used for finalization from the next
+-- finalization index.
+setSyncBakerIdentity :: forall c. (
+    (SkovQueryMonad (SkovProtocolVersion c) (SkovT () c LogIO)),
+    (BakerMonad (SkovProtocolVersion c) (SkovTLogIO c))
+    ) => SyncRunner c -> BakerIdentity -> (SkovContext c -> SkovContext c) -> IO ()
+setSyncBakerIdentity sr@SyncRunner{..} bid updateContext = do
+        wasBaking <- not <$> isEmptyMVar syncBakerThread
+        stopSyncRunner sr
+        -- The identity and the context are replaced together under the state lock,
+        -- so that no transaction sees only one of them.
+        runWithStateLog syncState syncLogMethod $ \s -> do
+            liftIO $ do
+                writeIORef syncBakerIdentity bid
+                modifyIORef' syncContext updateContext
+            return ((), s)
+        syncLogMethod Runner LLInfo $ "Replaced the baker identity; now baker " ++ show (bakerId bid)
+        when wasBaking $ startSyncRunner sr
 
 isSlotTooEarly :: (TimeMonad m, SkovQueryMonad pv m) => Slot -> m Bool
 isSlotTooEarly s = do
@@ -317,6 +360,12 @@ shutdownSyncPassiveRunner SyncPassiveRunner{..} = do
         Nothing -> return ()
         Just thrd -> killThread thrd
 
+-- |Write a consistent copy of the global state to the given directory.
+syncPassiveSnapshot :: SkovConfiguration c => SyncPassiveRunner c -> FilePath -> IO ()
+syncPassiveSnapshot SyncPassiveRunner{..} dir = do
+  s <- readMVar syncPState
+  runLoggerT (snapshotSkov syncPContext s dir) syncPLogMethod
+
 syncPassiveReceiveBlock :: (SkovMonad (SkovProtocolVersion c) (SkovT (SkovPassiveHandlers c LogIO) c LogIO))
                         => SyncPassiveRunner c -> PendingBlock -> IO UpdateResult
 syncPassiveReceiveBlock spr block = do
@@ -114,6 +114,8 @@ class (
     initialiseSkov :: c -> LogIO (SkovContext c, SkovState c)
     -- |Free any resources when we are done with the context and state.
     shutdownSkov :: SkovContext c -> SkovState c -> LogIO ()
+    -- |Write a consistent copy of the global state to the given directory.
+    snapshotSkov :: SkovContext c -> SkovState c -> FilePath -> LogIO ()
 
 -- |An instance of 'SkovTimerHandlers' provides a means for implementing
 -- a 'TimerMonad' instance for 'SkovT'.
@@ -437,6 +439,7 @@ instance (
         let (hctx, hst) = initialiseHandler conf
         return (SkovContext c finctx hctx, SkovState s finst hst logCtx)
     shutdownSkov (SkovContext c _ _) (SkovState s _ _ logCtx) = liftIO $ shutdownGlobalState (Proxy :: Proxy gsconf) c s logCtx
+    snapshotSkov (SkovContext c _ _) (SkovState s _ _ _) dir = liftIO $ snapshotGlobalState (Proxy :: Proxy gsconf) c s dir
 
 instance (FinalizationQueueLenses (FCState (SkovConfig pv gsconf finconf hconf)))
         => FinalizationQueueLenses (SkovState (SkovConfig pv gsconf finconf hconf)) where
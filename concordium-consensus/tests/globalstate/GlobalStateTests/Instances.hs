@@ -323,10 +323,18 @@ testFoldInstances insts model = allInsts === modInsts
         allInsts = (\i -> (instanceAddress (instanceParameters i), instanceData i)) <$> (insts ^.. foldInstances)
         modInsts = (\(ci, (csi, d)) -> (ContractAddress ci csi, d)) <$> Map.toAscList (modelInstances model)
 
+testInstancesFrom :: Instances -> Model -> Property
+testInstancesFrom insts model = forAll (choose (0, 5001)) $ \start ->
+        fromInsts (ContractIndex start) === modInsts (ContractIndex start)
+    where
+        fromInsts start = (\i -> (instanceAddress (instanceParameters i), instanceData i)) <$> instancesFrom start insts
+        modInsts start = (\(ci, (csi, d)) -> (ContractAddress ci csi, d)) <$> Map.toAscList (Map.dropWhileAntitone (< start) (modelInstances model))
+
 tests :: Word -> Spec
 tests lvl = describe "GlobalStateTests.Instances" $ do
     it "getInstance" $ withMaxSuccess (100 * fromIntegral lvl)
         $ forAllBlind (generateFromUpdates 5000) $ \(i,m) -> withMaxSuccess 100 $ testGetInstance i m
     it "foldInstances" $ withMaxSuccess 100 $ forAllBlind (generateFromUpdates 5000) $ uncurry testFoldInstances
+    it "instancesFrom" $ withMaxSuccess 100 $ forAllBlind (generateFromUpdates 5000) $ uncurry testInstancesFrom
     it "50000 create/delete - check at end" $ withMaxSuccess 10 $ testCreateDelete 50000
     it "500 instance updates - check every step" $ withMaxSuccess (100 * fromIntegral lvl) $ testUpdates 500
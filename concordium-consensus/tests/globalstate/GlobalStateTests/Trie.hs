@@ -22,7 +22,7 @@ newtype SerializeStorable v = SerStore v
 instance (Serialize v, MonadBlobStore m) => BlobStorable m (SerializeStorable v)
 
 tests :: Spec
-tests = describe "GlobalStateTests.Trie" $
+tests = describe "GlobalStateTests.Trie" $ do
     it "simple test" $
         runBlobStoreTemp "." $ do
             let e = Trie.empty :: Trie.TrieN (BufferedBlobbed BlobRef) Word64 (SerializeStorable String)
@@ -33,3 +33,18 @@ tests = describe "GlobalStateTests.Trie" $
             (e2' :: Trie.TrieN (CachedBlobbed BlobRef) Word64 (SerializeStorable String)) <- me2'
             r <- Trie.lookup 27 e2'
             liftIO $ r `shouldBe` Just (SerStore "Hello")
+    it "keys from a given key" $
+        runBlobStoreTemp "." $ do
+            let keys = [1, 5, 300, 70000, 2^(40 :: Int)] :: [Word64]
+            (t :: Trie.TrieN (BufferedBlobbed BlobRef) Word64 (SerializeStorable String)) <-
+                Trie.fromList [(k, SerStore (show k)) | k <- reverse keys]
+            ks0 <- Trie.keysFrom Nothing 3 t
+            liftIO $ ks0 `shouldBe` take 3 keys
+            ks1 <- Trie.keysFrom (Just 6) 10 t
+            liftIO $ ks1 `shouldBe` drop 2 keys
+            ks2 <- Trie.keysFrom (Just 300) 2 t
+            liftIO $ ks2 `shouldBe` [300, 70000]
+            ks3 <- Trie.keysFrom (Just (2^(40 :: Int) + 1)) 5 t
+            liftIO $ ks3 `shouldBe` []
+            ks4 <- Trie.keysFrom (Just 0) 0 t
+            liftIO $ ks4 `shouldBe` []
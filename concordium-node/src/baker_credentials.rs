@@ -0,0 +1,243 @@
+//! Encrypting and decrypting baker credentials.
+//!
+//! Encrypted credentials are the JSON serialization of the data produced by
+//! `crypto_common::encryption`. The password to decrypt them is either entered
+//! interactively or, so that the node can run unattended, read from a file
+//! descriptor, a password file or an environment variable.
+
+use anyhow::{anyhow, bail, ensure, Context};
+use parking_lot::Mutex;
+use std::{fs, io::Read, path::Path};
+
+/// The environment variable the password of the baker credentials is read from
+/// if no other source is given. It is removed from the environment when it is
+/// claimed by [`claim_environment`].
+pub const BAKER_CREDENTIALS_PASSWORD_ENV: &str = "CONCORDIUM_NODE_BAKER_CREDENTIALS_PASSWORD";
+
+/// The password sources that belong to the process rather than to a path,
+/// from the time they are claimed until the password is read.
+#[derive(Default)]
+struct ClaimedSources {
+    #[cfg(unix)]
+    descriptor:  Option<(i32, fs::File)>,
+    environment: Option<String>,
+}
+
+lazy_static! {
+    static ref CLAIMED_SOURCES: Mutex<ClaimedSources> = Mutex::new(ClaimedSources::default());
+}
+
+/// Takes ownership of the file descriptor the password is read from.
+///
+/// This must happen at the start of `main`, before the process opens any
+/// descriptor of its own. The descriptor must then be open, so it was
+/// inherited by the process and nothing else in it owns the descriptor. It is
+/// closed once the password is read, and not inherited by child processes.
+#[cfg(unix)]
+pub fn claim_descriptor(fd: i32) -> anyhow::Result<()> {
+    use std::os::unix::io::FromRawFd;
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    ensure!(flags != -1, "The password descriptor {} is not open", fd);
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) } == -1 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Can't claim the password descriptor {}", fd));
+    }
+    // SAFETY: the descriptor is open and, since the process has not opened any
+    // descriptor yet, it was inherited and is not owned by anything else. From now
+    // on the file owns it.
+    let file = unsafe { fs::File::from_raw_fd(fd) };
+    CLAIMED_SOURCES.lock().descriptor = Some((fd, file));
+    Ok(())
+}
+
+/// Takes the password from the [`BAKER_CREDENTIALS_PASSWORD_ENV`] environment
+/// variable, if it is set, and removes the variable so that it isn't inherited
+/// by child processes.
+///
+/// This must happen at the start of `main`, before the process starts any
+/// thread that could read the environment while it is changed.
+pub fn claim_environment() -> anyhow::Result<()> {
+    if let Some(password) = std::env::var_os(BAKER_CREDENTIALS_PASSWORD_ENV) {
+        std::env::remove_var(BAKER_CREDENTIALS_PASSWORD_ENV);
+        let password = password
+            .into_string()
+            .map_err(|_| anyhow!("{} is not valid Unicode", BAKER_CREDENTIALS_PASSWORD_ENV))?;
+        CLAIMED_SOURCES.lock().environment = Some(password);
+    }
+    Ok(())
+}
+
+/// Whether a password was claimed from the [`BAKER_CREDENTIALS_PASSWORD_ENV`]
+/// environment variable and not read yet.
+pub fn password_in_environment() -> bool { CLAIMED_SOURCES.lock().environment.is_some() }
+
+/// Where to read the password of encrypted baker credentials from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PasswordSource<'a> {
+    /// An open file descriptor, e.g., a pipe, which is read until its end. It
+    /// must have been claimed with [`claim_descriptor`].
+    #[cfg(unix)]
+    FileDescriptor(i32),
+    /// A file that is only accessible to its owner.
+    File(&'a Path),
+    /// The [`BAKER_CREDENTIALS_PASSWORD_ENV`] environment variable, as claimed
+    /// with [`claim_environment`].
+    Environment,
+    /// The terminal, with the given prompt.
+    Terminal(&'a str),
+}
+
+impl PasswordSource<'_> {
+    /// Reads the password. A single trailing newline is not part of the
+    /// password read from a file descriptor or a file.
+    pub fn read(&self) -> anyhow::Result<String> {
+        match self {
+            #[cfg(unix)]
+            PasswordSource::FileDescriptor(fd) => {
+                // the descriptor is closed once the password is read
+                let mut file = match CLAIMED_SOURCES.lock().descriptor.take() {
+                    Some((claimed, file)) if claimed == *fd => file,
+                    _ => bail!("The password descriptor {} was not claimed at startup", fd),
+                };
+                let mut password = String::new();
+                file.read_to_string(&mut password)
+                    .with_context(|| format!("Can't read the password from descriptor {}", fd))?;
+                Ok(trim_newline(password))
+            }
+            PasswordSource::File(path) => {
+                let mut file = open_password_file(path)?;
+                let mut password = String::new();
+                file.read_to_string(&mut password)
+                    .with_context(|| format!("Can't read the password file {:?}", path))?;
+                Ok(trim_newline(password))
+            }
+            PasswordSource::Environment => CLAIMED_SOURCES
+                .lock()
+                .environment
+                .take()
+                .with_context(|| format!("{} is not set", BAKER_CREDENTIALS_PASSWORD_ENV)),
+            PasswordSource::Terminal(prompt) => {
+                Ok(rpassword::read_password_from_tty(Some(prompt))?)
+            }
+        }
+    }
+}
+
+fn trim_newline(mut password: String) -> String {
+    if password.ends_with('\n') {
+        password.pop();
+        if password.ends_with('\r') {
+            password.pop();
+        }
+    }
+    password
+}
+
+/// Opens a password file, rejecting it if it is accessible to anyone but its
+/// owner. The permissions are checked on the open file, so that it can't be
+/// replaced between the check and the read.
+#[cfg(unix)]
+fn open_password_file(path: &Path) -> anyhow::Result<fs::File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    // so that opening a named pipe doesn't wait for a writer before it is rejected
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .with_context(|| format!("Can't open the password file {:?}", path))?;
+    let metadata =
+        file.metadata().with_context(|| format!("Can't read the password file {:?}", path))?;
+    ensure!(metadata.is_file(), "The password file {:?} is not a regular file", path);
+    let mode = metadata.permissions().mode();
+    ensure!(
+        mode & 0o077 == 0,
+        "The password file {:?} is accessible to other users (mode {:o}); it must only be \
+         accessible to its owner, e.g., with mode 600",
+        path,
+        mode & 0o777
+    );
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn open_password_file(path: &Path) -> anyhow::Result<fs::File> {
+    fs::File::open(path).with_context(|| format!("Can't open the password file {:?}", path))
+}
+
+/// Decrypts baker credentials in the format written by the key generation
+/// tools with the given password.
+pub fn decrypt_baker_credentials(encrypted: &[u8], password: String) -> anyhow::Result<Vec<u8>> {
+    let et = serde_json::from_slice(encrypted)?;
+    match crypto_common::encryption::decrypt(&password.into(), &et) {
+        Ok(d) => Ok(d),
+        Err(_) => bail!(
+            "Could not decrypt baker credentials. Most likely the password you provided is \
+             incorrect."
+        ),
+    }
+}
+
+/// Encrypts baker credentials with the given password, in the format read by
+/// [`decrypt_baker_credentials`].
+pub fn encrypt_baker_credentials(credentials: &[u8], password: String) -> anyhow::Result<Vec<u8>> {
+    ensure!(
+        serde_json::from_slice::<serde_json::Value>(credentials)
+            .map_or(false, |credentials| credentials.is_object()),
+        "The baker credentials are not a JSON object"
+    );
+    let et =
+        crypto_common::encryption::encrypt(&password.into(), credentials, &mut rand::thread_rng());
+    Ok(serde_json::to_vec_pretty(&et)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let credentials = br#"{"bakerId":1}"#;
+        let encrypted = encrypt_baker_credentials(credentials, "secret".to_owned()).unwrap();
+        assert_ne!(&encrypted[..], &credentials[..]);
+        assert_eq!(
+            decrypt_baker_credentials(&encrypted, "secret".to_owned()).unwrap(),
+            credentials
+        );
+        assert!(decrypt_baker_credentials(&encrypted, "wrong".to_owned()).is_err());
+        assert!(encrypt_baker_credentials(b"[]", "secret".to_owned()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_password_file() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("password");
+        fs::write(&path, "secret\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(PasswordSource::File(&path).read().is_err());
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(PasswordSource::File(&path).read().unwrap(), "secret");
+        assert!(PasswordSource::File(dir.path()).read().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_password_descriptor() {
+        use std::{io::Write, os::unix::io::FromRawFd};
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let mut writer = unsafe { fs::File::from_raw_fd(fds[1]) };
+        writer.write_all(b"secret\n").unwrap();
+        drop(writer);
+
+        // the descriptor must be claimed before the password can be read from it
+        assert!(PasswordSource::FileDescriptor(fds[0]).read().is_err());
+        claim_descriptor(fds[0]).unwrap();
+        let flags = unsafe { libc::fcntl(fds[0], libc::F_GETFD) };
+        assert_eq!(flags & libc::FD_CLOEXEC, libc::FD_CLOEXEC);
+        assert_eq!(PasswordSource::FileDescriptor(fds[0]).read().unwrap(), "secret");
+        // the password is only read once
+        assert!(PasswordSource::FileDescriptor(fds[0]).read().is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Encrypts baker credentials, or re-encrypts them with a new password, in the
+//! format read by the node with `--decrypt-baker-credentials`.
+
+use anyhow::{ensure, Context};
+use concordium_node::baker_credentials::{
+    decrypt_baker_credentials, encrypt_baker_credentials, PasswordSource,
+};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "Baker credentials tool")]
+enum Command {
+    #[structopt(about = "Encrypt plaintext baker credentials")]
+    Encrypt {
+        #[structopt(flatten)]
+        files:         Files,
+        #[structopt(
+            long = "password-file",
+            help = "Read the password from this file instead of the terminal"
+        )]
+        password_file: Option<PathBuf>,
+    },
+    #[structopt(about = "Re-encrypt encrypted baker credentials with a new password")]
+    Reencrypt {
+        #[structopt(flatten)]
+        files:             Files,
+        #[structopt(
+            long = "old-password-file",
+            help = "Read the current password from this file instead of the terminal"
+        )]
+        old_password_file: Option<PathBuf>,
+        #[structopt(
+            long = "password-file",
+            help = "Read the new password from this file instead of the terminal"
+        )]
+        password_file:     Option<PathBuf>,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+struct Files {
+    #[structopt(long = "in", help = "The baker credentials to read")]
+    input:           PathBuf,
+    #[structopt(long = "out", help = "Where to write the encrypted baker credentials")]
+    output:          PathBuf,
+    #[structopt(long = "force-overwrite", help = "Overwrite the output file if it exists")]
+    force_overwrite: bool,
+}
+
+/// Reads a password from the given file, or from the terminal, in which case
+/// a new password has to be entered twice.
+fn read_password(file: Option<&Path>, prompt: &str, confirm: bool) -> anyhow::Result<String> {
+    if let Some(file) = file {
+        return PasswordSource::File(file).read();
+    }
+    let password = PasswordSource::Terminal(prompt).read()?;
+    if confirm {
+        let repeated = PasswordSource::Terminal("Repeat the password: ").read()?;
+        ensure!(password == repeated, "The passwords don't match");
+    }
+    Ok(password)
+}
+
+/// Writes the encrypted credentials, which only the owner can access.
+fn write_output(files: &Files, encrypted: &[u8]) -> anyhow::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if files.force_overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file =
+        options.open(&files.output).with_context(|| format!("Can't create {:?}", files.output))?;
+    file.write_all(encrypted)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+pub fn main() -> anyhow::Result<()> {
+    let files = match Command::from_args() {
+        Command::Encrypt {
+            files,
+            password_file,
+        } => {
+            let credentials = fs::read(&files.input)
+                .with_context(|| format!("Can't read the baker credentials {:?}", files.input))?;
+            let password = read_password(
+                password_file.as_deref(),
+                "Enter a password to encrypt with: ",
+                true,
+            )?;
+            write_output(&files, &encrypt_baker_credentials(&credentials, password)?)?;
+            files
+        }
+        Command::Reencrypt {
+            files,
+            old_password_file,
+            password_file,
+        } => {
+            let encrypted = fs::read(&files.input)
+                .with_context(|| format!("Can't read the baker credentials {:?}", files.input))?;
+            let old_password =
+                read_password(old_password_file.as_deref(), "Enter the current password: ", false)?;
+            let credentials = decrypt_baker_credentials(&encrypted, old_password)?;
+            let password =
+                read_password(password_file.as_deref(), "Enter the new password: ", true)?;
+            write_output(&files, &encrypt_baker_credentials(&credentials, password)?)?;
+            files
+        }
+    };
+    println!("Encrypted baker credentials written to {:?}", files.output);
+    Ok(())
+}
@@ -12,31 +12,39 @@ use concordium_node::{
     common::PeerType,
     configuration as config,
     consensus_ffi::{
+        block_export::{export_blocks, sign_manifest, ExportRange},
         blockchain_types::BlockHash,
         consensus::{
-            ConsensusContainer, ConsensusLogLevel, CALLBACK_QUEUE, CONSENSUS_QUEUE_DEPTH_IN_HI,
-            CONSENSUS_QUEUE_DEPTH_OUT_HI,
+            configure_queue_depths, Consensus, ConsensusLogLevel, QueueDepths, CALLBACK_QUEUE,
         },
         ffi,
-        helpers::QueueMsg,
-        messaging::ConsensusMessage,
     },
     p2p::{
         connectivity::connect,
         maintenance::{attempt_bootstrap, spawn},
         *,
     },
-    plugins::{self, consensus::*},
+    plugins::{
+        self,
+        archive_import::{
+            import_from_archives, parse_archive_key, ArchiveSource, ARCHIVE_CACHE_DIRECTORY_NAME,
+        },
+        consensus::*,
+    },
     read_or_die,
     rpc::RpcServerImpl,
+    snapshot::restore_snapshot,
     spawn_or_die,
     stats_export_service::{instantiate_stats_export_engine, StatsExportService},
-    utils::get_config_and_logging_setup,
+    utils::get_logging_setup,
 };
+use ed25519_dalek::SecretKey;
 use mio::Poll;
 use parking_lot::Mutex as ParkingMutex;
 use rand::Rng;
 use std::{
+    fs,
+    path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -44,14 +52,33 @@ use std::{
     thread::JoinHandle,
 };
 
+#[cfg(feature = "http_gateway")]
+use concordium_node::http_gateway;
 #[cfg(feature = "instrumentation")]
 use concordium_node::stats_export_service::start_push_gateway;
 #[cfg(feature = "instrumentation")]
 use std::net::{IpAddr, SocketAddr};
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let (conf, mut app_prefs) = get_config_and_logging_setup()?;
+fn main() -> anyhow::Result<()> {
+    // The password sources of the baker credentials are claimed before the node
+    // opens any file or starts any thread, including those of the runtime.
+    let conf = config::parse_config()?;
+    conf.cli.baker.claim_password_sources()?;
+    let (conf, app_prefs) = get_logging_setup(conf)?;
+    tokio::runtime::Builder::new_multi_thread().enable_all().build()?.block_on(run(conf, app_prefs))
+}
+
+async fn run(conf: config::Config, mut app_prefs: config::AppPreferences) -> anyhow::Result<()> {
+    if let Some(ref snapshot) = conf.cli.baker.restore_snapshot {
+        // this must happen before the node opens its stores
+        return restore_data_directory(&conf, &app_prefs, snapshot);
+    }
+    configure_queue_depths(QueueDepths {
+        inbound_high:  conf.cli.baker.inbound_queue_depth_high,
+        inbound_low:   conf.cli.baker.inbound_queue_depth_low,
+        outbound_high: conf.cli.baker.outbound_queue_depth_high,
+        outbound_low:  conf.cli.baker.outbound_queue_depth_low,
+    })?;
     let shutdown_handler_state = Arc::new(AtomicBool::new(false));
 
     let stats_export_service = instantiate_stats_export_engine(&conf)?;
@@ -79,16 +106,45 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
-    // Register a SIGTERM handler for a POSIX.1-2001 system
+    // The consensus layer, once it is started, for draining the node
+    let drain_consensus: Arc<ParkingMutex<Option<Arc<dyn Consensus>>>> = Default::default();
+
+    // Drain the node and shut it down on SIGTERM on a POSIX.1-2001 system. A SIGINT
+    // received while draining shuts the node down at once.
     #[cfg(not(windows))]
     {
-        let sigterm_shutdown_handler_state = shutdown_handler_state.clone();
-        let signal_hook_node = node.clone();
-        unsafe {
-            signal_hook::register(signal_hook::SIGTERM, move || {
-                signal_closure(&signal_hook_node, &sigterm_shutdown_handler_state)
-            })
-        }?;
+        let signals = signal_hook::iterator::Signals::new(&[signal_hook::SIGTERM])?;
+        let sigterm_node = node.clone();
+        let sigterm_consensus = drain_consensus.clone();
+        spawn_or_die!("SIGTERM handler", move || {
+            for _ in signals.forever() {
+                info!("SIGTERM received; draining the node before shutting down");
+                let consensus = sigterm_consensus.lock().clone();
+                if !sigterm_node.drain_and_close(consensus.as_deref()) {
+                    error!("Can't shutdown node properly!");
+                    std::process::exit(1);
+                }
+            }
+        });
+    }
+
+    // Apply the runtime configuration file again on SIGHUP
+    #[cfg(not(windows))]
+    {
+        if let Some(path) = conf.common.runtime_config_file.clone() {
+            let signals = signal_hook::iterator::Signals::new(&[signal_hook::SIGHUP])?;
+            let sighup_node = node.clone();
+            spawn_or_die!("SIGHUP handler", move || {
+                for _ in signals.forever() {
+                    info!("SIGHUP received; applying the runtime configuration file");
+                    if let Err(e) = reconfiguration::read_config_file(&path)
+                        .and_then(|changes| sighup_node.apply_config_changes(changes))
+                    {
+                        error!("Can't apply the runtime configuration file: {:#}", e);
+                    }
+                }
+            });
+        }
     }
 
     // Register a safe handler for SIGINT / ^C
@@ -157,11 +213,58 @@ async fn main() -> anyhow::Result<()> {
         regenesis_arc,
     )?;
     info!("Consensus layer started");
+    // the P2P layer and the RPC server only use the consensus through its interface
+    let consensus_interface: Arc<dyn Consensus> = Arc::new(consensus.clone());
+    *drain_consensus.lock() = Some(consensus_interface.clone());
+
+    if let Some(ref export_path) = conf.cli.baker.export_path {
+        let range = ExportRange {
+            from_height:     conf.cli.baker.export_from_height,
+            to_height:       conf.cli.baker.export_to_height,
+            blocks_per_file: conf.cli.baker.export_blocks_per_file,
+        };
+        let genesis_block = read_or_die!(node.config.regenesis_arc).first().cloned();
+        let result =
+            export_blocks(&consensus, genesis_block, export_path, range, &|| false, &mut |file| {
+                info!(
+                    "Exported the blocks at heights {} to {}",
+                    file.first_height, file.last_height
+                )
+            })
+            .and_then(|_| match conf.cli.baker.export_signing_key {
+                Some(ref key_file) => {
+                    let key = fs::read(key_file)
+                        .with_context(|| format!("Can't read the signing key {:?}", key_file))?;
+                    let key = SecretKey::from_bytes(&key)
+                        .map_err(|e| anyhow::anyhow!("Invalid signing key: {}", e))?;
+                    sign_manifest(export_path, &key)
+                }
+                None => Ok(()),
+            });
+        consensus.stop();
+        ffi::stop_haskell();
+        return result.context("Can't export the blocks");
+    }
 
     // Start the RPC server
     if !conf.cli.rpc.no_rpc_server {
-        let mut serv = RpcServerImpl::new(node.clone(), Some(consensus.clone()), &conf.cli.rpc)
-            .context("Cannot create RPC server.")?;
+        let mut serv =
+            RpcServerImpl::new(node.clone(), Some(consensus_interface.clone()), &conf.cli.rpc)
+                .context("Cannot create RPC server.")?;
+        #[cfg(feature = "http_gateway")]
+        if let Some(port) = conf.cli.rpc.rpc_http_gateway_port {
+            let addr = std::net::SocketAddr::new(
+                conf.cli.rpc.rpc_server_addr.parse().context("Invalid RPC server address")?,
+                port,
+            );
+            http_gateway::start_http_gateway(
+                serv.clone(),
+                addr,
+                conf.cli.rpc.rpc_http_gateway_admin,
+            )
+            .await?;
+            info!("HTTP gateway started on {}", addr);
+        }
         tokio::spawn(async move {
             serv.start_server().await.expect("Can't start the RPC server");
         });
@@ -174,11 +277,45 @@ async fn main() -> anyhow::Result<()> {
         info!("Completed out of band catch-up");
     }
 
+    if !conf.cli.baker.import_archives.is_empty() {
+        let sources = conf
+            .cli
+            .baker
+            .import_archives
+            .iter()
+            .map(|source| source.parse::<ArchiveSource>())
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("Invalid block archive")?;
+        let keys = conf
+            .cli
+            .baker
+            .import_archive_keys
+            .iter()
+            .map(|key| parse_archive_key(key))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("Invalid block archive key")?;
+        let genesis_block = read_or_die!(node.config.regenesis_arc).first().cloned();
+        info!("Starting out of band catch-up from block archives");
+        import_from_archives(
+            &consensus,
+            &sources,
+            &keys,
+            genesis_block.as_ref(),
+            &data_dir_path.join(ARCHIVE_CACHE_DIRECTORY_NAME),
+        )
+        .await;
+        info!("Completed out of band catch-up from block archives");
+    }
+
     // Consensus queue threads
-    let consensus_queue_threads = start_consensus_message_threads(&node, consensus.clone());
+    let consensus_queue_threads = start_consensus_message_threads(
+        &node,
+        consensus_interface.clone(),
+        conf.cli.baker.transaction_workers,
+    );
 
     // The P2P node event loop thread
-    spawn(&node, poll, Some(consensus.clone()));
+    spawn(&node, poll, Some(consensus_interface));
 
     // Connect to nodes (args and bootstrap)
     if !conf.cli.no_network {
@@ -191,6 +328,13 @@ async fn main() -> anyhow::Result<()> {
     // Wait for the P2PNode to close
     node.join().context("The node thread panicked!")?;
 
+    // A drain started by the Shutdown RPC reports its failure on the node; the
+    // consensus queues may not have stopped then, so they aren't waited for
+    if node.drain_failed() {
+        error!("Can't shutdown node properly!");
+        std::process::exit(1);
+    }
+
     // Wait for the consensus queue threads to stop
     for consensus_queue_thread in consensus_queue_threads {
         consensus_queue_thread.join().expect("A consensus queue thread panicked");
@@ -212,6 +356,31 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Restores the data directory from a snapshot for the chain of the node's
+/// genesis data.
+fn restore_data_directory(
+    conf: &config::Config,
+    app_prefs: &config::AppPreferences,
+    snapshot: &Path,
+) -> anyhow::Result<()> {
+    let genesis_data = plugins::consensus::get_genesis_data(app_prefs)?;
+    // the runtime computes the genesis block and reads the version of the restored
+    // tree state
+    plugins::consensus::start_haskell(&conf.cli.baker);
+    let result = ffi::genesis_block_hash(&genesis_data).and_then(|genesis_block| {
+        restore_snapshot(
+            snapshot,
+            &genesis_block,
+            app_prefs.get_user_app_dir(),
+            app_prefs.get_user_config_dir(),
+            &ffi::tree_state_version,
+        )
+    });
+    ffi::stop_haskell();
+    result.context("Can't restore the snapshot")?;
+    Ok(())
+}
+
 fn instantiate_node(
     conf: &config::Config,
     app_prefs: &mut config::AppPreferences,
@@ -271,61 +440,15 @@ fn connect_to_config_nodes(node: &Arc<P2PNode>) {
 
 fn start_consensus_message_threads(
     node: &Arc<P2PNode>,
-    consensus: ConsensusContainer,
+    consensus: Arc<dyn Consensus>,
+    transaction_workers: usize,
 ) -> Vec<JoinHandle<()>> {
-    let mut threads: Vec<JoinHandle<()>> = Default::default();
-
-    let node_ref = Arc::clone(node);
-    threads.push(spawn_or_die!("inbound consensus requests", {
-        let consensus_receiver_high_priority =
-            CALLBACK_QUEUE.inbound.receiver_high_priority.lock().unwrap();
-        let consensus_receiver_low_priority =
-            CALLBACK_QUEUE.inbound.receiver_low_priority.lock().unwrap();
-        let cvar = &CALLBACK_QUEUE.inbound.signaler;
-        let lock = ParkingMutex::new(false);
-        let mut lock_guard = lock.lock();
-        let mut exhausted: bool;
-
-        'outer_loop: loop {
-            exhausted = false;
-            // Update size of queues
-            node_ref.stats.set_inbound_low_priority_consensus_size(
-                consensus_receiver_low_priority.len() as i64,
-            );
-            node_ref.stats.set_inbound_high_priority_consensus_size(
-                consensus_receiver_high_priority.len() as i64,
-            );
-            // instead of using `try_iter()` we specifically only loop over the max numbers
-            // possible to ever be in the queue
-            for _ in 0..CONSENSUS_QUEUE_DEPTH_IN_HI {
-                if let Ok(message) = consensus_receiver_high_priority.try_recv() {
-                    let stop_loop = !handle_queue_stop(message, "inbound", |msg| {
-                        handle_consensus_inbound_msg(&node_ref, &consensus, msg)
-                    });
-                    if stop_loop {
-                        break 'outer_loop;
-                    }
-                } else {
-                    exhausted = true;
-                    break;
-                }
-            }
-
-            if let Ok(message) = consensus_receiver_low_priority.try_recv() {
-                exhausted = false;
-                let stop_loop = !handle_queue_stop(message, "inbound", |msg| {
-                    handle_consensus_inbound_msg(&node_ref, &consensus, msg)
-                });
-                if stop_loop {
-                    break 'outer_loop;
-                }
-            }
-
-            if exhausted {
-                cvar.wait(&mut lock_guard);
-            }
-        }
-    }));
+    let mut threads = start_inbound_consensus_threads(
+        node,
+        consensus,
+        &CALLBACK_QUEUE.inbound,
+        transaction_workers,
+    );
 
     let node_ref = Arc::clone(node);
     threads.push(spawn_or_die!("outbound consensus requests", {
@@ -349,7 +472,7 @@ fn start_consensus_message_threads(
             );
             // instead of using `try_iter()` we specifically only loop over the max numbers
             // possible to ever be in the queue
-            for _ in 0..CONSENSUS_QUEUE_DEPTH_OUT_HI {
+            for _ in 0..CALLBACK_QUEUE.depths.outbound_high {
                 if let Ok(message) = consensus_receiver_high_priority.try_recv() {
                     let stop_loop = !handle_queue_stop(message, "outbound", |msg| {
                         handle_consensus_outbound_msg(&node_ref, msg)
@@ -381,20 +504,3 @@ fn start_consensus_message_threads(
 
     threads
 }
-
-fn handle_queue_stop<F>(msg: QueueMsg<ConsensusMessage>, dir: &'static str, f: F) -> bool
-where
-    F: FnOnce(ConsensusMessage) -> anyhow::Result<()>, {
-    match msg {
-        QueueMsg::Relay(msg) => {
-            if let Err(e) = f(msg) {
-                error!("There's an issue with an {} consensus request: {}", dir, e);
-            }
-        }
-        QueueMsg::Stop => {
-            debug!("Closing the {} consensus channel", dir);
-            return false;
-        }
-    }
-    true
-}
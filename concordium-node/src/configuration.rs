@@ -1,9 +1,11 @@
 //! The client's parameters and constants used by other modules.
 
 use crate::{
+    baker_credentials::{self, PasswordSource},
     common::P2PNodeId,
     connection::DeduplicationHashAlgorithm,
     network::{WireProtocolVersion, WIRE_PROTOCOL_VERSION},
+    rpc_limits::ClientIdentity,
 };
 use anyhow::{ensure, Context};
 use app_dirs2::*;
@@ -51,9 +53,6 @@ const APP_PREFERENCES_KEY_VERSION: &str = "VERSION";
 /// Used for a persistent node id setup.
 pub const APP_PREFERENCES_PERSISTED_NODE_ID: &str = "PERSISTED_NODE_ID";
 
-/// Maximum time allowed for a peer to catch up with, in milliseconds.
-pub const MAX_CATCH_UP_TIME: u64 = 300_000;
-
 // dump queue depths
 #[cfg(feature = "network_dump")]
 pub const DUMP_QUEUE_DEPTH: usize = 100;
@@ -270,12 +269,114 @@ pub struct BakerConfig {
         env = "CONCORDIUM_NODE_CONSENSUS_TRANSACTIONS_PURGING_DELAY"
     )]
     pub transactions_purging_delay: u32,
+    #[structopt(
+        long = "consensus-inbound-queue-depth-high",
+        help = "Capacity of the queue of blocks, finalization messages and catch-up messages \
+                received from peers",
+        default_value = "16384",
+        env = "CONCORDIUM_NODE_CONSENSUS_INBOUND_QUEUE_DEPTH_HIGH"
+    )]
+    pub inbound_queue_depth_high: usize,
+    #[structopt(
+        long = "consensus-inbound-queue-depth-low",
+        help = "Capacity of the queue of transactions received from peers",
+        default_value = "32768",
+        env = "CONCORDIUM_NODE_CONSENSUS_INBOUND_QUEUE_DEPTH_LOW"
+    )]
+    pub inbound_queue_depth_low: usize,
+    #[structopt(
+        long = "consensus-outbound-queue-depth-high",
+        help = "Capacity of the queue of messages from consensus to peers that must not be dropped",
+        default_value = "8192",
+        env = "CONCORDIUM_NODE_CONSENSUS_OUTBOUND_QUEUE_DEPTH_HIGH"
+    )]
+    pub outbound_queue_depth_high: usize,
+    #[structopt(
+        long = "consensus-outbound-queue-depth-low",
+        help = "Capacity of the queue of other messages from consensus to peers",
+        default_value = "16384",
+        env = "CONCORDIUM_NODE_CONSENSUS_OUTBOUND_QUEUE_DEPTH_LOW"
+    )]
+    pub outbound_queue_depth_low: usize,
+    #[structopt(
+        long = "consensus-inbound-queue-high-water-mark",
+        help = "Percentage of the capacity of an inbound consensus queue above which the node \
+                stops reading from its peers until the queue drains to half of it",
+        default_value = "80",
+        env = "CONCORDIUM_NODE_CONSENSUS_INBOUND_QUEUE_HIGH_WATER_MARK"
+    )]
+    pub inbound_queue_high_water_mark: u8,
+    #[structopt(
+        long = "transaction-workers",
+        help = "Number of threads processing the transactions received from peers",
+        default_value = "2",
+        env = "CONCORDIUM_NODE_CONSENSUS_TRANSACTION_WORKERS"
+    )]
+    pub transaction_workers: usize,
     #[structopt(
         long = "import-blocks-from",
         help = "Path to a file exported by the database exporter",
         env = "CONCORDIUM_NODE_CONSENSUS_IMPORT_BLOCKS_FROM"
     )]
     pub import_path: Option<String>,
+    #[structopt(
+        long = "import-blocks-archive",
+        help = "Block archive to catch up from before connecting to peers: a directory or an \
+                HTTP(S) URL containing an export with a signed manifest; several archives are \
+                tried in order",
+        env = "CONCORDIUM_NODE_CONSENSUS_IMPORT_BLOCKS_ARCHIVES",
+        use_delimiter = true
+    )]
+    pub import_archives: Vec<String>,
+    #[structopt(
+        long = "import-blocks-archive-key",
+        help = "Hex-encoded Ed25519 public key trusted to sign the manifests of block archives",
+        env = "CONCORDIUM_NODE_CONSENSUS_IMPORT_BLOCKS_ARCHIVE_KEYS",
+        use_delimiter = true
+    )]
+    pub import_archive_keys: Vec<String>,
+    #[structopt(
+        long = "export-blocks-to",
+        help = "Export the finalized blocks to block files and a manifest in this directory, and \
+                exit",
+        env = "CONCORDIUM_NODE_CONSENSUS_EXPORT_BLOCKS_TO"
+    )]
+    pub export_path: Option<PathBuf>,
+    #[structopt(
+        long = "export-from-height",
+        help = "First height of the exported blocks",
+        default_value = "1",
+        env = "CONCORDIUM_NODE_CONSENSUS_EXPORT_FROM_HEIGHT"
+    )]
+    pub export_from_height: u64,
+    #[structopt(
+        long = "export-to-height",
+        help = "Last height of the exported blocks; the last finalized block if not given",
+        env = "CONCORDIUM_NODE_CONSENSUS_EXPORT_TO_HEIGHT"
+    )]
+    pub export_to_height: Option<u64>,
+    #[structopt(
+        long = "export-blocks-per-file",
+        help = "Maximum number of blocks in an exported file; 0 puts all of them in one file",
+        default_value = "10000",
+        env = "CONCORDIUM_NODE_CONSENSUS_EXPORT_BLOCKS_PER_FILE"
+    )]
+    pub export_blocks_per_file: u64,
+    #[structopt(
+        long = "export-signing-key",
+        help = "File with the Ed25519 key (as written by the DNS key generator) used to sign the \
+                manifest of an export",
+        env = "CONCORDIUM_NODE_CONSENSUS_EXPORT_SIGNING_KEY"
+    )]
+    pub export_signing_key: Option<PathBuf>,
+    #[structopt(
+        long = "restore-snapshot",
+        help = "Restore the data directory from a snapshot made by the `CreateSnapshot` RPC, and \
+                exit. The snapshot must belong to the chain of the node's genesis data and there \
+                must not be a database yet",
+        env = "CONCORDIUM_NODE_CONSENSUS_RESTORE_SNAPSHOT"
+    )]
+    pub restore_snapshot: Option<PathBuf>,
     #[structopt(
         long = "max-expiry-duration",
         help = "Maximum allowed time difference between now and a transaction's expiry time in \
@@ -297,6 +398,59 @@ pub struct BakerConfig {
         env = "CONCORDIUM_NODE_BAKER_DECRYPT_CREDENTIALS"
     )]
     pub decrypt_baker_credentials: bool,
+    #[cfg(unix)]
+    #[structopt(
+        long = "baker-credentials-password-fd",
+        help = "Read the password of the encrypted baker credentials from this open file \
+                descriptor. Implies --decrypt-baker-credentials",
+        env = "CONCORDIUM_NODE_BAKER_CREDENTIALS_PASSWORD_FD",
+        conflicts_with = "baker-credentials-password-file"
+    )]
+    pub baker_credentials_password_fd: Option<i32>,
+    #[structopt(
+        long = "baker-credentials-password-file",
+        help = "Read the password of the encrypted baker credentials from this file, which must \
+                only be accessible to its owner. Implies --decrypt-baker-credentials",
+        env = "CONCORDIUM_NODE_BAKER_CREDENTIALS_PASSWORD_FILE"
+    )]
+    pub baker_credentials_password_file: Option<PathBuf>,
+}
+
+impl BakerConfig {
+    /// Claims the sources of the password of the baker credentials that belong
+    /// to the process, i.e., the file descriptor and the environment variable.
+    /// This must happen at the start of `main`, before the process opens any
+    /// descriptor or starts any thread.
+    pub fn claim_password_sources(&self) -> anyhow::Result<()> {
+        #[cfg(unix)]
+        {
+            if let Some(fd) = self.baker_credentials_password_fd {
+                baker_credentials::claim_descriptor(fd)?;
+            }
+        }
+        baker_credentials::claim_environment()
+    }
+
+    /// Where to read the password of the baker credentials from, if they are
+    /// encrypted. The password is read from the terminal if no other source
+    /// is configured.
+    pub fn baker_credentials_password_source(&self) -> Option<PasswordSource> {
+        #[cfg(unix)]
+        {
+            if let Some(fd) = self.baker_credentials_password_fd {
+                return Some(PasswordSource::FileDescriptor(fd));
+            }
+        }
+        if let Some(ref path) = self.baker_credentials_password_file {
+            Some(PasswordSource::File(path))
+        } else if baker_credentials::password_in_environment() {
+            Some(PasswordSource::Environment)
+        } else if self.decrypt_baker_credentials {
+            Some(PasswordSource::Terminal("Enter password to decrypt baker credentials: "))
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(StructOpt, Debug)]
@@ -307,21 +461,21 @@ pub struct RpcCliConfig {
         help = "Disable the built-in RPC server",
         env = "CONCORDIUM_NODE_DISABLE_RPC_SERVER"
     )]
-    pub no_rpc_server:    bool,
+    pub no_rpc_server: bool,
     #[structopt(
         long = "rpc-server-port",
         help = "RPC server port",
         default_value = "10000",
         env = "CONCORDIUM_NODE_RPC_SERVER_PORT"
     )]
-    pub rpc_server_port:  u16,
+    pub rpc_server_port: u16,
     #[structopt(
         long = "rpc-server-addr",
         help = "RPC server listen address",
         default_value = "127.0.0.1",
         env = "CONCORDIUM_NODE_RPC_SERVER_ADDR"
     )]
-    pub rpc_server_addr:  String,
+    pub rpc_server_addr: String,
     #[structopt(
         long = "rpc-server-token",
         help = "RPC server access token",
@@ -330,6 +484,177 @@ pub struct RpcCliConfig {
         hide_env_values = true
     )]
     pub rpc_server_token: String,
+    #[structopt(
+        long = "rpc-server-admin-socket",
+        help = "Path of a Unix domain socket on which the RPC server also listens. Requests made \
+                over it are not required to carry the access token",
+        env = "CONCORDIUM_NODE_RPC_SERVER_ADMIN_SOCKET"
+    )]
+    pub rpc_server_admin_socket: Option<PathBuf>,
+    #[structopt(
+        long = "rpc-server-admin-socket-only",
+        help = "Only serve the node administration methods on the admin socket; the TCP listener \
+                then only serves queries and transaction submissions",
+        env = "CONCORDIUM_NODE_RPC_SERVER_ADMIN_SOCKET_ONLY"
+    )]
+    pub rpc_server_admin_socket_only: bool,
+    #[structopt(
+        long = "rpc-server-no-tcp",
+        help = "Do not listen on TCP; the RPC server is then only reachable on the admin socket",
+        env = "CONCORDIUM_NODE_RPC_SERVER_NO_TCP"
+    )]
+    pub rpc_server_no_tcp: bool,
+    #[structopt(
+        long = "rpc-server-grpc-web",
+        help = "Accept gRPC-Web requests on the RPC server port",
+        env = "CONCORDIUM_NODE_RPC_SERVER_GRPC_WEB"
+    )]
+    pub rpc_server_grpc_web: bool,
+    #[structopt(
+        long = "rpc-server-grpc-web-allowed-origins",
+        help = "Origins from which cross-origin gRPC-Web requests are allowed ('*' for any \
+                origin); by default no cross-origin requests are allowed",
+        env = "CONCORDIUM_NODE_RPC_SERVER_GRPC_WEB_ALLOWED_ORIGINS",
+        use_delimiter = true
+    )]
+    pub rpc_server_grpc_web_allowed_origins: Vec<String>,
+    #[structopt(
+        long = "rpc-server-audit-log",
+        help = "File to record the state-changing RPC calls in, as JSON lines",
+        env = "CONCORDIUM_NODE_RPC_SERVER_AUDIT_LOG"
+    )]
+    pub rpc_server_audit_log: Option<PathBuf>,
+    #[structopt(
+        long = "rpc-server-audit-log-max-size",
+        help = "Size in MiB at which the audit log is rotated",
+        default_value = "100",
+        env = "CONCORDIUM_NODE_RPC_SERVER_AUDIT_LOG_MAX_SIZE"
+    )]
+    pub rpc_server_audit_log_max_size: u64,
+    #[structopt(
+        long = "rpc-server-audit-log-max-files",
+        help = "Number of rotated audit log files to keep",
+        default_value = "5",
+        env = "CONCORDIUM_NODE_RPC_SERVER_AUDIT_LOG_MAX_FILES"
+    )]
+    pub rpc_server_audit_log_max_files: usize,
+    #[structopt(
+        long = "rpc-server-export-dir",
+        help = "Directory under which block exports and snapshots requested over RPC are written; \
+                such requests are rejected if it is not given",
+        env = "CONCORDIUM_NODE_RPC_SERVER_EXPORT_DIR"
+    )]
+    pub rpc_server_export_dir: Option<PathBuf>,
+    #[structopt(
+        long = "rpc-server-query-threads",
+        help = "Number of threads executing consensus queries on behalf of the RPC server",
+        default_value = "4",
+        env = "CONCORDIUM_NODE_RPC_SERVER_QUERY_THREADS"
+    )]
+    pub rpc_server_query_threads: usize,
+    #[structopt(
+        long = "rpc-server-query-queue-size",
+        help = "Maximum number of consensus queries waiting to be executed; further queries are \
+                rejected",
+        default_value = "256",
+        env = "CONCORDIUM_NODE_RPC_SERVER_QUERY_QUEUE_SIZE"
+    )]
+    pub rpc_server_query_queue_size: usize,
+    #[structopt(
+        long = "rpc-server-query-cache-size",
+        help = "Maximum size in MiB of the cached responses to queries about finalized blocks (0 \
+                disables the cache)",
+        default_value = "64",
+        env = "CONCORDIUM_NODE_RPC_SERVER_QUERY_CACHE_SIZE"
+    )]
+    pub rpc_server_query_cache_size: usize,
+    #[structopt(
+        long = "rpc-server-client-identity",
+        help = "How RPC clients are identified for the purpose of rate limiting [address|token]",
+        default_value = "address",
+        env = "CONCORDIUM_NODE_RPC_SERVER_CLIENT_IDENTITY"
+    )]
+    pub rpc_server_client_identity: ClientIdentity,
+    #[structopt(
+        long = "rpc-server-rate-limit-node",
+        help = "Maximum number of node and network management requests per second for each RPC \
+                client (0 for no limit)",
+        default_value = "0",
+        env = "CONCORDIUM_NODE_RPC_SERVER_RATE_LIMIT_NODE"
+    )]
+    pub rpc_server_rate_limit_node: u32,
+    #[structopt(
+        long = "rpc-server-max-in-flight-node",
+        help = "Maximum number of concurrent node and network management requests for each RPC \
+                client (0 for no limit)",
+        default_value = "0",
+        env = "CONCORDIUM_NODE_RPC_SERVER_MAX_IN_FLIGHT_NODE"
+    )]
+    pub rpc_server_max_in_flight_node: u32,
+    #[structopt(
+        long = "rpc-server-rate-limit-query",
+        help = "Maximum number of consensus query requests per second for each RPC client (0 for \
+                no limit)",
+        default_value = "0",
+        env = "CONCORDIUM_NODE_RPC_SERVER_RATE_LIMIT_QUERY"
+    )]
+    pub rpc_server_rate_limit_query: u32,
+    #[structopt(
+        long = "rpc-server-max-in-flight-query",
+        help = "Maximum number of concurrent consensus query requests for each RPC client (0 for \
+                no limit)",
+        default_value = "0",
+        env = "CONCORDIUM_NODE_RPC_SERVER_MAX_IN_FLIGHT_QUERY"
+    )]
+    pub rpc_server_max_in_flight_query: u32,
+    #[structopt(
+        long = "rpc-server-rate-limit-expensive",
+        help = "Maximum number of expensive consensus query requests per second for each RPC \
+                client (0 for no limit)",
+        default_value = "0",
+        env = "CONCORDIUM_NODE_RPC_SERVER_RATE_LIMIT_EXPENSIVE"
+    )]
+    pub rpc_server_rate_limit_expensive: u32,
+    #[structopt(
+        long = "rpc-server-max-in-flight-expensive",
+        help = "Maximum number of concurrent expensive consensus query requests for each RPC \
+                client (0 for no limit)",
+        default_value = "0",
+        env = "CONCORDIUM_NODE_RPC_SERVER_MAX_IN_FLIGHT_EXPENSIVE"
+    )]
+    pub rpc_server_max_in_flight_expensive: u32,
+    #[structopt(
+        long = "rpc-server-rate-limit-transaction",
+        help = "Maximum number of transaction submission requests per second for each RPC client \
+                (0 for no limit)",
+        default_value = "0",
+        env = "CONCORDIUM_NODE_RPC_SERVER_RATE_LIMIT_TRANSACTION"
+    )]
+    pub rpc_server_rate_limit_transaction: u32,
+    #[structopt(
+        long = "rpc-server-max-in-flight-transaction",
+        help = "Maximum number of concurrent transaction submission requests for each RPC client \
+                (0 for no limit)",
+        default_value = "0",
+        env = "CONCORDIUM_NODE_RPC_SERVER_MAX_IN_FLIGHT_TRANSACTION"
+    )]
+    pub rpc_server_max_in_flight_transaction: u32,
+    #[cfg(feature = "http_gateway")]
+    #[structopt(
+        long = "rpc-http-gateway-port",
+        help = "Port of the HTTP/JSON gateway to the RPC server. The gateway listens on the RPC \
+                server address and is disabled unless a port is given",
+        env = "CONCORDIUM_NODE_RPC_HTTP_GATEWAY_PORT"
+    )]
+    pub rpc_http_gateway_port: Option<u16>,
+    #[cfg(feature = "http_gateway")]
+    #[structopt(
+        long = "rpc-http-gateway-admin",
+        help = "Serve the node administration endpoints (e.g., shutdown, bans and baking) on the \
+                HTTP/JSON gateway",
+        env = "CONCORDIUM_NODE_RPC_HTTP_GATEWAY_ADMIN"
+    )]
+    pub rpc_http_gateway_admin: bool,
 }
 
 #[derive(StructOpt, Debug)]
@@ -466,6 +791,21 @@ pub struct ConnectionConfig {
         env = "CONCORDIUM_NODE_CONNECTION_CATCH_UP_BATCH_LIMIT"
     )]
     pub catch_up_batch_limit: i64,
+    #[structopt(
+        long = "catch-up-parallelism",
+        help = "The maximum number of peers to catch up with at the same time.",
+        default_value = "1",
+        env = "CONCORDIUM_NODE_CONNECTION_CATCH_UP_PARALLELISM"
+    )]
+    pub catch_up_parallelism: usize,
+    #[structopt(
+        long = "catch-up-timeout",
+        help = "The time (in seconds) after which a peer that we are catching up with is dropped \
+                if it does not make progress.",
+        default_value = "300",
+        env = "CONCORDIUM_NODE_CONNECTION_CATCH_UP_TIMEOUT"
+    )]
+    pub catch_up_timeout: u64,
     #[structopt(
         long = "thread-pool-size",
         help = "The size of the threadpool processing connection events in parallel",
@@ -601,6 +941,12 @@ pub struct CommonConfig {
         env = "CONCORDIUM_NODE_DATA_DIR"
     )]
     pub(crate) data_dir: PathBuf,
+    #[structopt(
+        long = "runtime-config-file",
+        help = "JSON file with parameters that are applied to the running node on SIGHUP",
+        env = "CONCORDIUM_NODE_RUNTIME_CONFIG_FILE"
+    )]
+    pub runtime_config_file: Option<PathBuf>,
     #[structopt(
         long = "no-log-timestamp",
         help = "Do not output timestamp in log output",
@@ -641,6 +987,15 @@ pub struct CliConfig {
         env = "CONCORDIUM_NODE_POLL_INTERVAL"
     )]
     pub poll_interval: u64,
+    #[structopt(
+        long = "shutdown-drain-timeout",
+        help = "The maximum time (in seconds) the node spends sending pending messages to its \
+                peers and finishing the current baking slot when it is shut down gracefully, \
+                i.e., on SIGTERM or with the graceful Shutdown RPC",
+        default_value = "30",
+        env = "CONCORDIUM_NODE_SHUTDOWN_DRAIN_TIMEOUT"
+    )]
+    pub shutdown_drain_timeout: u64,
     #[structopt(flatten)]
     pub baker: BakerConfig,
     #[structopt(flatten)]
@@ -829,6 +1184,34 @@ pub fn parse_config() -> anyhow::Result<Config> {
         PROTOCOL_MAX_MESSAGE_SIZE
     );
 
+    ensure!(
+        conf.cli.baker.inbound_queue_depth_high > 0
+            && conf.cli.baker.inbound_queue_depth_low > 0
+            && conf.cli.baker.outbound_queue_depth_high > 0
+            && conf.cli.baker.outbound_queue_depth_low > 0,
+        "The consensus queues must have a capacity of at least 1"
+    );
+
+    ensure!(
+        (1..=100).contains(&conf.cli.baker.inbound_queue_high_water_mark),
+        "The high-water mark of the inbound consensus queues must be between 1 and 100"
+    );
+
+    ensure!(
+        conf.cli.baker.import_archives.is_empty() || !conf.cli.baker.import_archive_keys.is_empty(),
+        "import-blocks-archive requires at least one import-blocks-archive-key"
+    );
+
+    ensure!(
+        conf.cli.baker.transaction_workers > 0,
+        "At least one thread must process the transactions received from peers"
+    );
+
+    ensure!(
+        conf.connection.catch_up_parallelism > 0,
+        "The node must be allowed to catch up with at least one peer at a time"
+    );
+
     ensure!(
         conf.connection.socket_read_size >= 65535,
         "Socket read size must be set to at least 65535"
@@ -839,6 +1222,28 @@ pub fn parse_config() -> anyhow::Result<Config> {
         "Socket read size must be greater or equal to the write size"
     );
 
+    ensure!(
+        conf.cli.rpc.rpc_server_query_threads > 0 && conf.cli.rpc.rpc_server_query_queue_size > 0,
+        "The RPC server needs at least one query thread and a query queue size of at least 1"
+    );
+
+    ensure!(
+        conf.cli.rpc.rpc_server_admin_socket.is_some()
+            || !(conf.cli.rpc.rpc_server_admin_socket_only || conf.cli.rpc.rpc_server_no_tcp),
+        "rpc-server-admin-socket-only and rpc-server-no-tcp require rpc-server-admin-socket"
+    );
+
+    ensure!(
+        conf.cli.rpc.rpc_server_audit_log_max_size > 0,
+        "The audit log must be allowed to grow to at least 1 MiB"
+    );
+
+    #[cfg(not(unix))]
+    ensure!(
+        conf.cli.rpc.rpc_server_admin_socket.is_none(),
+        "The RPC admin socket is only supported on Unix"
+    );
+
     ensure!(
         conf.bootstrapper.wait_until_minimum_nodes as usize <= conf.bootstrapper.peer_list_size,
         "wait-until-minimum-nodes must be lower than or equal to peer-list-size"
@@ -950,4 +1355,10 @@ impl AppPreferences {
 
     /// Returns the path to the config directory.
     pub fn get_user_config_dir(&self) -> &Path { &self.override_config_dir }
+
+    /// Returns the path to the file with the preferences in the given config
+    /// directory.
+    pub fn get_preferences_file(config_dir: &Path) -> PathBuf {
+        Self::calculate_config_file_path(&config_dir.to_path_buf(), APP_PREFERENCES_MAIN)
+    }
 }
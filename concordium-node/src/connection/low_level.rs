@@ -17,6 +17,7 @@ use std::{
     io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write},
     mem,
     sync::{Arc, Weak},
+    time::{Duration, Instant},
 };
 
 /// The size of the noise message payload.
@@ -123,6 +124,10 @@ pub struct ConnectionLowLevel {
     is_initialized: bool,
     /// If specified, the linger value to set for the socket
     so_linger:      Option<u16>,
+    /// The time at which the noise handshake started.
+    created:        Instant,
+    /// The time it took to complete the noise handshake.
+    handshake_time: Option<Duration>,
 }
 
 macro_rules! recv_xx_msg {
@@ -189,6 +194,8 @@ impl ConnectionLowLevel {
             is_writable: false,
             is_initialized: false,
             so_linger,
+            created: Instant::now(),
+            handshake_time: None,
         }
     }
 
@@ -312,6 +319,12 @@ impl ConnectionLowLevel {
         Ok(payload)
     }
 
+    /// The time it took to complete the noise handshake, if it is complete.
+    pub fn noise_handshake_time(&self) -> Option<Duration> { self.handshake_time }
+
+    /// The number of encrypted bytes waiting to be written to the socket.
+    pub fn pending_output_bytes(&self) -> usize { self.output_queue.len() }
+
     #[inline]
     /// Checks whether the low-level noise handshake is complete.
     fn is_post_handshake(&self) -> bool {
@@ -431,6 +444,10 @@ impl ConnectionLowLevel {
                     _ => bail!("invalid XX handshake"),
                 }?;
 
+                if self.is_post_handshake() {
+                    self.handshake_time = Some(self.created.elapsed());
+                }
+
                 if !self.noise_session.is_initiator() {
                     if self.noise_session.get_message_count() == 1 && payload != PSK {
                         bail!("Invalid PSK");
@@ -7,14 +7,17 @@ use crate::{
     },
     configuration::{is_compatible_version, is_compatible_wire_version, MAX_PEER_NETWORKS},
     connection::{ConnChange, Connection},
+    consensus_ffi::{consensus::CALLBACK_QUEUE, helpers::PacketType},
     network::{
         Handshake, NetworkMessage, NetworkPacket, NetworkPayload, NetworkRequest, NetworkResponse,
         PacketDestination,
     },
+    p2p::consensus_events::genesis_mismatch,
     plugins::consensus::*,
     read_or_die,
 };
 use anyhow::{bail, ensure};
+use std::sync::atomic::Ordering;
 
 impl Connection {
     /// Processes a network message based on its type.
@@ -88,7 +91,8 @@ impl Connection {
         if handshake.wire_versions.is_empty() {
             bail!("Rejecting handshake: Handshake message lacked wire versions.");
         }
-        if is_compatible_wire_version(&handshake.wire_versions).is_none() {
+        let wire_version = is_compatible_wire_version(&handshake.wire_versions);
+        if wire_version.is_none() {
             if handshake.wire_versions.len() > 10 {
                 bail!("Rejecting handshake: incompatible wire protocol versions received.",);
             } else {
@@ -106,12 +110,9 @@ impl Connection {
             let our_blocks = read_or_die!(self.handler.config.regenesis_arc);
             // we will consider that the list of regenesis blocks is sorted
             // by height, so we check sequentially.
-            let common_blocks = our_blocks
-                .iter()
-                .zip(handshake.genesis_blocks.iter())
-                .enumerate()
-                .find(|(_, (a, b))| a != b);
-            if let Some((i, (ours, theirs))) = common_blocks {
+            if let Some((i, ours, theirs)) =
+                genesis_mismatch(&our_blocks, &handshake.genesis_blocks)
+            {
                 bail!(
                     "Rejecting handshake: Didn't find a common prefix on the genesis block \
                      hashes. Difference: our block: {}, their block {} at position {}.",
@@ -122,6 +123,9 @@ impl Connection {
             }
         }
 
+        self.wire_version = wire_version;
+        // kept to drop the peer if we move to a chain it isn't on
+        self.genesis_blocks = handshake.genesis_blocks;
         self.promote_to_post_handshake(
             handshake.remote_id,
             handshake.remote_port,
@@ -151,7 +155,22 @@ impl Connection {
             _ => false,
         };
 
+        // Keep track of the kinds of packets the peer sends, so that reads from
+        // peers that mostly send transactions can be deferred under load.
+        if pac.message.first() == Some(&(PacketType::Transaction as u8)) {
+            self.stats.transaction_packets.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.stats.consensus_packets.fetch_add(1, Ordering::Relaxed);
+        }
+
         // Ignore the deserialized p2p node ids to be excluded from the wire.
-        handle_pkt_out(&self.handler, vec![peer_id], peer_id, pac.message, is_broadcast)
+        handle_pkt_out(
+            &self.handler,
+            &CALLBACK_QUEUE,
+            vec![peer_id],
+            peer_id,
+            pac.message,
+            is_broadcast,
+        )
     }
 }
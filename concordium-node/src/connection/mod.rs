@@ -24,13 +24,13 @@ use crate::{
     netmsg,
     network::{
         NetworkId, NetworkMessage, NetworkPacket, NetworkPayload, NetworkRequest, NetworkResponse,
-        Networks,
+        Networks, WireProtocolVersion,
     },
     p2p::P2PNode,
     read_or_die, write_or_die,
 };
 
-use crate::consensus_ffi::helpers::PacketType;
+use crate::consensus_ffi::{blockchain_types::BlockHash, helpers::PacketType};
 
 use std::{
     collections::VecDeque,
@@ -214,45 +214,60 @@ impl DeduplicationQueues {
 /// Contains all the statistics of a connection.
 pub struct ConnectionStats {
     /// Timestamp of connection creation.
-    pub created:           u64,
+    pub created:             u64,
     /// Timestamp at which the connection was last seen.
     /// For regular peers, this is the timestamp of the
     /// last received message.
-    pub last_seen:         AtomicU64,
+    pub last_seen:           AtomicU64,
     /// Timestamp of last ping message being sent
-    last_ping:             AtomicU64,
+    last_ping:               AtomicU64,
     /// Interval between sending the last two pings
-    last_ping_interval:    AtomicU64,
+    last_ping_interval:      AtomicU64,
     /// Number of pings sent minus number of pongs received
-    pending_pongs:         AtomicI64,
+    pending_pongs:           AtomicI64,
     /// Latency measured at last received pong
-    last_latency:          AtomicU64,
+    last_latency:            AtomicU64,
     /// Number of messages sent.
-    pub messages_sent:     AtomicU64,
+    pub messages_sent:       AtomicU64,
     /// Number of messages received.
-    pub messages_received: AtomicU64,
+    pub messages_received:   AtomicU64,
     /// Number of bytes received.
-    pub bytes_received:    AtomicU64,
+    pub bytes_received:      AtomicU64,
     /// Number of bytes sent.
-    pub bytes_sent:        AtomicU64,
+    pub bytes_sent:          AtomicU64,
+    /// Number of received packets that were dropped as duplicates.
+    pub duplicates_received: AtomicU64,
+    /// Number of received consensus packets that are not transactions.
+    pub consensus_packets:   AtomicU64,
+    /// Number of received transactions.
+    pub transaction_packets: AtomicU64,
 }
 
 impl ConnectionStats {
     pub fn new(timestamp: u64) -> Self {
         ConnectionStats {
-            created:            timestamp,
-            last_seen:          AtomicU64::new(timestamp),
-            last_ping:          AtomicU64::new(0),
-            last_ping_interval: AtomicU64::new(0),
-            pending_pongs:      AtomicI64::new(0),
-            last_latency:       AtomicU64::new(0),
-            messages_sent:      AtomicU64::new(0),
-            messages_received:  AtomicU64::new(0),
-            bytes_received:     AtomicU64::new(0),
-            bytes_sent:         AtomicU64::new(0),
+            created:             timestamp,
+            last_seen:           AtomicU64::new(timestamp),
+            last_ping:           AtomicU64::new(0),
+            last_ping_interval:  AtomicU64::new(0),
+            pending_pongs:       AtomicI64::new(0),
+            last_latency:        AtomicU64::new(0),
+            messages_sent:       AtomicU64::new(0),
+            messages_received:   AtomicU64::new(0),
+            bytes_received:      AtomicU64::new(0),
+            bytes_sent:          AtomicU64::new(0),
+            duplicates_received: AtomicU64::new(0),
+            consensus_packets:   AtomicU64::new(0),
+            transaction_packets: AtomicU64::new(0),
         }
     }
 
+    /// Whether most of the consensus packets received were transactions.
+    pub fn mostly_sends_transactions(&self) -> bool {
+        self.transaction_packets.load(Ordering::Relaxed)
+            > self.consensus_packets.load(Ordering::Relaxed)
+    }
+
     pub fn notify_ping(&self) {
         let now = get_current_stamp();
         let previous_ping = self.last_ping.swap(now, Ordering::AcqRel);
@@ -373,6 +388,13 @@ pub struct Connection {
     pub stats:               ConnectionStats,
     /// The queue of messages to be sent to the connection.
     pub pending_messages:    MessageQueues,
+    /// The wire protocol version agreed upon in the handshake.
+    pub wire_version:        Option<WireProtocolVersion>,
+    /// The genesis blocks the peer advertised in the handshake.
+    pub genesis_blocks:      Vec<BlockHash>,
+    /// Whether there may be unread data in the socket, because reading from
+    /// it was deferred while the inbound consensus queues were full.
+    pub read_deferred:       bool,
 }
 
 impl PartialEq for Connection {
@@ -428,6 +450,9 @@ impl Connection {
             remote_end_networks: Default::default(),
             stats,
             pending_messages: MessageQueues::new(1024, 128),
+            wire_version: None,
+            genesis_blocks: Vec::new(),
+            read_deferred: false,
         })
     }
 
@@ -489,14 +514,24 @@ impl Connection {
         Ok(is_duplicate)
     }
 
-    /// Keeps reading from the socket as long as there is data to be read
-    /// and the operation is not blocking.
+    /// Keeps reading from the socket as long as there is data to be read,
+    /// the operation is not blocking and reading from the connection is not
+    /// deferred because of full inbound consensus queues.
     /// The return value indicates if the connection is still open.
     #[inline]
     pub fn read_stream(&mut self, conn_stats: &[PeerStats]) -> anyhow::Result<bool> {
+        self.read_deferred = false;
         loop {
             match self.low_level.read_from_socket()? {
-                ReadResult::Complete(msg) => self.process_message(Arc::from(msg), conn_stats)?,
+                ReadResult::Complete(msg) => {
+                    self.process_message(Arc::from(msg), conn_stats)?;
+                    // stop reading once the inbound consensus queues fill up; the rest is
+                    // read once they drain
+                    if self.handler.backpressure.update().defers(self) {
+                        self.read_deferred = true;
+                        return Ok(true);
+                    }
+                }
                 ReadResult::Incomplete => {}
                 ReadResult::WouldBlock => return Ok(true),
                 ReadResult::Closed => return Ok(false),
@@ -530,6 +565,7 @@ impl Connection {
             }
             // deduplicate the incoming packet payload
             if self.is_packet_duplicate(packet)? {
+                self.stats.duplicates_received.fetch_add(1, Ordering::Relaxed);
                 return Ok(());
             }
         }
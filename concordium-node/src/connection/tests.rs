@@ -2,16 +2,22 @@ use itertools::Itertools;
 
 use crate::{
     common::PeerType,
-    consensus_ffi::helpers::PacketType,
-    network::NetworkId,
-    p2p::connectivity::send_broadcast_message,
+    consensus_ffi::{blockchain_types::BlockHash, helpers::PacketType},
+    network::{NetworkId, WIRE_PROTOCOL_VERSION},
+    p2p::{
+        backpressure::ReadThrottle, connectivity::send_broadcast_message,
+        consensus_events::ConsensusEvent,
+    },
     test_utils::{
         await_handshakes, connect, dummy_regenesis_blocks, make_node_and_sync, next_available_port,
         stop_node_delete_dirs,
     },
 };
 
-use std::sync::Arc;
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
 
 const NID: u16 = 100;
 const NODE_COUNT: usize = 10;
@@ -71,3 +77,140 @@ fn basic_connectivity() {
         stop_node_delete_dirs(dp, node);
     }
 }
+
+#[test]
+fn peer_details() {
+    let (node_1, dp_1) = make_node_and_sync(
+        next_available_port(),
+        vec![NID],
+        PeerType::Node,
+        dummy_regenesis_blocks(),
+    )
+    .unwrap();
+    let (node_2, dp_2) = make_node_and_sync(
+        next_available_port(),
+        vec![NID],
+        PeerType::Node,
+        dummy_regenesis_blocks(),
+    )
+    .unwrap();
+    connect(&node_1, &node_2);
+    await_handshakes(&node_1);
+    await_handshakes(&node_2);
+
+    let details = node_1.get_peer_details(node_2.id()).unwrap();
+    assert_eq!(details.node_id, node_2.id().to_string());
+    assert_eq!(details.wire_version, Some(WIRE_PROTOCOL_VERSION));
+    assert_eq!(details.networks, vec![NID]);
+    assert!(details.noise_handshake_time.is_some());
+    assert!(node_1.get_peer_details(node_1.id()).is_none());
+
+    stop_node_delete_dirs(dp_1, node_1);
+    stop_node_delete_dirs(dp_2, node_2);
+}
+
+#[test]
+fn peers_on_other_chains_are_dropped() {
+    let (node_1, dp_1) = make_node_and_sync(
+        next_available_port(),
+        vec![NID],
+        PeerType::Node,
+        dummy_regenesis_blocks(),
+    )
+    .unwrap();
+    // the second node already went through a regenesis the first one doesn't know
+    let mut blocks_2 = dummy_regenesis_blocks();
+    blocks_2.push(BlockHash::from([3; 32]));
+    let (node_2, dp_2) =
+        make_node_and_sync(next_available_port(), vec![NID], PeerType::Node, blocks_2).unwrap();
+    connect(&node_1, &node_2);
+    await_handshakes(&node_1);
+    await_handshakes(&node_2);
+
+    // the first node moves to a different chain
+    let mut events = node_1.subscribe_consensus_events();
+    node_1.config.regenesis_arc.write().unwrap().push(BlockHash::from([4; 32]));
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !node_1.connections().read().unwrap().is_empty() {
+        assert!(Instant::now() < deadline, "The peer on the other chain wasn't dropped");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    match events.try_recv().unwrap() {
+        ConsensusEvent::Regenesis {
+            genesis_index,
+            genesis_block,
+            ..
+        } => {
+            assert_eq!(genesis_index, 3);
+            assert_eq!(genesis_block, BlockHash::from([4; 32]));
+        }
+        event => panic!("Unexpected event {:?}", event),
+    }
+
+    stop_node_delete_dirs(dp_1, node_1);
+    stop_node_delete_dirs(dp_2, node_2);
+}
+
+#[test]
+fn reads_deferred_while_inbound_queues_are_full() {
+    let (node_1, dp_1) = make_node_and_sync(
+        next_available_port(),
+        vec![NID],
+        PeerType::Node,
+        dummy_regenesis_blocks(),
+    )
+    .unwrap();
+    let (node_2, dp_2) = make_node_and_sync(
+        next_available_port(),
+        vec![NID],
+        PeerType::Node,
+        dummy_regenesis_blocks(),
+    )
+    .unwrap();
+    connect(&node_1, &node_2);
+    await_handshakes(&node_1);
+    await_handshakes(&node_2);
+
+    let received = || {
+        node_1
+            .connections()
+            .read()
+            .unwrap()
+            .values()
+            .map(|conn| conn.stats.messages_received.load(Ordering::Relaxed))
+            .sum::<u64>()
+    };
+    let deferred = || node_1.connections().read().unwrap().values().any(|conn| conn.read_deferred);
+    let wait_for = |condition: &dyn Fn() -> bool, what: &str| {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "Timed out waiting for {}", what);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    };
+
+    // the first node's inbound queues fill up
+    node_1.backpressure.simulate_usage(Some((1.0, 1.0)));
+    wait_for(&|| node_1.backpressure.current() == ReadThrottle::All, "the reads to be throttled");
+
+    let before = received();
+    send_broadcast_message(
+        &node_2,
+        vec![],
+        NetworkId::from(NID),
+        Arc::from(&[PacketType::Block as u8][..]),
+    );
+    wait_for(&deferred, "the read to be deferred");
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(received(), before, "A packet was read while the inbound queues were full");
+
+    // once the queues drain, the packet is read without any new readiness event,
+    // since the socket's events are edge-triggered
+    node_1.backpressure.simulate_usage(None);
+    wait_for(&|| received() > before, "the deferred read");
+    assert!(!deferred());
+
+    stop_node_delete_dirs(dp_1, node_1);
+    stop_node_delete_dirs(dp_2, node_2);
+}
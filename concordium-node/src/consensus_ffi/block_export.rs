@@ -0,0 +1,389 @@
+//! Exporting finalized blocks to block import files.
+//!
+//! The files read by `--import-blocks-from` and the `database_emitter` start
+//! with the version of their format and contain a sequence of blocks, each
+//! preceded by its length as a 64-bit big-endian integer. An export writes a
+//! range of finalized blocks to such files, each holding at most a given number
+//! of blocks, along with a manifest listing the height range, the size and the
+//! SHA-256 checksum of every file. The manifest is written last, so a directory
+//! without one holds an incomplete export. The manifest can be signed with an
+//! Ed25519 key, so that nodes importing the export from an untrusted location
+//! can verify it. An export can take hours, so it reports every file it
+//! completes and can be cancelled between blocks.
+
+use crate::consensus_ffi::{blockchain_types::BlockHash, consensus::Consensus, helpers::HashBytes};
+use anyhow::{bail, ensure, Context};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    convert::TryFrom,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// The version of the block file format, which is written as its first byte.
+pub const BLOCK_FILE_VERSION: u8 = 2;
+
+/// The name of the manifest in an export directory.
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// The name of the hex-encoded signature of the manifest in an export
+/// directory.
+pub const SIGNATURE_FILE_NAME: &str = "manifest.json.sig";
+
+/// The description of an export.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportManifest {
+    /// The genesis block of the chain the blocks belong to, if known.
+    pub genesis_block: Option<BlockHash>,
+    /// The block files, in the order of their heights.
+    pub files:         Vec<ExportedFile>,
+}
+
+/// A single block file of an export.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedFile {
+    /// The name of the file within the export directory.
+    pub name:         String,
+    pub first_height: u64,
+    pub last_height:  u64,
+    /// The size of the file in bytes.
+    pub size:         u64,
+    pub sha256:       HashBytes,
+}
+
+/// The blocks to export.
+#[derive(Debug, Clone, Copy)]
+pub struct ExportRange {
+    /// The first height to export; the genesis block is never exported.
+    pub from_height:     u64,
+    /// The last height to export; the export ends at the last finalized block
+    /// if it is not given or not finalized yet.
+    pub to_height:       Option<u64>,
+    /// The maximum number of blocks in a file; 0 puts all of them in one file.
+    pub blocks_per_file: u64,
+}
+
+/// A writer computing the size and the SHA-256 checksum of what it writes.
+struct HashingWriter<W> {
+    inner:  W,
+    hasher: Sha256,
+    size:   u64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
+}
+
+/// A block file that is being written.
+struct BlockFile {
+    writer:       HashingWriter<BufWriter<File>>,
+    first_height: u64,
+    last_height:  u64,
+}
+
+impl BlockFile {
+    fn create(directory: &Path, first_height: u64) -> anyhow::Result<Self> {
+        let path = directory.join(partial_file_name(first_height));
+        let file = File::create(&path).with_context(|| format!("Can't create {:?}", path))?;
+        let mut writer = HashingWriter {
+            inner:  BufWriter::new(file),
+            hasher: Sha256::new(),
+            size:   0,
+        };
+        writer.write_all(&[BLOCK_FILE_VERSION])?;
+        Ok(BlockFile {
+            writer,
+            first_height,
+            last_height: first_height,
+        })
+    }
+
+    fn append(&mut self, height: u64, block: &[u8]) -> anyhow::Result<()> {
+        self.writer.write_all(&(block.len() as u64).to_be_bytes())?;
+        self.writer.write_all(block)?;
+        self.last_height = height;
+        Ok(())
+    }
+
+    /// Flushes the file and gives it its final name.
+    fn finish(self, directory: &Path) -> anyhow::Result<ExportedFile> {
+        let HashingWriter {
+            inner,
+            hasher,
+            size,
+        } = self.writer;
+        inner.into_inner()?.sync_all()?;
+        let name = format!("blocks-{}-{}.dat", self.first_height, self.last_height);
+        fs::rename(directory.join(partial_file_name(self.first_height)), directory.join(&name))?;
+        Ok(ExportedFile {
+            name,
+            first_height: self.first_height,
+            last_height: self.last_height,
+            size,
+            sha256: HashBytes::new(&hasher.finalize())?,
+        })
+    }
+
+    /// Removes the unfinished file.
+    fn discard(self, directory: &Path) {
+        let path = directory.join(partial_file_name(self.first_height));
+        drop(self.writer);
+        if let Err(e) = fs::remove_file(&path) {
+            warn!("Can't remove {:?}: {}", path, e);
+        }
+    }
+}
+
+fn partial_file_name(first_height: u64) -> String { format!("blocks-{}.partial", first_height) }
+
+/// Exports the finalized blocks in the given range to block files in
+/// `directory`, which is created if it doesn't exist, and writes their
+/// manifest. An existing export in the directory is never overwritten.
+/// `on_file` is called with every completed file; once `cancelled` returns
+/// true, the export stops without writing the manifest.
+pub fn export_blocks(
+    consensus: &dyn Consensus,
+    genesis_block: Option<BlockHash>,
+    directory: &Path,
+    range: ExportRange,
+    cancelled: &dyn Fn() -> bool,
+    on_file: &mut dyn FnMut(&ExportedFile),
+) -> anyhow::Result<ExportManifest> {
+    let from_height = range.from_height.max(1);
+    if let Some(to_height) = range.to_height {
+        ensure!(
+            to_height >= from_height,
+            "The last height to export ({}) is below the first one ({})",
+            to_height,
+            from_height
+        );
+    }
+    fs::create_dir_all(directory)
+        .with_context(|| format!("Can't create the export directory {:?}", directory))?;
+    let manifest_path = directory.join(MANIFEST_FILE_NAME);
+    if manifest_path.exists() {
+        bail!("{:?} already contains an export", directory);
+    }
+
+    let mut files = Vec::new();
+    let mut current: Option<BlockFile> = None;
+    let mut height = from_height;
+    while range.to_height.map_or(true, |to_height| height <= to_height) {
+        if cancelled() {
+            if let Some(file) = current {
+                file.discard(directory);
+            }
+            bail!("The export to {:?} was cancelled at height {}", directory, height);
+        }
+        let block = consensus.get_finalized_block_at_height(height);
+        if block.is_empty() {
+            // the end of the finalized chain
+            break;
+        }
+        let mut file = match current.take() {
+            Some(file) => file,
+            None => BlockFile::create(directory, height)?,
+        };
+        file.append(height, &block)?;
+        if range.blocks_per_file > 0 && height - file.first_height + 1 >= range.blocks_per_file {
+            let file = file.finish(directory)?;
+            on_file(&file);
+            files.push(file);
+        } else {
+            current = Some(file);
+        }
+        height += 1;
+    }
+    if let Some(file) = current {
+        let file = file.finish(directory)?;
+        on_file(&file);
+        files.push(file);
+    }
+    ensure!(!files.is_empty(), "There is no finalized block at height {}", from_height);
+    if let Some(to_height) = range.to_height {
+        if height <= to_height {
+            warn!("The export ends at the last finalized block, at height {}", height - 1);
+        }
+    }
+
+    let manifest = ExportManifest {
+        genesis_block,
+        files,
+    };
+    fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)
+        .with_context(|| format!("Can't write the manifest {:?}", manifest_path))?;
+    info!(
+        "Exported the finalized blocks at heights {} to {} to {:?}",
+        from_height,
+        height - 1,
+        directory
+    );
+    Ok(manifest)
+}
+
+/// Signs the manifest in an export directory with the given key and writes the
+/// signature next to it.
+pub fn sign_manifest(directory: &Path, secret_key: &SecretKey) -> anyhow::Result<()> {
+    let manifest = fs::read(directory.join(MANIFEST_FILE_NAME))
+        .with_context(|| format!("Can't read the manifest in {:?}", directory))?;
+    let keypair = Keypair {
+        public: PublicKey::from(secret_key),
+        secret: SecretKey::from_bytes(secret_key.as_bytes())?,
+    };
+    let signature = keypair.sign(&manifest);
+    fs::write(directory.join(SIGNATURE_FILE_NAME), hex::encode(signature.to_bytes()))?;
+    Ok(())
+}
+
+/// Parses a manifest after checking that its hex-encoded signature was made
+/// with one of the given keys.
+pub fn verify_manifest(
+    manifest: &[u8],
+    signature: &[u8],
+    keys: &[PublicKey],
+) -> anyhow::Result<ExportManifest> {
+    let signature = hex::decode(String::from_utf8_lossy(signature).trim())
+        .context("The signature of the manifest is not hex-encoded")?;
+    let signature = Signature::try_from(&signature[..])
+        .map_err(|e| anyhow::anyhow!("Invalid signature of the manifest: {}", e))?;
+    ensure!(
+        keys.iter().any(|key| key.verify(manifest, &signature).is_ok()),
+        "The manifest is not signed by any of the trusted keys"
+    );
+    serde_json::from_slice(manifest).context("Can't parse the manifest")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus_ffi::mock::MockConsensus;
+
+    /// Reads the blocks of a block file.
+    fn read_blocks(bytes: &[u8]) -> Vec<Vec<u8>> {
+        assert_eq!(bytes[0], BLOCK_FILE_VERSION);
+        let mut rest = &bytes[1..];
+        let mut blocks = Vec::new();
+        while !rest.is_empty() {
+            let mut len = [0; 8];
+            len.copy_from_slice(&rest[..8]);
+            let len = u64::from_be_bytes(len) as usize;
+            blocks.push(rest[8..8 + len].to_vec());
+            rest = &rest[8 + len..];
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_export_blocks() {
+        let consensus = MockConsensus::default();
+        for block in 1..=5u8 {
+            consensus.add_finalized_block(&vec![block; block as usize]);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let range = ExportRange {
+            from_height:     2,
+            to_height:       None,
+            blocks_per_file: 3,
+        };
+        let manifest =
+            export_blocks(&consensus, None, dir.path(), range, &|| false, &mut |_| {}).unwrap();
+        let heights = manifest
+            .files
+            .iter()
+            .map(|file| (file.first_height, file.last_height))
+            .collect::<Vec<_>>();
+        assert_eq!(heights, vec![(2, 4), (5, 5)]);
+
+        let contents = fs::read(dir.path().join(&manifest.files[0].name)).unwrap();
+        assert_eq!(read_blocks(&contents), vec![vec![2; 2], vec![3; 3], vec![4; 4]]);
+        assert_eq!(manifest.files[0].size, contents.len() as u64);
+        assert_eq!(&manifest.files[0].sha256[..], &Sha256::digest(&contents)[..]);
+        let written: ExportManifest =
+            serde_json::from_slice(&fs::read(dir.path().join(MANIFEST_FILE_NAME)).unwrap())
+                .unwrap();
+        assert_eq!(written, manifest);
+
+        // an existing export is not overwritten
+        assert!(export_blocks(&consensus, None, dir.path(), range, &|| false, &mut |_| {}).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let range = ExportRange {
+            from_height:     0,
+            to_height:       Some(2),
+            blocks_per_file: 0,
+        };
+        let manifest =
+            export_blocks(&consensus, None, dir.path(), range, &|| false, &mut |_| {}).unwrap();
+        assert_eq!(manifest.files.len(), 1);
+        assert_eq!((manifest.files[0].first_height, manifest.files[0].last_height), (1, 2));
+    }
+
+    #[test]
+    fn test_cancelled_export() {
+        let consensus = MockConsensus::default();
+        for block in 1..=5u8 {
+            consensus.add_finalized_block(&[block]);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let range = ExportRange {
+            from_height:     1,
+            to_height:       None,
+            blocks_per_file: 2,
+        };
+        // the export is cancelled once the first file is complete
+        let mut completed = Vec::new();
+        let cancelled = std::cell::Cell::new(false);
+        let result =
+            export_blocks(&consensus, None, dir.path(), range, &|| cancelled.get(), &mut |file| {
+                completed.push(file.name.clone());
+                cancelled.set(true);
+            });
+        assert!(result.is_err());
+        assert_eq!(completed, vec!["blocks-1-2.dat"]);
+        let mut names = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        // neither the manifest nor the unfinished file is left behind
+        assert_eq!(names, vec!["blocks-1-2.dat"]);
+    }
+
+    #[test]
+    fn test_signed_manifest() {
+        let consensus = MockConsensus::default();
+        consensus.add_finalized_block(&[1]);
+        let dir = tempfile::tempdir().unwrap();
+        let range = ExportRange {
+            from_height:     1,
+            to_height:       None,
+            blocks_per_file: 0,
+        };
+        let manifest =
+            export_blocks(&consensus, None, dir.path(), range, &|| false, &mut |_| {}).unwrap();
+        let secret_key = crate::utils::generate_ed25519_key();
+        sign_manifest(dir.path(), &secret_key).unwrap();
+
+        let bytes = fs::read(dir.path().join(MANIFEST_FILE_NAME)).unwrap();
+        let signature = fs::read(dir.path().join(SIGNATURE_FILE_NAME)).unwrap();
+        let trusted = PublicKey::from(&secret_key);
+        let other = PublicKey::from(&crate::utils::generate_ed25519_key());
+        assert_eq!(verify_manifest(&bytes, &signature, &[other, trusted]).unwrap(), manifest);
+        assert!(verify_manifest(&bytes, &signature, &[other]).is_err());
+        let mut tampered = bytes.clone();
+        tampered.push(b' ');
+        assert!(verify_manifest(&tampered, &signature, &[trusted]).is_err());
+    }
+}
@@ -116,13 +116,47 @@ pub fn create_serialization_cursor(size: usize) -> Cursor<Box<[u8]>> {
     Cursor::new(buf.into_boxed_slice())
 }
 
+/// Splits versioned data into the version and the remaining bytes.
+///
+/// The version is a `u32` in variable-length encoding: big endian groups of 7
+/// bits, where all bytes except the last one have the most significant bit
+/// set.
+pub fn split_version(bytes: &[u8]) -> anyhow::Result<(u32, &[u8])> {
+    let mut version: u64 = 0;
+    for (i, &byte) in bytes.iter().enumerate().take(5) {
+        version = (version << 7) | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            let version =
+                u32::try_from(version).map_err(|_| anyhow!("Version number out of range"))?;
+            return Ok((version, &bytes[i + 1..]));
+        }
+    }
+    Err(anyhow!("Malformed version prefix"))
+}
+
+/// Computes the hash of a transaction given in the versioned format that is
+/// accepted by consensus, i.e., the hash of the block item without the version.
+pub fn versioned_transaction_hash(bytes: &[u8]) -> anyhow::Result<TransactionHash> {
+    use sha2::{Digest, Sha256};
+    let (_, block_item) = split_version(bytes)?;
+    HashBytes::new(&Sha256::digest(block_item))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::consensus_ffi::blockchain_types::AccountAddress;
+    use crate::consensus_ffi::blockchain_types::{split_version, AccountAddress};
     #[test]
     fn check_encoding_of_address() {
         let expected_result = &"2xBvQb4QFBzCDcRdyuGzPDcWSMvDDisfMUnXeRnNJFdWqBBmK7";
         let bytes = (1..=32).collect::<Vec<_>>();
         assert_eq!(expected_result, &AccountAddress::new(&bytes).to_string());
     }
+
+    #[test]
+    fn check_version_prefix() {
+        assert_eq!(split_version(&[0, 1, 2]).unwrap(), (0, &[1u8, 2][..]));
+        assert_eq!(split_version(&[0x81, 0x00, 7]).unwrap(), (128, &[7u8][..]));
+        assert!(split_version(&[0x80]).is_err());
+        assert!(split_version(&[]).is_err());
+    }
 }
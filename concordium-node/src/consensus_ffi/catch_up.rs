@@ -1,8 +1,14 @@
-use crate::common::{p2p_node_id::PeerId, p2p_peer::RemotePeerId};
+use crate::common::{
+    get_current_stamp,
+    p2p_node_id::{P2PNodeId, PeerId},
+    p2p_peer::RemotePeerId,
+};
+use lru::LruCache;
 use nohash_hasher::BuildNoHashHasher;
+use serde::Serialize;
 use std::{
-    cmp::Ordering,
-    collections::{HashMap, VecDeque},
+    cmp::{Ordering, Reverse},
+    collections::{HashMap, HashSet, VecDeque},
     time::Instant,
 };
 
@@ -44,39 +50,434 @@ pub enum PeerStatus {
     UpToDate   = 0,
 }
 
-#[derive(Default)]
+/// The time (in milliseconds) before a peer that failed to catch us up is
+/// selected again; it doubles with every consecutive failure.
+const CATCH_UP_BACKOFF_BASE: u64 = 30_000;
+/// The maximum time (in milliseconds) before a failing peer is selected again.
+const CATCH_UP_BACKOFF_MAX: u64 = 3_600_000;
+/// The time (in milliseconds) after which a failure counts half as much
+/// against a peer.
+const CATCH_UP_FAILURE_HALF_LIFE: u64 = 600_000;
+/// The maximum number of peers whose catch-up history is remembered.
+const MAX_CATCH_UP_HISTORIES: usize = 1000;
+
+/// A catch-up session in progress.
+#[derive(Debug, Clone, Copy)]
+pub struct CatchUpSession {
+    /// The timestamp at which the session started.
+    pub started:       u64,
+    /// The timestamp at which the peer last made progress.
+    pub last_progress: u64,
+    /// The number of bytes of blocks and finalization records the peer sent
+    /// us during the session.
+    pub bytes:         u64,
+}
+
+/// How well a peer caught us up in the past. The history is kept across
+/// reconnections of the peer.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatchUpHistory {
+    /// The last measured round-trip time to the peer, in milliseconds.
+    pub latency:              Option<u64>,
+    /// The number of bytes of blocks and finalization records the peer sent
+    /// us in the finished catch-up sessions.
+    pub bytes_delivered:      u64,
+    /// The total duration of the finished catch-up sessions, in milliseconds.
+    pub time_catching_up:     u64,
+    /// The number of catch-up sessions that timed out.
+    pub timeouts:             u32,
+    /// The number of messages sent during catch-up that consensus rejected.
+    pub errors:               u32,
+    /// The number of timeouts and errors, where each counts half as much
+    /// with every half-life that passed since it occurred, as of
+    /// `failures_updated`.
+    pub recent_failures:      f64,
+    /// The timestamp at which `recent_failures` was last updated.
+    pub failures_updated:     u64,
+    /// The number of timeouts since the peer last completed a catch-up round.
+    pub consecutive_failures: u32,
+    /// The peer is not selected for catching up before this timestamp.
+    pub backoff_until:        u64,
+}
+
+impl CatchUpHistory {
+    /// The rate (in bytes per second) at which the peer caught us up, if it
+    /// ever did.
+    pub fn throughput(&self) -> Option<u64> {
+        if self.time_catching_up == 0 {
+            None
+        } else {
+            Some(self.bytes_delivered * 1000 / self.time_catching_up)
+        }
+    }
+
+    /// The decayed number of the peer's failures at the given time.
+    pub fn recent_failures_at(&self, now: u64) -> f64 {
+        let half_lives =
+            now.saturating_sub(self.failures_updated) as f64 / CATCH_UP_FAILURE_HALF_LIFE as f64;
+        self.recent_failures * 0.5f64.powf(half_lives)
+    }
+
+    /// Count a timeout or an error against the peer.
+    fn record_failure(&mut self, now: u64) {
+        self.recent_failures = self.recent_failures_at(now) + 1.0;
+        self.failures_updated = now;
+    }
+
+    /// Peers are ranked by the number of their recent failures, then by their
+    /// past throughput and then by their latency; lower ranks are better.
+    fn rank(&self, now: u64) -> (u64, Reverse<u64>, u64) {
+        (
+            self.recent_failures_at(now).round() as u64,
+            Reverse(self.throughput().unwrap_or(0)),
+            self.latency.unwrap_or(u64::MAX),
+        )
+    }
+
+    /// Back the peer off exponentially after a failure.
+    fn fail(&mut self, now: u64) {
+        self.consecutive_failures += 1;
+        let backoff = CATCH_UP_BACKOFF_BASE
+            .saturating_mul(1 << (self.consecutive_failures - 1).min(16))
+            .min(CATCH_UP_BACKOFF_MAX);
+        self.backoff_until = now + backoff;
+    }
+}
+
 pub struct PeerList {
     /// The state of each peer.
     pub peer_states:    HashMap<RemotePeerId, PeerStatus, BuildNoHashHasher<PeerId>>,
-    /// The timestamp at which we last tried to catch up with a peer.
-    pub catch_up_stamp: u64,
-    /// The peer that we are currently catching up with (if any).
-    pub catch_up_peer:  Option<RemotePeerId>,
+    /// The timestamp at which the state of each peer last changed.
+    pub status_stamps:  HashMap<RemotePeerId, u64, BuildNoHashHasher<PeerId>>,
+    /// The peers that we are currently catching up with.
+    pub catch_up_peers: HashMap<RemotePeerId, CatchUpSession, BuildNoHashHasher<PeerId>>,
+    /// The node ids of the peers, which identify them across reconnections.
+    pub node_ids:       HashMap<RemotePeerId, P2PNodeId, BuildNoHashHasher<PeerId>>,
+    /// How well the recently seen peers caught us up in the past, by their
+    /// node ids.
+    history:            LruCache<P2PNodeId, CatchUpHistory>,
     /// Queue of pending peers.
     pub pending_queue:  VecDeque<RemotePeerId>,
+    /// If set, only these peers are selected for catching up.
+    pub pinned_peers:   Option<HashSet<RemotePeerId, BuildNoHashHasher<PeerId>>>,
+}
+
+impl Default for PeerList {
+    fn default() -> Self {
+        PeerList {
+            peer_states:    Default::default(),
+            status_stamps:  Default::default(),
+            catch_up_peers: Default::default(),
+            node_ids:       Default::default(),
+            history:        LruCache::new(MAX_CATCH_UP_HISTORIES),
+            pending_queue:  Default::default(),
+            pinned_peers:   None,
+        }
+    }
 }
 
 impl PeerList {
-    /// Pull the next pending peer from the queue and mark it as catching-up.
-    /// This does not alter catch_up_stamp, but it does set catch_up_peer.
+    /// The catch-up history of a peer, if we know anything about it.
+    pub fn history(&self, peer: RemotePeerId) -> Option<CatchUpHistory> {
+        self.history.peek(self.node_ids.get(&peer)?).copied()
+    }
+
+    /// The catch-up history of a peer to be updated. Nothing is recorded for
+    /// a peer whose node id is unknown.
+    fn history_mut(&mut self, peer: RemotePeerId) -> Option<&mut CatchUpHistory> {
+        let node_id = *self.node_ids.get(&peer)?;
+        if !self.history.contains(&node_id) {
+            self.history.put(node_id, CatchUpHistory::default());
+        }
+        self.history.get_mut(&node_id)
+    }
+
+    /// Pull the best pending peer from the queue, mark it as catching-up and
+    /// start a catch-up session with it.
     /// pending_queue should only contain peers that are actually pending,
     /// (according to peer_states) but this is checked when they are dequeued
     /// and if a non-pending peer is encountered it is simply removed from
     /// the queue.
+    /// The peers are ranked by their catch-up history, and peers with the same
+    /// rank are selected in the order in which they became pending. Peers
+    /// that are backed off after failing to catch us up, and the peers outside
+    /// of the set that catch-up is pinned to, if any, are left in the queue.
     pub fn next_pending(&mut self) -> Option<RemotePeerId> {
-        let mut next = self.pending_queue.pop_front();
-        while let Some(peer) = next {
-            if let Some(state) = self.peer_states.get_mut(&peer) {
-                if let PeerStatus::Pending = *state {
-                    // The peer is actually pending.
-                    *state = PeerStatus::CatchingUp;
-                    break;
+        // The peers that are not actually pending are removed from the queue.
+        let peer_states = &self.peer_states;
+        self.pending_queue.retain(|peer| peer_states.get(peer) == Some(&PeerStatus::Pending));
+        let now = get_current_stamp();
+        let pinned = &self.pinned_peers;
+        let position = self
+            .pending_queue
+            .iter()
+            .enumerate()
+            .filter(|(_, peer)| pinned.as_ref().map_or(true, |pinned| pinned.contains(peer)))
+            .map(|(i, &peer)| (i, self.history(peer).unwrap_or_default()))
+            .filter(|(_, history)| history.backoff_until <= now)
+            .min_by_key(|(i, history)| (history.rank(now), *i))
+            .map(|(i, _)| i);
+        let next = position.and_then(|i| self.pending_queue.remove(i));
+        if let Some(peer) = next {
+            self.start_catch_up(peer);
+        }
+        next
+    }
+
+    /// Mark a peer as catching-up and start a catch-up session with it.
+    pub fn start_catch_up(&mut self, peer: RemotePeerId) {
+        self.pending_queue.retain(|&id| id != peer);
+        self.set_status(peer, PeerStatus::CatchingUp);
+        let now = get_current_stamp();
+        self.catch_up_peers.insert(peer, CatchUpSession {
+            started:       now,
+            last_progress: now,
+            bytes:         0,
+        });
+    }
+
+    /// Record that the catch-up session with a peer made progress by sending
+    /// us the given number of bytes, which postpones its timeout.
+    pub fn catch_up_progress(&mut self, peer: RemotePeerId, bytes: usize) {
+        if let Some(session) = self.catch_up_peers.get_mut(&peer) {
+            session.last_progress = get_current_stamp();
+            session.bytes += bytes as u64;
+        }
+    }
+
+    /// Record that consensus rejected a message sent by a peer we are
+    /// catching up with.
+    pub fn catch_up_error(&mut self, peer: RemotePeerId) {
+        if self.catch_up_peers.contains_key(&peer) {
+            if let Some(history) = self.history_mut(peer) {
+                history.errors += 1;
+                history.record_failure(get_current_stamp());
+            }
+        }
+    }
+
+    /// End the catch-up session with a peer, if there is one, adding it to the
+    /// history of the peer.
+    pub fn end_catch_up(&mut self, peer: RemotePeerId) -> bool {
+        match self.catch_up_peers.remove(&peer) {
+            Some(session) => {
+                if let Some(history) = self.history_mut(peer) {
+                    history.bytes_delivered += session.bytes;
+                    history.time_catching_up += get_current_stamp().saturating_sub(session.started);
                 }
+                true
             }
-            // The peer is not actually pending.
-            next = self.pending_queue.pop_front();
+            None => false,
         }
-        self.catch_up_peer = next;
-        next
+    }
+
+    /// End the catch-up session with a peer that completed a catch-up round,
+    /// which lifts its backoff.
+    pub fn finish_catch_up(&mut self, peer: RemotePeerId) {
+        if self.end_catch_up(peer) {
+            if let Some(history) = self.history_mut(peer) {
+                history.consecutive_failures = 0;
+                history.backoff_until = 0;
+            }
+        }
+    }
+
+    /// End the catch-up session with a peer that did not make progress in
+    /// time. The peer becomes pending again, but it is backed off.
+    pub fn time_out_catch_up(&mut self, peer: RemotePeerId) {
+        self.reset_to_pending(peer);
+        if let Some(history) = self.history_mut(peer) {
+            let now = get_current_stamp();
+            history.timeouts += 1;
+            history.record_failure(now);
+            history.fail(now);
+        }
+    }
+
+    /// Record the latest measured latency of a peer.
+    pub fn record_latency(&mut self, peer: RemotePeerId, latency: u64) {
+        if self.peer_states.contains_key(&peer) && latency > 0 {
+            if let Some(history) = self.history_mut(peer) {
+                history.latency = Some(latency);
+            }
+        }
+    }
+
+    /// Set the state of a peer, recording the time of the change.
+    pub fn set_status(&mut self, peer: RemotePeerId, status: PeerStatus) -> Option<PeerStatus> {
+        let previous = self.peer_states.insert(peer, status);
+        if previous != Some(status) {
+            self.status_stamps.insert(peer, get_current_stamp());
+        }
+        previous
+    }
+
+    /// Mark a peer as pending, unless it already is. Returns `false` if the
+    /// peer is unknown.
+    pub fn reset_to_pending(&mut self, peer: RemotePeerId) -> bool {
+        match self.peer_states.get(&peer) {
+            None => false,
+            Some(PeerStatus::Pending) => true,
+            Some(_) => {
+                self.set_status(peer, PeerStatus::Pending);
+                self.pending_queue.push_back(peer);
+                self.end_catch_up(peer);
+                true
+            }
+        }
+    }
+
+    /// Forget the peers for which the predicate does not hold. Their history
+    /// is kept in case they reconnect.
+    pub fn retain(&mut self, mut predicate: impl FnMut(&RemotePeerId) -> bool) {
+        self.peer_states.retain(|id, _| predicate(id));
+        self.status_stamps.retain(|id, _| predicate(id));
+        self.pending_queue.retain(|id| predicate(id));
+        if let Some(ref mut pinned) = self.pinned_peers {
+            pinned.retain(|id| predicate(id));
+            if pinned.is_empty() {
+                warn!("All the peers that catch-up was pinned to are gone; unpinning catch-up");
+                self.pinned_peers = None;
+            }
+        }
+        self.catch_up_peers.retain(|id, _| predicate(id));
+        self.node_ids.retain(|id, _| predicate(id));
+    }
+
+    /// Forget a single peer.
+    pub fn remove(&mut self, peer: RemotePeerId) { self.retain(|&id| id != peer) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pinned_catch_up() {
+        let (a, b, c) = (RemotePeerId::from(1), RemotePeerId::from(2), RemotePeerId::from(3));
+        let mut peers = PeerList::default();
+        for &id in &[a, b, c] {
+            peers.set_status(id, PeerStatus::Pending);
+            peers.pending_queue.push_back(id);
+        }
+        peers.pinned_peers = Some([c].iter().copied().collect());
+        assert_eq!(peers.next_pending(), Some(c));
+        assert_eq!(peers.peer_states[&c], PeerStatus::CatchingUp);
+        // the unpinned peers are kept in the queue
+        assert_eq!(peers.pending_queue, vec![a, b]);
+        assert_eq!(peers.next_pending(), None);
+
+        assert!(peers.reset_to_pending(c));
+        assert!(peers.catch_up_peers.is_empty());
+        peers.remove(c);
+        assert!(peers.pinned_peers.is_none());
+        assert_eq!(peers.next_pending(), Some(a));
+        assert!(!peers.reset_to_pending(c));
+    }
+
+    #[test]
+    fn test_parallel_catch_up() {
+        let (a, b, c) = (RemotePeerId::from(1), RemotePeerId::from(2), RemotePeerId::from(3));
+        let mut peers = PeerList::default();
+        for &id in &[a, b, c] {
+            peers.set_status(id, PeerStatus::Pending);
+            peers.pending_queue.push_back(id);
+        }
+        assert_eq!(peers.next_pending(), Some(a));
+        assert_eq!(peers.next_pending(), Some(b));
+        assert_eq!(peers.catch_up_peers.len(), 2);
+
+        // the sessions end independently of each other
+        assert!(peers.end_catch_up(a));
+        assert!(!peers.end_catch_up(a));
+        assert!(peers.catch_up_peers.contains_key(&b));
+        peers.remove(b);
+        assert!(peers.catch_up_peers.is_empty());
+
+        // a session can be started with a specific peer
+        peers.start_catch_up(c);
+        assert_eq!(peers.peer_states[&c], PeerStatus::CatchingUp);
+        assert!(peers.pending_queue.is_empty());
+        assert_eq!(peers.next_pending(), None);
+    }
+
+    /// A peer list with the given pending peers, whose node ids are the same
+    /// as their local ids.
+    fn pending_peers(ids: &[RemotePeerId]) -> PeerList {
+        let mut peers = PeerList::default();
+        for &id in ids {
+            peers.node_ids.insert(id, P2PNodeId(id.to_token().0 as u64));
+            peers.set_status(id, PeerStatus::Pending);
+            peers.pending_queue.push_back(id);
+        }
+        peers
+    }
+
+    #[test]
+    fn test_catch_up_peer_ranking() {
+        let (a, b, c) = (RemotePeerId::from(1), RemotePeerId::from(2), RemotePeerId::from(3));
+        let mut peers = pending_peers(&[a, b, c]);
+        peers.record_latency(a, 200);
+        peers.record_latency(b, 50);
+        peers.record_latency(c, 100);
+        // peers without history are ranked by latency
+        assert_eq!(peers.next_pending(), Some(b));
+
+        // a peer that timed out is backed off
+        peers.time_out_catch_up(b);
+        let history = peers.history(b).unwrap();
+        assert_eq!((history.timeouts, history.consecutive_failures), (1, 1));
+        assert!(history.backoff_until > get_current_stamp());
+        assert_eq!(peers.peer_states[&b], PeerStatus::Pending);
+        assert_eq!(peers.next_pending(), Some(c));
+        assert_eq!(peers.next_pending(), Some(a));
+        assert_eq!(peers.next_pending(), None);
+
+        // peers that delivered more before are preferred
+        peers.catch_up_progress(a, 1_000_000);
+        peers.history_mut(a).unwrap().time_catching_up = 1000;
+        peers.finish_catch_up(a);
+        peers.finish_catch_up(c);
+        assert!(peers.history(a).unwrap().throughput() > peers.history(c).unwrap().throughput());
+        for &id in &[c, a] {
+            peers.reset_to_pending(id);
+        }
+        assert_eq!(peers.pending_queue, vec![b, c, a]);
+        assert_eq!(peers.next_pending(), Some(a));
+    }
+
+    #[test]
+    fn test_catch_up_failures_decay() {
+        let mut history = CatchUpHistory::default();
+        history.record_failure(0);
+        history.record_failure(0);
+        assert_eq!(history.rank(0).0, 2);
+        assert!((history.recent_failures_at(CATCH_UP_FAILURE_HALF_LIFE) - 1.0).abs() < 1e-9);
+        // old failures stop counting against the peer
+        assert_eq!(history.rank(5 * CATCH_UP_FAILURE_HALF_LIFE).0, 0);
+        history.record_failure(5 * CATCH_UP_FAILURE_HALF_LIFE);
+        assert_eq!(history.rank(5 * CATCH_UP_FAILURE_HALF_LIFE).0, 1);
+    }
+
+    #[test]
+    fn test_catch_up_history_survives_reconnect() {
+        let a = RemotePeerId::from(1);
+        let mut peers = pending_peers(&[a]);
+        assert_eq!(peers.next_pending(), Some(a));
+        peers.time_out_catch_up(a);
+        peers.remove(a);
+        assert!(peers.history(a).is_none());
+
+        // the peer reconnects with a new local id
+        let reconnected = RemotePeerId::from(2);
+        peers.node_ids.insert(reconnected, P2PNodeId(1));
+        assert_eq!(peers.history(reconnected).map(|history| history.timeouts), Some(1));
+
+        // peers without a known node id have no history
+        let unknown = RemotePeerId::from(3);
+        peers.set_status(unknown, PeerStatus::CatchingUp);
+        peers.record_latency(unknown, 10);
+        assert!(peers.history(unknown).is_none());
     }
 }
@@ -1,13 +1,20 @@
-use crate::consensus_ffi::{
-    blockchain_types::BlockHash,
-    ffi::{consensus_runner, get_consensus_ptr, startBaker, stopBaker, stopConsensus},
-    helpers::{QueueReceiver, QueueSyncSender, RelayOrStopSenderHelper},
-    messaging::ConsensusMessage,
+use crate::{
+    common::p2p_peer::RemotePeerId,
+    consensus_ffi::{
+        blockchain_types::BlockHash,
+        ffi::{consensus_runner, get_consensus_ptr, stopConsensus},
+        helpers::{
+            ConsensusFfiResponse, ConsensusIsInBakingCommitteeResponse, QueueReceiver,
+            QueueSyncSender, RelayOrStopSenderHelper, SetBakerCredentialsResponse,
+            TreeStateVersion,
+        },
+        messaging::ConsensusMessage,
+    },
 };
 use parking_lot::Condvar;
 use std::{
     convert::TryFrom,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, AtomicPtr, Ordering},
         Arc, Mutex, RwLock,
@@ -42,28 +49,74 @@ pub const CONSENSUS_QUEUE_DEPTH_OUT_LO: usize = 16 * 1024;
 pub const CONSENSUS_QUEUE_DEPTH_IN_HI: usize = 16 * 1024;
 pub const CONSENSUS_QUEUE_DEPTH_IN_LO: usize = 32 * 1024;
 
+/// The capacities of the consensus queues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueDepths {
+    pub inbound_high:  usize,
+    pub inbound_low:   usize,
+    pub outbound_high: usize,
+    pub outbound_low:  usize,
+}
+
+impl Default for QueueDepths {
+    fn default() -> Self {
+        QueueDepths {
+            inbound_high:  CONSENSUS_QUEUE_DEPTH_IN_HI,
+            inbound_low:   CONSENSUS_QUEUE_DEPTH_IN_LO,
+            outbound_high: CONSENSUS_QUEUE_DEPTH_OUT_HI,
+            outbound_low:  CONSENSUS_QUEUE_DEPTH_OUT_LO,
+        }
+    }
+}
+
+/// The queues of messages received from peers. The high priority queue holds
+/// the messages that consensus must process in order, i.e., blocks,
+/// finalization messages and records and catch-up messages, while the low
+/// priority queue holds the transactions, which can be processed in parallel.
+/// Catch-up messages are moved from the high priority queue to the catch-up
+/// queue once the messages received before them were processed, so that
+/// handling them doesn't hold up the blocks received after them.
 pub struct ConsensusInboundQueues {
     pub receiver_high_priority: Mutex<QueueReceiver<ConsensusMessage>>,
     pub sender_high_priority:   QueueSyncSender<ConsensusMessage>,
     pub receiver_low_priority:  Mutex<QueueReceiver<ConsensusMessage>>,
     pub sender_low_priority:    QueueSyncSender<ConsensusMessage>,
-    pub signaler:               Condvar,
+    pub receiver_catch_up:      Mutex<QueueReceiver<ConsensusMessage>>,
+    pub sender_catch_up:        QueueSyncSender<ConsensusMessage>,
 }
 
 impl Default for ConsensusInboundQueues {
-    fn default() -> Self {
-        let (sender_high_priority, receiver_high_priority) =
-            crossbeam_channel::bounded(CONSENSUS_QUEUE_DEPTH_IN_HI);
-        let (sender_low_priority, receiver_low_priority) =
-            crossbeam_channel::bounded(CONSENSUS_QUEUE_DEPTH_IN_LO);
+    fn default() -> Self { Self::new(CONSENSUS_QUEUE_DEPTH_IN_HI, CONSENSUS_QUEUE_DEPTH_IN_LO) }
+}
+
+impl ConsensusInboundQueues {
+    pub fn new(depth_high: usize, depth_low: usize) -> Self {
+        let (sender_high_priority, receiver_high_priority) = crossbeam_channel::bounded(depth_high);
+        let (sender_low_priority, receiver_low_priority) = crossbeam_channel::bounded(depth_low);
+        let (sender_catch_up, receiver_catch_up) = crossbeam_channel::bounded(depth_high);
         Self {
             receiver_high_priority: Mutex::new(receiver_high_priority),
             sender_high_priority,
             receiver_low_priority: Mutex::new(receiver_low_priority),
             sender_low_priority,
-            signaler: Default::default(),
+            receiver_catch_up: Mutex::new(receiver_catch_up),
+            sender_catch_up,
         }
     }
+
+    /// The fractions of the capacities of the high and low priority queues
+    /// that are in use; the catch-up queue counts towards the high priority
+    /// one.
+    pub fn usage(&self) -> (f64, f64) {
+        let usage = |sender: &QueueSyncSender<ConsensusMessage>| match sender.capacity() {
+            Some(capacity) if capacity > 0 => sender.len() as f64 / capacity as f64,
+            _ => 0.0,
+        };
+        (
+            usage(&self.sender_high_priority).max(usage(&self.sender_catch_up)),
+            usage(&self.sender_low_priority),
+        )
+    }
 }
 
 pub struct ConsensusOutboundQueues {
@@ -75,11 +128,13 @@ pub struct ConsensusOutboundQueues {
 }
 
 impl Default for ConsensusOutboundQueues {
-    fn default() -> Self {
-        let (sender_high_priority, receiver_high_priority) =
-            crossbeam_channel::bounded(CONSENSUS_QUEUE_DEPTH_OUT_HI);
-        let (sender_low_priority, receiver_low_priority) =
-            crossbeam_channel::bounded(CONSENSUS_QUEUE_DEPTH_OUT_LO);
+    fn default() -> Self { Self::new(CONSENSUS_QUEUE_DEPTH_OUT_HI, CONSENSUS_QUEUE_DEPTH_OUT_LO) }
+}
+
+impl ConsensusOutboundQueues {
+    pub fn new(depth_high: usize, depth_low: usize) -> Self {
+        let (sender_high_priority, receiver_high_priority) = crossbeam_channel::bounded(depth_high);
+        let (sender_low_priority, receiver_low_priority) = crossbeam_channel::bounded(depth_low);
         Self {
             receiver_high_priority: Mutex::new(receiver_high_priority),
             sender_high_priority,
@@ -93,36 +148,28 @@ impl Default for ConsensusOutboundQueues {
 pub struct ConsensusQueues {
     pub inbound:  ConsensusInboundQueues,
     pub outbound: ConsensusOutboundQueues,
+    pub depths:   QueueDepths,
 }
 
 impl Default for ConsensusQueues {
-    fn default() -> Self {
+    fn default() -> Self { Self::new(QueueDepths::default()) }
+}
+
+impl ConsensusQueues {
+    pub fn new(depths: QueueDepths) -> Self {
         Self {
-            inbound:  Default::default(),
-            outbound: Default::default(),
+            inbound: ConsensusInboundQueues::new(depths.inbound_high, depths.inbound_low),
+            outbound: ConsensusOutboundQueues::new(depths.outbound_high, depths.outbound_low),
+            depths,
         }
     }
-}
 
-impl ConsensusQueues {
     pub fn send_in_high_priority_message(&self, message: ConsensusMessage) -> anyhow::Result<()> {
-        self.inbound
-            .sender_high_priority
-            .send_msg(message)
-            .map(|_| {
-                self.inbound.signaler.notify_one();
-            })
-            .map_err(|e| e.into())
+        self.inbound.sender_high_priority.send_msg(message).map_err(|e| e.into())
     }
 
     pub fn send_in_low_priority_message(&self, message: ConsensusMessage) -> anyhow::Result<()> {
-        self.inbound
-            .sender_low_priority
-            .send_msg(message)
-            .map(|_| {
-                self.inbound.signaler.notify_one();
-            })
-            .map_err(|e| e.into())
+        self.inbound.sender_low_priority.send_msg(message).map_err(|e| e.into())
     }
 
     pub fn send_out_message(&self, message: ConsensusMessage) -> anyhow::Result<()> {
@@ -170,6 +217,12 @@ impl ConsensusQueues {
                 q.try_iter().count()
             );
         }
+        if let Ok(ref mut q) = self.inbound.receiver_catch_up.try_lock() {
+            debug!(
+                "Drained the Consensus inbound catch-up queue for {} element(s)",
+                q.try_iter().count()
+            );
+        }
     }
 
     pub fn stop(&self) -> anyhow::Result<()> {
@@ -178,19 +231,38 @@ impl ConsensusQueues {
         self.outbound.signaler.notify_one();
         self.inbound.sender_low_priority.send_stop()?;
         self.inbound.sender_high_priority.send_stop()?;
-        self.inbound.signaler.notify_one();
         Ok(())
     }
 }
 
+/// Whether the global consensus queues were created.
+static QUEUES_CREATED: AtomicBool = AtomicBool::new(false);
+
 lazy_static! {
-    pub static ref CALLBACK_QUEUE: ConsensusQueues = ConsensusQueues::default();
+    /// The depths of the global consensus queues, if configured.
+    static ref QUEUE_DEPTHS: Mutex<Option<QueueDepths>> = Mutex::new(None);
+    pub static ref CALLBACK_QUEUE: ConsensusQueues = {
+        QUEUES_CREATED.store(true, Ordering::SeqCst);
+        ConsensusQueues::new(QUEUE_DEPTHS.lock().unwrap().take().unwrap_or_default())
+    };
+}
+
+/// Set the depths of the global consensus queues. This must happen before the
+/// queues are used for the first time.
+pub fn configure_queue_depths(depths: QueueDepths) -> anyhow::Result<()> {
+    let mut configured = QUEUE_DEPTHS.lock().unwrap();
+    anyhow::ensure!(
+        !QUEUES_CREATED.load(Ordering::SeqCst),
+        "The consensus queues are already in use"
+    );
+    *configured = Some(depths);
+    Ok(())
 }
 
 /// If a consensus instance is
 /// - `Active` it is either a baker or a member of the finalization committee
 /// - `Passive` it is neither a baker nor a member of the finalization committee
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ConsensusType {
     Active,
     Passive,
@@ -205,6 +277,124 @@ impl std::fmt::Display for ConsensusType {
     }
 }
 
+/// The consensus layer, as seen by the P2P layer and the RPC server.
+///
+/// It is implemented by the [`ConsensusContainer`], which calls into the
+/// Haskell runtime, and by the pure Rust
+/// [`MockConsensus`](crate::consensus_ffi::mock::MockConsensus) that allows
+/// testing them without it.
+pub trait Consensus: Send + Sync {
+    /// Whether the consensus layer is running, i.e., whether it can be called.
+    fn is_running(&self) -> bool;
+
+    fn consensus_type(&self) -> ConsensusType;
+
+    /// Starts baking; returns whether the baker was started.
+    fn start_baker(&self) -> bool;
+
+    /// Stops baking; returns whether the baker was stopped.
+    fn stop_baker(&self) -> bool;
+
+    fn is_baking(&self) -> bool;
+
+    fn in_baking_committee(&self) -> ConsensusIsInBakingCommitteeResponse;
+
+    fn in_finalization_committee(&self) -> bool;
+
+    fn send_block(&self, block: &[u8]) -> ConsensusFfiResponse;
+
+    fn send_finalization(&self, msg: &[u8]) -> ConsensusFfiResponse;
+
+    fn send_finalization_record(&self, rec: &[u8]) -> ConsensusFfiResponse;
+
+    fn send_transaction(&self, data: &[u8]) -> ConsensusFfiResponse;
+
+    /// Our catch-up status, as a complete packet ready to be sent to a peer.
+    fn get_catch_up_status(&self) -> Arc<[u8]>;
+
+    /// Handles the catch-up status of a peer; the blocks and finalization
+    /// records the peer is missing are sent to it directly.
+    fn receive_catch_up_status(
+        &self,
+        request: &[u8],
+        peer_id: RemotePeerId,
+        object_limit: i64,
+    ) -> ConsensusFfiResponse;
+
+    /// Imports the blocks in the given file; returns the FFI return code.
+    fn import_blocks(&self, import_file_path: &[u8]) -> u8;
+
+    /// Writes a consistent copy of the global state to the given directory
+    /// while consensus keeps running; returns whether it succeeded. The
+    /// reason of a failure is logged by consensus.
+    fn snapshot_database(&self, directory: &[u8]) -> bool;
+
+    /// Reads the version stored in the tree state database in the given
+    /// directory, e.g., of a copy written by `snapshot_database`.
+    fn tree_state_version(&self, tree_state_dir: &Path) -> anyhow::Result<TreeStateVersion>;
+
+    /// Replaces the baker credentials, given in the format of the baker
+    /// credentials file, without restarting consensus. Baking is stopped
+    /// while they are replaced and restarted afterwards if it was running.
+    fn set_baker_credentials(&self, private_data: &[u8]) -> SetBakerCredentialsResponse;
+
+    fn get_consensus_status(&self) -> String;
+
+    fn get_block_info(&self, block_hash: &str) -> String;
+
+    fn get_blocks_at_height(&self, block_height: u64) -> String;
+
+    /// Gets the finalized block at the given height in the format of block
+    /// import files; it is empty if the height is 0 or not finalized yet.
+    fn get_finalized_block_at_height(&self, block_height: u64) -> Vec<u8>;
+
+    fn get_ancestors(&self, block_hash: &str, amount: u64) -> String;
+
+    fn get_branches(&self) -> String;
+
+    fn get_account_list(&self, block_hash: &str) -> String;
+
+    fn get_instances(&self, block_hash: &str) -> String;
+
+    /// Gets at most `limit` account addresses of the given block, starting at
+    /// the address `cursor`, or at the first account if it is empty. The
+    /// response also contains the address starting the next page.
+    fn get_account_list_page(&self, block_hash: &str, cursor: &str, limit: u64) -> String;
+
+    /// Gets at most `limit` contract addresses of the given block, starting at
+    /// the contract index `start`. The response also contains the index
+    /// starting the next page.
+    fn get_instances_page(&self, block_hash: &str, start: u64, limit: u64) -> String;
+
+    fn get_account_info(&self, block_hash: &str, account_address: &str) -> String;
+
+    fn get_instance_info(&self, block_hash: &str, contract_address: &str) -> String;
+
+    fn get_reward_status(&self, block_hash: &str) -> String;
+
+    fn get_birk_parameters(&self, block_hash: &str) -> String;
+
+    fn get_module_list(&self, block_hash: &str) -> String;
+
+    fn get_module_source(&self, block_hash: &str, module_ref: &str) -> Vec<u8>;
+
+    fn get_account_non_finalized_transactions(&self, account_address: &str) -> String;
+
+    fn get_block_summary(&self, block_hash: &str) -> String;
+
+    fn get_transaction_status(&self, transaction_hash: &str) -> String;
+
+    fn get_transaction_status_in_block(&self, transaction_hash: &str, block_hash: &str) -> String;
+
+    fn get_next_account_nonce(&self, account_address: &str) -> String;
+
+    fn get_identity_providers(&self, block_hash: &str) -> String;
+
+    fn get_anonymity_revokers(&self, block_hash: &str) -> String;
+
+    fn get_cryptographic_parameters(&self, block_hash: &str) -> String;
+}
+
 #[derive(Clone)]
 pub struct ConsensusContainer {
     pub max_block_size:             u64,
@@ -283,39 +473,5 @@ impl ConsensusContainer {
         CALLBACK_QUEUE.clear();
     }
 
-    pub fn start_baker(&self) -> bool {
-        if !self.is_active() || self.is_baking() {
-            return false;
-        }
-
-        info!("Commencing baking");
-
-        let consensus = self.consensus.load(Ordering::SeqCst);
-        unsafe {
-            startBaker(consensus);
-        }
-        self.is_baking.store(true, Ordering::SeqCst);
-
-        true
-    }
-
-    pub fn stop_baker(&self) -> bool {
-        if !self.is_active() || !self.is_baking() {
-            return false;
-        }
-
-        info!("Stopping baking");
-
-        let consensus = self.consensus.load(Ordering::SeqCst);
-        unsafe {
-            stopBaker(consensus);
-        }
-        self.is_baking.store(false, Ordering::SeqCst);
-
-        true
-    }
-
-    pub fn is_baking(&self) -> bool { self.is_baking.load(Ordering::SeqCst) }
-
     pub fn is_active(&self) -> bool { self.consensus_type == ConsensusType::Active }
 }
@@ -4,7 +4,10 @@ use crate::{
         blockchain_types::BlockHash,
         catch_up::*,
         consensus::*,
-        helpers::{ConsensusFfiResponse, ConsensusIsInBakingCommitteeResponse, PacketType},
+        helpers::{
+            ConsensusFfiResponse, ConsensusIsInBakingCommitteeResponse, PacketType,
+            SetBakerCredentialsResponse, TreeStateVersion,
+        },
         messaging::*,
     },
     write_or_die,
@@ -17,7 +20,7 @@ use std::{
     ffi::{CStr, CString},
     io::{Cursor, Write},
     os::raw::{c_char, c_int},
-    path::PathBuf,
+    path::{Path, PathBuf},
     slice,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -231,6 +234,40 @@ pub fn stop_haskell() {
     stop_nopanic();
 }
 
+/// Computes the hash of the genesis block from the serialized genesis data.
+/// The GHC runtime must have been started, but consensus needn't be running.
+pub fn genesis_block_hash(genesis_data: &[u8]) -> anyhow::Result<BlockHash> {
+    let hash = unsafe {
+        let res = computeGenesisBlockHash(genesis_data.as_ptr(), genesis_data.len() as i64);
+        let mut raw_len_buf = Cursor::new(slice::from_raw_parts(res, 4));
+        let hash = match raw_len_buf.read_u32::<NetworkEndian>() {
+            Ok(size) => slice::from_raw_parts(res, 4 + size as usize)[4..].to_owned(),
+            _ => vec![],
+        };
+        freeCStr(res as *const i8);
+        hash
+    };
+    if hash.is_empty() {
+        bail!("The genesis data can't be deserialized");
+    }
+    BlockHash::new(&hash)
+}
+
+/// Reads the version stored in the tree state database in the given directory,
+/// which needn't be used by a running consensus. The GHC runtime must have been
+/// started.
+pub fn tree_state_version(tree_state_dir: &Path) -> anyhow::Result<TreeStateVersion> {
+    let directory = tree_state_dir.to_string_lossy();
+    let version = unsafe {
+        let res = treeStateVersion(directory.as_ptr(), directory.len() as i64);
+        let version = CStr::from_ptr(res).to_string_lossy().into_owned();
+        freeCStr(res);
+        version
+    };
+    serde_json::from_str::<Option<TreeStateVersion>>(&version)?
+        .ok_or_else(|| anyhow!("{:?} holds no database with a readable version", tree_state_dir))
+}
+
 extern "C" fn stop_nopanic() {
     STOP_ONCE.call_once(|| {
         unsafe { hs_exit() }; // does nothing if hs_init_count <= 0
@@ -336,6 +373,18 @@ extern "C" {
     pub fn getAccountList(consensus: *mut consensus_runner, block_hash: *const u8)
         -> *const c_char;
     pub fn getInstances(consensus: *mut consensus_runner, block_hash: *const u8) -> *const c_char;
+    pub fn getAccountListPage(
+        consensus: *mut consensus_runner,
+        block_hash: *const u8,
+        cursor: *const u8,
+        limit: u64,
+    ) -> *const c_char;
+    pub fn getInstancesPage(
+        consensus: *mut consensus_runner,
+        block_hash: *const u8,
+        start: u64,
+        limit: u64,
+    ) -> *const c_char;
     pub fn getAccountInfo(
         consensus: *mut consensus_runner,
         block_hash: *const u8,
@@ -381,6 +430,10 @@ extern "C" {
         block_hash: *const u8,
     ) -> *const c_char;
     pub fn getBlocksAtHeight(consensus: *mut consensus_runner, block_height: u64) -> *const c_char;
+    pub fn getFinalizedBlockAtHeight(
+        consensus: *mut consensus_runner,
+        block_height: u64,
+    ) -> *const u8;
     pub fn getTransactionStatus(
         consensus: *mut consensus_runner,
         transaction_hash: *const u8,
@@ -411,6 +464,18 @@ extern "C" {
         import_file_path: *const u8,
         import_file_path_len: i64,
     ) -> u8;
+    pub fn snapshotDatabase(
+        consensus: *mut consensus_runner,
+        directory: *const u8,
+        directory_len: i64,
+    ) -> i64;
+    pub fn computeGenesisBlockHash(genesis_data: *const u8, genesis_data_len: i64) -> *const u8;
+    pub fn treeStateVersion(directory: *const u8, directory_len: i64) -> *const c_char;
+    pub fn setBakerCredentials(
+        consensus: *mut consensus_runner,
+        private_data: *const u8,
+        private_data_len: i64,
+    ) -> i64;
 }
 
 // TODO : Simplify arguments to function, or group with struct
@@ -516,20 +581,58 @@ pub fn get_consensus_ptr(
     }
 }
 
-impl ConsensusContainer {
-    pub fn send_block(&self, block: &[u8]) -> ConsensusFfiResponse {
+impl Consensus for ConsensusContainer {
+    fn is_running(&self) -> bool { !self.consensus.load(Ordering::SeqCst).is_null() }
+
+    fn consensus_type(&self) -> ConsensusType { self.consensus_type.clone() }
+
+    fn start_baker(&self) -> bool {
+        if !self.is_active() || self.is_baking() {
+            return false;
+        }
+
+        info!("Commencing baking");
+
+        let consensus = self.consensus.load(Ordering::SeqCst);
+        unsafe {
+            startBaker(consensus);
+        }
+        self.is_baking.store(true, Ordering::SeqCst);
+
+        true
+    }
+
+    fn stop_baker(&self) -> bool {
+        if !self.is_active() || !self.is_baking() {
+            return false;
+        }
+
+        info!("Stopping baking");
+
+        let consensus = self.consensus.load(Ordering::SeqCst);
+        unsafe {
+            stopBaker(consensus);
+        }
+        self.is_baking.store(false, Ordering::SeqCst);
+
+        true
+    }
+
+    fn is_baking(&self) -> bool { self.is_baking.load(Ordering::SeqCst) }
+
+    fn send_block(&self, block: &[u8]) -> ConsensusFfiResponse {
         wrap_send_data_to_c!(self, block, receiveBlock)
     }
 
-    pub fn send_finalization(&self, msg: &[u8]) -> ConsensusFfiResponse {
+    fn send_finalization(&self, msg: &[u8]) -> ConsensusFfiResponse {
         wrap_send_data_to_c!(self, msg, receiveFinalization)
     }
 
-    pub fn send_finalization_record(&self, rec: &[u8]) -> ConsensusFfiResponse {
+    fn send_finalization_record(&self, rec: &[u8]) -> ConsensusFfiResponse {
         wrap_send_data_to_c!(self, rec, receiveFinalizationRecord)
     }
 
-    pub fn send_transaction(&self, data: &[u8]) -> ConsensusFfiResponse {
+    fn send_transaction(&self, data: &[u8]) -> ConsensusFfiResponse {
         let consensus = self.consensus.load(Ordering::SeqCst);
         let len = data.len();
 
@@ -546,11 +649,11 @@ impl ConsensusContainer {
         return_code.unwrap_or_else(|code| panic!("Unknown FFI return code: {}", code))
     }
 
-    pub fn get_consensus_status(&self) -> String {
+    fn get_consensus_status(&self) -> String {
         wrap_c_call_string!(self, consensus, |consensus| getConsensusStatus(consensus))
     }
 
-    pub fn get_block_info(&self, block_hash: &str) -> String {
+    fn get_block_info(&self, block_hash: &str) -> String {
         let c_str = CString::new(block_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getBlockInfo(
             consensus,
@@ -558,11 +661,15 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_blocks_at_height(&self, block_height: u64) -> String {
+    fn get_blocks_at_height(&self, block_height: u64) -> String {
         wrap_c_call_string!(self, consensus, |consensus| getBlocksAtHeight(consensus, block_height))
     }
 
-    pub fn get_ancestors(&self, block_hash: &str, amount: u64) -> String {
+    fn get_finalized_block_at_height(&self, block_height: u64) -> Vec<u8> {
+        wrap_c_call_bytes!(self, |consensus| getFinalizedBlockAtHeight(consensus, block_height))
+    }
+
+    fn get_ancestors(&self, block_hash: &str, amount: u64) -> String {
         let c_str = CString::new(block_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getAncestors(
             consensus,
@@ -571,11 +678,11 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_branches(&self) -> String {
+    fn get_branches(&self) -> String {
         wrap_c_call_string!(self, consensus, |consensus| getBranches(consensus))
     }
 
-    pub fn get_account_list(&self, block_hash: &str) -> String {
+    fn get_account_list(&self, block_hash: &str) -> String {
         let block_hash = CString::new(block_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getAccountList(
             consensus,
@@ -583,7 +690,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_instances(&self, block_hash: &str) -> String {
+    fn get_instances(&self, block_hash: &str) -> String {
         let block_hash = CString::new(block_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getInstances(
             consensus,
@@ -591,7 +698,28 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_account_info(&self, block_hash: &str, account_address: &str) -> String {
+    fn get_account_list_page(&self, block_hash: &str, cursor: &str, limit: u64) -> String {
+        let block_hash = CString::new(block_hash).unwrap();
+        let cursor = CString::new(cursor).unwrap();
+        wrap_c_call_string!(self, consensus, |consensus| getAccountListPage(
+            consensus,
+            block_hash.as_ptr() as *const u8,
+            cursor.as_ptr() as *const u8,
+            limit
+        ))
+    }
+
+    fn get_instances_page(&self, block_hash: &str, start: u64, limit: u64) -> String {
+        let block_hash = CString::new(block_hash).unwrap();
+        wrap_c_call_string!(self, consensus, |consensus| getInstancesPage(
+            consensus,
+            block_hash.as_ptr() as *const u8,
+            start,
+            limit
+        ))
+    }
+
+    fn get_account_info(&self, block_hash: &str, account_address: &str) -> String {
         let block_hash = CString::new(block_hash).unwrap();
         let account_address = CString::new(account_address).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getAccountInfo(
@@ -601,7 +729,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_instance_info(&self, block_hash: &str, contract_address: &str) -> String {
+    fn get_instance_info(&self, block_hash: &str, contract_address: &str) -> String {
         let block_hash = CString::new(block_hash).unwrap();
         let contract_address = CString::new(contract_address).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getInstanceInfo(
@@ -611,7 +739,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_reward_status(&self, block_hash: &str) -> String {
+    fn get_reward_status(&self, block_hash: &str) -> String {
         let block_hash = CString::new(block_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getRewardStatus(
             consensus,
@@ -619,7 +747,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_birk_parameters(&self, block_hash: &str) -> String {
+    fn get_birk_parameters(&self, block_hash: &str) -> String {
         let block_hash = CString::new(block_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getBirkParameters(
             consensus,
@@ -627,7 +755,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_module_list(&self, block_hash: &str) -> String {
+    fn get_module_list(&self, block_hash: &str) -> String {
         let block_hash = CString::new(block_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getModuleList(
             consensus,
@@ -635,7 +763,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_module_source(&self, block_hash: &str, module_ref: &str) -> Vec<u8> {
+    fn get_module_source(&self, block_hash: &str, module_ref: &str) -> Vec<u8> {
         let block_hash = CString::new(block_hash).unwrap();
         let module_ref = CString::new(module_ref).unwrap();
         wrap_c_call_bytes!(self, |consensus| getModuleSource(
@@ -645,7 +773,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_catch_up_status(&self) -> Arc<[u8]> {
+    fn get_catch_up_status(&self) -> Arc<[u8]> {
         wrap_c_call_payload!(
             self,
             |consensus| getCatchUpStatus(consensus),
@@ -653,7 +781,7 @@ impl ConsensusContainer {
         )
     }
 
-    pub fn receive_catch_up_status(
+    fn receive_catch_up_status(
         &self,
         request: &[u8],
         peer_id: RemotePeerId,
@@ -669,22 +797,22 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn in_baking_committee(&self) -> ConsensusIsInBakingCommitteeResponse {
+    fn in_baking_committee(&self) -> ConsensusIsInBakingCommitteeResponse {
         wrap_c_committee_call!(self, |consensus| bakerIdBestBlock(consensus))
     }
 
-    pub fn in_finalization_committee(&self) -> bool {
+    fn in_finalization_committee(&self) -> bool {
         wrap_c_bool_call!(self, |consensus| checkIfWeAreFinalizer(consensus))
     }
 
-    pub fn get_account_non_finalized_transactions(&self, account_address: &str) -> String {
+    fn get_account_non_finalized_transactions(&self, account_address: &str) -> String {
         let account_address = CString::new(account_address).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| {
             getAccountNonFinalizedTransactions(consensus, account_address.as_ptr() as *const u8)
         })
     }
 
-    pub fn get_block_summary(&self, block_hash: &str) -> String {
+    fn get_block_summary(&self, block_hash: &str) -> String {
         let block_hash = CString::new(block_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getBlockSummary(
             consensus,
@@ -692,7 +820,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_transaction_status(&self, transaction_hash: &str) -> String {
+    fn get_transaction_status(&self, transaction_hash: &str) -> String {
         let transaction_hash = CString::new(transaction_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getTransactionStatus(
             consensus,
@@ -700,11 +828,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_transaction_status_in_block(
-        &self,
-        transaction_hash: &str,
-        block_hash: &str,
-    ) -> String {
+    fn get_transaction_status_in_block(&self, transaction_hash: &str, block_hash: &str) -> String {
         let transaction_hash = CString::new(transaction_hash).unwrap();
         let block_hash = CString::new(block_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getTransactionStatusInBlock(
@@ -714,7 +838,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_next_account_nonce(&self, account_address: &str) -> String {
+    fn get_next_account_nonce(&self, account_address: &str) -> String {
         let account_address = CString::new(account_address).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getNextAccountNonce(
             consensus,
@@ -722,7 +846,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_identity_providers(&self, block_hash: &str) -> String {
+    fn get_identity_providers(&self, block_hash: &str) -> String {
         let block_hash = CString::new(block_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getAllIdentityProviders(
             consensus,
@@ -730,7 +854,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_anonymity_revokers(&self, block_hash: &str) -> String {
+    fn get_anonymity_revokers(&self, block_hash: &str) -> String {
         let block_hash = CString::new(block_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getAllAnonymityRevokers(
             consensus,
@@ -738,7 +862,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn get_cryptographic_parameters(&self, block_hash: &str) -> String {
+    fn get_cryptographic_parameters(&self, block_hash: &str) -> String {
         let block_hash = CString::new(block_hash).unwrap();
         wrap_c_call_string!(self, consensus, |consensus| getCryptographicParameters(
             consensus,
@@ -746,7 +870,7 @@ impl ConsensusContainer {
         ))
     }
 
-    pub fn import_blocks(&self, import_file_path: &[u8]) -> u8 {
+    fn import_blocks(&self, import_file_path: &[u8]) -> u8 {
         let consensus = self.consensus.load(Ordering::SeqCst);
         let len = import_file_path.len();
 
@@ -758,6 +882,32 @@ impl ConsensusContainer {
             )
         }
     }
+
+    fn snapshot_database(&self, directory: &[u8]) -> bool {
+        let consensus = self.consensus.load(Ordering::SeqCst);
+        let len = directory.len();
+
+        unsafe {
+            snapshotDatabase(
+                consensus,
+                CString::from_vec_unchecked(directory.to_vec()).as_ptr() as *const u8,
+                len as i64,
+            ) == 0
+        }
+    }
+
+    fn tree_state_version(&self, tree_state_dir: &Path) -> anyhow::Result<TreeStateVersion> {
+        tree_state_version(tree_state_dir)
+    }
+
+    fn set_baker_credentials(&self, private_data: &[u8]) -> SetBakerCredentialsResponse {
+        let consensus = self.consensus.load(Ordering::SeqCst);
+        let result = unsafe {
+            setBakerCredentials(consensus, private_data.as_ptr(), private_data.len() as i64)
+        };
+        SetBakerCredentialsResponse::try_from(result)
+            .unwrap_or_else(|code| panic!("Unknown FFI return code: {}", code))
+    }
 }
 
 pub enum CallbackType {
@@ -326,6 +326,41 @@ impl TryFrom<i64> for ConsensusIsInBakingCommitteeResponse {
     }
 }
 
+/// The outcome of replacing the baker credentials of a running node.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SetBakerCredentialsResponse {
+    Success,
+    InvalidCredentials,
+    NotABaker,
+    WrongKeys,
+}
+
+impl TryFrom<i64> for SetBakerCredentialsResponse {
+    type Error = anyhow::Error;
+
+    #[inline]
+    fn try_from(value: i64) -> anyhow::Result<SetBakerCredentialsResponse> {
+        use SetBakerCredentialsResponse::*;
+
+        match value {
+            0 => Ok(Success),
+            1 => Ok(InvalidCredentials),
+            2 => Ok(NotABaker),
+            3 => Ok(WrongKeys),
+            _ => Err(anyhow!("Unsupported FFI return code for setting credentials ({})", value)),
+        }
+    }
+}
+
+/// The version of a tree state database, as stored in it.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TreeStateVersion {
+    /// The description of the stored version.
+    pub version:   String,
+    /// Whether this node can open the database.
+    pub supported: bool,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum ConsensusIsInFinalizationCommitteeResponse {
     NotInCommittee = 0,
@@ -2,7 +2,7 @@ use crate::{
     common::p2p_peer::RemotePeerId,
     consensus_ffi::{catch_up::PeerStatus, helpers::PacketType},
 };
-use std::{fmt, sync::Arc};
+use std::{fmt, sync::Arc, time::Instant};
 
 /// The type of messages passed between GlobalState and the consensus layer.
 ///
@@ -14,6 +14,8 @@ pub struct ConsensusMessage {
     pub payload:       Arc<[u8]>,
     pub dont_relay_to: Vec<RemotePeerId>,
     pub omit_status:   Option<PeerStatus>,
+    /// The time at which the message was created, i.e., queued.
+    pub created:       Instant,
 }
 
 impl ConsensusMessage {
@@ -30,6 +32,7 @@ impl ConsensusMessage {
             payload,
             dont_relay_to,
             omit_status,
+            created: Instant::now(),
         }
     }
 
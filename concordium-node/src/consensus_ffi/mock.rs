@@ -0,0 +1,359 @@
+//! A pure Rust implementation of the consensus layer for testing.
+//!
+//! The [`MockConsensus`] doesn't validate anything; it answers every message
+//! with a scripted [`ConsensusFfiResponse`] (by default `Success`) and every
+//! query with a scripted JSON response (by default `null`), and it records the
+//! messages and queries it receives. This allows testing how the P2P layer and
+//! the RPC server react to the responses of consensus, e.g., `PendingBlock` or
+//! `InvalidResult`, without the Haskell runtime.
+
+use crate::{
+    common::p2p_peer::RemotePeerId,
+    consensus_ffi::{
+        consensus::{Consensus, ConsensusType},
+        helpers::{
+            ConsensusFfiResponse, ConsensusIsInBakingCommitteeResponse, PacketType,
+            SetBakerCredentialsResponse, TreeStateVersion,
+        },
+    },
+    lock_or_die,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+#[derive(Default)]
+struct MockState {
+    /// Responses returned once, in order, before the default ones.
+    queued_responses:  HashMap<PacketType, VecDeque<ConsensusFfiResponse>>,
+    default_responses: HashMap<PacketType, ConsensusFfiResponse>,
+    /// How long processing a message of the given type takes.
+    delays:            HashMap<PacketType, Duration>,
+    query_responses:   HashMap<&'static str, String>,
+    catch_up_status:   Vec<u8>,
+    finalized_blocks:  Vec<Vec<u8>>,
+    baking:            bool,
+    received:          Vec<(PacketType, Vec<u8>)>,
+    queries:           Vec<(&'static str, Vec<String>)>,
+}
+
+/// A scriptable stand-in for the consensus layer.
+pub struct MockConsensus {
+    consensus_type: ConsensusType,
+    state:          Mutex<MockState>,
+}
+
+impl Default for MockConsensus {
+    fn default() -> Self { MockConsensus::new(ConsensusType::Passive) }
+}
+
+impl MockConsensus {
+    /// Creates a mock of the given type; only an active one can bake.
+    pub fn new(consensus_type: ConsensusType) -> Self {
+        MockConsensus {
+            consensus_type,
+            state: Default::default(),
+        }
+    }
+
+    /// Answers all the following messages of the given type with `response`.
+    pub fn respond_with(&self, packet_type: PacketType, response: ConsensusFfiResponse) {
+        lock_or_die!(self.state).default_responses.insert(packet_type, response);
+    }
+
+    /// Answers the next message of the given type with `response`; queued
+    /// responses are used in order before the default one.
+    pub fn queue_response(&self, packet_type: PacketType, response: ConsensusFfiResponse) {
+        lock_or_die!(self.state)
+            .queued_responses
+            .entry(packet_type)
+            .or_default()
+            .push_back(response);
+    }
+
+    /// Makes processing each following message of the given type take
+    /// `delay`, as if consensus were busy with it.
+    pub fn delay(&self, packet_type: PacketType, delay: Duration) {
+        lock_or_die!(self.state).delays.insert(packet_type, delay);
+    }
+
+    /// Answers the query with the given name, e.g., `GetBlockInfo`, with
+    /// `response`.
+    pub fn set_query_response(&self, query: &'static str, response: &str) {
+        lock_or_die!(self.state).query_responses.insert(query, response.to_owned());
+    }
+
+    /// Sets the catch-up status sent to peers, without the packet type.
+    pub fn set_catch_up_status(&self, status: &[u8]) {
+        lock_or_die!(self.state).catch_up_status = status.to_owned();
+    }
+
+    /// Appends a block to the finalized chain; the first one is at height 1.
+    pub fn add_finalized_block(&self, block: &[u8]) {
+        lock_or_die!(self.state).finalized_blocks.push(block.to_owned());
+    }
+
+    /// The messages received so far, along with their types.
+    pub fn received(&self) -> Vec<(PacketType, Vec<u8>)> {
+        lock_or_die!(self.state).received.clone()
+    }
+
+    /// The queries made so far, along with their arguments.
+    pub fn queries(&self) -> Vec<(&'static str, Vec<String>)> {
+        lock_or_die!(self.state).queries.clone()
+    }
+
+    fn receive(&self, packet_type: PacketType, payload: &[u8]) -> ConsensusFfiResponse {
+        let (response, delay) = {
+            let mut state = lock_or_die!(self.state);
+            state.received.push((packet_type, payload.to_owned()));
+            let queued = state.queued_responses.get_mut(&packet_type).and_then(VecDeque::pop_front);
+            let response = queued
+                .or_else(|| state.default_responses.get(&packet_type).copied())
+                .unwrap_or(ConsensusFfiResponse::Success);
+            (response, state.delays.get(&packet_type).copied())
+        };
+        // the messages are processed without holding the lock, so that the
+        // slow ones don't hold up the others
+        if let Some(delay) = delay {
+            thread::sleep(delay);
+        }
+        response
+    }
+
+    fn query(&self, query: &'static str, arguments: &[&dyn ToString]) -> String {
+        let mut state = lock_or_die!(self.state);
+        state.queries.push((query, arguments.iter().map(|arg| arg.to_string()).collect()));
+        state.query_responses.get(query).cloned().unwrap_or_else(|| "null".to_owned())
+    }
+}
+
+impl Consensus for MockConsensus {
+    fn is_running(&self) -> bool { true }
+
+    fn consensus_type(&self) -> ConsensusType { self.consensus_type.clone() }
+
+    fn start_baker(&self) -> bool {
+        let mut state = lock_or_die!(self.state);
+        if self.consensus_type != ConsensusType::Active || state.baking {
+            return false;
+        }
+        state.baking = true;
+        true
+    }
+
+    fn stop_baker(&self) -> bool {
+        let mut state = lock_or_die!(self.state);
+        if self.consensus_type != ConsensusType::Active || !state.baking {
+            return false;
+        }
+        state.baking = false;
+        true
+    }
+
+    fn is_baking(&self) -> bool { lock_or_die!(self.state).baking }
+
+    fn in_baking_committee(&self) -> ConsensusIsInBakingCommitteeResponse {
+        ConsensusIsInBakingCommitteeResponse::NotInCommittee
+    }
+
+    fn in_finalization_committee(&self) -> bool { false }
+
+    fn send_block(&self, block: &[u8]) -> ConsensusFfiResponse {
+        self.receive(PacketType::Block, block)
+    }
+
+    fn send_finalization(&self, msg: &[u8]) -> ConsensusFfiResponse {
+        self.receive(PacketType::FinalizationMessage, msg)
+    }
+
+    fn send_finalization_record(&self, rec: &[u8]) -> ConsensusFfiResponse {
+        self.receive(PacketType::FinalizationRecord, rec)
+    }
+
+    fn send_transaction(&self, data: &[u8]) -> ConsensusFfiResponse {
+        self.receive(PacketType::Transaction, data)
+    }
+
+    fn get_catch_up_status(&self) -> Arc<[u8]> {
+        let mut status = vec![PacketType::CatchUpStatus as u8];
+        status.extend_from_slice(&lock_or_die!(self.state).catch_up_status);
+        Arc::from(status)
+    }
+
+    fn receive_catch_up_status(
+        &self,
+        request: &[u8],
+        _peer_id: RemotePeerId,
+        _object_limit: i64,
+    ) -> ConsensusFfiResponse {
+        self.receive(PacketType::CatchUpStatus, request)
+    }
+
+    fn import_blocks(&self, import_file_path: &[u8]) -> u8 {
+        self.query("ImportBlocks", &[&String::from_utf8_lossy(import_file_path)]);
+        0
+    }
+
+    fn snapshot_database(&self, directory: &[u8]) -> bool {
+        let directory = String::from_utf8_lossy(directory).into_owned();
+        self.query("SnapshotDatabase", &[&directory]);
+        // stands in for the tree state and the block state file
+        fs::create_dir_all(Path::new(&directory).join("treestate-0"))
+            .and_then(|_| fs::write(Path::new(&directory).join("blockstate-0.dat"), b"mock"))
+            .is_ok()
+    }
+
+    fn tree_state_version(&self, tree_state_dir: &Path) -> anyhow::Result<TreeStateVersion> {
+        self.query("TreeStateVersion", &[&tree_state_dir.to_string_lossy()]);
+        Ok(TreeStateVersion {
+            version:   "mock".to_owned(),
+            supported: true,
+        })
+    }
+
+    fn set_baker_credentials(&self, private_data: &[u8]) -> SetBakerCredentialsResponse {
+        let private_data = String::from_utf8_lossy(private_data).into_owned();
+        self.query("SetBakerCredentials", &[&private_data]);
+        if self.consensus_type != ConsensusType::Active {
+            SetBakerCredentialsResponse::NotABaker
+        } else if serde_json::from_str::<serde_json::Value>(&private_data)
+            .map_or(true, |credentials| !credentials.is_object())
+        {
+            SetBakerCredentialsResponse::InvalidCredentials
+        } else {
+            SetBakerCredentialsResponse::Success
+        }
+    }
+
+    fn get_consensus_status(&self) -> String { self.query("GetConsensusStatus", &[]) }
+
+    fn get_block_info(&self, block_hash: &str) -> String {
+        self.query("GetBlockInfo", &[&block_hash])
+    }
+
+    fn get_blocks_at_height(&self, block_height: u64) -> String {
+        self.query("GetBlocksAtHeight", &[&block_height])
+    }
+
+    fn get_finalized_block_at_height(&self, block_height: u64) -> Vec<u8> {
+        let state = lock_or_die!(self.state);
+        match block_height.checked_sub(1) {
+            Some(index) => state.finalized_blocks.get(index as usize).cloned().unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    fn get_ancestors(&self, block_hash: &str, amount: u64) -> String {
+        self.query("GetAncestors", &[&block_hash, &amount])
+    }
+
+    fn get_branches(&self) -> String { self.query("GetBranches", &[]) }
+
+    fn get_account_list(&self, block_hash: &str) -> String {
+        self.query("GetAccountList", &[&block_hash])
+    }
+
+    fn get_instances(&self, block_hash: &str) -> String {
+        self.query("GetInstances", &[&block_hash])
+    }
+
+    fn get_account_list_page(&self, block_hash: &str, cursor: &str, limit: u64) -> String {
+        self.query("StreamAccountList", &[&block_hash, &cursor, &limit])
+    }
+
+    fn get_instances_page(&self, block_hash: &str, start: u64, limit: u64) -> String {
+        self.query("StreamInstances", &[&block_hash, &start, &limit])
+    }
+
+    fn get_account_info(&self, block_hash: &str, account_address: &str) -> String {
+        self.query("GetAccountInfo", &[&block_hash, &account_address])
+    }
+
+    fn get_instance_info(&self, block_hash: &str, contract_address: &str) -> String {
+        self.query("GetInstanceInfo", &[&block_hash, &contract_address])
+    }
+
+    fn get_reward_status(&self, block_hash: &str) -> String {
+        self.query("GetRewardStatus", &[&block_hash])
+    }
+
+    fn get_birk_parameters(&self, block_hash: &str) -> String {
+        self.query("GetBirkParameters", &[&block_hash])
+    }
+
+    fn get_module_list(&self, block_hash: &str) -> String {
+        self.query("GetModuleList", &[&block_hash])
+    }
+
+    fn get_module_source(&self, block_hash: &str, module_ref: &str) -> Vec<u8> {
+        self.query("GetModuleSource", &[&block_hash, &module_ref]).into_bytes()
+    }
+
+    fn get_account_non_finalized_transactions(&self, account_address: &str) -> String {
+        self.query("GetAccountNonFinalizedTransactions", &[&account_address])
+    }
+
+    fn get_block_summary(&self, block_hash: &str) -> String {
+        self.query("GetBlockSummary", &[&block_hash])
+    }
+
+    fn get_transaction_status(&self, transaction_hash: &str) -> String {
+        self.query("GetTransactionStatus", &[&transaction_hash])
+    }
+
+    fn get_transaction_status_in_block(&self, transaction_hash: &str, block_hash: &str) -> String {
+        self.query("GetTransactionStatusInBlock", &[&transaction_hash, &block_hash])
+    }
+
+    fn get_next_account_nonce(&self, account_address: &str) -> String {
+        self.query("GetNextAccountNonce", &[&account_address])
+    }
+
+    fn get_identity_providers(&self, block_hash: &str) -> String {
+        self.query("GetIdentityProviders", &[&block_hash])
+    }
+
+    fn get_anonymity_revokers(&self, block_hash: &str) -> String {
+        self.query("GetAnonymityRevokers", &[&block_hash])
+    }
+
+    fn get_cryptographic_parameters(&self, block_hash: &str) -> String {
+        self.query("GetCryptographicParameters", &[&block_hash])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripted_responses() {
+        let consensus = MockConsensus::default();
+        consensus.respond_with(PacketType::Block, ConsensusFfiResponse::PendingBlock);
+        consensus.queue_response(PacketType::Block, ConsensusFfiResponse::InvalidResult);
+        assert_eq!(consensus.send_block(&[1]), ConsensusFfiResponse::InvalidResult);
+        assert_eq!(consensus.send_block(&[2]), ConsensusFfiResponse::PendingBlock);
+        assert_eq!(consensus.send_transaction(&[3]), ConsensusFfiResponse::Success);
+        assert_eq!(consensus.received(), vec![
+            (PacketType::Block, vec![1]),
+            (PacketType::Block, vec![2]),
+            (PacketType::Transaction, vec![3]),
+        ]);
+
+        consensus.set_query_response("GetBlockInfo", r#"{"finalized":true}"#);
+        assert_eq!(consensus.get_block_info("abc"), r#"{"finalized":true}"#);
+        assert_eq!(consensus.get_branches(), "null");
+        assert_eq!(consensus.queries()[0], ("GetBlockInfo", vec!["abc".to_owned()]));
+
+        assert!(!consensus.start_baker());
+        let baker = MockConsensus::new(ConsensusType::Active);
+        assert!(baker.start_baker() && baker.is_baking());
+        assert!(baker.stop_baker() && !baker.is_baking());
+    }
+}
@@ -100,6 +100,7 @@ macro_rules! wrap_c_committee_call {
     }};
 }
 
+pub mod block_export;
 #[macro_use]
 pub mod blockchain_types;
 pub mod catch_up;
@@ -107,3 +108,6 @@ pub mod consensus;
 pub mod ffi;
 pub mod helpers;
 pub mod messaging;
+#[cfg(any(test, bench, feature = "test_utils"))]
+pub mod mock;
+pub mod transactions;
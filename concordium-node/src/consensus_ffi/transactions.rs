@@ -0,0 +1,214 @@
+//! Cheap checks of transactions before they are handed over to consensus.
+//!
+//! Consensus deserializes and verifies every transaction it receives, which
+//! requires crossing the FFI boundary. Transactions that are malformed or
+//! expired can be rejected on this side already, so that they neither reach
+//! consensus nor take up room in its queues. The checks follow the versioned
+//! block item format accepted by consensus:
+//!
+//! - an account transaction (tag 0) consists of the signatures, the header and
+//!   the payload, whose size is given in the header;
+//! - a credential deployment (tag 1) consists of the expiry time and the
+//!   credential with its proofs;
+//! - a chain update (tag 2) consists of the header, the payload and the
+//!   signatures of the update keys.
+//!
+//! The signatures, headers and credentials are deserialized with their
+//! `Deserial` implementations, which reject the same malformed input as
+//! consensus. The payloads are left to consensus, and so is the verification
+//! of the signatures and proofs.
+
+use crate::consensus_ffi::{
+    blockchain_types::{split_version, AccountAddress},
+    helpers::ConsensusFfiResponse,
+};
+use anyhow::{bail, ensure};
+use crypto_common::{
+    deserial_map_no_length,
+    types::{CredentialIndex, KeyIndex, Signature, TransactionTime},
+    Deserial,
+};
+use id::{
+    constants::{ArCurve, AttributeKind, IpPairing},
+    types::AccountCredential,
+};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{Cursor, Read},
+};
+
+/// The only version of block items understood by consensus.
+const BLOCK_ITEM_VERSION: u32 = 0;
+
+/// The reason for rejecting a transaction.
+#[derive(Debug)]
+pub enum TransactionRejection {
+    /// The transaction does not follow the wire format.
+    Malformed(anyhow::Error),
+    /// The transaction expired before it was received.
+    Expired,
+}
+
+impl TransactionRejection {
+    /// The response consensus would give for the transaction.
+    pub fn response(&self) -> ConsensusFfiResponse {
+        match self {
+            TransactionRejection::Malformed(_) => ConsensusFfiResponse::DeserializationError,
+            TransactionRejection::Expired => ConsensusFfiResponse::Stale,
+        }
+    }
+}
+
+impl fmt::Display for TransactionRejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionRejection::Malformed(e) => write!(f, "Malformed transaction: {}", e),
+            TransactionRejection::Expired => write!(f, "The transaction already expired"),
+        }
+    }
+}
+
+/// Checks a transaction in the versioned format, given the current time in
+/// seconds since the Unix epoch.
+pub fn check_transaction(bytes: &[u8], now: u64) -> Result<(), TransactionRejection> {
+    let expiry = parse_block_item(bytes).map_err(TransactionRejection::Malformed)?;
+    if now > expiry.seconds {
+        return Err(TransactionRejection::Expired);
+    }
+    Ok(())
+}
+
+/// Parses a versioned block item and returns its expiry time.
+fn parse_block_item(bytes: &[u8]) -> anyhow::Result<TransactionTime> {
+    let (version, block_item) = split_version(bytes)?;
+    ensure!(version == BLOCK_ITEM_VERSION, "Unsupported block item version {}", version);
+    let mut cursor = Cursor::new(block_item);
+    let expiry = match u8::deserial(&mut cursor)? {
+        0 => parse_account_transaction(&mut cursor)?,
+        1 => parse_credential_deployment(&mut cursor)?,
+        2 => parse_chain_update(&mut cursor)?,
+        tag => bail!("Unknown block item type {}", tag),
+    };
+    ensure_consumed(&mut cursor)?;
+    Ok(expiry)
+}
+
+fn parse_account_transaction(cursor: &mut Cursor<&[u8]>) -> anyhow::Result<TransactionTime> {
+    // the signatures of the account keys, grouped by credential
+    let num_credentials = u8::deserial(cursor)?;
+    let mut last_credential_index = None;
+    for _ in 0..num_credentials {
+        let credential_index = CredentialIndex::deserial(cursor)?;
+        ensure!(
+            last_credential_index < Some(credential_index),
+            "The credential indices are not in increasing order"
+        );
+        last_credential_index = Some(credential_index);
+        let num_signatures = u8::deserial(cursor)?;
+        let _signatures: BTreeMap<KeyIndex, Signature> =
+            deserial_map_no_length(cursor, num_signatures.into())?;
+    }
+    let _sender = AccountAddress::deserial(cursor)?;
+    let _nonce = u64::deserial(cursor)?;
+    let _energy = u64::deserial(cursor)?;
+    let payload_size = u32::deserial(cursor)?;
+    let expiry = TransactionTime::deserial(cursor)?;
+    skip(cursor, payload_size as usize)?;
+    Ok(expiry)
+}
+
+fn parse_credential_deployment(cursor: &mut Cursor<&[u8]>) -> anyhow::Result<TransactionTime> {
+    let expiry = TransactionTime::deserial(cursor)?;
+    let _credential: AccountCredential<IpPairing, ArCurve, AttributeKind> =
+        AccountCredential::deserial(cursor)?;
+    Ok(expiry)
+}
+
+fn parse_chain_update(cursor: &mut Cursor<&[u8]>) -> anyhow::Result<TransactionTime> {
+    let _sequence_number = u64::deserial(cursor)?;
+    let _effective_time = TransactionTime::deserial(cursor)?;
+    let timeout = TransactionTime::deserial(cursor)?;
+    let payload_size = u32::deserial(cursor)?;
+    skip(cursor, payload_size as usize)?;
+    let num_signatures = u16::deserial(cursor)?;
+    let _signatures: BTreeMap<u16, Signature> =
+        deserial_map_no_length(cursor, num_signatures.into())?;
+    Ok(timeout)
+}
+
+fn skip(cursor: &mut Cursor<&[u8]>, length: usize) -> anyhow::Result<()> {
+    let remaining = cursor.get_ref().len() - cursor.position() as usize;
+    ensure!(remaining >= length, "Expected {} more bytes, but only {} remain", length, remaining);
+    cursor.set_position(cursor.position() + length as u64);
+    Ok(())
+}
+
+fn ensure_consumed(cursor: &mut Cursor<&[u8]>) -> anyhow::Result<()> {
+    let mut rest = Vec::new();
+    cursor.read_to_end(&mut rest)?;
+    ensure!(rest.is_empty(), "{} trailing bytes after the transaction", rest.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::dummy_account_transaction as account_transaction;
+    use byteorder::{BigEndian, WriteBytesExt};
+
+    #[test]
+    fn test_account_transactions() {
+        let now = 1_600_000_000;
+        assert!(check_transaction(&account_transaction(64, 41, now + 10), now).is_ok());
+        assert!(matches!(
+            check_transaction(&account_transaction(64, 41, now - 10), now),
+            Err(TransactionRejection::Expired)
+        ));
+        // the signatures are verified by consensus, whatever their length
+        assert!(check_transaction(&account_transaction(63, 41, now + 10), now).is_ok());
+        // but their indices must be increasing, as consensus requires
+        let mut unordered = vec![BLOCK_ITEM_VERSION as u8, 0, 2];
+        unordered.extend_from_slice(&[1, 1, 0, 0, 1, 9]);
+        unordered.extend_from_slice(&[0, 1, 0, 0, 1, 9]);
+        unordered.extend_from_slice(&account_transaction(1, 41, now + 10)[9..]);
+        assert!(matches!(
+            check_transaction(&unordered, now),
+            Err(TransactionRejection::Malformed(_))
+        ));
+
+        let mut truncated = account_transaction(64, 41, now + 10);
+        truncated.pop();
+        assert!(check_transaction(&truncated, now).is_err());
+        let mut trailing = account_transaction(64, 41, now + 10);
+        trailing.push(0);
+        assert!(check_transaction(&trailing, now).is_err());
+        let mut unversioned = account_transaction(64, 41, now + 10);
+        unversioned[0] = 1;
+        assert!(check_transaction(&unversioned, now).is_err());
+    }
+
+    #[test]
+    fn test_credential_deployments() {
+        let now = 1_600_000_000;
+        let credential = |credential_type: u8| {
+            let mut bytes = vec![BLOCK_ITEM_VERSION as u8, 1];
+            bytes.write_u64::<BigEndian>(now + 10).unwrap();
+            // a credential with one key, but neither the rest of the credential
+            // values nor the proofs
+            bytes.extend_from_slice(&[credential_type, 1, 0, 0]);
+            bytes.extend(vec![5; 32]);
+            bytes.push(1);
+            bytes
+        };
+        // the whole credential is checked, not only the keys it starts with
+        assert!(matches!(
+            check_transaction(&credential(1), now),
+            Err(TransactionRejection::Malformed(_))
+        ));
+        assert!(matches!(
+            check_transaction(&credential(2), now),
+            Err(TransactionRejection::Malformed(_))
+        ));
+    }
+}
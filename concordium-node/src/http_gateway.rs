@@ -0,0 +1,1290 @@
+//! An HTTP/JSON gateway to the gRPC API.
+//!
+//! Every endpoint is a thin translation layer: the HTTP request is turned into
+//! the corresponding gRPC request message, the `authentication` header is
+//! copied into the request metadata, and the call is dispatched to the very
+//! same [`P2p`](crate::common::grpc_api::p2p_server::P2p) implementation that
+//! serves gRPC clients. Authentication, validation and the mapping of consensus
+//! failures are therefore identical for both transports.
+
+use crate::{
+    common::grpc_api::{p2p_server::P2p, *},
+    configuration::PROTOCOL_MAX_TRANSACTION_SIZE,
+    rpc::{RpcServerImpl, MAX_TRANSACTION_BATCH_SIZE},
+    rpc_limits::GatewayClient,
+};
+use anyhow::Context;
+use gotham::{
+    handler::HandlerError,
+    helpers::http::response::create_response,
+    middleware::state::StateMiddleware,
+    pipeline::{single::single_pipeline, single_middleware},
+    router::{builder::*, Router},
+    state::{client_addr, FromState, State},
+};
+use hyper::{
+    body::{Bytes, HttpBody},
+    Body, HeaderMap, Response, StatusCode,
+};
+use serde::{
+    de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_json::json;
+use std::{cell::Cell, fmt, io, net::SocketAddr, panic::RefUnwindSafe, rc::Rc, str::FromStr};
+use tokio::{net::TcpListener, sync::mpsc};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{metadata::MetadataValue, Code, Request, Status};
+
+/// The name of the header (and the gRPC metadata key) carrying the access
+/// token.
+const AUTHENTICATION_HEADER: &str = "authentication";
+
+/// The media type of streamed responses, which hold one JSON value per line.
+const NDJSON_MIME: &str = "application/x-ndjson";
+
+/// The maximum size of a JSON request body.
+const MAX_JSON_BODY_SIZE: usize = 4096;
+
+/// The maximum number of bytes of the body of a batch submission that may be
+/// read for a single transaction: its hex encoding, the quotes and the
+/// separator, with some slack for whitespace.
+const MAX_BATCH_ELEMENT_SIZE: usize = 2 * PROTOCOL_MAX_TRANSACTION_SIZE + 1024;
+
+/// The number of chunks of a batch submission buffered between the task
+/// receiving the body and the thread parsing it.
+const BATCH_BODY_CHUNKS: usize = 16;
+
+#[derive(Clone, StateData)]
+struct GatewayStateData {
+    server: RpcServerImpl,
+}
+
+// The server only exposes its state through locks and atomics, so observing it
+// after a handler panicked cannot break any invariants.
+impl RefUnwindSafe for GatewayStateData {}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct BlockPath {
+    hash: String,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct ModuleSourcePath {
+    hash:       String,
+    module_ref: String,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct TransactionInBlockPath {
+    hash:        String,
+    transaction: String,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct HeightPath {
+    height: u64,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct AccountPath {
+    address: String,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct InstancePath {
+    index:    u64,
+    subindex: u64,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct TransactionPath {
+    hash: String,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct NetworkPath {
+    id: i32,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct PeerPath {
+    id: String,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct BanPath {
+    ip: String,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct BlockQuery {
+    block: Option<String>,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct AncestorsQuery {
+    amount: u64,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct PageQuery {
+    #[serde(default)]
+    cursor: String,
+    #[serde(default)]
+    limit:  u64,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+#[serde(rename_all = "camelCase")]
+struct PeersQuery {
+    #[serde(default)]
+    include_bootstrappers: bool,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct ShutdownQuery {
+    #[serde(default)]
+    graceful: bool,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+struct SendTransactionQuery {
+    #[serde(default = "default_network_id")]
+    network: u32,
+}
+
+fn default_network_id() -> u32 { 1000 }
+
+#[derive(Deserialize)]
+struct PeerAddressBody {
+    ip:   String,
+    port: u16,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BanBody {
+    ip:      Option<String>,
+    node_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PinPeersBody {
+    node_ids: Vec<String>,
+}
+
+/// Parses the body of a batch submission, an object whose `transactions`
+/// field holds an array of hex-encoded transactions. The transactions are
+/// decoded as they are parsed, so the encoded body is never held in memory.
+struct TransactionBatchBody {
+    /// The number of bytes read since the last transaction was parsed.
+    read: Rc<Cell<usize>>,
+}
+
+impl<'de> DeserializeSeed<'de> for TransactionBatchBody {
+    type Value = Vec<Vec<u8>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for TransactionBatchBody {
+    type Value = Vec<Vec<u8>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an object with a list of transactions")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut transactions = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if key != "transactions" {
+                map.next_value::<IgnoredAny>()?;
+            } else if transactions.is_some() {
+                return Err(de::Error::duplicate_field("transactions"));
+            } else {
+                transactions = Some(map.next_value_seed(TransactionList {
+                    read: self.read.clone(),
+                })?);
+            }
+        }
+        transactions.ok_or_else(|| de::Error::missing_field("transactions"))
+    }
+}
+
+/// Parses the list of hex-encoded transactions of a batch submission.
+struct TransactionList {
+    read: Rc<Cell<usize>>,
+}
+
+impl<'de> DeserializeSeed<'de> for TransactionList {
+    type Value = Vec<Vec<u8>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for TransactionList {
+    type Value = Vec<Vec<u8>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of hex-encoded transactions")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut payloads = Vec::new();
+        while let Some(transaction) = seq.next_element::<String>()? {
+            if payloads.len() == MAX_TRANSACTION_BATCH_SIZE {
+                return Err(de::Error::custom(format!(
+                    "A batch can contain at most {} transactions.",
+                    MAX_TRANSACTION_BATCH_SIZE
+                )));
+            }
+            let payload = hex::decode(&transaction)
+                .map_err(|e| de::Error::custom(format!("Malformed transaction: {}", e)))?;
+            payloads.push(payload);
+            self.read.set(0);
+        }
+        Ok(payloads)
+    }
+}
+
+#[derive(Deserialize)]
+struct DumpBody {
+    #[serde(default)]
+    file: String,
+    #[serde(default)]
+    raw:  bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportBlocksBody {
+    directory:       String,
+    #[serde(default)]
+    from_height:     u64,
+    #[serde(default)]
+    to_height:       u64,
+    #[serde(default)]
+    blocks_per_file: u64,
+}
+
+#[derive(Deserialize)]
+struct CreateSnapshotBody {
+    path: String,
+}
+
+/// The body of an error response.
+#[derive(Serialize)]
+struct ErrorBody {
+    code:    String,
+    message: String,
+}
+
+/// Maps the status code of a failed gRPC call to the HTTP status code
+/// returned by the gateway.
+fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::InvalidArgument | Code::OutOfRange => StatusCode::BAD_REQUEST,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::FailedPrecondition => StatusCode::PRECONDITION_FAILED,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::Cancelled | Code::Unknown | Code::Internal | Code::DataLoss => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Conversion of a gRPC response message into the body of an HTTP response.
+trait IntoHttpBody {
+    fn into_http_body(self) -> (mime::Mime, Vec<u8>);
+}
+
+fn json_body(value: serde_json::Value) -> (mime::Mime, Vec<u8>) {
+    (mime::APPLICATION_JSON, value.to_string().into_bytes())
+}
+
+impl IntoHttpBody for JsonResponse {
+    // the consensus layer already responds with JSON
+    fn into_http_body(self) -> (mime::Mime, Vec<u8>) {
+        (mime::APPLICATION_JSON, self.value.into_bytes())
+    }
+}
+
+impl IntoHttpBody for BoolResponse {
+    fn into_http_body(self) -> (mime::Mime, Vec<u8>) { json_body(json!(self.value)) }
+}
+
+impl IntoHttpBody for NumberResponse {
+    fn into_http_body(self) -> (mime::Mime, Vec<u8>) { json_body(json!(self.value)) }
+}
+
+impl IntoHttpBody for StringResponse {
+    fn into_http_body(self) -> (mime::Mime, Vec<u8>) { json_body(json!(self.value)) }
+}
+
+impl IntoHttpBody for BytesResponse {
+    fn into_http_body(self) -> (mime::Mime, Vec<u8>) {
+        (mime::APPLICATION_OCTET_STREAM, self.value)
+    }
+}
+
+fn transaction_outcome_json(response: SendTransactionResponse) -> serde_json::Value {
+    json!({
+        "outcome": send_transaction_response::Outcome::from_i32(response.outcome)
+            .map(|outcome| format!("{:?}", outcome)),
+        "transactionHash": response.transaction_hash,
+        "message": response.message,
+    })
+}
+
+impl IntoHttpBody for SendTransactionResponse {
+    fn into_http_body(self) -> (mime::Mime, Vec<u8>) { json_body(transaction_outcome_json(self)) }
+}
+
+impl IntoHttpBody for SendTransactionBatchResponse {
+    fn into_http_body(self) -> (mime::Mime, Vec<u8>) {
+        json_body(serde_json::Value::Array(
+            self.results.into_iter().map(transaction_outcome_json).collect(),
+        ))
+    }
+}
+
+impl IntoHttpBody for PeerStatsResponse {
+    fn into_http_body(self) -> (mime::Mime, Vec<u8>) {
+        let peers = self
+            .peerstats
+            .into_iter()
+            .map(|peer| {
+                json!({
+                    "nodeId": peer.node_id,
+                    "packetsSent": peer.packets_sent,
+                    "packetsReceived": peer.packets_received,
+                    "latency": peer.latency,
+                })
+            })
+            .collect::<Vec<_>>();
+        json_body(json!({
+            "peerStats": peers,
+            "avgBpsIn": self.avg_bps_in,
+            "avgBpsOut": self.avg_bps_out,
+        }))
+    }
+}
+
+impl IntoHttpBody for PeerListResponse {
+    fn into_http_body(self) -> (mime::Mime, Vec<u8>) {
+        let peers = self
+            .peers
+            .into_iter()
+            .map(|peer| {
+                json!({
+                    "nodeId": peer.node_id,
+                    "ip": peer.ip,
+                    "port": peer.port,
+                    "catchupStatus": peer_element::CatchupStatus::from_i32(peer.catchup_status)
+                        .map(|status| format!("{:?}", status)),
+                })
+            })
+            .collect::<Vec<_>>();
+        json_body(json!({
+            "peerType": self.peer_type,
+            "peers": peers,
+        }))
+    }
+}
+
+impl IntoHttpBody for NodeInfoResponse {
+    fn into_http_body(self) -> (mime::Mime, Vec<u8>) {
+        json_body(json!({
+            "nodeId": self.node_id,
+            "currentLocaltime": self.current_localtime,
+            "peerType": self.peer_type,
+            "consensusBakerRunning": self.consensus_baker_running,
+            "consensusRunning": self.consensus_running,
+            "consensusType": self.consensus_type,
+            "consensusBakerCommittee":
+                node_info_response::IsInBakingCommittee::from_i32(self.consensus_baker_committee),
+            "consensusFinalizerCommittee": self.consensus_finalizer_committee,
+            "consensusBakerId": self.consensus_baker_id,
+        }))
+    }
+}
+
+/// Builds the HTTP response corresponding to the outcome of a gRPC call.
+fn respond<T: IntoHttpBody>(
+    state: &State,
+    result: Result<tonic::Response<T>, Status>,
+) -> Result<Response<Body>, HandlerError> {
+    match result {
+        Ok(resp) => {
+            let (mime, body) = resp.into_inner().into_http_body();
+            Ok(create_response(state, StatusCode::OK, mime, body))
+        }
+        Err(status) => Ok(error_response(state, status)),
+    }
+}
+
+/// Builds the HTTP response to a streaming gRPC call, with one JSON value per
+/// line. As the status of the response is sent before the stream starts, an
+/// error ending the stream is sent as a last line holding an error body. The
+/// stream is dropped, e.g., cancelling a job, once the client goes away.
+fn respond_stream<S>(
+    state: &State,
+    result: Result<tonic::Response<S>, Status>,
+) -> Result<Response<Body>, HandlerError>
+where
+    S: Stream<Item = Result<JsonResponse, Status>> + Send + Unpin + 'static, {
+    let mut stream = match result {
+        Ok(resp) => resp.into_inner(),
+        Err(status) => return Ok(error_response(state, status)),
+    };
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        while let Some(item) = stream.next().await {
+            let mut line = match item {
+                Ok(resp) => resp.value.into_bytes(),
+                Err(status) => error_body(&status),
+            };
+            line.push(b'\n');
+            if sender.send_data(line.into()).await.is_err() {
+                break;
+            }
+        }
+    });
+    let mime = mime::Mime::from_str(NDJSON_MIME).expect("The NDJSON media type is valid");
+    Ok(create_response(state, StatusCode::OK, mime, body))
+}
+
+fn error_body(status: &Status) -> Vec<u8> {
+    let body = ErrorBody {
+        code:    format!("{:?}", status.code()),
+        message: status.message().to_owned(),
+    };
+    serde_json::to_vec(&body).unwrap_or_default()
+}
+
+fn error_response(state: &State, status: Status) -> Response<Body> {
+    create_response(state, http_status(status.code()), mime::APPLICATION_JSON, error_body(&status))
+}
+
+/// Wraps a message in a gRPC request, carrying over the access token supplied
+/// in the HTTP headers and the address of the client.
+fn grpc_request<T>(state: &State, message: T) -> Request<T> {
+    let mut req = Request::new(message);
+    if let Some(addr) = client_addr(state) {
+        req.extensions_mut().insert(GatewayClient(addr.ip()));
+    }
+    if let Some(token) = HeaderMap::borrow_from(state)
+        .get(AUTHENTICATION_HEADER)
+        .and_then(|val| val.to_str().ok())
+        .and_then(|val| MetadataValue::from_str(val).ok())
+    {
+        req.metadata_mut().insert(AUTHENTICATION_HEADER, token);
+    }
+    req
+}
+
+/// Reads the request body, failing if it exceeds the given size.
+async fn read_body(state: &mut State, limit: usize) -> Result<Vec<u8>, Status> {
+    let mut body = Body::take_from(state);
+    let mut content = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| Status::invalid_argument(e.to_string()))?;
+        if content.len() + chunk.len() > limit {
+            return Err(Status::invalid_argument("Request body is too large."));
+        }
+        content.extend_from_slice(&chunk);
+    }
+    Ok(content)
+}
+
+/// Reads the body of a request from the chunks forwarded by the task receiving
+/// it, failing once too many bytes are read without the counter being reset.
+struct BodyReader {
+    chunks: mpsc::Receiver<io::Result<Bytes>>,
+    chunk:  Bytes,
+    read:   Rc<Cell<usize>>,
+    limit:  usize,
+}
+
+impl io::Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => self.chunk = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let len = std::cmp::min(buf.len(), self.chunk.len());
+        if self.read.get() + len > self.limit {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "A transaction is too large."));
+        }
+        self.read.set(self.read.get() + len);
+        buf[..len].copy_from_slice(&self.chunk.split_to(len));
+        Ok(len)
+    }
+}
+
+fn parse_transaction_batch(
+    chunks: mpsc::Receiver<io::Result<Bytes>>,
+) -> serde_json::Result<Vec<Vec<u8>>> {
+    let read = Rc::new(Cell::new(0));
+    let reader = BodyReader {
+        chunks,
+        chunk: Bytes::new(),
+        read: read.clone(),
+        limit: MAX_BATCH_ELEMENT_SIZE,
+    };
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let payloads = TransactionBatchBody {
+        read,
+    }
+    .deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(payloads)
+}
+
+/// Reads the body of a batch submission, which is parsed on a blocking thread
+/// while it arrives. At most one encoded transaction is buffered at a time,
+/// and the parsing stops as soon as the batch holds too many transactions.
+async fn read_transaction_batch(state: &mut State) -> Result<Vec<Vec<u8>>, Status> {
+    let mut body = Body::take_from(state);
+    let (sender, receiver) = mpsc::channel(BATCH_BODY_CHUNKS);
+    let parser = tokio::task::spawn_blocking(move || parse_transaction_batch(receiver));
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+        // the parser stops receiving once it fails
+        if sender.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    drop(sender);
+    parser
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e| Status::invalid_argument(format!("Malformed request body: {}", e)))
+}
+
+/// Reads and parses a JSON request body.
+async fn read_json<T: for<'de> Deserialize<'de>>(state: &mut State) -> Result<T, Status> {
+    let body = read_body(state, MAX_JSON_BODY_SIZE).await?;
+    serde_json::from_slice(&body)
+        .map_err(|e| Status::invalid_argument(format!("Malformed request body: {}", e)))
+}
+
+/// Dispatches a request message to the given method of the gRPC server and
+/// responds with the outcome.
+macro_rules! forward {
+    ($state:expr, $method:ident, $msg:expr) => {{
+        let server = GatewayStateData::borrow_from($state).server.clone();
+        let req = grpc_request($state, $msg);
+        let result = server.$method(req).await;
+        respond($state, result)
+    }};
+    // for methods responding with a stream
+    (stream $state:expr, $method:ident, $msg:expr) => {{
+        let server = GatewayStateData::borrow_from($state).server.clone();
+        let req = grpc_request($state, $msg);
+        let result = server.$method(req).await;
+        respond_stream($state, result)
+    }};
+}
+
+/// Unwraps the result of parsing the request, responding with the error if it
+/// failed.
+macro_rules! try_request {
+    ($state:expr, $e:expr) => {
+        match $e {
+            Ok(val) => val,
+            Err(status) => return Ok(error_response($state, status)),
+        }
+    };
+}
+
+fn required_block(query: BlockQuery) -> Result<String, Status> {
+    query.block.ok_or_else(|| Status::invalid_argument("Missing query parameter 'block'"))
+}
+
+async fn peer_version(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    forward!(state, peer_version, Empty {})
+}
+
+async fn peer_uptime(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    forward!(state, peer_uptime, Empty {})
+}
+
+async fn peer_total_received(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    forward!(state, peer_total_received, Empty {})
+}
+
+async fn peer_total_sent(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    forward!(state, peer_total_sent, Empty {})
+}
+
+async fn node_info(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    forward!(state, node_info, Empty {})
+}
+
+async fn shutdown(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let ShutdownQuery {
+        graceful,
+    } = ShutdownQuery::take_from(state);
+    forward!(state, shutdown, ShutdownRequest {
+        graceful
+    })
+}
+
+async fn create_snapshot(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let body: CreateSnapshotBody = try_request!(state, read_json(state).await);
+    forward!(stream state, create_snapshot, CreateSnapshotRequest {
+        path: body.path,
+    })
+}
+
+async fn peer_list(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let PeersQuery {
+        include_bootstrappers,
+    } = PeersQuery::take_from(state);
+    forward!(state, peer_list, PeersRequest {
+        include_bootstrappers
+    })
+}
+
+async fn peer_stats(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let PeersQuery {
+        include_bootstrappers,
+    } = PeersQuery::take_from(state);
+    forward!(state, peer_stats, PeersRequest {
+        include_bootstrappers
+    })
+}
+
+async fn get_peer_details(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let PeerPath {
+        id,
+    } = PeerPath::take_from(state);
+    forward!(state, get_peer_details, GetPeerDetailsRequest {
+        node_id: id,
+    })
+}
+
+async fn get_catch_up_status(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    forward!(state, get_catch_up_status, Empty {})
+}
+
+async fn catch_up_with_peer(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let PeerPath {
+        id,
+    } = PeerPath::take_from(state);
+    forward!(state, catch_up_with_peer, CatchUpPeerRequest {
+        node_id: id,
+    })
+}
+
+async fn reset_catch_up_peer(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let PeerPath {
+        id,
+    } = PeerPath::take_from(state);
+    forward!(state, reset_catch_up_peer, CatchUpPeerRequest {
+        node_id: id,
+    })
+}
+
+async fn pin_catch_up_peers(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let body: PinPeersBody = try_request!(state, read_json(state).await);
+    forward!(state, pin_catch_up_peers, PinCatchUpPeersRequest {
+        node_ids: body.node_ids,
+    })
+}
+
+async fn peer_connect(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let body: PeerAddressBody = try_request!(state, read_json(state).await);
+    forward!(state, peer_connect, PeerConnectRequest {
+        ip:   Some(body.ip),
+        port: Some(body.port.into()),
+    })
+}
+
+async fn peer_disconnect(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let body: PeerAddressBody = try_request!(state, read_json(state).await);
+    forward!(state, peer_disconnect, PeerConnectRequest {
+        ip:   Some(body.ip),
+        port: Some(body.port.into()),
+    })
+}
+
+async fn join_network(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let NetworkPath {
+        id,
+    } = NetworkPath::take_from(state);
+    forward!(state, join_network, NetworkChangeRequest {
+        network_id: Some(id),
+    })
+}
+
+async fn leave_network(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let NetworkPath {
+        id,
+    } = NetworkPath::take_from(state);
+    forward!(state, leave_network, NetworkChangeRequest {
+        network_id: Some(id),
+    })
+}
+
+async fn get_banned_peers(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    forward!(state, get_banned_peers, Empty {})
+}
+
+async fn ban_node(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let body: BanBody = try_request!(state, read_json(state).await);
+    forward!(state, ban_node, PeerElement {
+        node_id:        body.node_id,
+        ip:             body.ip,
+        port:           None,
+        catchup_status: peer_element::CatchupStatus::Pending as i32,
+    })
+}
+
+async fn unban_node(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let BanPath {
+        ip,
+    } = BanPath::take_from(state);
+    forward!(state, unban_node, PeerElement {
+        node_id:        None,
+        ip:             Some(ip),
+        port:           None,
+        catchup_status: peer_element::CatchupStatus::Pending as i32,
+    })
+}
+
+async fn dump_start(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let body: DumpBody = try_request!(state, read_json(state).await);
+    forward!(state, dump_start, DumpRequest {
+        file: body.file,
+        raw:  body.raw,
+    })
+}
+
+async fn dump_stop(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    forward!(state, dump_stop, Empty {})
+}
+
+async fn update_node_config(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let body = try_request!(state, read_body(state, MAX_JSON_BODY_SIZE).await);
+    let config = try_request!(
+        state,
+        String::from_utf8(body)
+            .map_err(|_| Status::invalid_argument("The request body must be valid UTF-8"))
+    );
+    forward!(state, update_node_config, UpdateNodeConfigRequest {
+        config
+    })
+}
+
+async fn get_consensus_status(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    forward!(state, get_consensus_status, Empty {})
+}
+
+async fn start_baker(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    forward!(state, start_baker, Empty {})
+}
+
+async fn stop_baker(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    forward!(state, stop_baker, Empty {})
+}
+
+async fn get_branches(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    forward!(state, get_branches, Empty {})
+}
+
+async fn get_blocks_at_height(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let HeightPath {
+        height,
+    } = HeightPath::take_from(state);
+    forward!(state, get_blocks_at_height, BlockHeight {
+        block_height: height,
+    })
+}
+
+async fn export_blocks(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let body: ExportBlocksBody = try_request!(state, read_json(state).await);
+    forward!(stream state, export_blocks, ExportBlocksRequest {
+        directory:       body.directory,
+        from_height:     body.from_height,
+        to_height:       body.to_height,
+        blocks_per_file: body.blocks_per_file,
+    })
+}
+
+async fn get_ancestors(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let BlockPath {
+        hash,
+    } = BlockPath::take_from(state);
+    let AncestorsQuery {
+        amount,
+    } = AncestorsQuery::take_from(state);
+    forward!(state, get_ancestors, BlockHashAndAmount {
+        block_hash: hash,
+        amount,
+    })
+}
+
+/// Generates a handler for a query that takes the block hash from the path.
+macro_rules! block_query {
+    ($name:ident) => {
+        async fn $name(state: &mut State) -> Result<Response<Body>, HandlerError> {
+            let BlockPath {
+                hash,
+            } = BlockPath::take_from(state);
+            forward!(state, $name, BlockHash {
+                block_hash: hash,
+            })
+        }
+    };
+}
+
+block_query!(get_block_info);
+block_query!(get_block_summary);
+block_query!(get_account_list);
+block_query!(get_instances);
+block_query!(get_reward_status);
+block_query!(get_birk_parameters);
+block_query!(get_module_list);
+block_query!(get_identity_providers);
+block_query!(get_anonymity_revokers);
+block_query!(get_cryptographic_parameters);
+
+/// Generates a handler for a paginated query that takes the block hash from the
+/// path and the page from the query string.
+macro_rules! page_query {
+    ($name:ident) => {
+        async fn $name(state: &mut State) -> Result<Response<Body>, HandlerError> {
+            let BlockPath {
+                hash,
+            } = BlockPath::take_from(state);
+            let PageQuery {
+                cursor,
+                limit,
+            } = PageQuery::take_from(state);
+            forward!(state, $name, PageRequest {
+                block_hash: hash,
+                cursor,
+                limit,
+            })
+        }
+    };
+}
+
+page_query!(get_account_list_page);
+page_query!(get_instances_page);
+
+async fn get_module_source(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let ModuleSourcePath {
+        hash,
+        module_ref,
+    } = ModuleSourcePath::take_from(state);
+    forward!(state, get_module_source, GetModuleSourceRequest {
+        block_hash: hash,
+        module_ref,
+    })
+}
+
+async fn get_transaction_status_in_block(
+    state: &mut State,
+) -> Result<Response<Body>, HandlerError> {
+    let TransactionInBlockPath {
+        hash,
+        transaction,
+    } = TransactionInBlockPath::take_from(state);
+    forward!(state, get_transaction_status_in_block, GetTransactionStatusInBlockRequest {
+        transaction_hash: transaction,
+        block_hash:       hash,
+    })
+}
+
+async fn get_account_info(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let AccountPath {
+        address,
+    } = AccountPath::take_from(state);
+    let block_hash = try_request!(state, required_block(BlockQuery::take_from(state)));
+    forward!(state, get_account_info, GetAddressInfoRequest {
+        block_hash,
+        address,
+    })
+}
+
+async fn get_account_non_finalized_transactions(
+    state: &mut State,
+) -> Result<Response<Body>, HandlerError> {
+    let AccountPath {
+        address,
+    } = AccountPath::take_from(state);
+    forward!(state, get_account_non_finalized_transactions, AccountAddress {
+        account_address: address,
+    })
+}
+
+async fn get_next_account_nonce(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let AccountPath {
+        address,
+    } = AccountPath::take_from(state);
+    forward!(state, get_next_account_nonce, AccountAddress {
+        account_address: address,
+    })
+}
+
+async fn get_instance_info(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let InstancePath {
+        index,
+        subindex,
+    } = InstancePath::take_from(state);
+    let block_hash = try_request!(state, required_block(BlockQuery::take_from(state)));
+    forward!(state, get_instance_info, GetAddressInfoRequest {
+        block_hash,
+        address: json!({ "index": index, "subindex": subindex }).to_string(),
+    })
+}
+
+async fn get_transaction_status(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let TransactionPath {
+        hash,
+    } = TransactionPath::take_from(state);
+    forward!(state, get_transaction_status, TransactionHash {
+        transaction_hash: hash,
+    })
+}
+
+async fn send_transaction(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let SendTransactionQuery {
+        network,
+    } = SendTransactionQuery::take_from(state);
+    let payload = try_request!(state, read_body(state, PROTOCOL_MAX_TRANSACTION_SIZE).await);
+    forward!(state, send_transaction_detailed, SendTransactionRequest {
+        network_id: network,
+        payload,
+    })
+}
+
+async fn send_transaction_batch(state: &mut State) -> Result<Response<Body>, HandlerError> {
+    let SendTransactionQuery {
+        network,
+    } = SendTransactionQuery::take_from(state);
+    let payloads = try_request!(state, read_transaction_batch(state).await);
+    forward!(state, send_transaction_batch, SendTransactionBatchRequest {
+        network_id: network,
+        payloads,
+    })
+}
+
+/// Builds the routes of the gateway, including those of the node
+/// administration endpoints if `admin` is set.
+fn router(server: RpcServerImpl, admin: bool) -> Router {
+    let middleware = StateMiddleware::new(GatewayStateData {
+        server,
+    });
+    let (chain, pipelines) = single_pipeline(single_middleware(middleware));
+    build_router(chain, pipelines, |route| {
+        route.scope("/v1", |route| {
+            route.get("/node/version").to_async_borrowing(peer_version);
+            route.get("/node/uptime").to_async_borrowing(peer_uptime);
+            route.get("/node/total-received").to_async_borrowing(peer_total_received);
+            route.get("/node/total-sent").to_async_borrowing(peer_total_sent);
+            route.get("/node/info").to_async_borrowing(node_info);
+
+            route
+                .get("/peers")
+                .with_query_string_extractor::<PeersQuery>()
+                .to_async_borrowing(peer_list);
+            route
+                .get("/peers/stats")
+                .with_query_string_extractor::<PeersQuery>()
+                .to_async_borrowing(peer_stats);
+            route
+                .get("/peers/:id/details")
+                .with_path_extractor::<PeerPath>()
+                .to_async_borrowing(get_peer_details);
+            route.get("/catch-up").to_async_borrowing(get_catch_up_status);
+            route.get("/bans").to_async_borrowing(get_banned_peers);
+
+            route.get("/consensus").to_async_borrowing(get_consensus_status);
+            route.get("/consensus/branches").to_async_borrowing(get_branches);
+
+            route
+                .get("/heights/:height/blocks")
+                .with_path_extractor::<HeightPath>()
+                .to_async_borrowing(get_blocks_at_height);
+            route
+                .get("/blocks/:hash")
+                .with_path_extractor::<BlockPath>()
+                .to_async_borrowing(get_block_info);
+            route
+                .get("/blocks/:hash/summary")
+                .with_path_extractor::<BlockPath>()
+                .to_async_borrowing(get_block_summary);
+            route
+                .get("/blocks/:hash/ancestors")
+                .with_path_extractor::<BlockPath>()
+                .with_query_string_extractor::<AncestorsQuery>()
+                .to_async_borrowing(get_ancestors);
+            route
+                .get("/blocks/:hash/accounts")
+                .with_path_extractor::<BlockPath>()
+                .to_async_borrowing(get_account_list);
+            route
+                .get("/blocks/:hash/instances")
+                .with_path_extractor::<BlockPath>()
+                .to_async_borrowing(get_instances);
+            route
+                .get("/blocks/:hash/accounts/page")
+                .with_path_extractor::<BlockPath>()
+                .with_query_string_extractor::<PageQuery>()
+                .to_async_borrowing(get_account_list_page);
+            route
+                .get("/blocks/:hash/instances/page")
+                .with_path_extractor::<BlockPath>()
+                .with_query_string_extractor::<PageQuery>()
+                .to_async_borrowing(get_instances_page);
+            route
+                .get("/blocks/:hash/reward-status")
+                .with_path_extractor::<BlockPath>()
+                .to_async_borrowing(get_reward_status);
+            route
+                .get("/blocks/:hash/birk-parameters")
+                .with_path_extractor::<BlockPath>()
+                .to_async_borrowing(get_birk_parameters);
+            route
+                .get("/blocks/:hash/modules")
+                .with_path_extractor::<BlockPath>()
+                .to_async_borrowing(get_module_list);
+            route
+                .get("/blocks/:hash/modules/:module_ref/source")
+                .with_path_extractor::<ModuleSourcePath>()
+                .to_async_borrowing(get_module_source);
+            route
+                .get("/blocks/:hash/identity-providers")
+                .with_path_extractor::<BlockPath>()
+                .to_async_borrowing(get_identity_providers);
+            route
+                .get("/blocks/:hash/anonymity-revokers")
+                .with_path_extractor::<BlockPath>()
+                .to_async_borrowing(get_anonymity_revokers);
+            route
+                .get("/blocks/:hash/cryptographic-parameters")
+                .with_path_extractor::<BlockPath>()
+                .to_async_borrowing(get_cryptographic_parameters);
+            route
+                .get("/blocks/:hash/transactions/:transaction")
+                .with_path_extractor::<TransactionInBlockPath>()
+                .to_async_borrowing(get_transaction_status_in_block);
+
+            route
+                .get("/accounts/:address")
+                .with_path_extractor::<AccountPath>()
+                .with_query_string_extractor::<BlockQuery>()
+                .to_async_borrowing(get_account_info);
+            route
+                .get("/accounts/:address/non-finalized-transactions")
+                .with_path_extractor::<AccountPath>()
+                .to_async_borrowing(get_account_non_finalized_transactions);
+            route
+                .get("/accounts/:address/next-nonce")
+                .with_path_extractor::<AccountPath>()
+                .to_async_borrowing(get_next_account_nonce);
+            route
+                .get("/instances/:index/:subindex")
+                .with_path_extractor::<InstancePath>()
+                .with_query_string_extractor::<BlockQuery>()
+                .to_async_borrowing(get_instance_info);
+
+            route
+                .get("/transactions/:hash")
+                .with_path_extractor::<TransactionPath>()
+                .to_async_borrowing(get_transaction_status);
+            route
+                .post("/transactions")
+                .with_query_string_extractor::<SendTransactionQuery>()
+                .to_async_borrowing(send_transaction);
+            route
+                .post("/transactions/batch")
+                .with_query_string_extractor::<SendTransactionQuery>()
+                .to_async_borrowing(send_transaction_batch);
+
+            // the node administration endpoints are only served if enabled explicitly
+            if admin {
+                route
+                    .post("/node/shutdown")
+                    .with_query_string_extractor::<ShutdownQuery>()
+                    .to_async_borrowing(shutdown);
+                route.post("/node/dump/start").to_async_borrowing(dump_start);
+                route.post("/node/dump/stop").to_async_borrowing(dump_stop);
+                route.post("/node/config").to_async_borrowing(update_node_config);
+                route.post("/node/snapshot").to_async_borrowing(create_snapshot);
+                route
+                    .post("/catch-up/peers/:id/start")
+                    .with_path_extractor::<PeerPath>()
+                    .to_async_borrowing(catch_up_with_peer);
+                route
+                    .post("/catch-up/peers/:id/reset")
+                    .with_path_extractor::<PeerPath>()
+                    .to_async_borrowing(reset_catch_up_peer);
+                route.put("/catch-up/pinned").to_async_borrowing(pin_catch_up_peers);
+                route.post("/peers/connect").to_async_borrowing(peer_connect);
+                route.post("/peers/disconnect").to_async_borrowing(peer_disconnect);
+                route.post("/bans").to_async_borrowing(ban_node);
+                route
+                    .delete("/bans/:ip")
+                    .with_path_extractor::<BanPath>()
+                    .to_async_borrowing(unban_node);
+                route
+                    .post("/networks/:id/join")
+                    .with_path_extractor::<NetworkPath>()
+                    .to_async_borrowing(join_network);
+                route
+                    .post("/networks/:id/leave")
+                    .with_path_extractor::<NetworkPath>()
+                    .to_async_borrowing(leave_network);
+                route.post("/baker/start").to_async_borrowing(start_baker);
+                route.post("/baker/stop").to_async_borrowing(stop_baker);
+                route.post("/blocks/export").to_async_borrowing(export_blocks);
+            }
+        });
+    })
+}
+
+/// Starts the HTTP/JSON gateway, serving requests with the given gRPC server.
+/// This returns once the gateway is listening, or with an error if the address
+/// can't be bound; requests are then served on a background task. The node
+/// administration endpoints are only served if `admin` is set.
+pub async fn start_http_gateway(
+    server: RpcServerImpl,
+    listen_addr: SocketAddr,
+    admin: bool,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(listen_addr)
+        .await
+        .with_context(|| format!("Can't bind the HTTP gateway to {}", listen_addr))?;
+    tokio::spawn(gotham::bind_server(listener, router(server, admin), |socket| {
+        Box::pin(async move { Ok(socket) })
+    }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{http_status, start_http_gateway, AUTHENTICATION_HEADER, MAX_BATCH_ELEMENT_SIZE};
+    use crate::{
+        common::{get_current_stamp, PeerType},
+        consensus_ffi::{consensus::ConsensusType, helpers::PacketType, mock::MockConsensus},
+        rpc::RpcServerImpl,
+        test_utils::{
+            dummy_account_transaction, dummy_regenesis_blocks, get_test_config, make_node_and_sync,
+            next_available_port, stop_node_delete_dirs,
+        },
+    };
+    use hyper::StatusCode;
+    use serde_json::{json, Value};
+    use std::{net::SocketAddr, sync::Arc};
+    use tonic::Code;
+
+    const TOKEN: &str = "rpcadmin";
+
+    #[test]
+    fn test_consensus_unavailable_mapping() {
+        // the codes used by `call_consensus!` when consensus is not running
+        assert_eq!(http_status(Code::Internal), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(http_status(Code::FailedPrecondition), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(http_status(Code::Unauthenticated), StatusCode::UNAUTHORIZED);
+        assert_eq!(http_status(Code::InvalidArgument), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_gateway_routes() -> anyhow::Result<()> {
+        let consensus = Arc::new(MockConsensus::new(ConsensusType::Active));
+        let (node, dp) = make_node_and_sync(
+            next_available_port(),
+            vec![100],
+            PeerType::Node,
+            dummy_regenesis_blocks(),
+        )
+        .unwrap();
+        let mut config = get_test_config(8888, vec![100]);
+        config.cli.rpc.rpc_server_addr = "127.0.0.1".to_owned();
+        config.cli.rpc.rpc_server_token = TOKEN.to_owned();
+        let server = RpcServerImpl::new(node.clone(), Some(consensus.clone()), &config.cli.rpc)?;
+        let port = next_available_port();
+        start_http_gateway(server, SocketAddr::from(([127, 0, 0, 1], port)), false).await?;
+        let url = |path: &str| format!("http://127.0.0.1:{}/v1{}", port, path);
+        let client = reqwest::Client::new();
+
+        let info =
+            client.get(&url("/node/info")).header(AUTHENTICATION_HEADER, TOKEN).send().await?;
+        assert_eq!(info.status(), StatusCode::OK);
+        let info: Value = info.json().await?;
+        assert_eq!(info["consensusRunning"], json!(true));
+        assert_eq!(info["consensusType"], json!("Active"));
+
+        // the access token is checked as for gRPC
+        let unauthenticated = client.get(&url("/node/info")).send().await?;
+        assert_eq!(unauthenticated.status(), StatusCode::UNAUTHORIZED);
+        let error: Value = unauthenticated.json().await?;
+        assert_eq!(error["code"], json!(format!("{:?}", Code::Unauthenticated)));
+
+        let transaction = dummy_account_transaction(64, 10, get_current_stamp() / 1000 + 60);
+        let sent = client
+            .post(&url("/transactions?network=100"))
+            .header(AUTHENTICATION_HEADER, TOKEN)
+            .body(transaction.clone())
+            .send()
+            .await?;
+        assert_eq!(sent.status(), StatusCode::OK);
+        let sent: Value = sent.json().await?;
+        assert_eq!(sent["outcome"], json!("Success"));
+        assert!(!sent["transactionHash"].as_str().unwrap().is_empty());
+        assert_eq!(consensus.received(), vec![(PacketType::Transaction, transaction.clone())]);
+
+        let batch = client
+            .post(&url("/transactions/batch?network=100"))
+            .header(AUTHENTICATION_HEADER, TOKEN)
+            .json(&json!({ "transactions": [hex::encode(&transaction)] }))
+            .send()
+            .await?;
+        assert_eq!(batch.status(), StatusCode::OK);
+        let outcomes: Value = batch.json().await?;
+        assert_eq!(outcomes.as_array().map(Vec::len), Some(1));
+
+        // batches with too many or too large transactions are rejected while
+        // their body is read
+        for transactions in vec![vec![hex::encode(&transaction); 1001], vec![
+            "00".repeat(MAX_BATCH_ELEMENT_SIZE / 2)
+        ]] {
+            let rejected = client
+                .post(&url("/transactions/batch?network=100"))
+                .header(AUTHENTICATION_HEADER, TOKEN)
+                .json(&json!({ "transactions": transactions }))
+                .send()
+                .await?;
+            assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(consensus.received().len(), 2);
+
+        // the administration endpoints are only served if enabled
+        let shutdown =
+            client.post(&url("/node/shutdown")).header(AUTHENTICATION_HEADER, TOKEN).send().await?;
+        assert_eq!(shutdown.status(), StatusCode::NOT_FOUND);
+
+        stop_node_delete_dirs(dp, node);
+        Ok(())
+    }
+}
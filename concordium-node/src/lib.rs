@@ -5,14 +5,12 @@ extern crate log;
 #[macro_use]
 extern crate lazy_static;
 
-cfg_if! {
-    if #[cfg(feature = "instrumentation")] {
-        #[macro_use]
-        extern crate prometheus;
-        #[macro_use]
-        extern crate gotham_derive;
-    }
-}
+#[cfg(feature = "instrumentation")]
+#[macro_use]
+extern crate prometheus;
+#[cfg(any(feature = "instrumentation", feature = "http_gateway"))]
+#[macro_use]
+extern crate gotham_derive;
 
 #[macro_use]
 extern crate cfg_if;
@@ -26,6 +24,7 @@ pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 /// Client's name.
 pub const APPNAME: &str = env!("CARGO_PKG_NAME");
 
+pub mod baker_credentials;
 pub mod common;
 pub mod configuration;
 
@@ -37,7 +36,17 @@ pub mod plugins;
 
 #[cfg(feature = "network_dump")]
 pub mod dumper;
+#[cfg(feature = "http_gateway")]
+pub mod http_gateway;
 pub mod rpc;
+#[cfg(unix)]
+pub mod rpc_admin_socket;
+pub mod rpc_audit;
+pub mod rpc_jobs;
+pub mod rpc_limits;
+pub mod rpc_query_cache;
+pub mod rpc_query_pool;
+pub mod snapshot;
 pub mod stats_export_service;
 pub mod utils;
 
@@ -0,0 +1,163 @@
+//! Flow control from the inbound consensus queues to the network reads.
+//!
+//! When consensus can't keep up with the packets received from the network,
+//! the inbound queues fill up and the packets that don't fit in them are
+//! dropped. To avoid that, the node stops reading from its connections once a
+//! queue passes the high-water mark, which leaves the packets in the socket
+//! buffers (and eventually makes the peers slow down), and resumes reading once
+//! the queue has drained to half of the mark. If only the low priority queue,
+//! which holds transactions, is above the mark, the node keeps reading from the
+//! connections that mostly send it blocks and finalization messages.
+
+use crate::{connection::Connection, consensus_ffi::consensus::CALLBACK_QUEUE};
+use std::sync::atomic::{AtomicU8, Ordering};
+#[cfg(any(test, feature = "test_utils"))]
+use {crate::lock_or_die, std::sync::Mutex};
+
+/// The connections that the node does not read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadThrottle {
+    /// The node reads from all the connections.
+    None = 0,
+    /// The node does not read from the connections that mostly send it
+    /// transactions.
+    LowValue,
+    /// The node does not read from any connection.
+    All,
+}
+
+impl ReadThrottle {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => ReadThrottle::None,
+            1 => ReadThrottle::LowValue,
+            _ => ReadThrottle::All,
+        }
+    }
+
+    /// The throttle that follows this one, given the fractions of the high
+    /// and low priority queues in use.
+    fn next(self, high_usage: f64, low_usage: f64, high_water_mark: f64) -> Self {
+        // a queue that caused reads to be throttled must drain to half of the
+        // mark before they are resumed
+        let above = |usage: f64, throttled: bool| {
+            usage >= high_water_mark || (throttled && usage > high_water_mark / 2.0)
+        };
+        if above(high_usage, self == ReadThrottle::All) {
+            ReadThrottle::All
+        } else if above(low_usage, self != ReadThrottle::None) {
+            ReadThrottle::LowValue
+        } else {
+            ReadThrottle::None
+        }
+    }
+
+    /// Whether the node should not read from the given connection. Reads
+    /// from connections that have not completed the handshake are never
+    /// deferred, since they don't send packets yet.
+    pub fn defers(self, conn: &Connection) -> bool {
+        if !conn.is_post_handshake() {
+            return false;
+        }
+        match self {
+            ReadThrottle::None => false,
+            ReadThrottle::LowValue => conn.stats.mostly_sends_transactions(),
+            ReadThrottle::All => true,
+        }
+    }
+}
+
+/// The state of the flow control.
+pub struct Backpressure {
+    /// The fraction of the capacity of an inbound queue above which reads are
+    /// throttled.
+    high_water_mark: f64,
+    throttle:        AtomicU8,
+    /// The queue usage reported instead of the actual one, so that tests can
+    /// simulate full queues.
+    #[cfg(any(test, feature = "test_utils"))]
+    simulated_usage: Mutex<Option<(f64, f64)>>,
+}
+
+impl Backpressure {
+    /// Creates the flow control with the high-water mark given as a
+    /// percentage of the capacity of the queues.
+    pub fn new(high_water_mark: u8) -> Self {
+        Backpressure {
+            high_water_mark: f64::from(high_water_mark) / 100.0,
+            throttle: AtomicU8::new(ReadThrottle::None as u8),
+            #[cfg(any(test, feature = "test_utils"))]
+            simulated_usage: Mutex::new(None),
+        }
+    }
+
+    /// Makes the flow control act as if the high and low priority queues had
+    /// the given usage, or the actual one if `None`.
+    #[cfg(any(test, feature = "test_utils"))]
+    pub fn simulate_usage(&self, usage: Option<(f64, f64)>) {
+        *lock_or_die!(self.simulated_usage) = usage;
+    }
+
+    /// The fractions of the high and low priority inbound queues in use.
+    fn usage(&self) -> (f64, f64) {
+        #[cfg(any(test, feature = "test_utils"))]
+        {
+            if let Some(usage) = *lock_or_die!(self.simulated_usage) {
+                return usage;
+            }
+        }
+        CALLBACK_QUEUE.inbound.usage()
+    }
+
+    /// The current throttle.
+    pub fn current(&self) -> ReadThrottle {
+        ReadThrottle::from_u8(self.throttle.load(Ordering::Relaxed))
+    }
+
+    /// Updates the throttle from the current usage of the inbound consensus
+    /// queues and returns it.
+    pub fn update(&self) -> ReadThrottle {
+        let (high_usage, low_usage) = self.usage();
+        let current = self.current();
+        let next = current.next(high_usage, low_usage, self.high_water_mark);
+        if next != current
+            && self
+                .throttle
+                .compare_exchange(current as u8, next as u8, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            match next {
+                ReadThrottle::None => info!("The inbound consensus queues drained; resuming reads"),
+                ReadThrottle::LowValue => warn!(
+                    "The inbound transaction queue is {:.0}% full; pausing reads from peers that \
+                     mostly send transactions",
+                    low_usage * 100.0
+                ),
+                ReadThrottle::All => warn!(
+                    "The inbound consensus queue is {:.0}% full; pausing reads from all peers",
+                    high_usage * 100.0
+                ),
+            }
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReadThrottle::*, *};
+
+    #[test]
+    fn test_read_throttle_hysteresis() {
+        let mark = 0.8;
+        assert_eq!(None.next(0.7, 0.7, mark), None);
+        assert_eq!(None.next(0.7, 0.8, mark), LowValue);
+        assert_eq!(None.next(0.9, 0.1, mark), All);
+        // reads stay throttled until the queue drained to half of the mark
+        assert_eq!(All.next(0.5, 0.1, mark), All);
+        assert_eq!(All.next(0.4, 0.5, mark), LowValue);
+        assert_eq!(All.next(0.4, 0.4, mark), None);
+        assert_eq!(LowValue.next(0.5, 0.5, mark), LowValue);
+        assert_eq!(LowValue.next(0.5, 0.3, mark), None);
+    }
+}
@@ -7,7 +7,7 @@ use crypto_common::{Buffer, Deserial, Serial};
 use rkv::{StoreOptions, Value};
 use std::net::{IpAddr, SocketAddr};
 
-const BAN_STORE_NAME: &str = "bans";
+pub(crate) const BAN_STORE_NAME: &str = "bans";
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 /// A node can be banned either by its IP or
@@ -188,14 +188,14 @@ impl P2PNode {
         let peers_to_skip = match inner_pkt.destination {
             PacketDestination::Direct(..) => vec![],
             PacketDestination::Broadcast(ref dont_relay_to) => {
-                if self.config.relay_broadcast_percentage < 1.0 {
+                let relay_broadcast_percentage = self.live_config().relay_broadcast_percentage;
+                if relay_broadcast_percentage < 1.0 {
                     use rand::seq::SliceRandom;
                     let mut rng = rand::thread_rng();
                     let mut peers = self.get_node_peer_tokens();
                     peers.retain(|token| !dont_relay_to.contains(&token));
-                    let peers_to_take = f64::floor(
-                        f64::from(peers.len() as u32) * self.config.relay_broadcast_percentage,
-                    );
+                    let peers_to_take =
+                        f64::floor(f64::from(peers.len() as u32) * relay_broadcast_percentage);
                     peers
                         .choose_multiple(&mut rng, peers_to_take as usize)
                         .copied()
@@ -259,7 +259,16 @@ impl P2PNode {
                     return;
                 }
 
-                if events.iter().any(|event| event.token() == conn.token() && event.is_readable()) {
+                // Since the events are edge-triggered, connections whose reads were deferred
+                // are read from once the inbound consensus queues drain, even without new
+                // events.
+                let readable = conn.read_deferred
+                    || events
+                        .iter()
+                        .any(|event| event.token() == conn.token() && event.is_readable());
+                if readable && self.backpressure.current().defers(conn) {
+                    conn.read_deferred = true;
+                } else if readable {
                     match conn.read_stream(&conn_stats) {
                         Err(e) => {
                             error!("[receiving from {}] {}", conn, e);
@@ -447,12 +456,9 @@ pub fn connect(
 
     if respect_max_peers && peer_type == PeerType::Node {
         let current_peer_count = node.get_peer_stats(Some(PeerType::Node)).len() as u16;
-        if current_peer_count >= node.config.max_allowed_nodes {
-            bail!(
-                "Maximum number of peers reached {}/{}",
-                current_peer_count,
-                node.config.max_allowed_nodes
-            );
+        let max_allowed_nodes = node.live_config().max_allowed_nodes;
+        if current_peer_count >= max_allowed_nodes {
+            bail!("Maximum number of peers reached {}/{}", current_peer_count, max_allowed_nodes);
         }
     }
 
@@ -530,8 +536,9 @@ pub fn connection_housekeeping(node: &Arc<P2PNode>) -> bool {
     let curr_stamp = get_current_stamp();
     let peer_type = node.peer_type();
 
+    let max_latency = node.live_config().max_latency;
     let is_conn_faulty = |conn: &Connection| -> bool {
-        if let Some(max_latency) = node.config.max_latency {
+        if let Some(max_latency) = max_latency {
             conn.get_latency() >= max_latency
         } else {
             false
@@ -571,7 +578,7 @@ pub fn connection_housekeeping(node: &Arc<P2PNode>) -> bool {
     // if the number of peers exceeds the desired value, close a random selection of
     // post-handshake non-given connections to lower it
     if peer_type == PeerType::Node {
-        let max_allowed_nodes = node.config.max_allowed_nodes;
+        let max_allowed_nodes = node.live_config().max_allowed_nodes;
         let peer_count = node.get_peer_stats(Some(PeerType::Node)).len() as u16;
         if peer_count > max_allowed_nodes {
             // drop connections to any non-given peers.
@@ -618,6 +625,9 @@ pub fn connection_housekeeping(node: &Arc<P2PNode>) -> bool {
     for (peer_id, dropped) in lock_or_die!(node.bad_events.dropped_low_queue).drain() {
         warn!("Dropped {} low priority messages from peer {}.", dropped, peer_id);
     }
+    for (peer_id, expired) in lock_or_die!(node.bad_events.expired_transactions).drain() {
+        debug!("Dropped {} expired transactions from peer {}.", expired, peer_id);
+    }
 
     // Reconnect to bootstrappers after a specified amount of time.
     // It's unclear whether we should always be doing this, even if we have enough
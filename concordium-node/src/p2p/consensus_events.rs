@@ -0,0 +1,187 @@
+//! Notifications about regenesis and protocol updates.
+//!
+//! Consensus registers a new genesis block in the node's `regenesis_arc` when
+//! a protocol update takes effect. The poll loop picks it up, updates the
+//! genesis metrics, publishes a [`ConsensusEvent`] to the subscribers (e.g.,
+//! the `StreamConsensusEvents` RPC), and drops the peers that advertised a
+//! different genesis block in their handshake, so they can connect again on
+//! the new chain. Protocol updates scheduled on the chain are picked up from
+//! the consensus status during housekeeping.
+
+use crate::{
+    common::get_current_stamp,
+    consensus_ffi::{blockchain_types::BlockHash, consensus::Consensus},
+    p2p::P2PNode,
+    read_or_die, write_or_die,
+};
+use mio::Token;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// How many events are buffered for a subscriber that doesn't keep up.
+pub const CONSENSUS_EVENTS_CAPACITY: usize = 16;
+
+/// An event concerning the chain the node is on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum ConsensusEvent {
+    /// A new genesis block was registered by consensus.
+    #[serde(rename_all = "camelCase")]
+    Regenesis {
+        genesis_index: usize,
+        genesis_block: BlockHash,
+        /// When the node learned about it, in milliseconds since the Unix
+        /// epoch.
+        timestamp:     u64,
+    },
+    /// The protocol update reported by consensus changed. It is `null` if no
+    /// protocol update is scheduled.
+    #[serde(rename_all = "camelCase")]
+    ProtocolUpdate {
+        protocol_update: serde_json::Value,
+        timestamp:       u64,
+    },
+}
+
+impl P2PNode {
+    /// Subscribes to the events published from now on.
+    pub fn subscribe_consensus_events(&self) -> broadcast::Receiver<ConsensusEvent> {
+        self.consensus_events.subscribe()
+    }
+
+    /// The genesis blocks of the chain, starting with the original one.
+    pub fn genesis_blocks(&self) -> Vec<BlockHash> {
+        read_or_die!(self.config.regenesis_arc).clone()
+    }
+
+    /// Processes the genesis blocks registered beyond the first `known` ones
+    /// and returns the number of genesis blocks now known. With `known` being
+    /// 0, this only sets the metrics and doesn't drop any peers.
+    pub fn process_regenesis(&self, known: usize) -> usize {
+        // this runs on every poll, so the blocks are only copied if there are new ones
+        let blocks = {
+            let blocks = read_or_die!(self.config.regenesis_arc);
+            if blocks.len() <= known {
+                return known;
+            }
+            blocks.clone()
+        };
+        let timestamp = get_current_stamp();
+        for (genesis_index, genesis_block) in blocks.iter().enumerate().skip(known) {
+            if known > 0 {
+                info!("Regenesis: genesis block {} at index {}", genesis_block, genesis_index);
+            }
+            // there may be no subscribers
+            let _ = self.consensus_events.send(ConsensusEvent::Regenesis {
+                genesis_index,
+                genesis_block: genesis_block.clone(),
+                timestamp,
+            });
+        }
+        if let Some(last) = blocks.last() {
+            self.stats.set_genesis_block(blocks.len() - 1, last);
+        }
+        if known > 0 {
+            self.drop_peers_on_other_chains(&blocks);
+        }
+        blocks.len()
+    }
+
+    /// The protocol update consensus reported last, or `None` if its status
+    /// hasn't been read yet.
+    pub fn protocol_update(&self) -> Option<serde_json::Value> {
+        read_or_die!(self.protocol_update).clone()
+    }
+
+    /// Publishes the protocol update in the consensus status if it differs
+    /// from the one reported last, which is then updated.
+    pub fn process_protocol_update(&self, consensus: &dyn Consensus) {
+        let status: serde_json::Value =
+            match serde_json::from_str(&consensus.get_consensus_status()) {
+                Ok(status) => status,
+                Err(e) => {
+                    warn!("Can't parse the consensus status: {}", e);
+                    return;
+                }
+            };
+        let protocol_update = status["protocolUpdate"].clone();
+        let mut known = write_or_die!(self.protocol_update);
+        if known.as_ref() == Some(&protocol_update) {
+            return;
+        }
+        if !protocol_update.is_null() {
+            info!("Protocol update: {}", protocol_update);
+        }
+        let _ = self.consensus_events.send(ConsensusEvent::ProtocolUpdate {
+            protocol_update: protocol_update.clone(),
+            timestamp:       get_current_stamp(),
+        });
+        *known = Some(protocol_update);
+    }
+
+    /// Drops the connections to peers whose genesis blocks, as advertised in
+    /// their handshake, are incompatible with `ours`. They are not banned,
+    /// so they can connect again once they are on the same chain.
+    fn drop_peers_on_other_chains(&self, ours: &[BlockHash]) {
+        let to_drop = read_or_die!(self.connections())
+            .iter()
+            .filter_map(|(&token, conn)| {
+                let (i, _, theirs) = genesis_mismatch(ours, &conn.genesis_blocks)?;
+                warn!(
+                    "Dropping {}, which is on a different chain (genesis block {} at index {})",
+                    conn, theirs, i
+                );
+                Some(token)
+            })
+            .collect::<Vec<Token>>();
+        self.remove_connections(&to_drop);
+    }
+}
+
+/// Finds the first position at which two lists of genesis blocks differ,
+/// returning it with our and their genesis block. The lists are compatible if
+/// one is a prefix of the other.
+pub fn genesis_mismatch<'a>(
+    ours: &'a [BlockHash],
+    theirs: &'a [BlockHash],
+) -> Option<(usize, &'a BlockHash, &'a BlockHash)> {
+    ours.iter()
+        .zip(theirs.iter())
+        .enumerate()
+        .find(|(_, (a, b))| a != b)
+        .map(|(i, (a, b))| (i, a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genesis_mismatch() {
+        let blocks = (0..3u8).map(|i| BlockHash::from([i; 32])).collect::<Vec<_>>();
+        assert_eq!(genesis_mismatch(&blocks, &blocks), None);
+        assert_eq!(genesis_mismatch(&blocks, &blocks[..1]), None);
+        assert_eq!(genesis_mismatch(&blocks[..2], &blocks), None);
+        assert_eq!(genesis_mismatch(&blocks, &[]), None);
+        let other = [blocks[0].clone(), blocks[2].clone()];
+        assert_eq!(genesis_mismatch(&blocks, &other), Some((1, &blocks[1], &blocks[2])));
+    }
+
+    #[test]
+    fn test_consensus_event_json() {
+        let event = ConsensusEvent::Regenesis {
+            genesis_index: 1,
+            genesis_block: BlockHash::from([1; 32]),
+            timestamp:     5,
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "event": "regenesis",
+                "genesisIndex": 1,
+                "genesisBlock": "01".repeat(32),
+                "timestamp": 5,
+            })
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Draining the node before shutting it down.
+//!
+//! A drain is triggered by `SIGTERM` or by the `Shutdown` RPC with the
+//! `graceful` flag. It rejects new transactions submitted over RPC, lets the
+//! baker finish the current slot, and waits until the blocks, finalization
+//! messages and transactions that consensus produced have been written to the
+//! peers, before closing the node. It never takes longer than the configured
+//! timeout.
+
+use crate::{
+    common::get_current_stamp,
+    consensus_ffi::consensus::{Consensus, CALLBACK_QUEUE},
+    p2p::P2PNode,
+    read_or_die,
+};
+use chrono::DateTime;
+use std::{
+    sync::atomic::Ordering,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often the queues are checked while draining.
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+impl P2PNode {
+    /// Whether the node is draining before shutting down.
+    pub fn is_draining(&self) -> bool { self.is_draining.load(Ordering::SeqCst) }
+
+    /// Marks the node as draining, so that new transactions are rejected from
+    /// now on. Returns whether this call started the drain; only then should
+    /// the caller go on with [`P2PNode::close_after_drain`].
+    pub fn start_draining(&self) -> bool { !self.is_draining.swap(true, Ordering::SeqCst) }
+
+    /// Whether closing the node after a drain failed. A drain started by the
+    /// `Shutdown` RPC runs on a thread of its own, so the binary checks this
+    /// once the node has stopped, to exit with an error.
+    pub fn drain_failed(&self) -> bool { self.drain_failed.load(Ordering::SeqCst) }
+
+    /// Starts draining the node and then closes it like
+    /// [`P2PNode::close_after_drain`]. If the node is already draining, this
+    /// only returns `true`.
+    pub fn drain_and_close(&self, consensus: Option<&dyn Consensus>) -> bool {
+        if !self.start_draining() {
+            info!("The node is already draining");
+            return true;
+        }
+        self.close_after_drain(consensus)
+    }
+
+    /// Drains a node that [`P2PNode::start_draining`] was called on and then
+    /// closes it like [`P2PNode::close`]. This blocks for at most the
+    /// configured drain timeout, plus the time needed to stop the baker. The
+    /// drain ends early if the node is closed in the meantime. If the node
+    /// can't be closed properly, this is also recorded for
+    /// [`P2PNode::drain_failed`].
+    pub fn close_after_drain(&self, consensus: Option<&dyn Consensus>) -> bool {
+        let timeout = Duration::from_secs(self.config.shutdown_drain_timeout);
+        info!("Draining the node before shutting down (for at most {}s)", timeout.as_secs());
+        let deadline = Instant::now() + timeout;
+
+        if let Some(consensus) = consensus {
+            if consensus.is_baking() {
+                if let Some(wait) =
+                    time_to_slot_end(&consensus.get_consensus_status(), get_current_stamp())
+                {
+                    debug!("Waiting {}ms for the current slot to end", wait.as_millis());
+                    thread::sleep(wait.min(deadline.saturating_duration_since(Instant::now())));
+                }
+                consensus.stop_baker();
+            }
+        }
+
+        // A message is briefly in neither queue while it is moved from the consensus
+        // queue to the connections, so both have to be empty twice in a row.
+        let mut empty_checks = 0;
+        while empty_checks < 2 && Instant::now() < deadline {
+            if self.is_terminated.load(Ordering::Relaxed) {
+                info!("The node was closed while draining");
+                return true;
+            }
+            if self.has_pending_output() {
+                empty_checks = 0;
+            } else {
+                empty_checks += 1;
+            }
+            thread::sleep(DRAIN_CHECK_INTERVAL);
+        }
+        if empty_checks < 2 {
+            warn!("The drain timed out; some messages to peers may be lost");
+        } else {
+            info!("The node is drained");
+        }
+        let closed = self.close();
+        if !closed {
+            self.drain_failed.store(true, Ordering::SeqCst);
+        }
+        closed
+    }
+
+    /// Whether there are messages from consensus, or to peers, that have not
+    /// been sent yet.
+    fn has_pending_output(&self) -> bool {
+        let outbound = &CALLBACK_QUEUE.outbound;
+        if !outbound.sender_high_priority.is_empty() || !outbound.sender_low_priority.is_empty() {
+            return true;
+        }
+        read_or_die!(self.connections()).values().any(|conn| {
+            !conn.pending_messages.high.is_empty()
+                || !conn.pending_messages.low.is_empty()
+                || conn.low_level.pending_output_bytes() > 0
+        })
+    }
+}
+
+/// The time from `now` (in milliseconds since the Unix epoch) until the end
+/// of the current slot, based on the genesis time and the slot duration in the
+/// consensus status.
+fn time_to_slot_end(status: &str, now: u64) -> Option<Duration> {
+    let status: serde_json::Value = serde_json::from_str(status).ok()?;
+    let genesis_time = DateTime::parse_from_rfc3339(status["genesisTime"].as_str()?).ok()?;
+    let slot_duration = status["slotDuration"].as_u64().filter(|&duration| duration > 0)?;
+    let since_genesis = now.checked_sub(genesis_time.timestamp_millis() as u64)?;
+    Some(Duration::from_millis(slot_duration - since_genesis % slot_duration))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_to_slot_end() {
+        let status = r#"{"genesisTime":"2021-05-01T00:00:00Z","slotDuration":250}"#;
+        let genesis = 1_619_827_200_000;
+        assert_eq!(time_to_slot_end(status, genesis + 1_000), Some(Duration::from_millis(250)));
+        assert_eq!(time_to_slot_end(status, genesis + 1_100), Some(Duration::from_millis(150)));
+        // before genesis
+        assert_eq!(time_to_slot_end(status, genesis - 1), None);
+        assert_eq!(time_to_slot_end("null", genesis), None);
+    }
+}
@@ -20,14 +20,17 @@ use crate::{
     consensus_ffi::{
         blockchain_types::BlockHash,
         catch_up::PeerList,
-        consensus::{ConsensusContainer, CALLBACK_QUEUE},
+        consensus::{Consensus, CALLBACK_QUEUE},
     },
     lock_or_die,
     network::{Buckets, NetworkId, Networks},
     p2p::{
+        backpressure::Backpressure,
         bans::BanId,
         connectivity::{accept, connect, connection_housekeeping, AcceptFailureReason, SELF_TOKEN},
+        consensus_events::{ConsensusEvent, CONSENSUS_EVENTS_CAPACITY},
         peers::check_peers,
+        reconfiguration::LiveConfig,
     },
     plugins::consensus::{check_peer_states, update_peer_list},
     read_or_die, spawn_or_die,
@@ -52,11 +55,11 @@ use std::{
     thread::JoinHandle,
     time::{Duration, Instant},
 };
+use tokio::sync::broadcast;
 
 /// Configuration bits applicable to a node.
 pub struct NodeConfig {
     pub no_net: bool,
-    pub desired_nodes_count: u16,
     pub no_bootstrap_dns: bool,
     /// Do not clear persistent bans on startup.
     pub no_clear_bans: bool,
@@ -71,18 +74,24 @@ pub struct NodeConfig {
     /// are resolved on startup or when they are added and during execution
     /// we only keep them instead of the domain name.
     pub given_addresses: RwLock<HashSet<SocketAddr>>,
-    pub max_allowed_nodes: u16,
-    pub relay_broadcast_percentage: f64,
+    /// The parameters that can be changed while the node is running.
+    pub live: RwLock<LiveConfig>,
     pub poll_interval: u64,
-    pub housekeeping_interval: u64,
+    /// The maximum time (in seconds) spent draining the node before it
+    /// shuts down.
+    pub shutdown_drain_timeout: u64,
     pub bootstrapping_interval: u64,
     pub print_peers: bool,
     pub bootstrapper_wait_minimum_peers: u16,
     pub data_dir_path: PathBuf,
-    pub max_latency: Option<u64>,
+    pub config_dir_path: PathBuf,
     pub hard_connection_limit: u16,
     pub conn_requests_batch_limit: u16,
     pub catch_up_batch_limit: i64,
+    pub catch_up_parallelism: usize,
+    /// The time (in milliseconds) after which a catch-up session that does
+    /// not make progress times out.
+    pub catch_up_timeout: u64,
     pub timeout_bucket_entry_period: u64,
     pub bucket_cleanup_interval: u64,
     pub thread_pool_size: usize,
@@ -209,12 +218,15 @@ impl NetworkDumper {
 pub struct BadEvents {
     /// Number of high priority messages that were dropped because they could
     /// not be enqueued.
-    pub dropped_high_queue: Mutex<HashMap<RemotePeerId, u64>>,
+    pub dropped_high_queue:   Mutex<HashMap<RemotePeerId, u64>>,
     /// Number of low priority messages that were dropped because they could not
     /// be enqueued.
-    pub dropped_low_queue:  Mutex<HashMap<RemotePeerId, u64>>,
+    pub dropped_low_queue:    Mutex<HashMap<RemotePeerId, u64>>,
     /// Number of invalid messages received from the given peer.
-    pub invalid_messages:   Mutex<HashMap<RemotePeerId, u64>>,
+    pub invalid_messages:     Mutex<HashMap<RemotePeerId, u64>>,
+    /// Number of transactions received from the given peer that had already
+    /// expired.
+    pub expired_transactions: Mutex<HashMap<RemotePeerId, u64>>,
 }
 
 impl BadEvents {
@@ -235,6 +247,12 @@ impl BadEvents {
     pub fn inc_invalid_messages(&self, peer_id: RemotePeerId) -> u64 {
         *lock_or_die!(self.invalid_messages).entry(peer_id).and_modify(|x| *x += 1).or_insert(1)
     }
+
+    /// Register a new expired transaction for the given peer and return the
+    /// amount of expired transactions that were received.
+    pub fn inc_expired_transactions(&self, peer_id: RemotePeerId) -> u64 {
+        *lock_or_die!(self.expired_transactions).entry(peer_id).and_modify(|x| *x += 1).or_insert(1)
+    }
 }
 
 /// The central object belonging to a node in the network; it handles
@@ -254,6 +272,14 @@ pub struct P2PNode {
     pub start_time:         DateTime<Utc>,
     /// The flag indicating whether a node should shut down.
     pub is_terminated:      AtomicBool,
+    /// The flag indicating whether the node is draining before it shuts down.
+    pub is_draining:        AtomicBool,
+    /// Set if the node couldn't be closed properly after a drain.
+    pub drain_failed:       AtomicBool,
+    /// Publishes regenesis and protocol update events.
+    pub consensus_events:   broadcast::Sender<ConsensusEvent>,
+    /// The protocol update consensus reported last, once its status was read.
+    pub protocol_update:    RwLock<Option<serde_json::Value>>,
     /// The key-value store holding the node's persistent data.
     pub kvs:                Arc<RwLock<Rkv<LmdbEnvironment>>>,
     /// The catch-up list of peers.
@@ -261,6 +287,8 @@ pub struct P2PNode {
     /// Cache of bad events that we report on each connection housekeeping
     /// interval to avoid spamming the logs in case of failure.
     pub bad_events:         BadEvents,
+    /// The flow control from the inbound consensus queues to network reads.
+    pub backpressure:       Backpressure,
 }
 
 impl P2PNode {
@@ -328,7 +356,6 @@ impl P2PNode {
 
         let config = NodeConfig {
             no_net: conf.cli.no_network,
-            desired_nodes_count: conf.connection.desired_nodes,
             no_bootstrap_dns: conf.connection.no_bootstrap_dns,
             no_clear_bans: conf.connection.no_clear_bans,
             bootstrap_server: conf.connection.bootstrap_server.clone(),
@@ -337,17 +364,22 @@ impl P2PNode {
             disallow_multiple_peers_on_ip: conf.connection.disallow_multiple_peers_on_ip,
             bootstrap_nodes: conf.connection.bootstrap_nodes.clone(),
             given_addresses,
-            max_allowed_nodes: if let Some(max) = conf.connection.max_allowed_nodes {
-                max
-            } else {
-                f64::floor(
-                    f64::from(conf.connection.desired_nodes)
-                        * (f64::from(conf.connection.max_allowed_nodes_percentage) / 100f64),
-                ) as u16
-            },
-            relay_broadcast_percentage: conf.connection.relay_broadcast_percentage,
+            live: RwLock::new(LiveConfig {
+                desired_nodes_count:        conf.connection.desired_nodes,
+                max_allowed_nodes:          if let Some(max) = conf.connection.max_allowed_nodes {
+                    max
+                } else {
+                    f64::floor(
+                        f64::from(conf.connection.desired_nodes)
+                            * (f64::from(conf.connection.max_allowed_nodes_percentage) / 100f64),
+                    ) as u16
+                },
+                max_latency:                conf.connection.max_latency,
+                relay_broadcast_percentage: conf.connection.relay_broadcast_percentage,
+                housekeeping_interval:      conf.connection.housekeeping_interval,
+            }),
             poll_interval: conf.cli.poll_interval,
-            housekeeping_interval: conf.connection.housekeeping_interval,
+            shutdown_drain_timeout: conf.cli.shutdown_drain_timeout,
             bootstrapping_interval: conf.connection.bootstrapping_interval,
             print_peers: true,
             bootstrapper_wait_minimum_peers: match peer_type {
@@ -355,10 +387,12 @@ impl P2PNode {
                 PeerType::Node => 0,
             },
             data_dir_path: conf.common.data_dir.clone(),
-            max_latency: conf.connection.max_latency,
+            config_dir_path: conf.common.config_dir.clone(),
             conn_requests_batch_limit: conf.connection.conn_requests_batch_limit,
             hard_connection_limit: conf.connection.hard_connection_limit,
             catch_up_batch_limit: conf.connection.catch_up_batch_limit,
+            catch_up_parallelism: conf.connection.catch_up_parallelism,
+            catch_up_timeout: conf.connection.catch_up_timeout * 1000,
             timeout_bucket_entry_period: if peer_type == PeerType::Bootstrapper {
                 conf.bootstrapper.bootstrapper_timeout_bucket_entry_period
             } else {
@@ -403,9 +437,14 @@ impl P2PNode {
             self_peer,
             stats,
             is_terminated: Default::default(),
+            is_draining: Default::default(),
+            drain_failed: Default::default(),
+            consensus_events: broadcast::channel(CONSENSUS_EVENTS_CAPACITY).0,
+            protocol_update: Default::default(),
             kvs,
             peers: Default::default(),
             bad_events: BadEvents::default(),
+            backpressure: Backpressure::new(conf.cli.baker.inbound_queue_high_water_mark),
         });
 
         if !node.config.no_clear_bans {
@@ -627,7 +666,7 @@ impl P2PNode {
 }
 
 /// Spawn the node's poll thread.
-pub fn spawn(node_ref: &Arc<P2PNode>, mut poll: Poll, consensus: Option<ConsensusContainer>) {
+pub fn spawn(node_ref: &Arc<P2PNode>, mut poll: Poll, consensus: Option<Arc<dyn Consensus>>) {
     let node = Arc::clone(node_ref);
     let poll_thread = spawn_or_die!("poll loop", move || {
         let mut events = Events::with_capacity(node.config.events_queue_size);
@@ -653,6 +692,9 @@ pub fn spawn(node_ref: &Arc<P2PNode>, mut poll: Poll, consensus: Option<Consensu
         // Maximum number of connection requests to process per iteration.
         let max_num_requests = node.config.conn_requests_batch_limit;
 
+        // The number of genesis blocks registered by consensus.
+        let mut known_genesis_blocks = node.process_regenesis(0);
+
         // Process network events until signalled to terminate.
         // For each loop iteration do the following in sequence
         // - check whether ther are any incoming connection requests
@@ -708,6 +750,8 @@ pub fn spawn(node_ref: &Arc<P2PNode>, mut poll: Poll, consensus: Option<Consensu
                 process_conn_change(&node, conn_change)
             }
 
+            known_genesis_blocks = node.process_regenesis(known_genesis_blocks);
+
             if let Some(ref consensus) = consensus {
                 let new_last_peer_update = node.last_peer_update();
                 if new_last_peer_update > last_peer_list_update {
@@ -718,6 +762,8 @@ pub fn spawn(node_ref: &Arc<P2PNode>, mut poll: Poll, consensus: Option<Consensu
             }
 
             // perform socket reads and writes in parallel across connections
+            let throttle = node.backpressure.update();
+            node.stats.set_inbound_consensus_read_throttle(throttle as i64);
             pool.install(|| node.process_network_events(&events));
 
             // Run periodic tasks
@@ -726,7 +772,7 @@ pub fn spawn(node_ref: &Arc<P2PNode>, mut poll: Poll, consensus: Option<Consensu
             // housekeeping.
             if iterations_since_housekeeping >= 10 {
                 if Instant::now().duration_since(log_time)
-                    >= Duration::from_secs(node.config.housekeeping_interval)
+                    >= Duration::from_secs(node.live_config().housekeeping_interval)
                 {
                     let attempted_bootstrap = connection_housekeeping(&node);
                     if node.peer_type() != PeerType::Bootstrapper {
@@ -738,6 +784,9 @@ pub fn spawn(node_ref: &Arc<P2PNode>, mut poll: Poll, consensus: Option<Consensu
                     if let Err(e) = node.measure_throughput(&peer_stat_list) {
                         error!("Could not measure throughput: {}", e);
                     }
+                    if let Some(ref consensus) = consensus {
+                        node.process_protocol_update(consensus.as_ref());
+                    }
 
                     log_time = Instant::now();
                     iterations_since_housekeeping = 0;
@@ -803,6 +852,7 @@ fn process_conn_change(node: &Arc<P2PNode>, conn_change: ConnChange) {
             let current_peers = node.get_peer_stats(Some(PeerType::Node));
 
             let curr_peer_count = current_peers.len();
+            let desired_nodes_count = node.live_config().desired_nodes_count as usize;
 
             // Shuffle the peers we received try to discover more useful peers over time
             // and not get stuck continuously connecting to useless ones, and then dropping
@@ -812,7 +862,7 @@ fn process_conn_change(node: &Arc<P2PNode>, conn_change: ConnChange) {
             // Try to connect to each peer in turn.
             // If we are already connected to a peer, this will fail.
             for peer in peers {
-                if new_peers + curr_peer_count >= node.config.desired_nodes_count as usize {
+                if new_peers + curr_peer_count >= desired_nodes_count {
                     break;
                 }
 
@@ -1,9 +1,13 @@
 //! Central node object handling.
 
+pub mod backpressure;
 pub mod bans;
 pub mod connectivity;
+pub mod consensus_events;
+pub mod drain;
 pub mod maintenance;
 pub mod peers;
+pub mod reconfiguration;
 
 pub use self::maintenance::{Connections, P2PNode};
 
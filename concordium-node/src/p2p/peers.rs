@@ -1,16 +1,59 @@
 //! Peer handling.
 
 use crate::{
-    common::{get_current_stamp, p2p_peer::RemotePeerId, PeerStats, PeerType},
+    common::{get_current_stamp, p2p_peer::RemotePeerId, P2PNodeId, PeerStats, PeerType},
     connection::Connection,
-    netmsg,
-    network::NetworkRequest,
+    lock_or_die, netmsg,
+    network::{NetworkRequest, WireProtocolVersion},
     p2p::{maintenance::attempt_bootstrap, P2PNode},
     read_or_die,
 };
 use anyhow::ensure;
 use chrono::Utc;
-use std::sync::{atomic::Ordering, Arc};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+
+/// Detailed diagnostics of the connection to a single peer.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerDetails {
+    pub node_id:               String,
+    /// The local identifier of the connection, as used when banning a peer.
+    pub local_id:              String,
+    pub addr:                  SocketAddr,
+    pub external_port:         u16,
+    pub peer_type:             String,
+    pub catchup_status:        Option<String>,
+    /// The timestamp (in milliseconds) at which the connection was created.
+    pub created:               u64,
+    /// The age of the connection in milliseconds.
+    pub connection_age:        u64,
+    pub last_seen:             u64,
+    pub latency:               u64,
+    pub messages_sent:         u64,
+    pub messages_received:     u64,
+    pub bytes_sent:            u64,
+    pub bytes_received:        u64,
+    /// The number of received packets that were dropped as duplicates.
+    pub duplicates_received:   u64,
+    pub pending_high_messages: usize,
+    pub pending_low_messages:  usize,
+    /// The number of encrypted bytes waiting to be written to the socket.
+    pub pending_output_bytes:  usize,
+    pub wire_version:          Option<WireProtocolVersion>,
+    pub networks:              Vec<u16>,
+    /// The duration of the noise handshake in milliseconds.
+    pub noise_handshake_time:  Option<u64>,
+    /// The bad events registered since the last connection housekeeping.
+    pub dropped_high_queue:    u64,
+    pub dropped_low_queue:     u64,
+    pub invalid_messages:      u64,
+    pub expired_transactions:  u64,
+}
 
 impl P2PNode {
     /// Obtain the list of statistics from all the peers, optionally of a
@@ -32,6 +75,64 @@ impl P2PNode {
             .collect()
     }
 
+    /// Obtain the diagnostics of the connection to the peer with the given
+    /// node id, if there is one.
+    pub fn get_peer_details(&self, node_id: P2PNodeId) -> Option<PeerDetails> {
+        let (local_id, mut details) =
+            read_or_die!(self.connections()).values().find_map(|conn| {
+                if conn.remote_id() != Some(node_id) {
+                    return None;
+                }
+                let mut networks =
+                    conn.remote_end_networks.iter().map(|network| network.id).collect::<Vec<_>>();
+                networks.sort_unstable();
+                Some((conn.remote_peer.local_id, PeerDetails {
+                    node_id: node_id.to_string(),
+                    local_id: conn.remote_peer.local_id.to_string(),
+                    addr: conn.remote_addr(),
+                    external_port: conn.remote_peer_external_port(),
+                    peer_type: conn.remote_peer_type().to_string(),
+                    catchup_status: None,
+                    created: conn.stats.created,
+                    connection_age: get_current_stamp().saturating_sub(conn.stats.created),
+                    last_seen: conn.last_seen(),
+                    latency: conn.get_latency(),
+                    messages_sent: conn.stats.messages_sent.load(Ordering::Relaxed),
+                    messages_received: conn.stats.messages_received.load(Ordering::Relaxed),
+                    bytes_sent: conn.stats.bytes_sent.load(Ordering::Relaxed),
+                    bytes_received: conn.stats.bytes_received.load(Ordering::Relaxed),
+                    duplicates_received: conn.stats.duplicates_received.load(Ordering::Relaxed),
+                    pending_high_messages: conn.pending_messages.high.len(),
+                    pending_low_messages: conn.pending_messages.low.len(),
+                    pending_output_bytes: conn.low_level.pending_output_bytes(),
+                    wire_version: conn.wire_version,
+                    networks,
+                    noise_handshake_time: conn
+                        .low_level
+                        .noise_handshake_time()
+                        .map(|time| time.as_millis() as u64),
+                    dropped_high_queue: 0,
+                    dropped_low_queue: 0,
+                    invalid_messages: 0,
+                    expired_transactions: 0,
+                }))
+            })?;
+
+        // the locks below are never held together with the connections lock
+        details.catchup_status = read_or_die!(self.peers)
+            .peer_states
+            .get(&local_id)
+            .map(|status| format!("{:?}", status));
+        let count = |events: &Mutex<HashMap<RemotePeerId, u64>>| {
+            lock_or_die!(events).get(&local_id).copied().unwrap_or(0)
+        };
+        details.dropped_high_queue = count(&self.bad_events.dropped_high_queue);
+        details.dropped_low_queue = count(&self.bad_events.dropped_low_queue);
+        details.invalid_messages = count(&self.bad_events.invalid_messages);
+        details.expired_transactions = count(&self.bad_events.expired_transactions);
+        Some(details)
+    }
+
     /// Prints information about all the peers.
     pub fn print_stats(&self, peer_stat_list: &[PeerStats]) {
         for (i, peer) in peer_stat_list.iter().enumerate() {
@@ -118,7 +219,8 @@ impl P2PNode {
 /// work and the strange looking messages "already connected to ..." in the
 /// logs.
 pub fn check_peers(node: &Arc<P2PNode>, peer_stats: &[PeerStats], attempted_bootstrap: bool) {
-    debug!("I currently have {}/{} peers", peer_stats.len(), node.config.max_allowed_nodes);
+    let live_config = node.live_config();
+    debug!("I currently have {}/{} peers", peer_stats.len(), live_config.max_allowed_nodes);
 
     if node.config.print_peers {
         node.print_stats(&peer_stats);
@@ -127,7 +229,7 @@ pub fn check_peers(node: &Arc<P2PNode>, peer_stats: &[PeerStats], attempted_boot
     if node.self_peer.peer_type == PeerType::Node {
         let node_count = peer_stats.iter().filter(|peer| peer.peer_type == PeerType::Node).count();
 
-        if !node.config.no_net && node_count < node.config.desired_nodes_count as usize {
+        if !node.config.no_net && node_count < live_config.desired_nodes_count as usize {
             if peer_stats.is_empty() {
                 if !attempted_bootstrap {
                     if !node.config.no_bootstrap_dns {
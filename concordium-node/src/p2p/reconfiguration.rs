@@ -0,0 +1,228 @@
+//! Changing the configuration of a running node.
+//!
+//! Only a subset of the configuration can be changed without restarting the
+//! node; these parameters are kept in a [`LiveConfig`] that is consulted
+//! whenever they are used. Changes are given as a JSON object mapping the names
+//! of parameters to their new values, either via the admin RPC
+//! `UpdateNodeConfig`, or in the file given by `--runtime-config-file`, which
+//! the node reads again when it receives a `SIGHUP`.
+
+use crate::{p2p::P2PNode, read_or_die, write_or_die};
+use anyhow::{bail, ensure, Context};
+use log::LevelFilter;
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path, str::FromStr};
+
+/// The names of the parameters that can be changed at runtime.
+pub const LIVE_FIELDS: [&str; 6] = [
+    "desiredNodes",
+    "maxAllowedNodes",
+    "maxLatency",
+    "relayBroadcastPercentage",
+    "housekeepingInterval",
+    "logLevel",
+];
+
+/// The parameters of the node that can be changed while it is running.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LiveConfig {
+    #[serde(rename = "desiredNodes")]
+    pub desired_nodes_count:        u16,
+    pub max_allowed_nodes:          u16,
+    /// Connections with a higher latency (in milliseconds) are dropped.
+    pub max_latency:                Option<u64>,
+    pub relay_broadcast_percentage: f64,
+    /// The interval (in seconds) between connection housekeeping runs.
+    pub housekeeping_interval:      u64,
+}
+
+/// A change of some of the parameters of a running node. Parameters that are
+/// `None` are left unchanged.
+#[derive(Debug, Default)]
+pub struct ConfigUpdate {
+    pub desired_nodes:              Option<u16>,
+    pub max_allowed_nodes:          Option<u16>,
+    /// A maximum latency of 0 disables the check.
+    pub max_latency:                Option<u64>,
+    pub relay_broadcast_percentage: Option<f64>,
+    pub housekeeping_interval:      Option<u64>,
+    pub log_level:                  Option<LevelFilter>,
+}
+
+/// Parsed configuration changes.
+#[derive(Debug)]
+pub struct ConfigChanges {
+    pub update:           ConfigUpdate,
+    /// Parameters that can't be changed at runtime.
+    pub restart_required: Vec<String>,
+}
+
+/// The configuration in effect after an update, as reported to the caller.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconfigurationReport {
+    pub config:           LiveConfig,
+    pub log_level:        String,
+    pub live_fields:      &'static [&'static str],
+    pub restart_required: Vec<String>,
+}
+
+impl P2PNode {
+    /// Returns a copy of the parameters that can be changed at runtime.
+    pub fn live_config(&self) -> LiveConfig { *read_or_die!(self.config.live) }
+
+    /// Validates and applies a configuration update. Either all of the
+    /// changes are applied, or none of them.
+    pub fn update_config(&self, update: &ConfigUpdate) -> anyhow::Result<LiveConfig> {
+        let mut live = write_or_die!(self.config.live);
+        let mut new = *live;
+        if let Some(desired_nodes) = update.desired_nodes {
+            new.desired_nodes_count = desired_nodes;
+        }
+        if let Some(max_allowed_nodes) = update.max_allowed_nodes {
+            new.max_allowed_nodes = max_allowed_nodes;
+        }
+        if let Some(max_latency) = update.max_latency {
+            new.max_latency = if max_latency == 0 {
+                None
+            } else {
+                Some(max_latency)
+            };
+        }
+        if let Some(percentage) = update.relay_broadcast_percentage {
+            new.relay_broadcast_percentage = percentage;
+        }
+        if let Some(interval) = update.housekeeping_interval {
+            new.housekeeping_interval = interval;
+        }
+
+        ensure!(
+            new.max_allowed_nodes >= new.desired_nodes_count,
+            "Max allowed nodes must be greater or equal to the desired amount of nodes"
+        );
+        ensure!(
+            self.config.hard_connection_limit >= new.desired_nodes_count,
+            "Hard connection limit can't be less than what desired nodes is set to"
+        );
+        ensure!(
+            (0.0..=1.0).contains(&new.relay_broadcast_percentage),
+            "Percentage of peers to relay broadcasted packets to, must be between 0.0 and 1.0"
+        );
+        ensure!(new.housekeeping_interval > 0, "The housekeeping interval must be positive");
+
+        *live = new;
+        if let Some(level) = update.log_level {
+            log::set_max_level(level);
+        }
+        info!("Node configuration updated: {:?}, log level {}", new, log::max_level());
+        Ok(new)
+    }
+
+    /// Applies the parameters that can be changed live and reports the ones
+    /// that require a restart.
+    pub fn apply_config_changes(
+        &self,
+        changes: ConfigChanges,
+    ) -> anyhow::Result<ReconfigurationReport> {
+        let ConfigChanges {
+            update,
+            restart_required,
+        } = changes;
+        for field in &restart_required {
+            warn!("Changing '{}' requires restarting the node", field);
+        }
+        let config = self.update_config(&update)?;
+        Ok(ReconfigurationReport {
+            config,
+            log_level: log::max_level().to_string(),
+            live_fields: &LIVE_FIELDS,
+            restart_required,
+        })
+    }
+}
+
+/// Parses a log level, as used in the configuration file and the RPC.
+pub fn parse_log_level(level: &str) -> anyhow::Result<LevelFilter> {
+    LevelFilter::from_str(level).map_err(|_| anyhow::anyhow!("Invalid log level '{}'", level))
+}
+
+/// Reads the runtime configuration file.
+pub fn read_config_file(path: &Path) -> anyhow::Result<ConfigChanges> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Can't read the runtime configuration file {:?}", path))?;
+    parse_config_changes(&contents)
+}
+
+/// Parses configuration changes given as a JSON object. Parameters that can't
+/// be changed at runtime are reported rather than rejected, since the runtime
+/// configuration file may also be used to keep track of the configuration for
+/// the next restart.
+pub fn parse_config_changes(contents: &str) -> anyhow::Result<ConfigChanges> {
+    let fields: BTreeMap<String, serde_json::Value> = serde_json::from_str(contents)
+        .context("The runtime configuration must be a JSON object")?;
+
+    fn number<T: std::convert::TryFrom<u64>>(
+        field: &str,
+        value: &serde_json::Value,
+    ) -> anyhow::Result<T> {
+        value
+            .as_u64()
+            .and_then(|n| T::try_from(n).ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid value for '{}'", field))
+    }
+
+    let mut update = ConfigUpdate::default();
+    let mut restart_required = Vec::new();
+    for (field, value) in fields.iter() {
+        match field.as_str() {
+            "desiredNodes" => update.desired_nodes = Some(number(field, value)?),
+            "maxAllowedNodes" => update.max_allowed_nodes = Some(number(field, value)?),
+            "maxLatency" => {
+                update.max_latency = Some(
+                    if value.is_null() {
+                        0
+                    } else {
+                        number(field, value)?
+                    },
+                )
+            }
+            "relayBroadcastPercentage" => match value.as_f64() {
+                Some(percentage) => update.relay_broadcast_percentage = Some(percentage),
+                None => bail!("Invalid value for '{}'", field),
+            },
+            "housekeepingInterval" => update.housekeeping_interval = Some(number(field, value)?),
+            "logLevel" => match value.as_str() {
+                Some(level) => update.log_level = Some(parse_log_level(level)?),
+                None => bail!("Invalid value for '{}'", field),
+            },
+            _ => restart_required.push(field.to_owned()),
+        }
+    }
+    Ok(ConfigChanges {
+        update,
+        restart_required,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config_changes() {
+        let file = parse_config_changes(
+            r#"{"desiredNodes": 10, "maxLatency": null, "logLevel": "debug", "listenPort": 8889}"#,
+        )
+        .unwrap();
+        assert_eq!(file.update.desired_nodes, Some(10));
+        assert_eq!(file.update.max_latency, Some(0));
+        assert_eq!(file.update.log_level, Some(LevelFilter::Debug));
+        assert_eq!(file.update.max_allowed_nodes, None);
+        assert_eq!(file.restart_required, vec!["listenPort".to_owned()]);
+
+        assert!(parse_config_changes(r#"{"desiredNodes": 70000}"#).is_err());
+        assert!(parse_config_changes(r#"{"logLevel": "loud"}"#).is_err());
+        assert!(parse_config_changes("[]").is_err());
+    }
+}
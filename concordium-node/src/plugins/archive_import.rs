@@ -0,0 +1,445 @@
+//! Out-of-band catch-up from block archives.
+//!
+//! Catching up with peers from genesis takes a long time, so before connecting
+//! to the network the node can import the blocks exported by another node (see
+//! [`block_export`](crate::consensus_ffi::block_export)) from archives, which
+//! are either local directories or HTTP(S) URLs, e.g., of a local mirror. The
+//! manifest of an archive must be signed by one of the trusted keys and name
+//! the genesis block of the node, if it is known, and every file is checked
+//! against its checksum in the manifest before it is imported.
+//!
+//! The archives are tried in order, and only the files with blocks above the
+//! last finalized block of the node are imported, so an interrupted import
+//! resumes where it stopped and an archive can pick up where the previous one
+//! failed. Files downloaded over HTTP are kept in a cache directory until they
+//! are imported, and partial downloads are resumed.
+
+use crate::consensus_ffi::{
+    block_export::{verify_manifest, ExportedFile, MANIFEST_FILE_NAME, SIGNATURE_FILE_NAME},
+    blockchain_types::BlockHash,
+    consensus::Consensus,
+    helpers::ConsensusFfiResponse,
+};
+use anyhow::{bail, ensure, Context};
+use ed25519_dalek::PublicKey;
+use sha2::{Digest, Sha256};
+use std::{
+    convert::TryFrom,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The name of the directory in the data directory where downloaded block
+/// files are kept until they are imported.
+pub const ARCHIVE_CACHE_DIRECTORY_NAME: &str = "archive-import";
+
+/// The location of a block archive.
+#[derive(Debug, Clone)]
+pub enum ArchiveSource {
+    Directory(PathBuf),
+    Http(reqwest::Url),
+}
+
+impl FromStr for ArchiveSource {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> anyhow::Result<Self> {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            // the files are resolved relative to the URL, so it must end with a slash
+            let mut url = source.to_owned();
+            if !url.ends_with('/') {
+                url.push('/');
+            }
+            Ok(ArchiveSource::Http(reqwest::Url::parse(&url)?))
+        } else {
+            Ok(ArchiveSource::Directory(PathBuf::from(source)))
+        }
+    }
+}
+
+impl fmt::Display for ArchiveSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveSource::Directory(path) => write!(f, "{}", path.display()),
+            ArchiveSource::Http(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+impl ArchiveSource {
+    /// Reads a small file of the archive, such as the manifest.
+    async fn read(&self, client: &reqwest::Client, name: &str) -> anyhow::Result<Vec<u8>> {
+        match self {
+            ArchiveSource::Directory(path) => {
+                let path = path.join(name);
+                fs::read(&path).with_context(|| format!("Can't read {:?}", path))
+            }
+            ArchiveSource::Http(url) => {
+                let url = url.join(name)?;
+                let response = client.get(url.clone()).send().await?.error_for_status()?;
+                Ok(response
+                    .bytes()
+                    .await
+                    .with_context(|| format!("Can't download {}", url))?
+                    .to_vec())
+            }
+        }
+    }
+
+    /// Makes a block file of the archive available locally, downloading it to
+    /// the cache directory if needed, and returns its path.
+    async fn fetch(
+        &self,
+        client: &reqwest::Client,
+        file: &ExportedFile,
+        cache_dir: &Path,
+    ) -> anyhow::Result<PathBuf> {
+        match self {
+            ArchiveSource::Directory(path) => Ok(path.join(&file.name)),
+            ArchiveSource::Http(url) => {
+                fs::create_dir_all(cache_dir)
+                    .with_context(|| format!("Can't create the cache directory {:?}", cache_dir))?;
+                let path = cache_dir.join(&file.name);
+                download(client, url.join(&file.name)?, &path, file.size)
+                    .await
+                    .with_context(|| format!("Can't download {}", file.name))?;
+                Ok(path)
+            }
+        }
+    }
+}
+
+/// Downloads a file of the given size, resuming an earlier partial download.
+async fn download(
+    client: &reqwest::Client,
+    url: reqwest::Url,
+    path: &Path,
+    size: u64,
+) -> anyhow::Result<()> {
+    if path.exists() {
+        // downloaded, but not imported, before
+        return Ok(());
+    }
+    let mut partial_path = path.as_os_str().to_owned();
+    partial_path.push(".partial");
+    let partial_path = PathBuf::from(partial_path);
+    let mut downloaded = fs::metadata(&partial_path).map(|metadata| metadata.len()).unwrap_or(0);
+
+    if downloaded < size {
+        let mut request = client.get(url.clone());
+        if downloaded > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", downloaded));
+        }
+        let mut response = request.send().await?.error_for_status()?;
+        let mut partial =
+            if downloaded > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+                info!("Resuming the download of {} at byte {}", url, downloaded);
+                OpenOptions::new().append(true).open(&partial_path)?
+            } else {
+                downloaded = 0;
+                File::create(&partial_path)?
+            };
+        while let Some(chunk) = response.chunk().await? {
+            partial.write_all(&chunk)?;
+            downloaded += chunk.len() as u64;
+            ensure!(downloaded <= size, "The file is larger than the manifest says");
+        }
+        partial.sync_all()?;
+    }
+    fs::rename(&partial_path, path)?;
+    Ok(())
+}
+
+/// Checks the size and the checksum of a block file.
+fn verify_file(path: &Path, file: &ExportedFile) -> anyhow::Result<()> {
+    let mut reader = File::open(path).with_context(|| format!("Can't open {:?}", path))?;
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut reader, &mut hasher)?;
+    ensure!(size == file.size, "{} has {} bytes instead of {}", file.name, size, file.size);
+    ensure!(
+        hasher.finalize()[..] == file.sha256[..],
+        "The checksum of {} doesn't match the manifest",
+        file.name
+    );
+    Ok(())
+}
+
+/// Parses a hex-encoded Ed25519 public key trusted to sign manifests.
+pub fn parse_archive_key(key: &str) -> anyhow::Result<PublicKey> {
+    let bytes = hex::decode(key).context("The key is not hex-encoded")?;
+    PublicKey::from_bytes(&bytes).map_err(|e| anyhow::anyhow!("Invalid key {}: {}", key, e))
+}
+
+/// The height of the last finalized block, according to the consensus status.
+fn last_finalized_height(consensus: &dyn Consensus) -> u64 {
+    serde_json::from_str::<serde_json::Value>(&consensus.get_consensus_status())
+        .ok()
+        .and_then(|status| status.get("lastFinalizedBlockHeight").and_then(|h| h.as_u64()))
+        .unwrap_or(0)
+}
+
+/// Catches up from the given archives, trying them in order. A failing
+/// archive is only logged, since the node can still catch up with its peers.
+pub async fn import_from_archives(
+    consensus: &dyn Consensus,
+    sources: &[ArchiveSource],
+    keys: &[PublicKey],
+    genesis_block: Option<&BlockHash>,
+    cache_dir: &Path,
+) {
+    let client = reqwest::Client::new();
+    for source in sources {
+        if let Err(e) =
+            import_from_archive(consensus, &client, source, keys, genesis_block, cache_dir).await
+        {
+            error!("Can't catch up from the block archive {}: {:#}", source, e);
+        }
+    }
+}
+
+async fn import_from_archive(
+    consensus: &dyn Consensus,
+    client: &reqwest::Client,
+    source: &ArchiveSource,
+    keys: &[PublicKey],
+    genesis_block: Option<&BlockHash>,
+    cache_dir: &Path,
+) -> anyhow::Result<()> {
+    let manifest = source.read(client, MANIFEST_FILE_NAME).await?;
+    let signature = source.read(client, SIGNATURE_FILE_NAME).await?;
+    let manifest = verify_manifest(&manifest, &signature, keys)?;
+    match (genesis_block, &manifest.genesis_block) {
+        (Some(ours), Some(theirs)) => ensure!(
+            ours == theirs,
+            "The archive belongs to the chain with genesis block {}",
+            theirs
+        ),
+        // an archive that doesn't say which chain it belongs to could be of any chain
+        (Some(_), None) => bail!("The manifest of the archive doesn't name its genesis block"),
+        (None, _) => {}
+    }
+
+    let start_height = last_finalized_height(consensus);
+    let end_height = manifest.files.last().map_or(0, |file| file.last_height);
+    if end_height <= start_height {
+        info!("The block archive {} has no blocks above height {}", source, start_height);
+        return Ok(());
+    }
+    info!("Catching up from the block archive {} up to height {}", source, end_height);
+    let mut height = start_height;
+    for file in &manifest.files {
+        if file.last_height <= height {
+            continue;
+        }
+        ensure!(
+            file.first_height <= height + 1,
+            "The archive has no blocks at heights {} to {}",
+            height + 1,
+            file.first_height - 1
+        );
+        let path = source.fetch(client, file, cache_dir).await?;
+        let downloaded = matches!(source, ArchiveSource::Http(_));
+        if let Err(e) = verify_file(&path, file) {
+            // a corrupt download must not be reused
+            if downloaded {
+                let _ = fs::remove_file(&path);
+            }
+            return Err(e);
+        }
+        let result = consensus.import_blocks(path.to_string_lossy().as_bytes());
+        if downloaded {
+            let _ = fs::remove_file(&path);
+        }
+        match ConsensusFfiResponse::try_from(i64::from(result)) {
+            Ok(ConsensusFfiResponse::Success) => {}
+            Ok(response) => bail!("Consensus couldn't import {}: {:?}", file.name, response),
+            Err(e) => bail!("Consensus couldn't import {}: {}", file.name, e),
+        }
+        height = file.last_height;
+        info!(
+            "Imported the blocks up to height {} of {} ({:.1}%)",
+            height,
+            end_height,
+            100.0 * (height - start_height) as f64 / (end_height - start_height) as f64
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus_ffi::{
+        block_export::{export_blocks, sign_manifest, ExportRange},
+        mock::MockConsensus,
+    };
+    use std::sync::{Arc, Mutex};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// The requested files and the offsets of the requested ranges.
+    type Requests = Arc<Mutex<Vec<(String, Option<usize>)>>>;
+
+    /// Serves the files of a directory over HTTP, honouring the offsets of
+    /// `Range` requests, and records the requests.
+    async fn serve_directory(directory: PathBuf) -> (reqwest::Url, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = reqwest::Url::parse(&format!("http://{}/", listener.local_addr().unwrap()));
+        let requests = Requests::default();
+        let recorded = Arc::clone(&requests);
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buf).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..read]);
+                }
+                let request = String::from_utf8_lossy(&request).to_lowercase();
+                let name = request.split_whitespace().nth(1).unwrap_or("/")[1..].to_owned();
+                let offset = request.lines().find_map(|line| {
+                    line.strip_prefix("range: bytes=")
+                        .map(|range| range.trim_end_matches('-').parse::<usize>().unwrap())
+                });
+                recorded.lock().unwrap().push((name.clone(), offset));
+                let response = match fs::read(directory.join(&name)) {
+                    Ok(content) => {
+                        let start = offset.unwrap_or(0).min(content.len());
+                        let status = if offset.is_some() {
+                            "206 Partial Content"
+                        } else {
+                            "200 OK"
+                        };
+                        let mut response = format!(
+                            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            status,
+                            content.len() - start
+                        )
+                        .into_bytes();
+                        response.extend_from_slice(&content[start..]);
+                        response
+                    }
+                    Err(_) => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: \
+                                close\r\n\r\n"
+                        .to_vec(),
+                };
+                stream.write_all(&response).await.unwrap();
+            }
+        });
+        (url.unwrap(), requests)
+    }
+
+    /// Exports 5 blocks in files of 2 blocks and signs the manifest.
+    fn signed_export(directory: &Path) -> PublicKey {
+        let consensus = MockConsensus::default();
+        for block in 1..=5u8 {
+            consensus.add_finalized_block(&[block]);
+        }
+        let range = ExportRange {
+            from_height:     1,
+            to_height:       None,
+            blocks_per_file: 2,
+        };
+        export_blocks(&consensus, None, directory, range, &|| false, &mut |_| {}).unwrap();
+        let secret_key = crate::utils::generate_ed25519_key();
+        sign_manifest(directory, &secret_key).unwrap();
+        PublicKey::from(&secret_key)
+    }
+
+    fn imported_files(consensus: &MockConsensus) -> Vec<String> {
+        consensus
+            .queries()
+            .into_iter()
+            .filter(|(query, _)| *query == "ImportBlocks")
+            .map(|(_, arguments)| {
+                Path::new(&arguments[0]).file_name().unwrap().to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_import_from_directory() {
+        let archive = tempfile::tempdir().unwrap();
+        let key = signed_export(archive.path());
+        let source = ArchiveSource::Directory(archive.path().to_owned());
+        let cache = tempfile::tempdir().unwrap();
+
+        // the blocks up to height 2 are already finalized
+        let consensus = MockConsensus::default();
+        consensus.set_query_response("GetConsensusStatus", r#"{"lastFinalizedBlockHeight":2}"#);
+        import_from_archives(&consensus, &[source.clone()], &[key], None, cache.path()).await;
+        assert_eq!(imported_files(&consensus), vec!["blocks-3-4.dat", "blocks-5-5.dat"]);
+
+        // untrusted manifests are not used
+        let consensus = MockConsensus::default();
+        let other_key = PublicKey::from(&crate::utils::generate_ed25519_key());
+        import_from_archives(&consensus, &[source.clone()], &[other_key], None, cache.path()).await;
+        assert!(imported_files(&consensus).is_empty());
+
+        // the import stops at a corrupt file
+        let mut corrupt = fs::read(archive.path().join("blocks-3-4.dat")).unwrap();
+        corrupt[1] ^= 1;
+        fs::write(archive.path().join("blocks-3-4.dat"), corrupt).unwrap();
+        let consensus = MockConsensus::default();
+        import_from_archives(&consensus, &[source.clone()], &[key], None, cache.path()).await;
+        assert_eq!(imported_files(&consensus), vec!["blocks-1-2.dat"]);
+
+        // an archive that doesn't name its genesis block is rejected once the node
+        // knows its own
+        let consensus = MockConsensus::default();
+        let genesis_block = BlockHash::new(&[1; 32]).unwrap();
+        import_from_archives(&consensus, &[source], &[key], Some(&genesis_block), cache.path())
+            .await;
+        assert!(imported_files(&consensus).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_import_over_http() {
+        let archive = tempfile::tempdir().unwrap();
+        let key = signed_export(archive.path());
+        let (url, requests) = serve_directory(archive.path().to_owned()).await;
+        let cache = tempfile::tempdir().unwrap();
+
+        // an earlier download of the first file was interrupted after 3 bytes
+        let first_file = fs::read(archive.path().join("blocks-1-2.dat")).unwrap();
+        fs::write(cache.path().join("blocks-1-2.dat.partial"), &first_file[..3]).unwrap();
+
+        let consensus = MockConsensus::default();
+        import_from_archives(&consensus, &[ArchiveSource::Http(url)], &[key], None, cache.path())
+            .await;
+        // the resumed download passed the checksum
+        assert_eq!(imported_files(&consensus), vec![
+            "blocks-1-2.dat",
+            "blocks-3-4.dat",
+            "blocks-5-5.dat"
+        ]);
+        let requests = requests.lock().unwrap().clone();
+        assert!(requests.contains(&("blocks-1-2.dat".to_owned(), Some(3))));
+        assert!(requests.contains(&("blocks-3-4.dat".to_owned(), None)));
+        // the imported files are not kept
+        assert_eq!(fs::read_dir(cache.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_archive_sources() {
+        match "http://mirror:8080/mainnet".parse::<ArchiveSource>().unwrap() {
+            ArchiveSource::Http(url) => {
+                assert_eq!(url.join("manifest.json").unwrap().path(), "/mainnet/manifest.json")
+            }
+            source => panic!("Unexpected source {:?}", source),
+        }
+        assert!(matches!(
+            "/var/archive".parse::<ArchiveSource>().unwrap(),
+            ArchiveSource::Directory(_)
+        ));
+    }
+}
@@ -1,38 +1,48 @@
 //! Consensus layer handling.
-use anyhow::{bail, ensure};
+use anyhow::{anyhow, bail, ensure};
 use crossbeam_channel::TrySendError;
 
 use crate::{
-    common::{get_current_stamp, p2p_peer::RemotePeerId},
-    configuration::{self, MAX_CATCH_UP_TIME},
+    baker_credentials::decrypt_baker_credentials,
+    common::{get_current_stamp, p2p_node_id::PeerId, p2p_peer::RemotePeerId, P2PNodeId, PeerType},
+    configuration,
     connection::ConnChange,
     consensus_ffi::{
         blockchain_types::BlockHash,
-        catch_up::{PeerList, PeerStatus},
-        consensus::{self, ConsensusContainer, CALLBACK_QUEUE},
+        catch_up::{CatchUpHistory, PeerList, PeerStatus},
+        consensus::{self, Consensus, ConsensusContainer, ConsensusInboundQueues, ConsensusQueues},
         ffi,
         helpers::{
             ConsensusFfiResponse,
             PacketType::{self, *},
-            QueueMsg,
+            QueueMsg, RelayOrStopSenderHelper,
         },
         messaging::{ConsensusMessage, DistributionMode, MessageType},
+        transactions::{check_transaction, TransactionRejection},
     },
+    lock_or_die,
     p2p::{
         connectivity::{send_broadcast_message, send_direct_message},
         P2PNode,
     },
-    read_or_die, write_or_die,
+    read_or_die, spawn_or_die, write_or_die,
 };
 use crypto_common::Deserial;
+use nohash_hasher::BuildNoHashHasher;
+use serde::Serialize;
 
 use std::{
-    collections::hash_map::Entry::*,
+    collections::{HashMap, HashSet},
     convert::TryFrom,
     fs::OpenOptions,
     io::{Cursor, Read},
     path::PathBuf,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    thread::JoinHandle,
+    time::Instant,
 };
 
 const FILE_NAME_GENESIS_DATA: &str = "genesis.dat";
@@ -49,18 +59,7 @@ pub fn start_consensus_layer(
 ) -> anyhow::Result<ConsensusContainer> {
     info!("Starting up the consensus thread");
 
-    #[cfg(feature = "profiling")]
-    ffi::start_haskell(
-        &conf.heap_profiling,
-        conf.stack_profiling,
-        conf.time_profiling,
-        conf.backtraces_profiling,
-        conf.gc_logging.clone(),
-        &conf.profiling_sampling_interval,
-        &conf.rts_flags,
-    );
-    #[cfg(not(feature = "profiling"))]
-    ffi::start_haskell(&conf.rts_flags);
+    start_haskell(conf);
 
     ConsensusContainer::new(
         u64::from(conf.maximum_block_size),
@@ -78,49 +77,62 @@ pub fn start_consensus_layer(
     )
 }
 
+/// Starts the GHC runtime with the configured flags.
+pub fn start_haskell(conf: &configuration::BakerConfig) {
+    #[cfg(feature = "profiling")]
+    ffi::start_haskell(
+        &conf.heap_profiling,
+        conf.stack_profiling,
+        conf.time_profiling,
+        conf.backtraces_profiling,
+        conf.gc_logging.clone(),
+        &conf.profiling_sampling_interval,
+        &conf.rts_flags,
+    );
+    #[cfg(not(feature = "profiling"))]
+    ffi::start_haskell(&conf.rts_flags);
+}
+
 /// Stop consensus container
 pub fn stop_consensus_layer(container: ConsensusContainer) {
     container.stop();
     crate::consensus_ffi::ffi::stop_haskell();
 }
 
-/// Obtains the genesis data and baker's private data.
-/// If the baker private data is encrypted this will query for the password.
-pub fn get_baker_data(
-    app_prefs: &configuration::AppPreferences,
-    conf: &configuration::BakerConfig,
-) -> anyhow::Result<(Vec<u8>, Option<Vec<u8>>)> {
+/// Reads the genesis data from the data directory.
+pub fn get_genesis_data(app_prefs: &configuration::AppPreferences) -> anyhow::Result<Vec<u8>> {
     let mut genesis_loc = app_prefs.get_user_app_dir().to_path_buf();
     genesis_loc.push(FILE_NAME_GENESIS_DATA);
 
-    let genesis_data = match OpenOptions::new().read(true).open(&genesis_loc) {
+    match OpenOptions::new().read(true).open(&genesis_loc) {
         Ok(mut file) => {
             let mut read_data = vec![];
             match file.read_to_end(&mut read_data) {
-                Ok(_) => read_data,
+                Ok(_) => Ok(read_data),
                 Err(_) => bail!("Couldn't read genesis file properly"),
             }
         }
         Err(e) => bail!("Can't open the genesis file ({})!", e),
-    };
+    }
+}
+
+/// Obtains the genesis data and baker's private data.
+/// If the baker private data is encrypted, the password is read from the
+/// configured source, or queried for if there is none.
+pub fn get_baker_data(
+    app_prefs: &configuration::AppPreferences,
+    conf: &configuration::BakerConfig,
+) -> anyhow::Result<(Vec<u8>, Option<Vec<u8>>)> {
+    let genesis_data = get_genesis_data(app_prefs)?;
 
     let private_data = if let Some(path) = &conf.baker_credentials_file {
         let read_data = match std::fs::read(&path) {
             Ok(read_data) => read_data,
             Err(e) => bail!("Can't open the baker credentials file ({})!", e),
         };
-        if conf.decrypt_baker_credentials {
-            let et = serde_json::from_slice(&read_data)?;
-            let pass = rpassword::read_password_from_tty(Some(
-                "Enter password to decrypt baker credentials: ",
-            ))?;
-            match crypto_common::encryption::decrypt(&pass.into(), &et) {
-                Ok(d) => Some(d),
-                Err(_) => bail!(
-                    "Could not decrypt baker credentials. Most likely the password you provided \
-                     is incorrect."
-                ),
-            }
+        if let Some(source) = conf.baker_credentials_password_source() {
+            let pass = source.read()?;
+            Some(decrypt_baker_credentials(&read_data, pass)?)
         } else {
             Some(read_data)
         }
@@ -131,9 +143,11 @@ pub fn get_baker_data(
     Ok((genesis_data, private_data))
 }
 
-/// Handles packets coming from other peers.
+/// Handles packets coming from other peers, putting the ones consensus should
+/// process in the given inbound queues.
 pub fn handle_pkt_out(
     node: &P2PNode,
+    queues: &ConsensusQueues,
     dont_relay_to: Vec<RemotePeerId>,
     peer_id: RemotePeerId, // id of the peer that sent the message.
     msg: Vec<u8>,
@@ -166,7 +180,24 @@ pub fn handle_pkt_out(
                 configuration::PROTOCOL_MAX_TRANSACTION_SIZE
             )
         }
-        if let Err(e) = CALLBACK_QUEUE.send_in_low_priority_message(request) {
+        match check_transaction(&request.payload[1..], get_current_stamp() / 1000) {
+            Ok(()) => {}
+            Err(TransactionRejection::Expired) => {
+                let num_expired = node.bad_events.inc_expired_transactions(peer_id);
+                if num_expired < 10 {
+                    debug!("Dropping an expired transaction from peer {}", peer_id);
+                }
+                return Ok(());
+            }
+            Err(TransactionRejection::Malformed(e)) => {
+                let num_bad_events = node.bad_events.inc_invalid_messages(peer_id);
+                if num_bad_events < 10 {
+                    warn!("Dropping a malformed transaction from peer {}: {}", peer_id, e);
+                }
+                return Ok(());
+            }
+        }
+        if let Err(e) = queues.send_in_low_priority_message(request) {
             match e.downcast::<TrySendError<QueueMsg<ConsensusMessage>>>()? {
                 TrySendError::Full(_) => {
                     node.stats.inbound_low_priority_consensus_drops_inc();
@@ -181,7 +212,7 @@ pub fn handle_pkt_out(
         }
     } else {
         // high priority message
-        if let Err(e) = CALLBACK_QUEUE.send_in_high_priority_message(request) {
+        if let Err(e) = queues.send_in_high_priority_message(request) {
             match e.downcast::<TrySendError<QueueMsg<ConsensusMessage>>>()? {
                 TrySendError::Full(_) => {
                     node.stats.inbound_high_priority_consensus_drops_inc();
@@ -229,10 +260,128 @@ pub fn handle_consensus_outbound_msg(
     Ok(())
 }
 
+/// Starts the threads that process the messages received from peers.
+///
+/// Blocks and finalization messages and records are processed one at a time,
+/// in the order in which they were received, since consensus depends on it.
+/// Catch-up messages are handled on a thread of their own once the messages
+/// received before them were processed, e.g., so that a catch-up response
+/// doesn't overtake the blocks sent before it, while answering a catch-up
+/// request doesn't hold up the blocks received after it. Transactions are
+/// independent of each other, so a pool of `transaction_workers` threads shares
+/// their queue.
+pub fn start_inbound_consensus_threads(
+    node: &Arc<P2PNode>,
+    consensus: Arc<dyn Consensus>,
+    queues: &'static ConsensusInboundQueues,
+    transaction_workers: usize,
+) -> Vec<JoinHandle<()>> {
+    let mut threads: Vec<JoinHandle<()>> = Default::default();
+
+    let node_ref = Arc::clone(node);
+    let consensus_ref = Arc::clone(&consensus);
+    threads.push(spawn_or_die!("inbound consensus requests", {
+        let receiver = lock_or_die!(queues.receiver_high_priority);
+        while let Ok(message) = receiver.recv() {
+            node_ref.stats.set_inbound_high_priority_consensus_size(receiver.len() as i64);
+            let keep_going = handle_queue_stop(message, "inbound", |msg| {
+                if msg.variant == CatchUpStatus {
+                    queues.sender_catch_up.send_msg(msg).map_err(|_| {
+                        node_ref.stats.inbound_catch_up_consensus_drops_inc();
+                        anyhow!("The catch-up queue is full; dropping a catch-up message")
+                    })
+                } else {
+                    handle_timed_consensus_inbound_msg(&node_ref, consensus_ref.as_ref(), msg)
+                }
+            });
+            if !keep_going {
+                let _ = queues.sender_catch_up.send_stop();
+                break;
+            }
+        }
+    }));
+
+    let node_ref = Arc::clone(node);
+    let consensus_ref = Arc::clone(&consensus);
+    threads.push(spawn_or_die!("inbound catch-up requests", {
+        let receiver = lock_or_die!(queues.receiver_catch_up);
+        while let Ok(message) = receiver.recv() {
+            node_ref.stats.set_inbound_catch_up_consensus_size(receiver.len() as i64);
+            let keep_going = handle_queue_stop(message, "inbound", |msg| {
+                handle_timed_consensus_inbound_msg(&node_ref, consensus_ref.as_ref(), msg)
+            });
+            if !keep_going {
+                break;
+            }
+        }
+    }));
+
+    // The queue is stopped with a single message, which each worker passes on
+    // to the ones still running.
+    let receiver = lock_or_die!(queues.receiver_low_priority).clone();
+    let running_workers = Arc::new(AtomicUsize::new(transaction_workers));
+    for _ in 0..transaction_workers {
+        let node_ref = Arc::clone(node);
+        let consensus_ref = Arc::clone(&consensus);
+        let receiver = receiver.clone();
+        let running_workers = Arc::clone(&running_workers);
+        threads.push(spawn_or_die!("inbound transactions", {
+            while let Ok(message) = receiver.recv() {
+                node_ref.stats.set_inbound_low_priority_consensus_size(receiver.len() as i64);
+                let keep_going = handle_queue_stop(message, "inbound", |msg| {
+                    handle_timed_consensus_inbound_msg(&node_ref, consensus_ref.as_ref(), msg)
+                });
+                if !keep_going {
+                    if running_workers.fetch_sub(1, Ordering::SeqCst) > 1 {
+                        let _ = queues.sender_low_priority.send_stop();
+                    }
+                    break;
+                }
+            }
+        }));
+    }
+
+    threads
+}
+
+/// Handles a message taken from a consensus queue with `f`; returns whether
+/// the queue should be read further, i.e., it wasn't stopped.
+pub fn handle_queue_stop<F>(msg: QueueMsg<ConsensusMessage>, dir: &'static str, f: F) -> bool
+where
+    F: FnOnce(ConsensusMessage) -> anyhow::Result<()>, {
+    match msg {
+        QueueMsg::Relay(msg) => {
+            if let Err(e) = f(msg) {
+                error!("There's an issue with an {} consensus request: {}", dir, e);
+            }
+        }
+        QueueMsg::Stop => {
+            debug!("Closing the {} consensus channel", dir);
+            return false;
+        }
+    }
+    true
+}
+
+/// Handle a message received from a peer, recording the time it spent in the
+/// queue and the time it took to process it.
+fn handle_timed_consensus_inbound_msg(
+    node: &P2PNode,
+    consensus: &dyn Consensus,
+    msg: ConsensusMessage,
+) -> anyhow::Result<()> {
+    let variant = msg.variant;
+    node.stats.inbound_consensus_queue_time_observe(variant, msg.created.elapsed());
+    let start = Instant::now();
+    let result = handle_consensus_inbound_msg(node, consensus, msg);
+    node.stats.inbound_consensus_processing_time_observe(variant, start.elapsed());
+    result
+}
+
 /// Processes a consensus message from the network.
 pub fn handle_consensus_inbound_msg(
     node: &P2PNode,
-    consensus: &ConsensusContainer,
+    consensus: &dyn Consensus,
     request: ConsensusMessage,
 ) -> anyhow::Result<()> {
     // If the drop_rebroadcast_probability parameter is set, do not
@@ -304,7 +453,7 @@ pub fn handle_consensus_inbound_msg(
 fn send_msg_to_consensus(
     node: &P2PNode,
     source_id: RemotePeerId,
-    consensus: &ConsensusContainer,
+    consensus: &dyn Consensus,
     message: &ConsensusMessage,
 ) -> anyhow::Result<ConsensusFfiResponse> {
     let payload = &message.payload[1..]; // non-empty, already checked
@@ -364,87 +513,239 @@ fn send_consensus_msg_to_net(
 pub fn update_peer_list(node: &P2PNode) {
     trace!("The peers have changed; updating the catch-up peer list");
 
-    let peer_ids = node.get_node_peer_tokens();
+    let node_ids = peer_node_ids(node);
 
     let mut peers = write_or_die!(node.peers);
     // remove global state peers whose connections were dropped
-    peers.peer_states.retain(|id, _| peer_ids.contains(&id));
-    peers.pending_queue.retain(|id| peer_ids.contains(&id));
-    if let Some(in_progress) = peers.catch_up_peer {
-        if !peers.peer_states.contains_key(&in_progress) {
-            peers.catch_up_peer = None;
-        }
-    }
+    peers.retain(|id| node_ids.contains_key(id));
 
     // include newly added peers
-    let new_peers = peer_ids.len() - peers.peer_states.len();
+    let new_peers = node_ids.len() - peers.peer_states.len();
     peers.peer_states.reserve(new_peers);
     peers.pending_queue.reserve(new_peers);
-    for id in peer_ids {
-        if let Vacant(v) = peers.peer_states.entry(id) {
-            v.insert(PeerStatus::Pending);
+    for (id, node_id) in node_ids {
+        // the catch-up history of a peer is kept by its node id
+        peers.node_ids.insert(id, node_id);
+        if !peers.peer_states.contains_key(&id) {
+            peers.set_status(id, PeerStatus::Pending);
             peers.pending_queue.push_back(id);
         }
     }
 }
 
-/// Try to catch up with a peer, if one is pending.
-fn try_catch_up(node: &P2PNode, consensus: &ConsensusContainer, peers: &mut PeerList) {
-    if let Some(id) = peers.next_pending() {
-        debug!("Attempting to catch up with peer {}", id);
-        peers.catch_up_stamp = get_current_stamp();
-        let sent = send_direct_message(
-            node,
-            id,
-            node.config.default_network,
-            consensus.get_catch_up_status(),
-        );
-        if sent > 0 {
-            info!(
-                "Sent a direct message to peer {} containing a {}",
-                id,
-                PacketType::CatchUpStatus
-            );
-        } else {
-            // If no packets were sent, then this must not be a valid peer,
-            // so remove it from the peers.
-            debug!("Could not send catch-up message to peer {}", id);
-            peers.catch_up_peer = None;
-            peers.peer_states.remove(&id);
+/// Start catching up with pending peers, as long as we are catching up with
+/// fewer peers than allowed.
+fn try_catch_up(node: &P2PNode, consensus: &dyn Consensus, peers: &mut PeerList) {
+    while peers.catch_up_peers.len() < node.config.catch_up_parallelism {
+        match peers.next_pending() {
+            Some(id) => {
+                send_catch_up_status(node, consensus, peers, id);
+            }
+            None => break,
         }
     }
 }
 
-/// Check whether the peers require catching up.
-pub fn check_peer_states(node: &P2PNode, consensus: &ConsensusContainer) {
-    // If we are catching-up with a peer, check if the peer has timed-out.
+/// Send our catch-up status to a peer we are catching up with. Returns
+/// whether it was sent.
+fn send_catch_up_status(
+    node: &P2PNode,
+    consensus: &dyn Consensus,
+    peers: &mut PeerList,
+    id: RemotePeerId,
+) -> bool {
+    debug!("Attempting to catch up with peer {}", id);
+    let sent =
+        send_direct_message(node, id, node.config.default_network, consensus.get_catch_up_status());
+    if sent > 0 {
+        info!("Sent a direct message to peer {} containing a {}", id, PacketType::CatchUpStatus);
+        true
+    } else {
+        // If no packets were sent, then this must not be a valid peer,
+        // so remove it from the peers.
+        debug!("Could not send catch-up message to peer {}", id);
+        peers.remove(id);
+        false
+    }
+}
+
+/// Check whether the peers require catching up. The latencies of the peers
+/// are recorded for choosing the peers to catch up with, the catch-up sessions
+/// with peers that are gone or that did not make progress in time are ended,
+/// and new sessions are started with pending peers in their place.
+pub fn check_peer_states(node: &P2PNode, consensus: &dyn Consensus) {
     let now = get_current_stamp();
-    let (catch_up_peer, catch_up_stamp) = {
-        let peers = read_or_die!(node.peers);
-        (peers.catch_up_peer, peers.catch_up_stamp)
-    };
-    if let Some(peer_id) = catch_up_peer {
-        if read_or_die!(node.connections()).get(&peer_id.to_token()).is_some() {
-            if now > catch_up_stamp + MAX_CATCH_UP_TIME {
-                // Try to remove the peer since it timed-out.
-                debug!("Peer {} took too long to catch up; dropping", peer_id);
-                // This function may not actually remove the peer, so we do not assume
-                // that it will be removed.
-                node.register_conn_change(ConnChange::RemovalByToken(peer_id.to_token()));
-            }
-        } else {
-            // Connection no longer exists
+    let latencies = read_or_die!(node.connections())
+        .values()
+        .map(|conn| (conn.remote_peer.local_id, conn.get_latency()))
+        .collect::<HashMap<_, _>>();
+
+    let peers = &mut write_or_die!(node.peers);
+    for (&peer_id, &latency) in latencies.iter() {
+        peers.record_latency(peer_id, latency);
+    }
+    let sessions = peers
+        .catch_up_peers
+        .iter()
+        .map(|(&id, session)| (id, session.last_progress))
+        .collect::<Vec<_>>();
+    for (peer_id, last_progress) in sessions {
+        if !latencies.contains_key(&peer_id) {
             debug!("Connection to catch-up-in-progress peer {} no longer exists", peer_id);
-            let peers = &mut write_or_die!(node.peers);
-            peers.catch_up_peer = None;
-            peers.peer_states.remove(&peer_id);
-            try_catch_up(node, consensus, peers);
+            peers.remove(peer_id);
+        } else if now > last_progress + node.config.catch_up_timeout {
+            // Back the peer off, so that we catch up with another peer instead.
+            debug!("Peer {} took too long to catch up; backing off", peer_id);
+            peers.time_out_catch_up(peer_id);
         }
-    } else {
-        try_catch_up(node, consensus, &mut write_or_die!(node.peers));
+    }
+    try_catch_up(node, consensus, peers);
+}
+
+/// The state of catch-up with a single peer.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatchUpPeer {
+    pub node_id:  String,
+    pub local_id: String,
+    pub status:   String,
+    /// The timestamp at which the status of the peer last changed.
+    pub since:    Option<u64>,
+    /// How well the peer caught us up in the past, if we know anything about
+    /// it.
+    pub history:  Option<CatchUpHistory>,
+}
+
+/// A catch-up session in progress.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatchUpSessionStatus {
+    pub node_id:       String,
+    /// The timestamp at which the session started.
+    pub started:       u64,
+    /// The timestamp at which the peer last made progress.
+    pub last_progress: u64,
+    /// The number of bytes the peer sent us during the session.
+    pub bytes:         u64,
+}
+
+/// The state of the catch-up peer selection.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatchUpOverview {
+    pub catch_up_peers: Vec<CatchUpSessionStatus>,
+    pub pending_queue:  Vec<String>,
+    pub pinned_peers:   Option<Vec<String>>,
+    pub peers:          Vec<CatchUpPeer>,
+}
+
+/// Obtain the node ids of the node peers, keyed by their local ids.
+fn peer_node_ids(node: &P2PNode) -> HashMap<RemotePeerId, P2PNodeId> {
+    node.get_peer_stats(Some(PeerType::Node))
+        .into_iter()
+        .map(|stats| (stats.local_id, stats.self_id))
+        .collect()
+}
+
+/// Find the local id of the node peer with the given node id.
+fn find_peer(node: &P2PNode, node_id: P2PNodeId) -> anyhow::Result<RemotePeerId> {
+    match peer_node_ids(node).into_iter().find(|(_, id)| *id == node_id) {
+        Some((local_id, _)) => Ok(local_id),
+        None => bail!("Not connected to peer {}", node_id),
     }
 }
 
+/// Describe the current state of the catch-up peer selection.
+pub fn catch_up_overview(node: &P2PNode) -> CatchUpOverview {
+    let node_ids = peer_node_ids(node);
+    let describe = |id: &RemotePeerId| match node_ids.get(id) {
+        Some(node_id) => node_id.to_string(),
+        None => id.to_string(),
+    };
+    let peers = read_or_die!(node.peers);
+    let mut peer_states = peers
+        .peer_states
+        .iter()
+        .map(|(id, status)| CatchUpPeer {
+            node_id:  describe(id),
+            local_id: id.to_string(),
+            status:   format!("{:?}", status),
+            since:    peers.status_stamps.get(id).copied(),
+            history:  peers.history(*id),
+        })
+        .collect::<Vec<_>>();
+    peer_states.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+    let mut sessions = peers
+        .catch_up_peers
+        .iter()
+        .map(|(id, session)| CatchUpSessionStatus {
+            node_id:       describe(id),
+            started:       session.started,
+            last_progress: session.last_progress,
+            bytes:         session.bytes,
+        })
+        .collect::<Vec<_>>();
+    sessions.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+    CatchUpOverview {
+        catch_up_peers: sessions,
+        pending_queue:  peers.pending_queue.iter().map(describe).collect(),
+        pinned_peers:   peers
+            .pinned_peers
+            .as_ref()
+            .map(|pinned| pinned.iter().map(describe).collect()),
+        peers:          peer_states,
+    }
+}
+
+/// Start catching up with the given peer right away, even if we are already
+/// catching up with as many peers as allowed.
+pub fn catch_up_with_peer(
+    node: &P2PNode,
+    consensus: &dyn Consensus,
+    node_id: P2PNodeId,
+) -> anyhow::Result<()> {
+    let id = find_peer(node, node_id)?;
+    let mut peers = write_or_die!(node.peers);
+    ensure!(peers.peer_states.contains_key(&id), "Peer {} is not a catch-up candidate", node_id);
+    peers.start_catch_up(id);
+    info!("Catching up with peer {} on request", node_id);
+    ensure!(
+        send_catch_up_status(node, consensus, &mut peers, id),
+        "Could not send a catch-up message to peer {}",
+        node_id
+    );
+    Ok(())
+}
+
+/// Mark the given peer as pending, so that we catch up with it again.
+pub fn reset_catch_up_peer(node: &P2PNode, node_id: P2PNodeId) -> anyhow::Result<()> {
+    let id = find_peer(node, node_id)?;
+    ensure!(
+        write_or_die!(node.peers).reset_to_pending(id),
+        "Peer {} is not a catch-up candidate",
+        node_id
+    );
+    info!("Peer {} was reset to pending on request", node_id);
+    Ok(())
+}
+
+/// Only catch up with the given peers; an empty list lifts the restriction.
+pub fn pin_catch_up_peers(node: &P2PNode, node_ids: &[P2PNodeId]) -> anyhow::Result<()> {
+    let pinned = if node_ids.is_empty() {
+        info!("Catch-up is no longer pinned");
+        None
+    } else {
+        let ids = node_ids
+            .iter()
+            .map(|&node_id| find_peer(node, node_id))
+            .collect::<anyhow::Result<HashSet<_, BuildNoHashHasher<PeerId>>>>()?;
+        info!("Catch-up is pinned to peers {:?}", node_ids);
+        Some(ids)
+    };
+    write_or_die!(node.peers).pinned_peers = pinned;
+    Ok(())
+}
+
 fn update_peer_states(
     node: &P2PNode,
     request: &ConsensusMessage,
@@ -459,29 +760,23 @@ fn update_peer_states(
         match consensus_result {
             Success => {
                 // We are up-to-date with the peer.
-                peers.peer_states.insert(source_peer, UpToDate);
-                if peers.catch_up_peer == Some(source_peer) {
-                    peers.catch_up_peer = None;
-                }
+                peers.set_status(source_peer, UpToDate);
+                peers.finish_catch_up(source_peer);
             }
             PendingBlock | PendingFinalization => {
                 // We are behind the peer.
-                match peers.peer_states.insert(source_peer, Pending) {
+                match peers.set_status(source_peer, Pending) {
                     Some(Pending) => {}
                     _ => peers.pending_queue.push_back(source_peer),
                 }
-                if peers.catch_up_peer == Some(source_peer) {
-                    peers.catch_up_peer = None;
-                }
+                peers.finish_catch_up(source_peer);
             }
             ContinueCatchUp => {
                 // This was not a response, and we're behind the peer, so
                 // set it to Pending if it's currently UpToDate.
-                if let Some(state) = peers.peer_states.get_mut(&source_peer) {
-                    if let UpToDate = *state {
-                        *state = Pending;
-                        peers.pending_queue.push_back(source_peer);
-                    }
+                if peers.peer_states.get(&source_peer) == Some(&UpToDate) {
+                    peers.set_status(source_peer, Pending);
+                    peers.pending_queue.push_back(source_peer);
                 }
             }
             ConsensusFfiResponse::InvalidResult => {
@@ -491,6 +786,7 @@ fn update_peer_states(
                      soft-banning",
                     source_peer
                 );
+                peers.end_catch_up(source_peer);
                 node.register_conn_change(ConnChange::ExpulsionByToken(source_peer.to_token()));
             }
             ConsensusFfiResponse::DeserializationError => {
@@ -498,11 +794,22 @@ fn update_peer_states(
                     "The peer {} sent a malformed catchup message, dropping and soft-banning",
                     source_peer
                 );
+                peers.end_catch_up(source_peer);
                 node.register_conn_change(ConnChange::ExpulsionByToken(source_peer.to_token()));
             }
             e => error!("Unexpected return from `receiveCatchUpStatus`: {:?}", e),
         }
     } else if [Block, FinalizationRecord].contains(&request.variant) {
+        // Blocks and finalization records sent to us directly are what a peer
+        // we catch up with sends us, so they keep its session from timing out
+        // and count towards its history.
+        if request.distribution_mode() == DistributionMode::Direct {
+            if consensus_result.is_acceptable() {
+                peers.catch_up_progress(source_peer, request.payload.len());
+            } else {
+                peers.catch_up_error(source_peer);
+            }
+        }
         match request.distribution_mode() {
             DistributionMode::Direct if consensus_result.is_successful() => {
                 // Directly sent blocks and finalization records that are
@@ -531,14 +838,209 @@ fn update_peer_states(
             DistributionMode::Broadcast if consensus_result.is_pending() => {
                 // We are missing some context for this message, so mark
                 // the peer as pending.
-                if let Some(state) = peers.peer_states.get_mut(&source_peer) {
-                    if let UpToDate = *state {
-                        *state = Pending;
-                        peers.pending_queue.push_back(source_peer);
-                    }
+                if peers.peer_states.get(&source_peer) == Some(&UpToDate) {
+                    peers.set_status(source_peer, Pending);
+                    peers.pending_queue.push_back(source_peer);
                 }
             }
             _ => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::PeerType,
+        consensus_ffi::mock::MockConsensus,
+        lock_or_die,
+        test_utils::{
+            dummy_account_transaction, dummy_regenesis_blocks, make_node_and_sync,
+            next_available_port, stop_node_delete_dirs,
+        },
+    };
+    use std::time::Duration;
+
+    fn inbound(
+        source: RemotePeerId,
+        variant: PacketType,
+        mode: DistributionMode,
+    ) -> ConsensusMessage {
+        ConsensusMessage::new(
+            MessageType::Inbound(source, mode),
+            variant,
+            Arc::from(vec![variant as u8, 1, 2, 3]),
+            vec![],
+            None,
+        )
+    }
+
+    #[test]
+    fn test_inbound_consensus_responses() -> anyhow::Result<()> {
+        let (node, dp) = make_node_and_sync(
+            next_available_port(),
+            vec![100],
+            PeerType::Node,
+            dummy_regenesis_blocks(),
+        )?;
+        let consensus = MockConsensus::default();
+        let peer = RemotePeerId::from(7usize);
+        write_or_die!(node.peers).node_ids.insert(peer, P2PNodeId(7));
+
+        // a peer that is ahead of us becomes a catch-up candidate
+        consensus.respond_with(CatchUpStatus, ConsensusFfiResponse::PendingBlock);
+        handle_consensus_inbound_msg(
+            &node,
+            &consensus,
+            inbound(peer, CatchUpStatus, DistributionMode::Direct),
+        )?;
+        {
+            let peers = read_or_die!(node.peers);
+            assert_eq!(peers.peer_states.get(&peer), Some(&PeerStatus::Pending));
+            assert_eq!(peers.pending_queue.iter().collect::<Vec<_>>(), vec![&peer]);
+        }
+        // the payload is handed over without the packet type
+        assert_eq!(consensus.received(), vec![(CatchUpStatus, vec![1, 2, 3])]);
+
+        // once we are up to date with it, it is no longer pending and the
+        // catch-up session with it ends
+        write_or_die!(node.peers).start_catch_up(peer);
+        consensus.queue_response(CatchUpStatus, ConsensusFfiResponse::Success);
+        handle_consensus_inbound_msg(
+            &node,
+            &consensus,
+            inbound(peer, CatchUpStatus, DistributionMode::Direct),
+        )?;
+        {
+            let peers = read_or_die!(node.peers);
+            assert_eq!(peers.peer_states.get(&peer), Some(&PeerStatus::UpToDate));
+            assert!(peers.catch_up_peers.is_empty());
+        }
+
+        // what a peer sends us during catch-up is recorded in its history
+        write_or_die!(node.peers).start_catch_up(peer);
+        consensus.queue_response(Block, ConsensusFfiResponse::Success);
+        consensus.queue_response(Block, ConsensusFfiResponse::InvalidResult);
+        for _ in 0..2 {
+            handle_consensus_inbound_msg(
+                &node,
+                &consensus,
+                inbound(peer, Block, DistributionMode::Direct),
+            )?;
+        }
+        {
+            let mut peers = write_or_die!(node.peers);
+            assert_eq!(peers.catch_up_peers[&peer].bytes, 4);
+            peers.finish_catch_up(peer);
+            let history = peers.history(peer).unwrap();
+            assert_eq!((history.bytes_delivered, history.errors), (4, 1));
+        }
+
+        // invalid messages count against the peer
+        consensus.respond_with(Block, ConsensusFfiResponse::InvalidResult);
+        for _ in 0..2 {
+            handle_consensus_inbound_msg(
+                &node,
+                &consensus,
+                inbound(peer, Block, DistributionMode::Broadcast),
+            )?;
+        }
+        assert_eq!(lock_or_die!(node.bad_events.invalid_messages).get(&peer), Some(&2));
+
+        stop_node_delete_dirs(dp, node);
+        Ok(())
+    }
+
+    #[test]
+    fn test_slow_messages_dont_hold_up_blocks() -> anyhow::Result<()> {
+        let (node, dp) = make_node_and_sync(
+            next_available_port(),
+            vec![100],
+            PeerType::Node,
+            dummy_regenesis_blocks(),
+        )?;
+        let consensus = Arc::new(MockConsensus::default());
+        consensus.delay(Transaction, Duration::from_secs(2));
+        consensus.delay(CatchUpStatus, Duration::from_secs(2));
+        let queues = Box::leak(Box::new(ConsensusInboundQueues::new(16, 16)));
+        let threads = start_inbound_consensus_threads(&node, consensus.clone(), queues, 1);
+        let peer = RemotePeerId::from(7usize);
+        let wait_for = |variant: PacketType, count: usize| {
+            let deadline = Instant::now() + Duration::from_secs(1);
+            while consensus.received().iter().filter(|(v, _)| *v == variant).count() < count {
+                assert!(Instant::now() < deadline, "A {} was held up", variant);
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+
+        // a block is processed while the only transaction worker is busy
+        queues.sender_low_priority.send_msg(inbound(
+            peer,
+            Transaction,
+            DistributionMode::Broadcast,
+        ))?;
+        wait_for(Transaction, 1);
+        queues.sender_high_priority.send_msg(inbound(peer, Block, DistributionMode::Broadcast))?;
+        wait_for(Block, 1);
+
+        // and while a catch-up request is being answered
+        queues.sender_high_priority.send_msg(inbound(
+            peer,
+            CatchUpStatus,
+            DistributionMode::Direct,
+        ))?;
+        wait_for(CatchUpStatus, 1);
+        queues.sender_high_priority.send_msg(inbound(peer, Block, DistributionMode::Broadcast))?;
+        wait_for(Block, 2);
+
+        queues.sender_high_priority.send_stop()?;
+        queues.sender_low_priority.send_stop()?;
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        stop_node_delete_dirs(dp, node);
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_transactions_are_dropped() -> anyhow::Result<()> {
+        let (node, dp) = make_node_and_sync(
+            next_available_port(),
+            vec![100],
+            PeerType::Node,
+            dummy_regenesis_blocks(),
+        )?;
+        let queues = ConsensusQueues::default();
+        let peer = RemotePeerId::from(7usize);
+        let packet = |transaction: Vec<u8>| {
+            let mut packet = vec![Transaction as u8];
+            packet.extend(transaction);
+            packet
+        };
+        let queued = || lock_or_die!(queues.inbound.receiver_low_priority).len();
+
+        // a truncated transaction counts against the peer and isn't queued for
+        // consensus
+        let mut malformed = dummy_account_transaction(64, 10, get_current_stamp() / 1000 + 60);
+        malformed.pop();
+        handle_pkt_out(&node, &queues, vec![], peer, packet(malformed), true)?;
+        assert_eq!(lock_or_die!(node.bad_events.invalid_messages).get(&peer), Some(&1));
+        assert_eq!(queued(), 0);
+
+        // an expired one is counted separately and isn't queued either
+        let expired = dummy_account_transaction(64, 10, get_current_stamp() / 1000 - 60);
+        handle_pkt_out(&node, &queues, vec![], peer, packet(expired), true)?;
+        assert_eq!(lock_or_die!(node.bad_events.expired_transactions).get(&peer), Some(&1));
+        assert_eq!(lock_or_die!(node.bad_events.invalid_messages).get(&peer), Some(&1));
+        assert_eq!(queued(), 0);
+
+        let transaction = dummy_account_transaction(64, 10, get_current_stamp() / 1000 + 60);
+        handle_pkt_out(&node, &queues, vec![], peer, packet(transaction), true)?;
+        assert_eq!(lock_or_die!(node.bad_events.invalid_messages).get(&peer), Some(&1));
+        assert_eq!(queued(), 1);
+
+        stop_node_delete_dirs(dp, node);
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
 //! Client plugins.
 
+pub mod archive_import;
 pub mod consensus;
@@ -1,18 +1,42 @@
 //! An implementation of an RPC server and functions handling all available gRPC
 //! calls.
 
+#[cfg(unix)]
+use crate::rpc_admin_socket;
 use crate::{
-    common::{grpc_api::*, p2p_peer::RemotePeerId, PeerType},
+    baker_credentials::decrypt_baker_credentials,
+    common::{get_current_stamp, grpc_api::*, p2p_peer::RemotePeerId, P2PNodeId, PeerType},
     configuration,
     connection::ConnChange,
     consensus_ffi::{
-        consensus::{ConsensusContainer, CALLBACK_QUEUE},
-        helpers::{ConsensusFfiResponse, ConsensusIsInBakingCommitteeResponse, PacketType},
+        block_export::{export_blocks, ExportRange},
+        blockchain_types::versioned_transaction_hash,
+        consensus::{Consensus, CALLBACK_QUEUE},
+        helpers::{
+            ConsensusFfiResponse, ConsensusIsInBakingCommitteeResponse, PacketType,
+            SetBakerCredentialsResponse,
+        },
         messaging::{ConsensusMessage, MessageType},
+        transactions::check_transaction,
     },
     network::NetworkId,
-    p2p::{bans::PersistedBanId, P2PNode},
+    p2p::{
+        bans::PersistedBanId,
+        consensus_events::{ConsensusEvent, CONSENSUS_EVENTS_CAPACITY},
+        reconfiguration::parse_config_changes,
+        P2PNode,
+    },
+    plugins::consensus::{
+        catch_up_overview, catch_up_with_peer, pin_catch_up_peers, reset_catch_up_peer,
+    },
     read_or_die,
+    rpc_audit::{AuditLog, Caller},
+    rpc_jobs::{spawn_job, JobStream},
+    rpc_limits::{MethodClass, OwnedRpcPermit, RpcLimiter},
+    rpc_query_cache::QueryCache,
+    rpc_query_pool::{request_deadline, QueryPool},
+    snapshot::create_snapshot,
+    spawn_or_die,
 };
 use byteorder::WriteBytesExt;
 use p2p_server::*;
@@ -20,27 +44,47 @@ use std::{
     convert::TryInto,
     io::Write,
     net::{IpAddr, SocketAddr},
+    path::{Component, Path, PathBuf},
     str::FromStr,
     sync::{atomic::Ordering, Arc},
     time::{SystemTime, UNIX_EPOCH},
 };
-use tonic::{transport::Server, Code, Request, Response, Status};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{metadata::MetadataValue, transport::Server, Code, Request, Response, Status};
 
 /// The object used to initiate a gRPC server.
 #[derive(Clone)]
 pub struct RpcServerImpl {
-    node:         Arc<P2PNode>,
-    listen_addr:  SocketAddr,
-    access_token: String,
+    node:             Arc<P2PNode>,
+    listen_addr:      SocketAddr,
+    /// Whether to listen on `listen_addr`.
+    listen_tcp:       bool,
+    /// The path of the Unix domain socket to listen on, if any.
+    admin_socket:     Option<PathBuf>,
+    /// Whether the node administration methods are served.
+    admin_methods:    bool,
+    /// Whether this is the server on the admin socket.
+    on_admin_socket:  bool,
+    /// If set, gRPC-Web requests are accepted, and cross-origin requests
+    /// are allowed from these origins.
+    grpc_web_origins: Option<Vec<String>>,
+    access_token:     String,
     // this field is optional only for test purposes
-    consensus:    Option<ConsensusContainer>,
+    consensus:        Option<Arc<dyn Consensus>>,
+    limiter:          Arc<RpcLimiter>,
+    query_pool:       Arc<QueryPool>,
+    query_cache:      Arc<QueryCache>,
+    audit:            Arc<AuditLog>,
+    /// The directory under which the exports requested over RPC are written.
+    export_dir:       Option<PathBuf>,
 }
 
 impl RpcServerImpl {
     /// Creates a new RPC server object.
     pub fn new(
         node: Arc<P2PNode>,
-        consensus: Option<ConsensusContainer>,
+        consensus: Option<Arc<dyn Consensus>>,
         conf: &configuration::RpcCliConfig,
     ) -> anyhow::Result<Self> {
         let listen_addr =
@@ -49,17 +93,193 @@ impl RpcServerImpl {
         Ok(RpcServerImpl {
             node: Arc::clone(&node),
             listen_addr,
+            listen_tcp: !conf.rpc_server_no_tcp,
+            admin_socket: conf.rpc_server_admin_socket.clone(),
+            admin_methods: !conf.rpc_server_admin_socket_only,
+            on_admin_socket: false,
+            grpc_web_origins: if conf.rpc_server_grpc_web {
+                Some(conf.rpc_server_grpc_web_allowed_origins.clone())
+            } else {
+                None
+            },
             access_token: conf.rpc_server_token.clone(),
             consensus,
+            limiter: Arc::new(RpcLimiter::new(conf, Arc::clone(&node.stats))),
+            query_pool: Arc::new(QueryPool::new(
+                conf.rpc_server_query_threads,
+                conf.rpc_server_query_queue_size,
+                Arc::clone(&node.stats),
+            )),
+            query_cache: Arc::new(QueryCache::new(
+                conf.rpc_server_query_cache_size * 1024 * 1024,
+                Arc::clone(&node.config.regenesis_arc),
+                Arc::clone(&node.stats),
+            )),
+            audit: Arc::new(AuditLog::new(
+                conf.rpc_server_audit_log.as_deref(),
+                conf.rpc_server_audit_log_max_size * 1024 * 1024,
+                conf.rpc_server_audit_log_max_files,
+            )?),
+            export_dir: conf.rpc_server_export_dir.clone(),
         })
     }
 
-    /// Starts the gRPC server.
+    /// Starts the gRPC server, listening on TCP and/or the admin socket.
     pub async fn start_server(&mut self) -> anyhow::Result<()> {
-        let self_clone = self.clone();
-        let server = Server::builder().add_service(P2pServer::new(self_clone));
+        let tcp = async {
+            if self.listen_tcp {
+                let self_clone = self.clone();
+                let service = P2pServer::new(self_clone);
+                if let Some(ref origins) = self.grpc_web_origins {
+                    info!("The RPC server accepts gRPC-Web requests");
+                    Server::builder()
+                        .accept_http1(true)
+                        .add_service(grpc_web_config(origins).enable(service))
+                        .serve(self.listen_addr)
+                        .await?;
+                } else {
+                    Server::builder().add_service(service).serve(self.listen_addr).await?;
+                }
+            }
+            Ok::<(), anyhow::Error>(())
+        };
+        let admin = async {
+            if let Some(ref path) = self.admin_socket {
+                self.serve_admin_socket(path).await?;
+            }
+            Ok::<(), anyhow::Error>(())
+        };
+        tokio::try_join!(tcp, admin)?;
+        Ok(())
+    }
+
+    /// Serves all the methods on the admin socket. Access to the socket is
+    /// restricted by its file permissions, so the access token is added to the
+    /// requests on behalf of the client.
+    #[cfg(unix)]
+    async fn serve_admin_socket(&self, path: &Path) -> anyhow::Result<()> {
+        let incoming = rpc_admin_socket::bind_admin_socket(path)?;
+        let mut admin_server = self.clone();
+        admin_server.admin_methods = true;
+        admin_server.on_admin_socket = true;
+        let token = MetadataValue::from_str(&self.access_token)?;
+        let service = P2pServer::with_interceptor(admin_server, move |mut req: Request<()>| {
+            req.metadata_mut().insert("authentication", token.clone());
+            Ok(req)
+        });
+        info!("RPC server listening on the admin socket {:?}", path);
+        Server::builder().add_service(service).serve_with_incoming(incoming).await?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    async fn serve_admin_socket(&self, _path: &Path) -> anyhow::Result<()> {
+        anyhow::bail!("The RPC admin socket is only supported on Unix")
+    }
+
+    /// Rejects the node administration methods if they are only served on the
+    /// admin socket.
+    fn check_admin(&self) -> Result<(), Status> {
+        if self.admin_methods {
+            Ok(())
+        } else {
+            Err(Status::permission_denied("This method is only available on the admin socket."))
+        }
+    }
 
-        server.serve(self.listen_addr).await.map_err(|e| e.into())
+    /// Resolves a path given in a request against the export directory. The
+    /// path must be relative and can't leave the export directory.
+    fn export_path(&self, path: &str) -> Result<PathBuf, Status> {
+        let export_dir = self.export_dir.as_ref().ok_or_else(|| {
+            Status::failed_precondition("The node has no export directory configured.")
+        })?;
+        let path = Path::new(path);
+        if path.as_os_str().is_empty()
+            || !path.components().all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(Status::invalid_argument(
+                "The path must be relative to the export directory and can't leave it.",
+            ));
+        }
+        Ok(export_dir.join(path))
+    }
+
+    /// Rejects new transactions while the node is draining before it shuts
+    /// down.
+    fn check_not_draining(&self) -> Result<(), Status> {
+        if self.node.is_draining() {
+            Err(Status::unavailable("The node is shutting down."))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Streams the pages produced by repeatedly calling `query` with the cursor
+    /// of the next page, starting with an empty cursor. Each page is only
+    /// queried once the client is ready to receive it.
+    fn stream_pages<F>(
+        &self,
+        method: &'static str,
+        permit: OwnedRpcPermit,
+        query: F,
+    ) -> Result<ReceiverStream<Result<JsonResponse, Status>>, Status>
+    where
+        F: Fn(&dyn Consensus, &str) -> String + Send + Sync + 'static, {
+        let consensus = match self.consensus {
+            Some(ref container) if container.is_running() => Arc::clone(container),
+            Some(_) => {
+                warn!("Can't respond to a {} request due to uninitialized Consensus", method);
+                return Err(Status::new(
+                    Code::Internal,
+                    "The consensus layer has not been initialized!",
+                ));
+            }
+            None => {
+                error!("Consensus container not supplied; is this a gRPC unit test?");
+                return Err(Status::new(
+                    Code::FailedPrecondition,
+                    "The consensus container is missing!",
+                ));
+            }
+        };
+        let query_pool = Arc::clone(&self.query_pool);
+        let query = Arc::new(query);
+        let (sender, receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            // the stream counts as in flight until it is done
+            let _permit = permit;
+            let mut cursor = String::new();
+            loop {
+                let (consensus, query) = (consensus.clone(), Arc::clone(&query));
+                let page = query_pool
+                    .run(method, None, move || query(consensus.as_ref(), &cursor))
+                    .await
+                    .and_then(|page| parse_page(&page));
+                match page {
+                    Ok((items, next)) => {
+                        // the client is gone if the receiver was dropped
+                        if sender
+                            .send(Ok(JsonResponse {
+                                value: items,
+                            }))
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                        match next {
+                            Some(next) => cursor = next,
+                            None => break,
+                        }
+                    }
+                    Err(status) => {
+                        let _ = sender.send(Err(status)).await;
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(ReceiverStream::new(receiver))
     }
 }
 
@@ -80,12 +300,62 @@ macro_rules! authenticate {
     };
 }
 
+/// Executes the body of a state-changing method and records the call in the
+/// audit log. Calls that fail authentication are recorded as well.
+macro_rules! audited {
+    ($self:ident, $req:ident, $method:expr, $resp_type:ty, { $($body:tt)* }) => {
+        audited!(
+            $self,
+            $req,
+            $method,
+            $resp_type,
+            serde_json::Value::String(format!("{:?}", $req.get_ref())),
+            { $($body)* }
+        )
+    };
+    // for requests carrying secrets, which are recorded as the given arguments
+    ($self:ident, $req:ident, $method:expr, $resp_type:ty, $arguments:expr, { $($body:tt)* }) => {{
+        let caller = Caller::of(&$req);
+        let arguments = $arguments;
+        let result: Result<Response<$resp_type>, Status> = async { $($body)* }.await;
+        $self.audit.record($method, caller, arguments, &result);
+        result
+    }};
+}
+
 macro_rules! call_consensus {
-    ($self:ident, $req_name:expr, $resp_type:ident, $consensus_call:expr) => {
+    ($self:ident, $req:ident, $req_name:expr, $resp_type:ident, $consensus_call:expr) => {
+        call_consensus!(@with_container $self, $req_name, $resp_type, container, {
+            let deadline = request_deadline(&$req);
+            $self
+                .query_pool
+                .run($req_name, deadline, move || $consensus_call(container.as_ref()))
+                .await?
+        })
+    };
+    // a call changing the state of the node has no deadline, as it can't be
+    // stopped once it is started
+    (update $self:ident, $req_name:expr, $resp_type:ident, $consensus_call:expr) => {
+        call_consensus!(@with_container $self, $req_name, $resp_type, container, {
+            $self
+                .query_pool
+                .run_update($req_name, move || $consensus_call(container.as_ref()))
+                .await?
+        })
+    };
+    (
+        @with_container $self:ident,
+        $req_name:expr,
+        $resp_type:ident,
+        $container:ident,
+        $value:expr
+    ) => {
         if let Some(ref container) = $self.consensus {
-            if !container.consensus.load(Ordering::Relaxed).is_null() {
+            if container.is_running() {
+                let $container = Arc::clone(container);
+                let value = $value;
                 Ok(Response::new($resp_type {
-                    value: $consensus_call(container),
+                    value,
                 }))
             } else {
                 warn!("Can't respond to a {} request due to uninitialized Consensus", $req_name);
@@ -98,6 +368,187 @@ macro_rules! call_consensus {
     };
 }
 
+/// Like `call_consensus`, but the response is cached if the queried block is
+/// finalized.
+macro_rules! call_consensus_cached {
+    (
+        $self:ident,
+        $req:ident,
+        $req_name:expr,
+        $resp_type:ident,
+        $block_hash:expr,
+        $argument:expr,
+        $consensus_call:expr
+    ) => {{
+        let cache = Arc::clone(&$self.query_cache);
+        call_consensus!($self, $req, $req_name, $resp_type, |cc: &dyn Consensus| {
+            cache.get_or_query($req_name, $block_hash, $argument, || $consensus_call(cc))
+        })
+    }};
+}
+
+/// The maximum number of transactions accepted in a single batch submission.
+pub(crate) const MAX_TRANSACTION_BATCH_SIZE: usize = 1000;
+
+/// The gRPC-Web configuration allowing cross-origin requests from the given
+/// origins, where `*` stands for any origin.
+fn grpc_web_config(origins: &[String]) -> tonic_web::Config {
+    if origins.iter().any(|origin| origin == "*") {
+        tonic_web::config().allow_all_origins()
+    } else {
+        tonic_web::config().allow_origins(origins.iter().cloned())
+    }
+}
+
+/// Parses the id of a peer, as reported by `PeerList`.
+fn parse_node_id(node_id: &str) -> Result<P2PNodeId, Status> {
+    P2PNodeId::from_str(node_id)
+        .map_err(|_| Status::new(Code::InvalidArgument, "Malformed node ID."))
+}
+
+/// The number of items in a page if the client does not ask for a particular
+/// number.
+const DEFAULT_PAGE_SIZE: u64 = 100;
+
+/// The maximum number of items in a page.
+const MAX_PAGE_SIZE: u64 = 1000;
+
+fn page_limit(limit: u64) -> u64 {
+    match limit {
+        0 => DEFAULT_PAGE_SIZE,
+        limit => limit.min(MAX_PAGE_SIZE),
+    }
+}
+
+/// Parses the cursor of a page of contract instances, which is the index of the
+/// first instance in the page.
+fn parse_instance_cursor(cursor: &str) -> Result<u64, Status> {
+    if cursor.is_empty() {
+        Ok(0)
+    } else {
+        cursor.parse().map_err(|_| Status::invalid_argument("Invalid cursor."))
+    }
+}
+
+/// Splits a page returned by consensus into the JSON list of its items and the
+/// cursor of the next page, if there is one.
+fn parse_page(page: &str) -> Result<(String, Option<String>), Status> {
+    let mut page = match serde_json::from_str::<serde_json::Value>(page) {
+        Ok(serde_json::Value::Object(page)) => page,
+        // consensus responds with null if the block does not exist, and with a
+        // string if the block hash is invalid
+        Ok(serde_json::Value::Null) => {
+            return Err(Status::not_found("The block could not be found."))
+        }
+        Ok(serde_json::Value::String(message)) => return Err(Status::invalid_argument(message)),
+        _ => return Err(Status::internal("Malformed response from consensus.")),
+    };
+    let items = page.remove("items").unwrap_or_else(|| serde_json::Value::Array(Vec::new()));
+    let next = match page.remove("next") {
+        Some(serde_json::Value::String(next)) => Some(next),
+        Some(serde_json::Value::Number(next)) => Some(next.to_string()),
+        _ => None,
+    };
+    Ok((items.to_string(), next))
+}
+
+fn check_transaction_size(transaction: &[u8]) -> Result<(), Status> {
+    if transaction.len() > configuration::PROTOCOL_MAX_TRANSACTION_SIZE {
+        warn!("Received a transaction that exceeds maximum transaction size.");
+        Err(Status::invalid_argument("Transaction size exceeds maximum allowed size."))
+    } else {
+        Ok(())
+    }
+}
+
+/// Hands a transaction to consensus and, if it was accepted, enqueues it for
+/// broadcasting to our peers. Malformed and expired transactions are rejected
+/// without involving consensus. An error is only returned if the transaction
+/// could not be enqueued.
+fn submit_transaction(
+    consensus: &dyn Consensus,
+    transaction: &[u8],
+) -> anyhow::Result<ConsensusFfiResponse> {
+    if let Err(rejection) = check_transaction(transaction, get_current_stamp() / 1000) {
+        debug!("Rejecting a transaction submitted via RPC: {}", rejection);
+        return Ok(rejection.response());
+    }
+    send_checked_transaction(consensus, transaction)
+}
+
+/// Hands a transaction that passed `check_transaction` to consensus and, if it
+/// was accepted, enqueues it for broadcasting to our peers.
+fn send_checked_transaction(
+    consensus: &dyn Consensus,
+    transaction: &[u8],
+) -> anyhow::Result<ConsensusFfiResponse> {
+    let consensus_result = consensus.send_transaction(transaction);
+
+    if consensus_result == ConsensusFfiResponse::Success {
+        let mut payload = Vec::with_capacity(1 + transaction.len());
+        payload.write_u8(PacketType::Transaction as u8)?;
+        payload.write_all(&transaction)?;
+
+        CALLBACK_QUEUE.send_out_message(ConsensusMessage::new(
+            MessageType::Outbound(None),
+            PacketType::Transaction,
+            Arc::from(payload),
+            vec![],
+            None,
+        ))?;
+    }
+    Ok(consensus_result)
+}
+
+/// Submits a transaction and reports the outcome together with the hash of the
+/// transaction and, if it was rejected before reaching consensus, the reason.
+fn submit_transaction_detailed(
+    consensus: &dyn Consensus,
+    transaction: &[u8],
+) -> SendTransactionResponse {
+    use send_transaction_response::Outcome;
+    use ConsensusFfiResponse::*;
+
+    // the hash is empty if the transaction cannot even be split into the version
+    // and the block item, in which case consensus rejects it as malformed
+    let transaction_hash =
+        versioned_transaction_hash(transaction).map(|hash| hash.to_string()).unwrap_or_default();
+    let (result, message) = match check_transaction(transaction, get_current_stamp() / 1000) {
+        Ok(()) => (send_checked_transaction(consensus, transaction), String::new()),
+        Err(rejection) => {
+            debug!("Rejecting a transaction submitted via RPC: {}", rejection);
+            (Ok(rejection.response()), rejection.to_string())
+        }
+    };
+    let outcome = match result {
+        Ok(Success) => Outcome::Success,
+        Ok(DuplicateEntry) => Outcome::DuplicateEntry,
+        Ok(Stale) => Outcome::Stale,
+        Ok(TooLowEnergy) => Outcome::TooLowEnergy,
+        Ok(ExpiryTooLate) => Outcome::ExpiryTooLate,
+        Ok(NonexistingSenderAccount) => Outcome::NonexistingSenderAccount,
+        Ok(DeserializationError) => Outcome::DeserializationError,
+        Ok(InvalidResult) => Outcome::InvalidResult,
+        Ok(VerificationFailed) => Outcome::VerificationFailed,
+        Ok(DuplicateNonce) => Outcome::DuplicateNonce,
+        Ok(NonceTooLarge) => Outcome::NonceTooLarge,
+        Ok(ConsensusShutDown) => Outcome::ConsensusShutDown,
+        Ok(e) => {
+            warn!("Consensus didn't accept a transaction via RPC due to {:?}", e);
+            Outcome::InternalError
+        }
+        Err(e) => {
+            warn!("Couldn't put a transaction in the outbound queue due to {:?}", e);
+            Outcome::InternalError
+        }
+    };
+    SendTransactionResponse {
+        outcome: outcome as i32,
+        transaction_hash,
+        message,
+    }
+}
+
 /// Enhances a request with an authentication token.
 #[macro_export]
 macro_rules! req_with_auth {
@@ -110,62 +561,77 @@ macro_rules! req_with_auth {
 
 #[tonic::async_trait]
 impl P2p for RpcServerImpl {
+    type CreateSnapshotStream = JobStream;
+    type ExportBlocksStream = JobStream;
+    type StreamAccountListStream = ReceiverStream<Result<JsonResponse, Status>>;
+    type StreamConsensusEventsStream = ReceiverStream<Result<JsonResponse, Status>>;
+    type StreamInstancesStream = ReceiverStream<Result<JsonResponse, Status>>;
+
     async fn peer_connect(
         &self,
         req: Request<PeerConnectRequest>,
     ) -> Result<Response<BoolResponse>, Status> {
-        authenticate!(req, self.access_token);
-        let req = req.get_ref();
+        audited!(self, req, "PeerConnect", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            let req = req.get_ref();
 
-        let ip = if let Some(ref ip) = req.ip {
-            IpAddr::from_str(ip)
-                .map_err(|_| Status::new(Code::InvalidArgument, "Invalid IP address"))
-        } else {
-            Err(Status::new(Code::InvalidArgument, "Missing IP address"))
-        }?;
-        let port = if let Some(port) = req.port {
-            port as u16
-        } else {
-            return Err(Status::new(Code::InvalidArgument, "Missing port"));
-        };
-        let addr = SocketAddr::new(ip, port);
-        self.node.register_conn_change(ConnChange::NewConn {
-            addr,
-            peer_type: PeerType::Node,
-            given: true,
-        });
-        Ok(Response::new(BoolResponse {
-            value: true,
-        }))
+            let ip = if let Some(ref ip) = req.ip {
+                IpAddr::from_str(ip)
+                    .map_err(|_| Status::new(Code::InvalidArgument, "Invalid IP address"))
+            } else {
+                Err(Status::new(Code::InvalidArgument, "Missing IP address"))
+            }?;
+            let port = if let Some(port) = req.port {
+                port as u16
+            } else {
+                return Err(Status::new(Code::InvalidArgument, "Missing port"));
+            };
+            let addr = SocketAddr::new(ip, port);
+            self.node.register_conn_change(ConnChange::NewConn {
+                addr,
+                peer_type: PeerType::Node,
+                given: true,
+            });
+            Ok(Response::new(BoolResponse {
+                value: true,
+            }))
+        })
     }
 
     async fn peer_disconnect(
         &self,
         req: Request<PeerConnectRequest>,
     ) -> Result<Response<BoolResponse>, Status> {
-        authenticate!(req, self.access_token);
-        let req = req.get_ref();
+        audited!(self, req, "PeerDisconnect", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            let req = req.get_ref();
 
-        let ip_addr = if let Some(ref ip) = req.ip {
-            IpAddr::from_str(ip)
-                .map_err(|_| Status::new(Code::InvalidArgument, "Invalid IP address"))
-        } else {
-            Err(Status::new(Code::InvalidArgument, "Missing IP address"))
-        }?;
-        if let Some(port) = req.port {
-            match port.try_into() {
-                Ok(x) => Ok(Response::new(BoolResponse {
-                    value: self.node.drop_addr(SocketAddr::new(ip_addr, x)),
-                })),
-                Err(_) => Err(Status::new(Code::InvalidArgument, "Port out of range.")),
+            let ip_addr = if let Some(ref ip) = req.ip {
+                IpAddr::from_str(ip)
+                    .map_err(|_| Status::new(Code::InvalidArgument, "Invalid IP address"))
+            } else {
+                Err(Status::new(Code::InvalidArgument, "Missing IP address"))
+            }?;
+            if let Some(port) = req.port {
+                match port.try_into() {
+                    Ok(x) => Ok(Response::new(BoolResponse {
+                        value: self.node.drop_addr(SocketAddr::new(ip_addr, x)),
+                    })),
+                    Err(_) => Err(Status::new(Code::InvalidArgument, "Port out of range.")),
+                }
+            } else {
+                Err(Status::new(Code::InvalidArgument, "Disconnect is only supported by address."))
             }
-        } else {
-            Err(Status::new(Code::InvalidArgument, "Disconnect is only supported by address."))
-        }
+        })
     }
 
     async fn peer_version(&self, req: Request<Empty>) -> Result<Response<StringResponse>, Status> {
         authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
         let resp = StringResponse {
             value: crate::VERSION.to_owned(),
         };
@@ -177,6 +643,7 @@ impl P2p for RpcServerImpl {
         req: Request<Empty>,
     ) -> Result<tonic::Response<NumberResponse>, Status> {
         authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
         Ok(Response::new(NumberResponse {
             value: self.node.get_uptime() as u64,
         }))
@@ -187,6 +654,7 @@ impl P2p for RpcServerImpl {
         req: Request<Empty>,
     ) -> Result<Response<NumberResponse>, Status> {
         authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
         let value = self.node.connection_handler.total_received.load(Ordering::Relaxed);
         Ok(Response::new(NumberResponse {
             value,
@@ -198,6 +666,7 @@ impl P2p for RpcServerImpl {
         req: Request<Empty>,
     ) -> Result<Response<NumberResponse>, Status> {
         authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
         let value = self.node.connection_handler.total_sent.load(Ordering::Relaxed);
         Ok(Response::new(NumberResponse {
             value,
@@ -211,32 +680,18 @@ impl P2p for RpcServerImpl {
         use ConsensusFfiResponse::*;
 
         authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Transaction)?;
+        self.check_not_draining()?;
         if let Some(ref consensus) = self.consensus {
-            let req = req.get_ref();
-            let transaction = &req.payload;
-            if transaction.len() > configuration::PROTOCOL_MAX_TRANSACTION_SIZE {
-                warn!("Received a transaction that exceeds maximum transaction size.");
-                return Err(Status::invalid_argument(
-                    "Transaction size exceeds maximum allowed size.",
-                ));
-            }
-            let consensus_result = consensus.send_transaction(transaction);
-
-            let result = if consensus_result == Success {
-                let mut payload = Vec::with_capacity(1 + transaction.len());
-                payload.write_u8(PacketType::Transaction as u8)?;
-                payload.write_all(&transaction)?;
-
-                CALLBACK_QUEUE.send_out_message(ConsensusMessage::new(
-                    MessageType::Outbound(None),
-                    PacketType::Transaction,
-                    Arc::from(payload),
-                    vec![],
-                    None,
-                ))
-            } else {
-                Ok(())
-            };
+            check_transaction_size(&req.get_ref().payload)?;
+            let consensus = Arc::clone(consensus);
+            let transaction = req.into_inner().payload;
+            let result = self
+                .query_pool
+                .run_update("SendTransaction", move || {
+                    submit_transaction(consensus.as_ref(), &transaction)
+                })
+                .await?;
             // make the successful response. If the transaction was added
             // and retransmitted then the response is true, otherwise
             // we respond with false
@@ -245,27 +700,27 @@ impl P2p for RpcServerImpl {
                     value,
                 })
             };
-            match (result, consensus_result) {
-                (Ok(_), Success) => Ok(mk_response(true)),
-                (Ok(_), DuplicateEntry) => Ok(mk_response(false)),
-                (Ok(_), DeserializationError) => Ok(mk_response(false)),
-                (Ok(_), Stale) => Ok(mk_response(false)),
-                (Ok(_), InvalidResult) => Ok(mk_response(false)),
-                (Ok(_), TooLowEnergy) => Ok(mk_response(false)),
-                (Ok(_), ExpiryTooLate) => Ok(mk_response(false)),
-                (Ok(_), NonexistingSenderAccount) => Ok(mk_response(false)),
-                (Err(e), Success) => {
-                    warn!("Couldn't put a transaction in the outbound queue due to {:?}", e);
+            match result {
+                Ok(Success) => Ok(mk_response(true)),
+                Ok(DuplicateEntry) => Ok(mk_response(false)),
+                Ok(DeserializationError) => Ok(mk_response(false)),
+                Ok(Stale) => Ok(mk_response(false)),
+                Ok(InvalidResult) => Ok(mk_response(false)),
+                Ok(TooLowEnergy) => Ok(mk_response(false)),
+                Ok(ExpiryTooLate) => Ok(mk_response(false)),
+                Ok(NonexistingSenderAccount) => Ok(mk_response(false)),
+                Ok(e) => {
+                    warn!("Consensus didn't accept a transaction via RPC due to {:?}", e);
                     Err(Status::new(
                         Code::Internal,
-                        format!("Couldn't put a transaction in the outbound queue due to {:?}", e),
+                        format!("Consensus didn't accept a transaction via RPC due to {:?}", e),
                     ))
                 }
-                (_, e) => {
-                    warn!("Consensus didn't accept a transaction via RPC due to {:?}", e);
+                Err(e) => {
+                    warn!("Couldn't put a transaction in the outbound queue due to {:?}", e);
                     Err(Status::new(
                         Code::Internal,
-                        format!("Consensus didn't accept a transaction via RPC due to {:?}", e),
+                        format!("Couldn't put a transaction in the outbound queue due to {:?}", e),
                     ))
                 }
             }
@@ -275,48 +730,125 @@ impl P2p for RpcServerImpl {
         }
     }
 
+    async fn send_transaction_detailed(
+        &self,
+        req: Request<SendTransactionRequest>,
+    ) -> Result<Response<SendTransactionResponse>, Status> {
+        authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Transaction)?;
+        self.check_not_draining()?;
+        if let Some(ref consensus) = self.consensus {
+            check_transaction_size(&req.get_ref().payload)?;
+            let consensus = Arc::clone(consensus);
+            let transaction = req.into_inner().payload;
+            let response = self
+                .query_pool
+                .run_update("SendTransactionDetailed", move || {
+                    submit_transaction_detailed(consensus.as_ref(), &transaction)
+                })
+                .await?;
+            Ok(Response::new(response))
+        } else {
+            warn!("Can't respond to a SendTransactionDetailed request due to stopped Consensus");
+            Err(Status::new(Code::Internal, "Consensus container is not initialized!"))
+        }
+    }
+
+    async fn send_transaction_batch(
+        &self,
+        req: Request<SendTransactionBatchRequest>,
+    ) -> Result<Response<SendTransactionBatchResponse>, Status> {
+        authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Transaction)?;
+        self.check_not_draining()?;
+        if let Some(ref consensus) = self.consensus {
+            let transactions = &req.get_ref().payloads;
+            if transactions.len() > MAX_TRANSACTION_BATCH_SIZE {
+                warn!("Received a transaction batch that exceeds the maximum batch size.");
+                return Err(Status::invalid_argument(format!(
+                    "A batch can contain at most {} transactions.",
+                    MAX_TRANSACTION_BATCH_SIZE
+                )));
+            }
+            // reject the whole batch up front so that either all or none of the
+            // transactions are handed to consensus
+            for transaction in transactions {
+                check_transaction_size(transaction)?;
+            }
+            // the batch is submitted as a single job on the query pool, as it can take
+            // up to MAX_TRANSACTION_BATCH_SIZE calls into consensus
+            let consensus = Arc::clone(consensus);
+            let transactions = req.into_inner().payloads;
+            let results = self
+                .query_pool
+                .run_update("SendTransactionBatch", move || {
+                    transactions
+                        .iter()
+                        .map(|transaction| {
+                            submit_transaction_detailed(consensus.as_ref(), transaction)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .await?;
+            Ok(Response::new(SendTransactionBatchResponse {
+                results,
+            }))
+        } else {
+            warn!("Can't respond to a SendTransactionBatch request due to stopped Consensus");
+            Err(Status::new(Code::Internal, "Consensus container is not initialized!"))
+        }
+    }
+
     async fn join_network(
         &self,
         req: Request<NetworkChangeRequest>,
     ) -> Result<Response<BoolResponse>, Status> {
-        authenticate!(req, self.access_token);
-        let req = req.get_ref();
-        if let Some(id) = req.network_id {
-            if id > 0 && id < 100_000 {
-                info!("Attempting to join network {}", id);
-                let network_id = NetworkId::from(id as u16);
-                self.node.send_join_network(network_id);
-                Ok(Response::new(BoolResponse {
-                    value: true,
-                }))
+        audited!(self, req, "JoinNetwork", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            let req = req.get_ref();
+            if let Some(id) = req.network_id {
+                if id > 0 && id < 100_000 {
+                    info!("Attempting to join network {}", id);
+                    let network_id = NetworkId::from(id as u16);
+                    self.node.send_join_network(network_id);
+                    Ok(Response::new(BoolResponse {
+                        value: true,
+                    }))
+                } else {
+                    Err(Status::new(Code::InvalidArgument, "Invalid network id"))
+                }
             } else {
-                Err(Status::new(Code::InvalidArgument, "Invalid network id"))
+                Err(Status::new(Code::InvalidArgument, "Missing network id"))
             }
-        } else {
-            Err(Status::new(Code::InvalidArgument, "Missing network id"))
-        }
+        })
     }
 
     async fn leave_network(
         &self,
         req: Request<NetworkChangeRequest>,
     ) -> Result<Response<BoolResponse>, Status> {
-        authenticate!(req, self.access_token);
-        let req = req.get_ref();
-        if let Some(id) = req.network_id {
-            if id > 0 && id < 100_000 {
-                info!("Attempting to leave network {}", id);
-                let network_id = NetworkId::from(id as u16);
-                self.node.send_leave_network(network_id);
-                Ok(Response::new(BoolResponse {
-                    value: true,
-                }))
+        audited!(self, req, "LeaveNetwork", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            let req = req.get_ref();
+            if let Some(id) = req.network_id {
+                if id > 0 && id < 100_000 {
+                    info!("Attempting to leave network {}", id);
+                    let network_id = NetworkId::from(id as u16);
+                    self.node.send_leave_network(network_id);
+                    Ok(Response::new(BoolResponse {
+                        value: true,
+                    }))
+                } else {
+                    Err(Status::new(Code::InvalidArgument, "Invalid network id"))
+                }
             } else {
-                Err(Status::new(Code::InvalidArgument, "Invalid network id"))
+                Err(Status::new(Code::InvalidArgument, "Missing network id"))
             }
-        } else {
-            Err(Status::new(Code::InvalidArgument, "Missing network id"))
-        }
+        })
     }
 
     async fn peer_stats(
@@ -324,6 +856,7 @@ impl P2p for RpcServerImpl {
         req: Request<PeersRequest>,
     ) -> Result<Response<PeerStatsResponse>, Status> {
         authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
         let peer_stats = self.node.get_peer_stats(None);
         let peerstats = peer_stats
             .into_iter()
@@ -346,11 +879,198 @@ impl P2p for RpcServerImpl {
         }))
     }
 
+    async fn get_peer_details(
+        &self,
+        req: Request<GetPeerDetailsRequest>,
+    ) -> Result<Response<JsonResponse>, Status> {
+        authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+        let node_id = parse_node_id(&req.get_ref().node_id)?;
+        let details = self
+            .node
+            .get_peer_details(node_id)
+            .ok_or_else(|| Status::not_found("Not connected to the given peer."))?;
+        Ok(Response::new(JsonResponse {
+            value: serde_json::to_string(&details)
+                .map_err(|_| Status::internal("Could not serialize the peer details."))?,
+        }))
+    }
+
+    async fn get_catch_up_status(
+        &self,
+        req: Request<Empty>,
+    ) -> Result<Response<JsonResponse>, Status> {
+        authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+        let overview = catch_up_overview(&self.node);
+        Ok(Response::new(JsonResponse {
+            value: serde_json::to_string(&overview)
+                .map_err(|_| Status::internal("Could not serialize the catch-up status."))?,
+        }))
+    }
+
+    async fn stream_consensus_events(
+        &self,
+        req: Request<Empty>,
+    ) -> Result<Response<Self::StreamConsensusEventsStream>, Status> {
+        authenticate!(req, self.access_token);
+        let permit = RpcLimiter::acquire_owned(&self.limiter, &req, MethodClass::Node)?;
+        // subscribing before reading the genesis blocks and the protocol update
+        // ensures that none are missed
+        let mut events = self.node.subscribe_consensus_events();
+        let genesis_blocks = self.node.genesis_blocks();
+        let mut protocol_update = self.node.protocol_update();
+        let timestamp = get_current_stamp();
+        let (sender, receiver) = mpsc::channel(CONSENSUS_EVENTS_CAPACITY);
+        tokio::spawn(async move {
+            // the stream counts towards the limits for as long as it runs
+            let _permit = permit;
+            let event_response = |event: &ConsensusEvent| JsonResponse {
+                value: serde_json::to_string(event).unwrap_or_default(),
+            };
+            // the stream starts with the genesis blocks known so far
+            for (genesis_index, genesis_block) in genesis_blocks.iter().cloned().enumerate() {
+                let event = ConsensusEvent::Regenesis {
+                    genesis_index,
+                    genesis_block,
+                    timestamp,
+                };
+                if sender.send(Ok(event_response(&event))).await.is_err() {
+                    return;
+                }
+            }
+            // and the current protocol update, once consensus reported one
+            if let Some(ref protocol_update) = protocol_update {
+                let event = ConsensusEvent::ProtocolUpdate {
+                    protocol_update: protocol_update.clone(),
+                    timestamp,
+                };
+                if sender.send(Ok(event_response(&event))).await.is_err() {
+                    return;
+                }
+            }
+            loop {
+                // the client is gone if the receiver was dropped
+                let event = tokio::select! {
+                    event = events.recv() => event,
+                    _ = sender.closed() => break,
+                };
+                match event {
+                    Ok(ConsensusEvent::Regenesis {
+                        genesis_index,
+                        ..
+                    }) if genesis_index < genesis_blocks.len() => {}
+                    Ok(ConsensusEvent::ProtocolUpdate {
+                        protocol_update: ref update,
+                        ..
+                    }) if protocol_update.as_ref() == Some(update) => {}
+                    Ok(event) => {
+                        if let ConsensusEvent::ProtocolUpdate {
+                            protocol_update: ref update,
+                            ..
+                        } = event
+                        {
+                            protocol_update = Some(update.clone());
+                        }
+                        if sender.send(Ok(event_response(&event))).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        let _ = sender
+                            .send(Err(Status::data_loss(format!(
+                                "{} consensus events were skipped",
+                                skipped
+                            ))))
+                            .await;
+                        break;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn catch_up_with_peer(
+        &self,
+        req: Request<CatchUpPeerRequest>,
+    ) -> Result<Response<BoolResponse>, Status> {
+        audited!(self, req, "CatchUpWithPeer", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            let node_id = parse_node_id(&req.get_ref().node_id)?;
+            let container = match self.consensus {
+                Some(ref container) => Arc::clone(container),
+                None => {
+                    error!("Consensus container not supplied; is this a gRPC unit test?");
+                    return Err(Status::new(
+                        Code::FailedPrecondition,
+                        "The consensus container is missing!",
+                    ));
+                }
+            };
+            // catching up changes the state of the node and can't be stopped once it
+            // is started, so it has no deadline
+            let node = Arc::clone(&self.node);
+            self.query_pool
+                .run_update("CatchUpWithPeer", move || {
+                    catch_up_with_peer(&node, container.as_ref(), node_id)
+                })
+                .await?
+                .map_err(|e| Status::failed_precondition(e.to_string()))?;
+            Ok(Response::new(BoolResponse {
+                value: true,
+            }))
+        })
+    }
+
+    async fn reset_catch_up_peer(
+        &self,
+        req: Request<CatchUpPeerRequest>,
+    ) -> Result<Response<BoolResponse>, Status> {
+        audited!(self, req, "ResetCatchUpPeer", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            let node_id = parse_node_id(&req.get_ref().node_id)?;
+            reset_catch_up_peer(&self.node, node_id)
+                .map_err(|e| Status::failed_precondition(e.to_string()))?;
+            Ok(Response::new(BoolResponse {
+                value: true,
+            }))
+        })
+    }
+
+    async fn pin_catch_up_peers(
+        &self,
+        req: Request<PinCatchUpPeersRequest>,
+    ) -> Result<Response<BoolResponse>, Status> {
+        audited!(self, req, "PinCatchUpPeers", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            let node_ids = req
+                .get_ref()
+                .node_ids
+                .iter()
+                .map(|id| parse_node_id(id))
+                .collect::<Result<Vec<_>, _>>()?;
+            pin_catch_up_peers(&self.node, &node_ids)
+                .map_err(|e| Status::failed_precondition(e.to_string()))?;
+            Ok(Response::new(BoolResponse {
+                value: true,
+            }))
+        })
+    }
+
     async fn peer_list(
         &self,
         req: Request<PeersRequest>,
     ) -> Result<Response<PeerListResponse>, Status> {
         authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
         let peer_catchup_stats = (*read_or_die!(self.node.peers)).peer_states.clone();
         let list = self
             .node
@@ -382,20 +1102,27 @@ impl P2p for RpcServerImpl {
                          // specification.
     async fn node_info(&self, req: Request<Empty>) -> Result<Response<NodeInfoResponse>, Status> {
         authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
         let node_id = Some(self.node.id().to_string());
         let peer_type = self.node.peer_type().to_string();
         let current_localtime =
             SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
         Ok(Response::new(match self.consensus {
             Some(ref consensus) => {
-                let consensus_baking_committee_status = consensus.in_baking_committee();
+                let committees = Arc::clone(consensus);
+                let (consensus_baking_committee_status, consensus_finalizer_committee) = self
+                    .query_pool
+                    .run("NodeInfo", request_deadline(&req), move || {
+                        (committees.in_baking_committee(), committees.in_finalization_committee())
+                    })
+                    .await?;
                 NodeInfoResponse {
                     node_id,
                     current_localtime,
                     peer_type,
                     consensus_baker_running: consensus.is_baking(),
                     consensus_running: true,
-                    consensus_type: consensus.consensus_type.to_string(),
+                    consensus_type: consensus.consensus_type().to_string(),
                     consensus_baker_committee: match consensus_baking_committee_status {
                         ConsensusIsInBakingCommitteeResponse::ActiveInCommittee(_) => {
                             node_info_response::IsInBakingCommittee::ActiveInCommittee.into()
@@ -411,7 +1138,7 @@ impl P2p for RpcServerImpl {
                             node_info_response::IsInBakingCommittee::NotInCommittee.into()
                         }
                     },
-                    consensus_finalizer_committee: consensus.in_finalization_committee(),
+                    consensus_finalizer_committee,
                     consensus_baker_id: match consensus_baking_committee_status {
                         ConsensusIsInBakingCommitteeResponse::ActiveInCommittee(baker_id) => {
                             Some(baker_id)
@@ -438,72 +1165,80 @@ impl P2p for RpcServerImpl {
     }
 
     async fn ban_node(&self, req: Request<PeerElement>) -> Result<Response<BoolResponse>, Status> {
-        authenticate!(req, self.access_token);
-        let req = req.get_ref();
-        let banned_node = match (&req.node_id, &req.ip) {
-            (Some(node_id), None) => {
-                if let Ok(id) = RemotePeerId::from_str(&node_id.to_string()) {
-                    Ok(self.node.drop_by_id(id))
-                } else {
-                    return Err(Status::new(Code::InvalidArgument, "Malformed node ID."));
+        audited!(self, req, "BanNode", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            let req = req.get_ref();
+            let banned_node = match (&req.node_id, &req.ip) {
+                (Some(node_id), None) => {
+                    if let Ok(id) = RemotePeerId::from_str(&node_id.to_string()) {
+                        Ok(self.node.drop_by_id(id))
+                    } else {
+                        return Err(Status::new(Code::InvalidArgument, "Malformed node ID."));
+                    }
                 }
-            }
-            (None, Some(ip)) => {
-                if let Ok(ip) = IpAddr::from_str(&ip.to_string()) {
-                    self.node.drop_by_ip_and_ban(ip)
-                } else {
-                    return Err(Status::new(Code::InvalidArgument, "Malformed IP address."));
+                (None, Some(ip)) => {
+                    if let Ok(ip) = IpAddr::from_str(&ip.to_string()) {
+                        self.node.drop_by_ip_and_ban(ip)
+                    } else {
+                        return Err(Status::new(Code::InvalidArgument, "Malformed IP address."));
+                    }
                 }
-            }
-            _ => {
-                return Err(Status::new(
-                    Code::InvalidArgument,
-                    "Exactly one of IP or node ID must be provided.",
-                ))
-            }
-        };
+                _ => {
+                    return Err(Status::new(
+                        Code::InvalidArgument,
+                        "Exactly one of IP or node ID must be provided.",
+                    ))
+                }
+            };
 
-        match banned_node {
-            Ok(value) => Ok(Response::new(BoolResponse {
-                value,
-            })),
-            Err(e) => {
-                warn!("couldn't fulfill a BanNode request: {}", e);
-                Err(Status::new(
-                    Code::Aborted,
-                    format!("couldn't fulfill a BanNode request: {}", e),
-                ))
+            match banned_node {
+                Ok(value) => Ok(Response::new(BoolResponse {
+                    value,
+                })),
+                Err(e) => {
+                    warn!("couldn't fulfill a BanNode request: {}", e);
+                    Err(Status::new(
+                        Code::Aborted,
+                        format!("couldn't fulfill a BanNode request: {}", e),
+                    ))
+                }
             }
-        }
+        })
     }
 
     async fn unban_node(
         &self,
         req: Request<PeerElement>,
     ) -> Result<Response<BoolResponse>, Status> {
-        authenticate!(req, self.access_token);
-        let req = req.get_ref();
-        let banned_node = match req.ip {
-            Some(ref ip) => IpAddr::from_str(&ip.to_string()).ok().map(PersistedBanId::Ip),
-            _ => None,
-        };
+        audited!(self, req, "UnbanNode", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            let req = req.get_ref();
+            let banned_node = match req.ip {
+                Some(ref ip) => IpAddr::from_str(&ip.to_string()).ok().map(PersistedBanId::Ip),
+                _ => None,
+            };
 
-        if let Some(to_unban) = banned_node {
-            match self.node.unban_node(to_unban) {
-                Ok(_) => Ok(Response::new(BoolResponse {
-                    value: true,
-                })),
-                Err(e) => {
-                    warn!("couldn't fulfill an UnbanNode request: {}", e);
-                    Err(Status::new(
-                        Code::Aborted,
-                        format!("couldn't fulfill a UnbanNode request: {}", e),
-                    ))
+            if let Some(to_unban) = banned_node {
+                match self.node.unban_node(to_unban) {
+                    Ok(_) => Ok(Response::new(BoolResponse {
+                        value: true,
+                    })),
+                    Err(e) => {
+                        warn!("couldn't fulfill an UnbanNode request: {}", e);
+                        Err(Status::new(
+                            Code::Aborted,
+                            format!("couldn't fulfill a UnbanNode request: {}", e),
+                        ))
+                    }
                 }
+            } else {
+                Err(Status::new(Code::InvalidArgument, "Missing IP or address to unban"))
             }
-        } else {
-            Err(Status::new(Code::InvalidArgument, "Missing IP or address to unban"))
-        }
+        })
     }
 
     async fn get_consensus_status(
@@ -511,80 +1246,350 @@ impl P2p for RpcServerImpl {
         req: Request<Empty>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetConsensusStatus", JsonResponse, |cc: &ConsensusContainer| {
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        call_consensus!(self, req, "GetConsensusStatus", JsonResponse, |cc: &dyn Consensus| {
             cc.get_consensus_status()
         })
     }
 
     async fn start_baker(&self, req: Request<Empty>) -> Result<Response<BoolResponse>, Status> {
-        authenticate!(req, self.access_token);
-        call_consensus!(self, "StartBaker", BoolResponse, |cc: &ConsensusContainer| {
-            cc.start_baker()
+        audited!(self, req, "StartBaker", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            call_consensus!(update self, "StartBaker", BoolResponse, |cc: &dyn Consensus| {
+                cc.start_baker()
+            })
         })
     }
 
     async fn stop_baker(&self, req: Request<Empty>) -> Result<Response<BoolResponse>, Status> {
+        audited!(self, req, "StopBaker", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            call_consensus!(update self, "StopBaker", BoolResponse, |cc: &dyn Consensus| {
+                cc.stop_baker()
+            })
+        })
+    }
+
+    async fn set_baker_credentials(
+        &self,
+        req: Request<SetBakerCredentialsRequest>,
+    ) -> Result<Response<BoolResponse>, Status> {
+        let arguments = serde_json::json!({ "encrypted": !req.get_ref().password.is_empty() });
+        audited!(self, req, "SetBakerCredentials", BoolResponse, arguments, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            // the keys are secret whether they are encrypted or not, and only the admin
+            // socket keeps them from being observed in transit
+            if !self.on_admin_socket {
+                return Err(Status::permission_denied(
+                    "Baker credentials are only accepted on the admin socket.",
+                ));
+            }
+            let container = match self.consensus {
+                Some(ref container) if container.is_running() => Arc::clone(container),
+                Some(_) => {
+                    return Err(Status::new(
+                        Code::Internal,
+                        "The consensus layer has not been initialized!",
+                    ))
+                }
+                None => {
+                    error!("Consensus container not supplied; is this a gRPC unit test?");
+                    return Err(Status::new(
+                        Code::FailedPrecondition,
+                        "The consensus container is missing!",
+                    ));
+                }
+            };
+            let SetBakerCredentialsRequest {
+                credentials,
+                password,
+            } = req.get_ref().clone();
+            // the credentials are replaced even if the deadline of the request expires;
+            // decrypting them runs a deliberately slow key derivation, so it is done on
+            // the query pool as well
+            let response = self
+                .query_pool
+                .run_update("SetBakerCredentials", move || {
+                    let credentials = if password.is_empty() {
+                        credentials
+                    } else {
+                        decrypt_baker_credentials(&credentials, password)
+                            .map_err(|e| Status::invalid_argument(e.to_string()))?
+                    };
+                    Ok::<_, Status>(container.set_baker_credentials(&credentials))
+                })
+                .await??;
+            match response {
+                SetBakerCredentialsResponse::Success => {
+                    info!("Replaced the baker credentials");
+                    Ok(Response::new(BoolResponse {
+                        value: true,
+                    }))
+                }
+                SetBakerCredentialsResponse::InvalidCredentials => {
+                    Err(Status::invalid_argument("The baker credentials are malformed."))
+                }
+                SetBakerCredentialsResponse::NotABaker => Err(Status::failed_precondition(
+                    "The node was started without baker credentials.",
+                )),
+                SetBakerCredentialsResponse::WrongKeys => Err(Status::failed_precondition(
+                    "The keys do not match the keys of the baker on the chain.",
+                )),
+            }
+        })
+    }
+
+    async fn get_branches(&self, req: Request<Empty>) -> Result<Response<JsonResponse>, Status> {
+        authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Expensive)?;
+        call_consensus!(self, req, "GetBranches", JsonResponse, |cc: &dyn Consensus| {
+            cc.get_branches()
+        })
+    }
+
+    async fn get_block_info(
+        &self,
+        req: Request<BlockHash>,
+    ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "StopBaker", BoolResponse, |cc: &ConsensusContainer| {
-            cc.stop_baker()
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        call_consensus_cached!(
+            self,
+            req,
+            "GetBlockInfo",
+            JsonResponse,
+            &req.get_ref().block_hash,
+            "",
+            |cc: &dyn Consensus| { cc.get_block_info(&req.get_ref().block_hash) }
+        )
+    }
+
+    async fn get_ancestors(
+        &self,
+        req: Request<BlockHashAndAmount>,
+    ) -> Result<Response<JsonResponse>, Status> {
+        authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Expensive)?;
+        call_consensus_cached!(
+            self,
+            req,
+            "GetAncestors",
+            JsonResponse,
+            &req.get_ref().block_hash,
+            &req.get_ref().amount.to_string(),
+            |cc: &dyn Consensus| {
+                cc.get_ancestors(&req.get_ref().block_hash, req.get_ref().amount)
+            }
+        )
+    }
+
+    async fn get_blocks_at_height(
+        &self,
+        req: Request<BlockHeight>,
+    ) -> Result<Response<JsonResponse>, Status> {
+        authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        call_consensus!(self, req, "GetBlocksAtHeight", JsonResponse, |cc: &dyn Consensus| {
+            cc.get_blocks_at_height(req.get_ref().block_height)
+        })
+    }
+
+    async fn export_blocks(
+        &self,
+        req: Request<ExportBlocksRequest>,
+    ) -> Result<Response<Self::ExportBlocksStream>, Status> {
+        audited!(self, req, "ExportBlocks", Self::ExportBlocksStream, {
+            authenticate!(req, self.access_token);
+            let permit = RpcLimiter::acquire_owned(&self.limiter, &req, MethodClass::Node)?;
+            self.check_admin()?;
+            let container = match self.consensus {
+                Some(ref container) if container.is_running() => Arc::clone(container),
+                Some(_) => {
+                    return Err(Status::new(
+                        Code::Internal,
+                        "The consensus layer has not been initialized!",
+                    ))
+                }
+                None => {
+                    error!("Consensus container not supplied; is this a gRPC unit test?");
+                    return Err(Status::new(
+                        Code::FailedPrecondition,
+                        "The consensus container is missing!",
+                    ));
+                }
+            };
+            let directory = self.export_path(&req.get_ref().directory)?;
+            let range = ExportRange {
+                from_height:     req.get_ref().from_height,
+                // 0 stands for the last finalized block
+                to_height:       Some(req.get_ref().to_height).filter(|&height| height > 0),
+                blocks_per_file: req.get_ref().blocks_per_file,
+            };
+            let genesis_block = read_or_die!(self.node.config.regenesis_arc).first().cloned();
+            // the client receives every completed file, followed by the manifest
+            Ok(Response::new(spawn_job("block export", permit, move |handle| {
+                export_blocks(
+                    container.as_ref(),
+                    genesis_block,
+                    &directory,
+                    range,
+                    &|| handle.is_cancelled(),
+                    &mut |file| handle.report(file),
+                )
+            })))
+        })
+    }
+
+    async fn create_snapshot(
+        &self,
+        req: Request<CreateSnapshotRequest>,
+    ) -> Result<Response<Self::CreateSnapshotStream>, Status> {
+        audited!(self, req, "CreateSnapshot", Self::CreateSnapshotStream, {
+            authenticate!(req, self.access_token);
+            let permit = RpcLimiter::acquire_owned(&self.limiter, &req, MethodClass::Node)?;
+            self.check_admin()?;
+            let container = match self.consensus {
+                Some(ref container) if container.is_running() => Arc::clone(container),
+                Some(_) => {
+                    return Err(Status::new(
+                        Code::Internal,
+                        "The consensus layer has not been initialized!",
+                    ))
+                }
+                None => {
+                    error!("Consensus container not supplied; is this a gRPC unit test?");
+                    return Err(Status::new(
+                        Code::FailedPrecondition,
+                        "The consensus container is missing!",
+                    ));
+                }
+            };
+            let genesis_block = read_or_die!(self.node.config.regenesis_arc)
+                .first()
+                .cloned()
+                .ok_or_else(|| Status::unavailable("The genesis block is not known yet."))?;
+            let output = self.export_path(&req.get_ref().path)?;
+            let node = Arc::clone(&self.node);
+            // the client receives the description of the snapshot once it is written
+            Ok(Response::new(spawn_job("snapshot", permit, move |handle| {
+                create_snapshot(
+                    container.as_ref(),
+                    &node.kvs,
+                    genesis_block,
+                    &configuration::AppPreferences::get_preferences_file(
+                        &node.config.config_dir_path,
+                    ),
+                    &output,
+                    &|| handle.is_cancelled(),
+                )
+            })))
         })
     }
 
-    async fn get_branches(&self, req: Request<Empty>) -> Result<Response<JsonResponse>, Status> {
+    async fn get_account_list(
+        &self,
+        req: Request<BlockHash>,
+    ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetBranches", JsonResponse, |cc: &ConsensusContainer| {
-            cc.get_branches()
-        })
+        let _permit = self.limiter.acquire(&req, MethodClass::Expensive)?;
+        call_consensus_cached!(
+            self,
+            req,
+            "GetAccountList",
+            JsonResponse,
+            &req.get_ref().block_hash,
+            "",
+            |cc: &dyn Consensus| { cc.get_account_list(&req.get_ref().block_hash) }
+        )
     }
 
-    async fn get_block_info(
+    async fn get_instances(
         &self,
         req: Request<BlockHash>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetBlockInfo", JsonResponse, |cc: &ConsensusContainer| {
-            cc.get_block_info(&req.get_ref().block_hash)
-        })
+        let _permit = self.limiter.acquire(&req, MethodClass::Expensive)?;
+        call_consensus_cached!(
+            self,
+            req,
+            "GetInstances",
+            JsonResponse,
+            &req.get_ref().block_hash,
+            "",
+            |cc: &dyn Consensus| { cc.get_instances(&req.get_ref().block_hash) }
+        )
     }
 
-    async fn get_ancestors(
+    async fn get_account_list_page(
         &self,
-        req: Request<BlockHashAndAmount>,
+        req: Request<PageRequest>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetAncestors", JsonResponse, |cc: &ConsensusContainer| {
-            cc.get_ancestors(&req.get_ref().block_hash, req.get_ref().amount)
-        })
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        let limit = page_limit(req.get_ref().limit);
+        call_consensus_cached!(
+            self,
+            req,
+            "GetAccountListPage",
+            JsonResponse,
+            &req.get_ref().block_hash,
+            &format!("{}/{}", req.get_ref().cursor, limit),
+            |cc: &dyn Consensus| {
+                cc.get_account_list_page(&req.get_ref().block_hash, &req.get_ref().cursor, limit)
+            }
+        )
     }
 
-    async fn get_blocks_at_height(
+    async fn get_instances_page(
         &self,
-        req: Request<BlockHeight>,
+        req: Request<PageRequest>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetBlocksAtHeight", JsonResponse, |cc: &ConsensusContainer| {
-            cc.get_blocks_at_height(req.get_ref().block_height)
-        })
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        let start = parse_instance_cursor(&req.get_ref().cursor)?;
+        let limit = page_limit(req.get_ref().limit);
+        call_consensus_cached!(
+            self,
+            req,
+            "GetInstancesPage",
+            JsonResponse,
+            &req.get_ref().block_hash,
+            &format!("{}/{}", start, limit),
+            |cc: &dyn Consensus| { cc.get_instances_page(&req.get_ref().block_hash, start, limit) }
+        )
     }
 
-    async fn get_account_list(
+    async fn stream_account_list(
         &self,
         req: Request<BlockHash>,
-    ) -> Result<Response<JsonResponse>, Status> {
+    ) -> Result<Response<Self::StreamAccountListStream>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetAccountList", JsonResponse, |cc: &ConsensusContainer| {
-            cc.get_account_list(&req.get_ref().block_hash)
-        })
+        let permit = RpcLimiter::acquire_owned(&self.limiter, &req, MethodClass::Expensive)?;
+        let block_hash = req.into_inner().block_hash;
+        let stream = self.stream_pages("StreamAccountList", permit, move |cc, cursor| {
+            cc.get_account_list_page(&block_hash, cursor, MAX_PAGE_SIZE)
+        })?;
+        Ok(Response::new(stream))
     }
 
-    async fn get_instances(
+    async fn stream_instances(
         &self,
         req: Request<BlockHash>,
-    ) -> Result<Response<JsonResponse>, Status> {
+    ) -> Result<Response<Self::StreamInstancesStream>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetInstances", JsonResponse, |cc: &ConsensusContainer| {
-            cc.get_instances(&req.get_ref().block_hash)
-        })
+        let permit = RpcLimiter::acquire_owned(&self.limiter, &req, MethodClass::Expensive)?;
+        let block_hash = req.into_inner().block_hash;
+        let stream = self.stream_pages("StreamInstances", permit, move |cc, cursor| {
+            // the cursors are produced by consensus, so they are valid
+            let start = parse_instance_cursor(cursor).unwrap_or_default();
+            cc.get_instances_page(&block_hash, start, MAX_PAGE_SIZE)
+        })?;
+        Ok(Response::new(stream))
     }
 
     async fn get_account_info(
@@ -592,9 +1597,18 @@ impl P2p for RpcServerImpl {
         req: Request<GetAddressInfoRequest>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetAccountInfo", JsonResponse, |cc: &ConsensusContainer| {
-            cc.get_account_info(&req.get_ref().block_hash, &req.get_ref().address)
-        })
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        call_consensus_cached!(
+            self,
+            req,
+            "GetAccountInfo",
+            JsonResponse,
+            &req.get_ref().block_hash,
+            &req.get_ref().address,
+            |cc: &dyn Consensus| {
+                cc.get_account_info(&req.get_ref().block_hash, &req.get_ref().address)
+            }
+        )
     }
 
     async fn get_instance_info(
@@ -602,9 +1616,18 @@ impl P2p for RpcServerImpl {
         req: Request<GetAddressInfoRequest>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetInstanceInfo", JsonResponse, |cc: &ConsensusContainer| {
-            cc.get_instance_info(&req.get_ref().block_hash, &req.get_ref().address)
-        })
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        call_consensus_cached!(
+            self,
+            req,
+            "GetInstanceInfo",
+            JsonResponse,
+            &req.get_ref().block_hash,
+            &req.get_ref().address,
+            |cc: &dyn Consensus| {
+                cc.get_instance_info(&req.get_ref().block_hash, &req.get_ref().address)
+            }
+        )
     }
 
     async fn get_reward_status(
@@ -612,9 +1635,16 @@ impl P2p for RpcServerImpl {
         req: Request<BlockHash>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetRewardStatus", JsonResponse, |cc: &ConsensusContainer| {
-            cc.get_reward_status(&req.get_ref().block_hash)
-        })
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        call_consensus_cached!(
+            self,
+            req,
+            "GetRewardStatus",
+            JsonResponse,
+            &req.get_ref().block_hash,
+            "",
+            |cc: &dyn Consensus| { cc.get_reward_status(&req.get_ref().block_hash) }
+        )
     }
 
     async fn get_birk_parameters(
@@ -622,9 +1652,16 @@ impl P2p for RpcServerImpl {
         req: Request<BlockHash>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetBirkParameters", JsonResponse, |cc: &ConsensusContainer| {
-            cc.get_birk_parameters(&req.get_ref().block_hash)
-        })
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        call_consensus_cached!(
+            self,
+            req,
+            "GetBirkParameters",
+            JsonResponse,
+            &req.get_ref().block_hash,
+            "",
+            |cc: &dyn Consensus| { cc.get_birk_parameters(&req.get_ref().block_hash) }
+        )
     }
 
     async fn get_module_list(
@@ -632,9 +1669,16 @@ impl P2p for RpcServerImpl {
         req: Request<BlockHash>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetModuleList", JsonResponse, |cc: &ConsensusContainer| {
-            cc.get_module_list(&req.get_ref().block_hash)
-        })
+        let _permit = self.limiter.acquire(&req, MethodClass::Expensive)?;
+        call_consensus_cached!(
+            self,
+            req,
+            "GetModuleList",
+            JsonResponse,
+            &req.get_ref().block_hash,
+            "",
+            |cc: &dyn Consensus| { cc.get_module_list(&req.get_ref().block_hash) }
+        )
     }
 
     async fn get_transaction_status(
@@ -642,7 +1686,8 @@ impl P2p for RpcServerImpl {
         req: Request<TransactionHash>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetTransactionStatus", JsonResponse, |cc: &ConsensusContainer| {
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        call_consensus!(self, req, "GetTransactionStatus", JsonResponse, |cc: &dyn Consensus| {
             cc.get_transaction_status(&req.get_ref().transaction_hash)
         })
     }
@@ -652,11 +1697,13 @@ impl P2p for RpcServerImpl {
         req: Request<GetTransactionStatusInBlockRequest>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
         call_consensus!(
             self,
+            req,
             "GetTransactionStatusInBlock",
             JsonResponse,
-            |cc: &ConsensusContainer| {
+            |cc: &dyn Consensus| {
                 cc.get_transaction_status_in_block(
                     &req.get_ref().transaction_hash,
                     &req.get_ref().block_hash,
@@ -670,11 +1717,13 @@ impl P2p for RpcServerImpl {
         req: Request<AccountAddress>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
         call_consensus!(
             self,
+            req,
             "GetAccountNonFinalizedTransactions",
             JsonResponse,
-            |cc: &ConsensusContainer| {
+            |cc: &dyn Consensus| {
                 cc.get_account_non_finalized_transactions(&req.get_ref().account_address)
             }
         )
@@ -685,7 +1734,8 @@ impl P2p for RpcServerImpl {
         req: Request<AccountAddress>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetNextAccountNonce", JsonResponse, |cc: &ConsensusContainer| {
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        call_consensus!(self, req, "GetNextAccountNonce", JsonResponse, |cc: &dyn Consensus| {
             cc.get_next_account_nonce(&req.get_ref().account_address)
         })
     }
@@ -695,7 +1745,8 @@ impl P2p for RpcServerImpl {
         req: Request<BlockHash>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetIdentityProviders", JsonResponse, |cc: &ConsensusContainer| {
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        call_consensus!(self, req, "GetIdentityProviders", JsonResponse, |cc: &dyn Consensus| {
             cc.get_identity_providers(&req.get_ref().block_hash)
         })
     }
@@ -705,7 +1756,8 @@ impl P2p for RpcServerImpl {
         req: Request<BlockHash>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetAnonymityRevokers", JsonResponse, |cc: &ConsensusContainer| {
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
+        call_consensus!(self, req, "GetAnonymityRevokers", JsonResponse, |cc: &dyn Consensus| {
             cc.get_anonymity_revokers(&req.get_ref().block_hash)
         })
     }
@@ -715,13 +1767,13 @@ impl P2p for RpcServerImpl {
         req: Request<BlockHash>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Query)?;
         call_consensus!(
             self,
+            req,
             "GetCryptographicParameters",
             JsonResponse,
-            |cc: &ConsensusContainer| {
-                cc.get_cryptographic_parameters(&req.get_ref().block_hash)
-            }
+            |cc: &dyn Consensus| { cc.get_cryptographic_parameters(&req.get_ref().block_hash) }
         )
     }
 
@@ -730,9 +1782,16 @@ impl P2p for RpcServerImpl {
         req: Request<BlockHash>,
     ) -> Result<Response<JsonResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetBlockSummary", JsonResponse, |cc: &ConsensusContainer| {
-            cc.get_block_summary(&req.get_ref().block_hash)
-        })
+        let _permit = self.limiter.acquire(&req, MethodClass::Expensive)?;
+        call_consensus_cached!(
+            self,
+            req,
+            "GetBlockSummary",
+            JsonResponse,
+            &req.get_ref().block_hash,
+            "",
+            |cc: &dyn Consensus| { cc.get_block_summary(&req.get_ref().block_hash) }
+        )
     }
 
     async fn get_module_source(
@@ -740,9 +1799,18 @@ impl P2p for RpcServerImpl {
         req: Request<GetModuleSourceRequest>,
     ) -> Result<Response<BytesResponse>, Status> {
         authenticate!(req, self.access_token);
-        call_consensus!(self, "GetModuleSource", BytesResponse, |cc: &ConsensusContainer| {
-            cc.get_module_source(&req.get_ref().block_hash, &req.get_ref().module_ref)
-        })
+        let _permit = self.limiter.acquire(&req, MethodClass::Expensive)?;
+        call_consensus_cached!(
+            self,
+            req,
+            "GetModuleSource",
+            BytesResponse,
+            &req.get_ref().block_hash,
+            &req.get_ref().module_ref,
+            |cc: &dyn Consensus| {
+                cc.get_module_source(&req.get_ref().block_hash, &req.get_ref().module_ref)
+            }
+        )
     }
 
     async fn get_banned_peers(
@@ -750,6 +1818,7 @@ impl P2p for RpcServerImpl {
         req: Request<Empty>,
     ) -> Result<Response<PeerListResponse>, Status> {
         authenticate!(req, self.access_token);
+        let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
         let peers = if let Ok(banlist) = self.node.get_banlist() {
             banlist
                 .into_iter()
@@ -780,11 +1849,54 @@ impl P2p for RpcServerImpl {
         }))
     }
 
-    async fn shutdown(&self, req: Request<Empty>) -> Result<Response<BoolResponse>, Status> {
-        authenticate!(req, self.access_token);
-        Ok(Response::new(BoolResponse {
-            value: self.node.close(),
-        }))
+    async fn shutdown(
+        &self,
+        req: Request<ShutdownRequest>,
+    ) -> Result<Response<BoolResponse>, Status> {
+        audited!(self, req, "Shutdown", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            let value = if req.get_ref().graceful {
+                // the drain takes a while, so the caller is only told that it started, but
+                // transactions are rejected by the time the caller is told
+                if self.node.start_draining() {
+                    let node = Arc::clone(&self.node);
+                    let consensus = self.consensus.clone();
+                    // a failure to close the node is recorded on it, and the binary exits
+                    // with an error once the node has stopped
+                    spawn_or_die!("drain", move || {
+                        node.close_after_drain(consensus.as_deref());
+                    });
+                } else {
+                    info!("The node is already draining");
+                }
+                true
+            } else {
+                self.node.close()
+            };
+            Ok(Response::new(BoolResponse {
+                value,
+            }))
+        })
+    }
+
+    async fn update_node_config(
+        &self,
+        req: Request<UpdateNodeConfigRequest>,
+    ) -> Result<Response<JsonResponse>, Status> {
+        audited!(self, req, "UpdateNodeConfig", JsonResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            let report = parse_config_changes(&req.get_ref().config)
+                .and_then(|changes| self.node.apply_config_changes(changes))
+                .map_err(|e| Status::invalid_argument(format!("{:#}", e)))?;
+            Ok(Response::new(JsonResponse {
+                value: serde_json::to_string(&report)
+                    .map_err(|_| Status::internal("Could not serialize the configuration."))?,
+            }))
+        })
     }
 
     #[cfg(not(feature = "network_dump"))]
@@ -792,9 +1904,13 @@ impl P2p for RpcServerImpl {
         &self,
         req: Request<DumpRequest>,
     ) -> Result<Response<BoolResponse>, Status> {
-        authenticate!(req, self.access_token);
-        warn!("DumpStart RPC request received, but the \"network_dump\" feature is not active");
-        Err(Status::new(Code::Unavailable, "Feature \"network_dump\" is not active"))
+        audited!(self, req, "DumpStart", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            warn!("DumpStart RPC request received, but the \"network_dump\" feature is not active");
+            Err(Status::new(Code::Unavailable, "Feature \"network_dump\" is not active"))
+        })
     }
 
     #[cfg(feature = "network_dump")]
@@ -802,55 +1918,75 @@ impl P2p for RpcServerImpl {
         &self,
         req: Request<DumpRequest>,
     ) -> Result<Response<BoolResponse>, Status> {
-        authenticate!(req, self.access_token);
-        let file_path = req.get_ref().file.to_owned();
-        let result = self
-            .node
-            .activate_dump(
-                if file_path.is_empty() {
-                    "dump"
-                } else {
-                    &file_path
-                },
-                req.get_ref().raw,
-            )
-            .is_ok();
-        Ok(Response::new(BoolResponse {
-            value: result,
-        }))
+        audited!(self, req, "DumpStart", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            let file_path = req.get_ref().file.to_owned();
+            let result = self
+                .node
+                .activate_dump(
+                    if file_path.is_empty() {
+                        "dump"
+                    } else {
+                        &file_path
+                    },
+                    req.get_ref().raw,
+                )
+                .is_ok();
+            Ok(Response::new(BoolResponse {
+                value: result,
+            }))
+        })
     }
 
     #[cfg(not(feature = "network_dump"))]
     async fn dump_stop(&self, req: Request<Empty>) -> Result<Response<BoolResponse>, Status> {
-        authenticate!(req, self.access_token);
-        warn!("DumpStop RPC request received, but the \"network_dump\" feature is not active");
-        Err(Status::new(Code::Unavailable, "Feature \"network_dump\" is not active"))
+        audited!(self, req, "DumpStop", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            warn!("DumpStop RPC request received, but the \"network_dump\" feature is not active");
+            Err(Status::new(Code::Unavailable, "Feature \"network_dump\" is not active"))
+        })
     }
 
     #[cfg(feature = "network_dump")]
     async fn dump_stop(&self, req: Request<Empty>) -> Result<Response<BoolResponse>, Status> {
-        authenticate!(req, self.access_token);
-        Ok(Response::new(BoolResponse {
-            value: self.node.stop_dump().is_ok(),
-        }))
+        audited!(self, req, "DumpStop", BoolResponse, {
+            authenticate!(req, self.access_token);
+            let _permit = self.limiter.acquire(&req, MethodClass::Node)?;
+            self.check_admin()?;
+            Ok(Response::new(BoolResponse {
+                value: self.node.stop_dump().is_ok(),
+            }))
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        common::{grpc_api, P2PNodeId, PeerType},
+        common::{get_current_stamp, grpc_api, P2PNodeId, PeerType},
+        configuration::RpcCliConfig,
+        consensus_ffi::{
+            blockchain_types::BlockHash,
+            consensus::{Consensus, ConsensusType},
+            helpers::{ConsensusFfiResponse, PacketType},
+            mock::MockConsensus,
+        },
         p2p::P2PNode,
-        rpc::RpcServerImpl,
+        rpc::{parse_page, RpcServerImpl},
         test_utils::{
-            await_handshakes, connect, dummy_regenesis_blocks, get_test_config, make_node_and_sync,
-            next_available_port, stop_node_delete_dirs, wait_node_delete_dirs, DeletePermission,
+            await_handshakes, connect, dummy_account_transaction, dummy_regenesis_blocks,
+            get_test_config, make_node_and_sync, next_available_port, stop_node_delete_dirs,
+            wait_node_delete_dirs, DeletePermission,
         },
     };
     use chrono::prelude::Utc;
     use tonic::{metadata::MetadataValue, transport::channel::Channel, Code, Request};
 
-    use grpc_api::p2p_client::P2pClient;
+    use grpc_api::{p2p_client::P2pClient, send_transaction_response::Outcome};
 
     use std::sync::Arc;
 
@@ -859,6 +1995,28 @@ mod tests {
     // The intended use is for spawning nodes for testing gRPC api.
     async fn create_test_rpc_node(
         nt: PeerType,
+    ) -> anyhow::Result<(P2pClient<Channel>, Arc<P2PNode>, DeletePermission)> {
+        create_test_rpc_node_with(nt, |_| {}).await
+    }
+
+    async fn create_test_rpc_node_with(
+        nt: PeerType,
+        configure: impl FnOnce(&mut RpcCliConfig),
+    ) -> anyhow::Result<(P2pClient<Channel>, Arc<P2PNode>, DeletePermission)> {
+        spawn_test_rpc_node(nt, None, configure).await
+    }
+
+    async fn create_test_rpc_node_with_consensus(
+        nt: PeerType,
+        consensus: Arc<dyn Consensus>,
+    ) -> anyhow::Result<(P2pClient<Channel>, Arc<P2PNode>, DeletePermission)> {
+        spawn_test_rpc_node(nt, Some(consensus), |_| {}).await
+    }
+
+    async fn spawn_test_rpc_node(
+        nt: PeerType,
+        consensus: Option<Arc<dyn Consensus>>,
+        configure: impl FnOnce(&mut RpcCliConfig),
     ) -> anyhow::Result<(P2pClient<Channel>, Arc<P2PNode>, DeletePermission)> {
         let (node, dp) =
             make_node_and_sync(next_available_port(), vec![100], nt, dummy_regenesis_blocks())
@@ -869,7 +2027,8 @@ mod tests {
         config.cli.rpc.rpc_server_port = rpc_port;
         config.cli.rpc.rpc_server_addr = "127.0.0.1".to_owned();
         config.cli.rpc.rpc_server_token = TOKEN.to_owned();
-        let mut rpc_server = RpcServerImpl::new(node.clone(), None, &config.cli.rpc)?;
+        configure(&mut config.cli.rpc);
+        let mut rpc_server = RpcServerImpl::new(node.clone(), consensus, &config.cli.rpc)?;
         tokio::spawn(async move { rpc_server.start_server().await });
         tokio::task::yield_now().await;
 
@@ -881,6 +2040,27 @@ mod tests {
         Ok((client, node, dp))
     }
 
+    /// Connects to the admin socket once the server listens on it.
+    #[cfg(unix)]
+    async fn connect_admin_socket(socket: &std::path::Path) -> anyhow::Result<P2pClient<Channel>> {
+        use tokio::net::UnixStream;
+        use tonic::transport::{Endpoint, Uri};
+
+        for _ in 0..100 {
+            if socket.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let socket = socket.to_owned();
+        let channel = Endpoint::from_static("http://[::]:50051")
+            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                UnixStream::connect(socket.clone())
+            }))
+            .await?;
+        Ok(P2pClient::new(channel))
+    }
+
     #[tokio::test]
     async fn test_grpc_noauth() -> anyhow::Result<()> {
         let (mut client, node, dp) = create_test_rpc_node(PeerType::Node).await.unwrap();
@@ -1125,28 +2305,464 @@ mod tests {
     // encounters a problem when inserting in the database as it's a default dummy
     // one.
 
-    // Some tests involve a baker so they might be tested as flow tests:
-    // - Consensus status
-    // - Get branches
-    // - Get block info
-    // - Get ancestors
-    // - Get last final account list
-    // - Get last final instances
-    // - Get last final account info
-    // - Get last final instance info
+    // The results of the consensus queries are tested in the flow tests; here
+    // consensus is mocked, so only the handling of its responses is tested.
+    #[tokio::test]
+    async fn test_consensus_responses() -> anyhow::Result<()> {
+        let consensus = Arc::new(MockConsensus::default());
+        consensus.set_query_response("GetBlockInfo", r#"{"finalized":false}"#);
+        consensus.respond_with(PacketType::Transaction, ConsensusFfiResponse::DuplicateEntry);
+        let (mut client, node, dp) =
+            create_test_rpc_node_with_consensus(PeerType::Node, consensus.clone()).await.unwrap();
+
+        let block_info = client
+            .get_block_info(req_with_auth!(
+                grpc_api::BlockHash {
+                    block_hash: "abc".to_owned(),
+                },
+                TOKEN
+            ))
+            .await
+            .unwrap();
+        assert_eq!(block_info.get_ref().value, r#"{"finalized":false}"#);
+        assert!(consensus.queries().contains(&("GetBlockInfo", vec!["abc".to_owned()])));
+
+        // malformed transactions don't reach consensus
+        let sent = client
+            .send_transaction(req_with_auth!(
+                grpc_api::SendTransactionRequest {
+                    network_id: 100,
+                    payload:    vec![1, 2, 3],
+                },
+                TOKEN
+            ))
+            .await
+            .unwrap();
+        assert!(!sent.get_ref().value);
+        assert!(consensus.received().is_empty());
+
+        // and the detailed response says why
+        let expired = dummy_account_transaction(64, 10, get_current_stamp() / 1000 - 60);
+        for (payload, outcome, message) in vec![
+            (vec![1, 2, 3], Outcome::DeserializationError, "Malformed transaction: "),
+            (expired, Outcome::Stale, "The transaction already expired"),
+        ] {
+            let sent = client
+                .send_transaction_detailed(req_with_auth!(
+                    grpc_api::SendTransactionRequest {
+                        network_id: 100,
+                        payload,
+                    },
+                    TOKEN
+                ))
+                .await
+                .unwrap();
+            assert_eq!(sent.get_ref().outcome, outcome as i32);
+            assert!(sent.get_ref().message.starts_with(message), "{}", sent.get_ref().message);
+        }
+        assert!(consensus.received().is_empty());
+
+        let transaction = dummy_account_transaction(64, 10, get_current_stamp() / 1000 + 60);
+        let sent = client
+            .send_transaction(req_with_auth!(
+                grpc_api::SendTransactionRequest {
+                    network_id: 100,
+                    payload:    transaction.clone(),
+                },
+                TOKEN
+            ))
+            .await
+            .unwrap();
+        assert!(!sent.get_ref().value);
+        assert_eq!(consensus.received(), vec![(PacketType::Transaction, transaction)]);
+
+        let info = client.node_info(req_with_auth!(grpc_api::Empty {}, TOKEN)).await.unwrap();
+        assert!(info.get_ref().consensus_running);
+        assert_eq!(info.get_ref().consensus_type, "Passive");
+        stop_node_delete_dirs(dp, node);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_export_blocks() -> anyhow::Result<()> {
+        let consensus = Arc::new(MockConsensus::default());
+        consensus.add_finalized_block(&[1, 2, 3]);
+        consensus.add_finalized_block(&[4, 5]);
+        let dir = tempfile::tempdir()?;
+        let export_dir = dir.path().to_owned();
+        let (mut client, node, dp) =
+            spawn_test_rpc_node(PeerType::Node, Some(consensus), move |conf| {
+                conf.rpc_server_export_dir = Some(export_dir)
+            })
+            .await
+            .unwrap();
+        let export = |directory: &str| {
+            req_with_auth!(
+                grpc_api::ExportBlocksRequest {
+                    directory:       directory.to_owned(),
+                    from_height:     0,
+                    to_height:       0,
+                    blocks_per_file: 1,
+                },
+                TOKEN
+            )
+        };
+
+        // every completed file is streamed, followed by the manifest
+        let mut stream = client.export_blocks(export("export")).await.unwrap().into_inner();
+        let mut updates = Vec::new();
+        while let Some(update) = stream.message().await? {
+            updates.push(serde_json::from_str::<serde_json::Value>(&update.value)?);
+        }
+        assert_eq!(updates.len(), 3);
+        assert_eq!(updates[0]["name"], "blocks-1-1.dat");
+        assert_eq!(updates[1]["name"], "blocks-2-2.dat");
+        assert_eq!(updates[2]["files"][1]["name"], "blocks-2-2.dat");
+        assert!(dir.path().join("export").join("blocks-2-2.dat").exists());
+
+        // an existing export is not overwritten
+        let mut stream = client.export_blocks(export("export")).await.unwrap().into_inner();
+        let status = stream.message().await.unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+
+        // the export can't leave the export directory
+        for directory in &["../export", "/tmp/export", "export/../../export", ""] {
+            let status = client.export_blocks(export(directory)).await.unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument);
+        }
+        stop_node_delete_dirs(dp, node);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_snapshot() -> anyhow::Result<()> {
+        let consensus = Arc::new(MockConsensus::default());
+        let dir = tempfile::tempdir()?;
+        let export_dir = dir.path().to_owned();
+        let (mut client, node, dp) =
+            spawn_test_rpc_node(PeerType::Node, Some(consensus.clone()), move |conf| {
+                conf.rpc_server_export_dir = Some(export_dir)
+            })
+            .await
+            .unwrap();
+        let snapshot = |path: &str| {
+            req_with_auth!(
+                grpc_api::CreateSnapshotRequest {
+                    path: path.to_owned(),
+                },
+                TOKEN
+            )
+        };
+
+        let mut stream = client.create_snapshot(snapshot("snapshot.tar.gz")).await?.into_inner();
+        let metadata = stream.message().await?.unwrap();
+        let metadata: serde_json::Value = serde_json::from_str(&metadata.value)?;
+        assert_eq!(metadata["genesisBlock"], dummy_regenesis_blocks()[0].to_string());
+        assert!(stream.message().await?.is_none());
+        assert!(dir.path().join("snapshot.tar.gz").exists());
+        assert_eq!(consensus.queries()[0].0, "SnapshotDatabase");
+        // an existing snapshot is not overwritten
+        let mut stream = client.create_snapshot(snapshot("snapshot.tar.gz")).await?.into_inner();
+        assert_eq!(stream.message().await.unwrap_err().code(), Code::FailedPrecondition);
+        // the snapshot can't leave the export directory
+        let status = client.create_snapshot(snapshot("../snapshot.tar.gz")).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        stop_node_delete_dirs(dp, node);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_set_baker_credentials() -> anyhow::Result<()> {
+        let consensus = Arc::new(MockConsensus::new(ConsensusType::Active));
+        let socket =
+            std::env::temp_dir().join(format!("concordium-{}.sock", next_available_port()));
+        let socket_path = socket.clone();
+        let (mut tcp_client, node, dp) =
+            spawn_test_rpc_node(PeerType::Node, Some(consensus.clone()), move |rpc| {
+                rpc.rpc_server_admin_socket = Some(socket_path)
+            })
+            .await
+            .unwrap();
+        let credentials = |credentials: &[u8], password: &str| {
+            req_with_auth!(
+                grpc_api::SetBakerCredentialsRequest {
+                    credentials: credentials.to_vec(),
+                    password:    password.to_owned(),
+                },
+                TOKEN
+            )
+        };
+
+        // credentials are never accepted over TCP, whether they are encrypted or not
+        for password in &["", "secret"] {
+            let status = tcp_client
+                .set_baker_credentials(credentials(br#"{"bakerId":1}"#, password))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), Code::PermissionDenied);
+        }
+        assert!(consensus.queries().is_empty());
+
+        let mut client = connect_admin_socket(&socket).await?;
+        let set = client.set_baker_credentials(credentials(br#"{"bakerId":1}"#, "")).await?;
+        assert!(set.get_ref().value);
+        assert!(consensus
+            .queries()
+            .contains(&("SetBakerCredentials", vec![r#"{"bakerId":1}"#.to_owned()])));
+        let status = client.set_baker_credentials(credentials(b"[]", "")).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let status = client.set_baker_credentials(credentials(b"{}", "secret")).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        stop_node_delete_dirs(dp, node);
+        let _ = std::fs::remove_file(socket);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_page() {
+        let (items, next) = parse_page(r#"{"items":[1,2],"next":3}"#).unwrap();
+        assert_eq!(items, "[1,2]");
+        assert_eq!(next.as_deref(), Some("3"));
+        let (items, next) = parse_page(r#"{"items":["a"],"next":null}"#).unwrap();
+        assert_eq!(items, r#"["a"]"#);
+        assert_eq!(next, None);
+        assert_eq!(parse_page("null").unwrap_err().code(), Code::NotFound);
+    }
 
     #[tokio::test]
     async fn test_shutdown() -> anyhow::Result<()> {
         let (mut client, node, dp) = create_test_rpc_node(PeerType::Node).await.unwrap();
         assert!(
             client
-                .shutdown(req_with_auth!(grpc_api::Empty {}, TOKEN))
+                .shutdown(req_with_auth!(
+                    grpc_api::ShutdownRequest {
+                        graceful: false,
+                    },
+                    TOKEN
+                ))
+                .await
+                .unwrap()
+                .get_ref()
+                .value
+        );
+        wait_node_delete_dirs(dp, node);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown() -> anyhow::Result<()> {
+        use chrono::{SecondsFormat, TimeZone};
+
+        let consensus = Arc::new(MockConsensus::new(ConsensusType::Active));
+        assert!(consensus.start_baker());
+        // the current slot started 200ms ago and lasts a second
+        let slot_start = get_current_stamp() - 200;
+        let genesis_time =
+            Utc.timestamp_millis(slot_start as i64).to_rfc3339_opts(SecondsFormat::Millis, true);
+        consensus.set_query_response(
+            "GetConsensusStatus",
+            &format!(r#"{{"genesisTime":"{}","slotDuration":1000}}"#, genesis_time),
+        );
+        let (mut client, node, dp) =
+            create_test_rpc_node_with_consensus(PeerType::Node, consensus.clone()).await.unwrap();
+        assert!(
+            client
+                .shutdown(req_with_auth!(
+                    grpc_api::ShutdownRequest {
+                        graceful: true,
+                    },
+                    TOKEN
+                ))
                 .await
                 .unwrap()
                 .get_ref()
                 .value
         );
+        // new transactions are rejected while draining
+        let transaction = dummy_account_transaction(64, 10, get_current_stamp() / 1000 + 60);
+        let status = client
+            .send_transaction(req_with_auth!(
+                grpc_api::SendTransactionRequest {
+                    network_id: 100,
+                    payload:    transaction,
+                },
+                TOKEN
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert!(consensus.received().is_empty());
+        // the baker finishes the current slot before it is stopped
+        assert!(consensus.is_baking());
+        let deadline = slot_start + 5_000;
+        while consensus.is_baking() && get_current_stamp() < deadline {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(!consensus.is_baking());
+        assert!(get_current_stamp() >= slot_start + 1000);
+        assert!(consensus.queries().iter().any(|(query, _)| *query == "GetConsensusStatus"));
         wait_node_delete_dirs(dp, node);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_grpc_web() -> anyhow::Result<()> {
+        use prost::Message;
+        use tonic::transport::NamedService;
+
+        const ALLOWED: &str = "https://wallet.example";
+        let mut port = 0;
+        let (_client, node, dp) = create_test_rpc_node_with(PeerType::Node, |rpc| {
+            port = rpc.rpc_server_port;
+            rpc.rpc_server_grpc_web = true;
+            rpc.rpc_server_grpc_web_allowed_origins = vec![ALLOWED.to_owned()];
+        })
+        .await
+        .unwrap();
+        let url = format!(
+            "http://127.0.0.1:{}/{}/PeerVersion",
+            port,
+            <grpc_api::p2p_server::P2pServer<RpcServerImpl> as NamedService>::NAME
+        );
+        let client = reqwest::Client::new();
+        // an empty message in a single uncompressed frame
+        let request = |origin: &str| {
+            client
+                .post(&url)
+                .header("content-type", "application/grpc-web+proto")
+                .header("x-grpc-web", "1")
+                .header("authentication", TOKEN)
+                .header("origin", origin)
+                .body(vec![0u8; 5])
+        };
+
+        let response = request(ALLOWED).send().await?;
+        assert!(response.status().is_success());
+        assert_eq!(
+            response.headers().get("access-control-allow-origin").map(|v| v.as_bytes()),
+            Some(ALLOWED.as_bytes())
+        );
+        let body = response.bytes().await?;
+        // a data frame with the response, then a trailers frame with the status
+        assert_eq!(body[0], 0);
+        let length = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+        let reply = grpc_api::StringResponse::decode(&body[5..5 + length])?;
+        assert_eq!(reply.value, crate::VERSION);
+        assert_eq!(body[5 + length], 0x80);
+        assert!(String::from_utf8_lossy(&body[5 + length..]).contains("grpc-status:0"));
+
+        let response = request("https://elsewhere.example").send().await?;
+        assert!(!response.status().is_success());
+        assert!(response.headers().get("access-control-allow-origin").is_none());
+
+        stop_node_delete_dirs(dp, node);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_consensus_events() -> anyhow::Result<()> {
+        let (mut client, node, dp) = create_test_rpc_node(PeerType::Node).await.unwrap();
+        let consensus = MockConsensus::default();
+        let status = |update: &str| format!(r#"{{"protocolUpdate":{}}}"#, update);
+        consensus.set_query_response("GetConsensusStatus", &status(r#"{"message":"first"}"#));
+        node.process_protocol_update(&consensus);
+        let mut stream = client
+            .stream_consensus_events(req_with_auth!(grpc_api::Empty {}, TOKEN))
+            .await
+            .unwrap()
+            .into_inner();
+        async fn next_event(
+            stream: &mut tonic::Streaming<grpc_api::JsonResponse>,
+        ) -> serde_json::Value {
+            let event = stream.message().await.unwrap().unwrap();
+            serde_json::from_str(&event.value).unwrap()
+        }
+        // the known genesis blocks come first
+        for (i, block) in dummy_regenesis_blocks().iter().enumerate() {
+            let event = next_event(&mut stream).await;
+            assert_eq!(event["event"], "regenesis");
+            assert_eq!(event["genesisIndex"], i);
+            assert_eq!(event["genesisBlock"], block.to_string());
+        }
+        // and the current protocol update
+        let event = next_event(&mut stream).await;
+        assert_eq!(event["event"], "protocolUpdate");
+        assert_eq!(event["protocolUpdate"]["message"], "first");
+        // then the regenesis picked up by the poll loop
+        let block = BlockHash::from([3; 32]);
+        node.config.regenesis_arc.write().unwrap().push(block.clone());
+        let event = next_event(&mut stream).await;
+        assert_eq!(event["genesisIndex"], 3);
+        assert_eq!(event["genesisBlock"], block.to_string());
+        // and only the protocol updates that differ from the current one
+        node.process_protocol_update(&consensus);
+        consensus.set_query_response("GetConsensusStatus", &status("null"));
+        node.process_protocol_update(&consensus);
+        let event = next_event(&mut stream).await;
+        assert_eq!(event["event"], "protocolUpdate");
+        assert!(event["protocolUpdate"].is_null());
+        stop_node_delete_dirs(dp, node);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_admin_socket_only() -> anyhow::Result<()> {
+        let socket =
+            std::env::temp_dir().join(format!("concordium-{}.sock", next_available_port()));
+        let socket_path = socket.clone();
+        let (mut client, node, dp) = create_test_rpc_node_with(PeerType::Node, move |rpc| {
+            rpc.rpc_server_admin_socket = Some(socket_path);
+            rpc.rpc_server_admin_socket_only = true;
+        })
+        .await
+        .unwrap();
+        match client
+            .shutdown(req_with_auth!(
+                grpc_api::ShutdownRequest {
+                    graceful: false,
+                },
+                TOKEN
+            ))
+            .await
+        {
+            Err(status) => assert_eq!(status.code(), Code::PermissionDenied),
+            _ => panic!("Admin method served over TCP"),
+        };
+        assert!(client.peer_version(req_with_auth!(grpc_api::Empty {}, TOKEN)).await.is_ok());
+        stop_node_delete_dirs(dp, node);
+        let _ = std::fs::remove_file(socket);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_admin_socket() -> anyhow::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let socket =
+            std::env::temp_dir().join(format!("concordium-{}.sock", next_available_port()));
+        let socket_path = socket.clone();
+        let (_, node, dp) = create_test_rpc_node_with(PeerType::Node, move |rpc| {
+            rpc.rpc_server_admin_socket = Some(socket_path);
+            rpc.rpc_server_admin_socket_only = true;
+        })
+        .await
+        .unwrap();
+        let mut client = connect_admin_socket(&socket).await?;
+        assert_eq!(std::fs::metadata(&socket)?.permissions().mode() & 0o777, 0o600);
+        // an admin method, without the access token
+        let banned = client
+            .ban_node(Request::new(grpc_api::PeerElement {
+                node_id:        None,
+                ip:             Some("10.1.2.3".to_owned()),
+                port:           None,
+                catchup_status: 0,
+            }))
+            .await;
+        assert!(banned.is_ok(), "{:?}", banned);
+        stop_node_delete_dirs(dp, node);
+        let _ = std::fs::remove_file(socket);
+        Ok(())
+    }
 }
@@ -0,0 +1,113 @@
+//! Serving the gRPC API on a Unix domain socket.
+//!
+//! The admin socket is meant for operating the node from the local host. Access
+//! to it is governed by the permissions of the socket file, which is only
+//! accessible to the user running the node, so requests made over it do not
+//! need to carry the access token.
+
+use crate::rpc_limits::AdminClient;
+use anyhow::{ensure, Context as _};
+use std::{
+    fs, io,
+    os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{UnixListener, UnixStream},
+};
+use tokio_stream::{wrappers::UnixListenerStream, Stream, StreamExt};
+use tonic::transport::server::Connected;
+
+/// A connection accepted on the admin socket, numbered in the order of
+/// acceptance.
+pub struct AdminStream(UnixStream, u64);
+
+// Connections on the admin socket have no remote address, so the requests
+// made on them carry the number of the connection instead.
+impl Connected for AdminStream {
+    type ConnectInfo = AdminClient;
+
+    fn connect_info(&self) -> Self::ConnectInfo { AdminClient(self.1) }
+}
+
+impl AsyncRead for AdminStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for AdminStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+/// Binds the admin socket at the given path and returns the stream of
+/// incoming connections. A socket left behind by a previous run is replaced,
+/// but any other kind of file at the path is an error.
+///
+/// The socket is bound in a directory only accessible to the user running the
+/// node and only moved to the path once its permissions are restricted, so it
+/// can't be connected to by anybody else in the meantime.
+pub fn bind_admin_socket(
+    path: &Path,
+) -> anyhow::Result<impl Stream<Item = io::Result<AdminStream>>> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        ensure!(
+            metadata.file_type().is_socket(),
+            "Refusing to replace {:?} with the admin socket, since it is not a socket",
+            path
+        );
+    }
+    let file_name = path.file_name().context("The admin socket path has no file name")?;
+    let staging_dir =
+        path.with_file_name(format!(".{}.{}", file_name.to_string_lossy(), std::process::id()));
+    // a directory left behind by a previous run with the same process id
+    if fs::symlink_metadata(&staging_dir).map_or(false, |metadata| metadata.is_dir()) {
+        let _ = fs::remove_dir_all(&staging_dir);
+    }
+    fs::DirBuilder::new().mode(0o700).create(&staging_dir).with_context(|| {
+        format!("Can't create the directory {:?} for the admin socket", staging_dir)
+    })?;
+    let staged = bind_in(&staging_dir, path);
+    let _ = fs::remove_dir_all(&staging_dir);
+    let mut connections = 0u64;
+    Ok(UnixListenerStream::new(staged?).map(move |stream| {
+        connections = connections.wrapping_add(1);
+        stream.map(|stream| AdminStream(stream, connections))
+    }))
+}
+
+/// Binds the socket in the given private directory, restricts its permissions
+/// and moves it to its final path.
+fn bind_in(staging_dir: &Path, path: &Path) -> anyhow::Result<UnixListener> {
+    let staged_path = staging_dir.join("admin.sock");
+    let listener = UnixListener::bind(&staged_path)
+        .with_context(|| format!("Can't bind the admin socket at {:?}", staged_path))?;
+    fs::set_permissions(&staged_path, fs::Permissions::from_mode(0o600)).with_context(|| {
+        format!("Can't restrict the permissions of the admin socket {:?}", staged_path)
+    })?;
+    // this atomically replaces a stale socket
+    fs::rename(&staged_path, path)
+        .with_context(|| format!("Can't move the admin socket to {:?}", path))?;
+    Ok(listener)
+}
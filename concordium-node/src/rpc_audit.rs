@@ -0,0 +1,245 @@
+//! An audit log of the state-changing RPC calls.
+//!
+//! Every call of a method that changes the state of the node, e.g., banning a
+//! peer or stopping the baker, is recorded as a single JSON object per line,
+//! along with the caller, the arguments and the outcome of the call. Calls that
+//! fail authentication are recorded too. The log is rotated once it reaches its
+//! maximum size, keeping a fixed number of old files.
+
+use crate::{lock_or_die, rpc_limits::GatewayClient};
+use anyhow::Context;
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tonic::{Request, Response, Status};
+
+/// The part of a response that is recorded in the audit log.
+pub trait AuditedResponse {
+    fn audit_value(&self) -> Value;
+}
+
+impl AuditedResponse for crate::common::grpc_api::BoolResponse {
+    fn audit_value(&self) -> Value { Value::Bool(self.value) }
+}
+
+impl AuditedResponse for crate::common::grpc_api::JsonResponse {
+    fn audit_value(&self) -> Value {
+        serde_json::from_str(&self.value).unwrap_or_else(|_| Value::String(self.value.clone()))
+    }
+}
+
+/// The outcome of a job is streamed to the client after the call returns, so
+/// only its start is recorded.
+impl AuditedResponse for crate::rpc_jobs::JobStream {
+    fn audit_value(&self) -> Value { Value::String("started".to_owned()) }
+}
+
+/// Who made a call.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Caller {
+    /// How the call reached the node: `tcp`, `gateway` or `adminSocket`.
+    via:               &'static str,
+    address:           Option<String>,
+    /// A fingerprint of the access token, which tells the callers apart
+    /// without revealing the token.
+    token_fingerprint: Option<String>,
+}
+
+impl Caller {
+    /// Identifies the caller of the given request.
+    pub fn of<T>(req: &Request<T>) -> Self {
+        // requests made over the admin socket and by the HTTP gateway have no remote
+        // address; only the gateway attaches the address of its client
+        let (via, address) = match (req.remote_addr(), req.extensions().get::<GatewayClient>()) {
+            (Some(addr), _) => ("tcp", Some(addr.to_string())),
+            (None, Some(GatewayClient(addr))) => ("gateway", Some(addr.to_string())),
+            (None, None) => ("adminSocket", None),
+        };
+        let token_fingerprint = req.metadata().get("authentication").map(|token| {
+            let digest = Sha256::digest(token.as_bytes());
+            hex::encode(&digest[..8])
+        });
+        Caller {
+            via,
+            address,
+            token_fingerprint,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    timestamp: String,
+    method:    &'a str,
+    caller:    Caller,
+    arguments: Value,
+    result:    Value,
+}
+
+struct AuditFile {
+    path:      PathBuf,
+    file:      File,
+    size:      u64,
+    max_size:  u64,
+    max_files: usize,
+}
+
+impl AuditFile {
+    fn open(path: &Path, max_size: u64, max_files: usize) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Can't open the audit log {:?}", path))?;
+        let size = file.metadata()?.len();
+        Ok(AuditFile {
+            path: path.to_owned(),
+            file,
+            size,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    /// Shifts the old files by one, dropping the oldest, and starts a new file.
+    fn rotate(&mut self) -> anyhow::Result<()> {
+        for n in (1..self.max_files).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(n + 1))?;
+            }
+        }
+        if self.max_files > 0 {
+            fs::rename(&self.path, self.rotated_path(1))?;
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+        *self = AuditFile::open(&self.path, self.max_size, self.max_files)?;
+        Ok(())
+    }
+
+    fn append(&mut self, line: &[u8]) -> anyhow::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.file.flush()?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// The audit log; if no file is configured, nothing is recorded.
+pub struct AuditLog {
+    file: Option<Mutex<AuditFile>>,
+}
+
+impl AuditLog {
+    /// Opens the audit log at the given path, if any. The log is rotated once
+    /// it exceeds `max_size` bytes, keeping `max_files` old files.
+    pub fn new(path: Option<&Path>, max_size: u64, max_files: usize) -> anyhow::Result<Self> {
+        let file = match path {
+            Some(path) => Some(Mutex::new(AuditFile::open(path, max_size, max_files)?)),
+            None => None,
+        };
+        Ok(AuditLog {
+            file,
+        })
+    }
+
+    /// Records a call and its outcome.
+    pub fn record<T: AuditedResponse>(
+        &self,
+        method: &str,
+        caller: Caller,
+        arguments: Value,
+        result: &Result<Response<T>, Status>,
+    ) {
+        let file = match self.file {
+            Some(ref file) => file,
+            None => return,
+        };
+        let result = match result {
+            Ok(response) => json!({ "ok": response.get_ref().audit_value() }),
+            Err(status) => json!({
+                "error": { "code": format!("{:?}", status.code()), "message": status.message() }
+            }),
+        };
+        let entry = AuditEntry {
+            timestamp: Utc::now().to_rfc3339(),
+            method,
+            caller,
+            arguments,
+            result,
+        };
+        let mut line = match serde_json::to_vec(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Can't serialize an audit log entry: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+        if let Err(e) = lock_or_die!(file).append(&line) {
+            error!("Can't write to the audit log: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::grpc_api::BoolResponse;
+
+    #[test]
+    fn test_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let log = AuditLog::new(Some(&path), 300, 2).unwrap();
+        let result = Ok(Response::new(BoolResponse {
+            value: true,
+        }));
+        for _ in 0..10 {
+            let caller = Caller::of(&Request::new(()));
+            log.record("StopBaker", caller, json!({}), &result);
+        }
+        let contents = fs::read_to_string(&path).unwrap();
+        let entry: Value = serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+        assert_eq!(entry["method"], "StopBaker");
+        assert_eq!(entry["caller"]["via"], "adminSocket");
+        assert_eq!(entry["result"]["ok"], true);
+        assert!(contents.len() <= 300);
+        assert!(dir.path().join("audit.log.1").exists());
+        assert!(dir.path().join("audit.log.2").exists());
+        assert!(!dir.path().join("audit.log.3").exists());
+    }
+
+    #[test]
+    fn test_caller() {
+        let mut req = Request::new(());
+        // only the gateway can mark a request as made on behalf of a client
+        req.metadata_mut().insert("x-forwarded-for", "10.0.0.1".parse().unwrap());
+        let caller = Caller::of(&req);
+        assert_eq!(caller.via, "adminSocket");
+        assert_eq!(caller.address, None);
+
+        req.extensions_mut().insert(GatewayClient("10.0.0.2".parse().unwrap()));
+        let caller = Caller::of(&req);
+        assert_eq!(caller.via, "gateway");
+        assert_eq!(caller.address.as_deref(), Some("10.0.0.2"));
+    }
+}
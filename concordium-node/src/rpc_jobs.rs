@@ -0,0 +1,159 @@
+//! Long-running administration jobs started through the RPC server, such as
+//! block exports and snapshots.
+//!
+//! A job can take hours, so it runs on a thread of its own instead of the
+//! query pool, whose few threads it would otherwise occupy for its whole
+//! duration, and it isn't subject to the deadline of the request that started
+//! it. That request is answered with a stream of the job's progress, ending
+//! with its result. Dropping the stream, i.e., the client cancelling the call
+//! or going away, asks the job to stop.
+
+use crate::{common::grpc_api::JsonResponse, rpc_limits::OwnedRpcPermit, spawn_or_die};
+use serde::Serialize;
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tonic::Status;
+
+/// The number of progress updates buffered for a client that is slow to read
+/// them.
+const PROGRESS_BUFFER_SIZE: usize = 16;
+
+/// The stream of the progress of a job; dropping it cancels the job.
+pub struct JobStream {
+    inner:     ReceiverStream<Result<JsonResponse, Status>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Stream for JobStream {
+    type Item = Result<JsonResponse, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+impl Drop for JobStream {
+    fn drop(&mut self) { self.cancelled.store(true, Ordering::Relaxed); }
+}
+
+/// The handle through which a job reports its progress and learns whether it
+/// was cancelled.
+pub struct JobHandle {
+    sender:    mpsc::Sender<Result<JsonResponse, Status>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl JobHandle {
+    /// Whether the job should stop, as nobody is waiting for its result.
+    pub fn is_cancelled(&self) -> bool { self.cancelled.load(Ordering::Relaxed) }
+
+    /// Sends a progress update to the client, waiting while its buffer is
+    /// full.
+    pub fn report<T: Serialize>(&self, progress: &T) {
+        match serde_json::to_string(progress) {
+            Ok(value) => self.send(Ok(JsonResponse {
+                value,
+            })),
+            Err(e) => error!("Can't serialize the progress of a job: {}", e),
+        }
+    }
+
+    fn send(&self, item: Result<JsonResponse, Status>) {
+        // the client is gone if the stream was dropped
+        if self.sender.blocking_send(item).is_err() {
+            self.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Starts a job on a thread of its own. The job reports its progress through
+/// the given handle and its result ends the returned stream; the permit is
+/// held until the job is done.
+pub fn spawn_job<T, F>(name: &'static str, permit: OwnedRpcPermit, job: F) -> JobStream
+where
+    T: Serialize,
+    F: FnOnce(&JobHandle) -> anyhow::Result<T> + Send + 'static, {
+    let (sender, receiver) = mpsc::channel(PROGRESS_BUFFER_SIZE);
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let handle = JobHandle {
+        sender,
+        cancelled: Arc::clone(&cancelled),
+    };
+    spawn_or_die!(name, move || {
+        // the job counts as in flight until it is done
+        let _permit = permit;
+        match job(&handle) {
+            Ok(result) => handle.report(&result),
+            Err(e) if handle.is_cancelled() => warn!("The {} job was cancelled: {:#}", name, e),
+            Err(e) => {
+                warn!("The {} job failed: {:#}", name, e);
+                handle.send(Err(Status::failed_precondition(format!("{:#}", e))));
+            }
+        }
+    });
+    JobStream {
+        inner: ReceiverStream::new(receiver),
+        cancelled,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        rpc_limits::{MethodClass, RpcLimiter},
+        stats_export_service::StatsExportService,
+        test_utils::get_test_config,
+    };
+    use tokio_stream::StreamExt;
+    use tonic::Request;
+
+    fn permit() -> OwnedRpcPermit {
+        let conf = get_test_config(8888, vec![100]).cli.rpc;
+        let limiter =
+            Arc::new(RpcLimiter::new(&conf, Arc::new(StatsExportService::new().unwrap())));
+        RpcLimiter::acquire_owned(&limiter, &Request::new(()), MethodClass::Node).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_job_progress_and_result() {
+        let mut stream = spawn_job("test", permit(), |handle| {
+            handle.report(&1);
+            handle.report(&2);
+            Ok(3)
+        });
+        let mut values = Vec::new();
+        while let Some(item) = stream.next().await {
+            values.push(item.unwrap().value);
+        }
+        assert_eq!(values, vec!["1", "2", "3"]);
+
+        let mut stream =
+            spawn_job("test", permit(), |_| -> anyhow::Result<()> { anyhow::bail!("broken") });
+        let status = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_stream_cancels_job() {
+        let (done_sender, done_receiver) = std::sync::mpsc::channel();
+        let stream = spawn_job("test", permit(), move |handle| {
+            while !handle.is_cancelled() {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            done_sender.send(()).unwrap();
+            anyhow::bail!("cancelled")
+        });
+        drop(stream);
+        done_receiver.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+    }
+}
@@ -0,0 +1,416 @@
+//! Per-client limits on the rate and concurrency of RPC requests.
+//!
+//! Every gRPC query results in a synchronous call into consensus, so a single
+//! client issuing expensive queries in a tight loop can slow down the whole
+//! node. Methods are grouped into classes, and each client is allowed a
+//! configurable number of requests per second and a configurable number of
+//! concurrent requests in each class.
+
+use crate::{configuration::RpcCliConfig, lock_or_die, stats_export_service::StatsExportService};
+use anyhow::bail;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tonic::{Request, Status};
+
+/// The address of the client on whose behalf the HTTP gateway is making a
+/// request. The gateway calls the server in-process and attaches this as a
+/// request extension, which, unlike metadata, can't be set by clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GatewayClient(pub IpAddr);
+
+/// The connection on the admin socket a request was made on. Connections on
+/// the socket have no remote address and all present the same access token,
+/// so each of them is limited on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdminClient(pub u64);
+
+/// Once this many clients are tracked, idle ones are forgotten. If none of them
+/// are idle, requests from new clients are rejected until some are.
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+/// How long a client has to be idle before it is forgotten.
+const CLIENT_IDLE_TIME: Duration = Duration::from_secs(60);
+
+/// How clients are told apart for the purpose of limiting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientIdentity {
+    /// Clients are identified by their IP address.
+    Address,
+    /// Clients are identified by the access token they present. All clients
+    /// presenting the same token share their limits.
+    Token,
+}
+
+impl FromStr for ClientIdentity {
+    type Err = anyhow::Error;
+
+    fn from_str(identity: &str) -> Result<Self, Self::Err> {
+        match identity {
+            "address" => Ok(ClientIdentity::Address),
+            "token" => Ok(ClientIdentity::Token),
+            _ => bail!("Could not parse the RPC client identity"),
+        }
+    }
+}
+
+/// Classes of RPC methods that are limited independently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MethodClass {
+    /// Queries and commands that do not involve consensus.
+    Node,
+    /// Consensus queries with small responses.
+    Query,
+    /// Consensus queries that potentially traverse a lot of state or produce
+    /// large responses, e.g., listing all the accounts.
+    Expensive,
+    /// Transaction submissions.
+    Transaction,
+}
+
+impl MethodClass {
+    const ALL: [MethodClass; 4] =
+        [MethodClass::Node, MethodClass::Query, MethodClass::Expensive, MethodClass::Transaction];
+
+    /// The name of the class, as used in the metrics.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MethodClass::Node => "node",
+            MethodClass::Query => "query",
+            MethodClass::Expensive => "expensive",
+            MethodClass::Transaction => "transaction",
+        }
+    }
+}
+
+/// The limits of a single method class. A limit of 0 means no limit.
+#[derive(Debug, Clone, Copy, Default)]
+struct ClassLimits {
+    /// Requests per second; this is also the size of the burst allowed.
+    rate_limit:    u32,
+    /// Requests processed at the same time.
+    max_in_flight: u32,
+}
+
+impl ClassLimits {
+    fn is_unlimited(self) -> bool { self.rate_limit == 0 && self.max_in_flight == 0 }
+}
+
+/// The state of a client with respect to one method class.
+struct ClientState {
+    /// The remaining requests in the token bucket.
+    tokens:      f64,
+    last_refill: Instant,
+    in_flight:   u32,
+}
+
+impl ClientState {
+    fn new(limits: ClassLimits) -> Self {
+        Self {
+            tokens:      limits.rate_limit.into(),
+            last_refill: Instant::now(),
+            in_flight:   0,
+        }
+    }
+
+    fn refill(&mut self, limits: ClassLimits) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        let rate = f64::from(limits.rate_limit);
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.last_refill = now;
+    }
+}
+
+/// Tracks the requests of every client and decides whether to admit new ones.
+pub struct RpcLimiter {
+    identity:    ClientIdentity,
+    node:        ClassLimits,
+    query:       ClassLimits,
+    expensive:   ClassLimits,
+    transaction: ClassLimits,
+    clients:     Mutex<HashMap<(String, MethodClass), ClientState>>,
+    stats:       Arc<StatsExportService>,
+}
+
+/// Permission to process a request. The request stops counting towards the
+/// in-flight limit once this is dropped.
+pub struct RpcPermit<'a> {
+    limiter: &'a RpcLimiter,
+    class:   MethodClass,
+    client:  Option<String>,
+}
+
+impl Drop for RpcPermit<'_> {
+    fn drop(&mut self) { self.limiter.release(self.class, self.client.take()); }
+}
+
+/// Like [`RpcPermit`], but holding its own reference to the limiter, so that
+/// it can be moved into a task that outlives the request handler, such as one
+/// producing the items of a stream.
+pub struct OwnedRpcPermit {
+    limiter: Arc<RpcLimiter>,
+    class:   MethodClass,
+    client:  Option<String>,
+}
+
+impl Drop for OwnedRpcPermit {
+    fn drop(&mut self) { self.limiter.release(self.class, self.client.take()); }
+}
+
+impl RpcLimiter {
+    /// Creates a limiter with the limits from the configuration.
+    pub fn new(conf: &RpcCliConfig, stats: Arc<StatsExportService>) -> Self {
+        let limiter = RpcLimiter {
+            identity: conf.rpc_server_client_identity,
+            node: ClassLimits {
+                rate_limit:    conf.rpc_server_rate_limit_node,
+                max_in_flight: conf.rpc_server_max_in_flight_node,
+            },
+            query: ClassLimits {
+                rate_limit:    conf.rpc_server_rate_limit_query,
+                max_in_flight: conf.rpc_server_max_in_flight_query,
+            },
+            expensive: ClassLimits {
+                rate_limit:    conf.rpc_server_rate_limit_expensive,
+                max_in_flight: conf.rpc_server_max_in_flight_expensive,
+            },
+            transaction: ClassLimits {
+                rate_limit:    conf.rpc_server_rate_limit_transaction,
+                max_in_flight: conf.rpc_server_max_in_flight_transaction,
+            },
+            clients: Default::default(),
+            stats,
+        };
+        for &class in MethodClass::ALL.iter() {
+            let limits = limiter.limits(class);
+            limiter.stats.set_rpc_limits(class.as_str(), limits.rate_limit, limits.max_in_flight);
+        }
+        limiter
+    }
+
+    fn limits(&self, class: MethodClass) -> ClassLimits {
+        match class {
+            MethodClass::Node => self.node,
+            MethodClass::Query => self.query,
+            MethodClass::Expensive => self.expensive,
+            MethodClass::Transaction => self.transaction,
+        }
+    }
+
+    /// Identifies the client that made the request.
+    fn client<T>(&self, req: &Request<T>) -> String {
+        if let Some(AdminClient(connection)) = req.extensions().get::<AdminClient>() {
+            return format!("admin/{}", connection);
+        }
+        match self.identity {
+            ClientIdentity::Token => req
+                .metadata()
+                .get("authentication")
+                .and_then(|token| token.to_str().ok())
+                .unwrap_or_default()
+                .to_owned(),
+            // requests coming from the HTTP gateway are made in-process and have no
+            // remote address, so we rely on the address attached by the gateway
+            ClientIdentity::Address => {
+                match (req.remote_addr(), req.extensions().get::<GatewayClient>()) {
+                    (Some(addr), _) => addr.ip().to_string(),
+                    (None, Some(GatewayClient(addr))) => addr.to_string(),
+                    (None, None) => String::new(),
+                }
+            }
+        }
+    }
+
+    /// Admits a request of the given class or rejects it with
+    /// `RESOURCE_EXHAUSTED` if the client exceeded one of its limits.
+    pub fn acquire<T>(&self, req: &Request<T>, class: MethodClass) -> Result<RpcPermit, Status> {
+        let client = self.admit(req, class)?;
+        Ok(RpcPermit {
+            limiter: self,
+            class,
+            client,
+        })
+    }
+
+    /// Like `acquire`, but the permit may outlive the borrow of the limiter.
+    pub fn acquire_owned<T>(
+        limiter: &Arc<Self>,
+        req: &Request<T>,
+        class: MethodClass,
+    ) -> Result<OwnedRpcPermit, Status> {
+        let client = limiter.admit(req, class)?;
+        Ok(OwnedRpcPermit {
+            limiter: Arc::clone(limiter),
+            class,
+            client,
+        })
+    }
+
+    /// Counts an admitted request towards the limits of its client, which is
+    /// returned if it has any limits.
+    fn admit<T>(&self, req: &Request<T>, class: MethodClass) -> Result<Option<String>, Status> {
+        let limits = self.limits(class);
+        let client = if limits.is_unlimited() {
+            None
+        } else {
+            let client = self.client(req);
+            let key = (client, class);
+            let mut clients = lock_or_die!(self.clients);
+            if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(&key) {
+                clients.retain(|_, state| {
+                    state.in_flight > 0 || state.last_refill.elapsed() < CLIENT_IDLE_TIME
+                });
+                if clients.len() >= MAX_TRACKED_CLIENTS {
+                    self.stats.rpc_requests_rejected_inc(class.as_str(), "clients");
+                    return Err(Status::resource_exhausted(
+                        "Too many clients are being served; try again later.",
+                    ));
+                }
+            }
+            let state = clients.entry(key.clone()).or_insert_with(|| ClientState::new(limits));
+            if limits.max_in_flight != 0 && state.in_flight >= limits.max_in_flight {
+                self.stats.rpc_requests_rejected_inc(class.as_str(), "in_flight");
+                return Err(Status::resource_exhausted(
+                    "Too many concurrent requests; try again later.",
+                ));
+            }
+            if limits.rate_limit != 0 {
+                state.refill(limits);
+                if state.tokens < 1.0 {
+                    self.stats.rpc_requests_rejected_inc(class.as_str(), "rate");
+                    return Err(Status::resource_exhausted(
+                        "Request rate limit exceeded; try again later.",
+                    ));
+                }
+                state.tokens -= 1.0;
+            }
+            state.in_flight += 1;
+            Some(key.0)
+        };
+        self.stats.rpc_requests_in_flight_inc(class.as_str());
+        Ok(client)
+    }
+
+    /// Stops counting a request that is done towards the in-flight limit.
+    fn release(&self, class: MethodClass, client: Option<String>) {
+        if let Some(client) = client {
+            if let Some(state) = lock_or_die!(self.clients).get_mut(&(client, class)) {
+                state.in_flight = state.in_flight.saturating_sub(1);
+            }
+        }
+        self.stats.rpc_requests_in_flight_dec(class.as_str());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::get_test_config;
+    use tonic::Code;
+
+    fn test_limiter(rate_limit: u32, max_in_flight: u32) -> RpcLimiter {
+        let mut conf = get_test_config(8888, vec![100]).cli.rpc;
+        conf.rpc_server_rate_limit_expensive = rate_limit;
+        conf.rpc_server_max_in_flight_expensive = max_in_flight;
+        RpcLimiter::new(&conf, Arc::new(StatsExportService::new().unwrap()))
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limiter = test_limiter(2, 0);
+        let req = Request::new(());
+        assert!(limiter.acquire(&req, MethodClass::Expensive).is_ok());
+        assert!(limiter.acquire(&req, MethodClass::Expensive).is_ok());
+        match limiter.acquire(&req, MethodClass::Expensive) {
+            Err(status) => assert_eq!(status.code(), Code::ResourceExhausted),
+            Ok(_) => panic!("The rate limit was not enforced"),
+        }
+        // other classes are not affected
+        assert!(limiter.acquire(&req, MethodClass::Query).is_ok());
+    }
+
+    #[test]
+    fn test_in_flight_limit() {
+        let limiter = test_limiter(0, 1);
+        let req = Request::new(());
+        let permit = limiter.acquire(&req, MethodClass::Expensive).unwrap();
+        assert!(limiter.acquire(&req, MethodClass::Expensive).is_err());
+        drop(permit);
+        assert!(limiter.acquire(&req, MethodClass::Expensive).is_ok());
+    }
+
+    #[test]
+    fn test_owned_permit() {
+        let limiter = Arc::new(test_limiter(0, 1));
+        let req = Request::new(());
+        let permit = RpcLimiter::acquire_owned(&limiter, &req, MethodClass::Expensive).unwrap();
+        // the permit still counts once it is moved elsewhere
+        let handle = std::thread::spawn(move || permit);
+        let permit = handle.join().unwrap();
+        assert!(limiter.acquire(&req, MethodClass::Expensive).is_err());
+        drop(permit);
+        assert!(limiter.acquire(&req, MethodClass::Expensive).is_ok());
+    }
+
+    #[test]
+    fn test_gateway_client_identity() {
+        let limiter = test_limiter(1, 0);
+        let gateway_req = |addr: &str| {
+            let mut req = Request::new(());
+            req.extensions_mut().insert(GatewayClient(addr.parse().unwrap()));
+            req
+        };
+        assert!(limiter.acquire(&gateway_req("10.0.0.1"), MethodClass::Expensive).is_ok());
+        assert!(limiter.acquire(&gateway_req("10.0.0.1"), MethodClass::Expensive).is_err());
+        // gateway clients are limited separately
+        assert!(limiter.acquire(&gateway_req("10.0.0.2"), MethodClass::Expensive).is_ok());
+
+        // a client can't choose its limits by claiming another address
+        let mut forged = Request::new(());
+        forged.metadata_mut().insert("x-forwarded-for", "10.0.0.3".parse().unwrap());
+        assert!(limiter.acquire(&forged, MethodClass::Expensive).is_ok());
+        forged.metadata_mut().insert("x-forwarded-for", "10.0.0.4".parse().unwrap());
+        assert!(limiter.acquire(&forged, MethodClass::Expensive).is_err());
+    }
+
+    #[test]
+    fn test_admin_client_identity() {
+        let limiter = test_limiter(1, 0);
+        let admin_req = |connection| {
+            let mut req = Request::new(());
+            req.extensions_mut().insert(AdminClient(connection));
+            req
+        };
+        assert!(limiter.acquire(&admin_req(0), MethodClass::Expensive).is_ok());
+        assert!(limiter.acquire(&admin_req(0), MethodClass::Expensive).is_err());
+        // every connection on the admin socket is limited separately
+        assert!(limiter.acquire(&admin_req(1), MethodClass::Expensive).is_ok());
+        // and separately from the clients without an address
+        assert!(limiter.acquire(&Request::new(()), MethodClass::Expensive).is_ok());
+    }
+
+    #[test]
+    fn test_tracked_clients_limit() {
+        let limiter = test_limiter(1, 0);
+        let gateway_req = |client: usize| {
+            let mut req = Request::new(());
+            let addr = IpAddr::from([10, 0, (client / 256) as u8, (client % 256) as u8]);
+            req.extensions_mut().insert(GatewayClient(addr));
+            req
+        };
+        for client in 0..MAX_TRACKED_CLIENTS {
+            assert!(limiter.acquire(&gateway_req(client), MethodClass::Expensive).is_ok());
+        }
+        // none of the clients are idle, so a new one can't be tracked
+        match limiter.acquire(&gateway_req(MAX_TRACKED_CLIENTS), MethodClass::Expensive) {
+            Err(status) => assert_eq!(status.code(), Code::ResourceExhausted),
+            Ok(_) => panic!("The number of tracked clients was not limited"),
+        }
+        assert_eq!(lock_or_die!(limiter.clients).len(), MAX_TRACKED_CLIENTS);
+    }
+}
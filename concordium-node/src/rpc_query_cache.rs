@@ -0,0 +1,285 @@
+//! A cache of the responses to consensus queries about finalized blocks.
+//!
+//! The state of a finalized block never changes, so queries such as
+//! `GetBlockInfo` or `GetAccountList` about a finalized block always produce
+//! the same response. Block explorers tend to ask about the same recent blocks
+//! over and over again, so such responses are kept in a size-bounded LRU cache
+//! instead of going through consensus every time. Responses about blocks that
+//! are not (yet) finalized are never cached. A block is only known to be
+//! finalized once a `GetBlockInfo` response saying so has gone through the
+//! cache, so caching never costs an additional query. Since a regenesis starts
+//! a new chain, the cache is cleared whenever a new regenesis block is
+//! registered.
+
+use crate::{
+    consensus_ffi::blockchain_types::BlockHash, lock_or_die, read_or_die,
+    stats_export_service::StatsExportService,
+};
+use lru::LruCache;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// The name of the query whose responses tell whether a block is finalized.
+const BLOCK_INFO: &str = "GetBlockInfo";
+
+/// The maximum number of blocks remembered as finalized.
+const MAX_FINALIZED_BLOCKS: usize = 100_000;
+
+/// Responses are identified by the query, the block and any additional
+/// argument of the query, e.g., an account address.
+type CacheKey = (&'static str, String, String);
+
+/// A cached response to a consensus query.
+#[derive(Clone)]
+pub enum CachedResponse {
+    Json(String),
+    Bytes(Vec<u8>),
+}
+
+impl CachedResponse {
+    fn size(&self) -> usize {
+        match self {
+            CachedResponse::Json(json) => json.len(),
+            CachedResponse::Bytes(bytes) => bytes.len(),
+        }
+    }
+}
+
+/// The types of responses that can be cached.
+pub trait Cacheable: Sized {
+    fn into_cached(self) -> CachedResponse;
+
+    fn from_cached(response: CachedResponse) -> Option<Self>;
+}
+
+impl Cacheable for String {
+    fn into_cached(self) -> CachedResponse { CachedResponse::Json(self) }
+
+    fn from_cached(response: CachedResponse) -> Option<Self> {
+        match response {
+            CachedResponse::Json(json) => Some(json),
+            CachedResponse::Bytes(_) => None,
+        }
+    }
+}
+
+impl Cacheable for Vec<u8> {
+    fn into_cached(self) -> CachedResponse { CachedResponse::Bytes(self) }
+
+    fn from_cached(response: CachedResponse) -> Option<Self> {
+        match response {
+            CachedResponse::Bytes(bytes) => Some(bytes),
+            CachedResponse::Json(_) => None,
+        }
+    }
+}
+
+struct CacheState {
+    entries:         LruCache<CacheKey, CachedResponse>,
+    /// The total size of the cached responses.
+    size:            usize,
+    /// The blocks known to be finalized, which outlive the eviction of their
+    /// info.
+    finalized:       LruCache<String, ()>,
+    /// The number of regenesis blocks known when the cache was last cleared.
+    regenesis_count: usize,
+}
+
+/// A size-bounded cache of the responses to queries about finalized blocks.
+pub struct QueryCache {
+    /// The maximum total size of the cached responses; 0 disables the cache.
+    max_size:      usize,
+    state:         Mutex<CacheState>,
+    regenesis_arc: Arc<RwLock<Vec<BlockHash>>>,
+    stats:         Arc<StatsExportService>,
+}
+
+impl QueryCache {
+    /// Creates an empty cache holding responses of at most `max_size` bytes in
+    /// total.
+    pub fn new(
+        max_size: usize,
+        regenesis_arc: Arc<RwLock<Vec<BlockHash>>>,
+        stats: Arc<StatsExportService>,
+    ) -> Self {
+        let regenesis_count = read_or_die!(regenesis_arc).len();
+        QueryCache {
+            max_size,
+            state: Mutex::new(CacheState {
+                entries: LruCache::unbounded(),
+                size: 0,
+                finalized: LruCache::new(MAX_FINALIZED_BLOCKS),
+                regenesis_count,
+            }),
+            regenesis_arc,
+            stats,
+        }
+    }
+
+    /// Answers a query about the given block from the cache, or executes it and
+    /// caches the response if the block is finalized.
+    pub fn get_or_query<T: Cacheable + Clone>(
+        &self,
+        method: &'static str,
+        block_hash: &str,
+        argument: &str,
+        query: impl FnOnce() -> T,
+    ) -> T {
+        if self.max_size == 0 {
+            return query();
+        }
+
+        let key = (method, block_hash.to_owned(), argument.to_owned());
+        if let Some(response) = self.get(&key).and_then(T::from_cached) {
+            self.stats.rpc_query_cache_hits_inc(method);
+            return response;
+        }
+        self.stats.rpc_query_cache_misses_inc(method);
+
+        // a regenesis taking place while the query is executed must not leave
+        // its response in the cache
+        let regenesis_count = read_or_die!(self.regenesis_arc).len();
+        let response = query();
+        let cached = response.clone().into_cached();
+        let finalized = match cached {
+            CachedResponse::Json(ref block_info) if method == BLOCK_INFO => {
+                is_finalized(block_info)
+            }
+            _ => self.block_is_finalized(block_hash),
+        };
+        if finalized {
+            self.insert(key, cached, regenesis_count);
+        }
+        response
+    }
+
+    /// Checks whether a block is known to be finalized. A block that isn't
+    /// may still be finalized; its responses are then not cached until its
+    /// info is queried.
+    fn block_is_finalized(&self, block_hash: &str) -> bool {
+        let mut state = lock_or_die!(self.state);
+        self.invalidate_on_regenesis(&mut state);
+        state.finalized.get(block_hash).is_some()
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<CachedResponse> {
+        let mut state = lock_or_die!(self.state);
+        self.invalidate_on_regenesis(&mut state);
+        state.entries.get(key).cloned()
+    }
+
+    fn insert(&self, key: CacheKey, response: CachedResponse, regenesis_count: usize) {
+        let mut state = lock_or_die!(self.state);
+        self.invalidate_on_regenesis(&mut state);
+        if state.regenesis_count != regenesis_count || response.size() > self.max_size {
+            return;
+        }
+        if key.0 == BLOCK_INFO {
+            state.finalized.put(key.1.clone(), ());
+        }
+        state.size += response.size();
+        if let Some(replaced) = state.entries.put(key, response) {
+            state.size -= replaced.size();
+        }
+        while state.size > self.max_size {
+            match state.entries.pop_lru() {
+                Some((_, evicted)) => state.size -= evicted.size(),
+                None => break,
+            }
+        }
+        self.stats.set_rpc_query_cache_size(state.size);
+    }
+
+    /// Clears the cache if a regenesis took place since it was last cleared.
+    fn invalidate_on_regenesis(&self, state: &mut CacheState) {
+        let regenesis_count = read_or_die!(self.regenesis_arc).len();
+        if state.regenesis_count != regenesis_count {
+            debug!("Clearing the query cache after a regenesis");
+            state.entries.clear();
+            state.finalized.clear();
+            state.size = 0;
+            state.regenesis_count = regenesis_count;
+            self.stats.set_rpc_query_cache_size(0);
+        }
+    }
+}
+
+/// Checks the `finalized` field of the JSON response to `GetBlockInfo`.
+fn is_finalized(block_info: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(block_info)
+        .ok()
+        .and_then(|info| info.get("finalized").and_then(serde_json::Value::as_bool))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::dummy_regenesis_blocks, write_or_die};
+
+    fn test_cache(max_size: usize) -> QueryCache {
+        QueryCache::new(
+            max_size,
+            Arc::new(RwLock::new(Vec::new())),
+            Arc::new(StatsExportService::new().unwrap()),
+        )
+    }
+
+    fn key(block_hash: &str) -> CacheKey {
+        ("GetBlockSummary", block_hash.to_owned(), String::new())
+    }
+
+    #[test]
+    fn test_eviction() {
+        let cache = test_cache(10);
+        cache.insert(key("a"), CachedResponse::Json("abcd".to_owned()), 0);
+        cache.insert(key("b"), CachedResponse::Json("efgh".to_owned()), 0);
+        // touch the first entry so that the second one is evicted
+        assert!(cache.get(&key("a")).is_some());
+        cache.insert(key("c"), CachedResponse::Json("ijkl".to_owned()), 0);
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("c")).is_some());
+        // responses larger than the whole cache are not cached
+        cache.insert(key("d"), CachedResponse::Bytes(vec![0; 11]), 0);
+        assert!(cache.get(&key("d")).is_none());
+    }
+
+    #[test]
+    fn test_regenesis_invalidation() {
+        let cache = test_cache(100);
+        cache.insert(key("a"), CachedResponse::Json("abcd".to_owned()), 0);
+        write_or_die!(cache.regenesis_arc).push(dummy_regenesis_blocks()[0].clone());
+        assert!(cache.get(&key("a")).is_none());
+        // responses obtained before the regenesis are discarded
+        cache.insert(key("a"), CachedResponse::Json("abcd".to_owned()), 0);
+        assert!(cache.get(&key("a")).is_none());
+    }
+
+    #[test]
+    fn test_finality_from_block_info() {
+        let cache = test_cache(100);
+        let summary = || "summary".to_owned();
+        // the finality of the block is unknown, so the summary isn't cached
+        cache.get_or_query("GetBlockSummary", "a", "", summary);
+        assert!(cache.get(&key("a")).is_none());
+        // the info of a block that isn't finalized isn't cached either
+        cache.get_or_query(BLOCK_INFO, "a", "", || r#"{"finalized":false}"#.to_owned());
+        cache.get_or_query("GetBlockSummary", "a", "", summary);
+        assert!(cache.get(&key("a")).is_none());
+        // once the info says that it is finalized, the summary is cached
+        cache.get_or_query(BLOCK_INFO, "a", "", || r#"{"finalized":true}"#.to_owned());
+        cache.get_or_query("GetBlockSummary", "a", "", summary);
+        assert!(cache.get(&key("a")).is_some());
+        // the finality is remembered even once the info is evicted
+        cache.insert(key("b"), CachedResponse::Bytes(vec![0; 90]), 0);
+        assert!(cache.get(&(BLOCK_INFO, "a".to_owned(), String::new())).is_none());
+        assert!(cache.block_is_finalized("a"));
+    }
+
+    #[test]
+    fn test_is_finalized() {
+        assert!(is_finalized(r#"{"blockHeight":1,"finalized":true}"#));
+        assert!(!is_finalized(r#"{"blockHeight":1,"finalized":false}"#));
+        assert!(!is_finalized("null"));
+    }
+}
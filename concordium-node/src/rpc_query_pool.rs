@@ -0,0 +1,202 @@
+//! A bounded pool of threads executing consensus queries on behalf of the RPC
+//! server.
+//!
+//! Consensus queries are synchronous calls into Haskell that can take a long
+//! time. Running them on the threads of the async runtime would stall every
+//! other request, so they are handed to a fixed number of dedicated threads
+//! through a bounded queue instead. The deadline of the gRPC request is
+//! honored: the client is answered with `DEADLINE_EXCEEDED` once it expires,
+//! and queries whose deadline expired while they were queued are not executed
+//! at all. Calls that change the state of the node have no deadline, as their
+//! effect can't be undone once they are started, and they are always executed
+//! once they are queued.
+
+use crate::{spawn_or_die, stats_export_service::StatsExportService};
+use crossbeam_channel::TrySendError;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+use tonic::{Request, Status};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// The pool of threads executing consensus queries.
+pub struct QueryPool {
+    sender: crossbeam_channel::Sender<Job>,
+    stats:  Arc<StatsExportService>,
+}
+
+impl QueryPool {
+    /// Starts a pool with the given number of threads, accepting at most
+    /// `queue_size` queries waiting to be executed. The threads stop once the
+    /// pool is dropped.
+    pub fn new(threads: usize, queue_size: usize, stats: Arc<StatsExportService>) -> Self {
+        let (sender, receiver) = crossbeam_channel::bounded::<Job>(queue_size);
+        for i in 0..threads {
+            let receiver = receiver.clone();
+            spawn_or_die!(format!("RPC query {}", i), move || {
+                while let Ok(job) = receiver.recv() {
+                    job();
+                }
+            });
+        }
+        QueryPool {
+            sender,
+            stats,
+        }
+    }
+
+    /// Executes a query on the pool and waits for its result until the
+    /// deadline, if any.
+    pub async fn run<T, F>(
+        &self,
+        method: &'static str,
+        deadline: Option<Instant>,
+        query: F,
+    ) -> Result<T, Status>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static, {
+        let result_receiver = self.submit(method, deadline, true, query)?;
+        let result = match deadline {
+            Some(deadline) => {
+                tokio::time::timeout_at(deadline.into(), result_receiver).await.map_err(|_| {
+                    debug!("The deadline of a {} request expired", method);
+                    Status::deadline_exceeded("The deadline of the query expired.")
+                })?
+            }
+            None => result_receiver.await,
+        };
+        // the sender is only dropped without a result if the deadline expired
+        // before the query could be started
+        result.map_err(|_| Status::deadline_exceeded("The deadline of the query expired."))
+    }
+
+    /// Executes a call that changes the state of the node on the pool and
+    /// waits for its result. The call has no deadline and is executed even if
+    /// the client stops waiting for it.
+    pub async fn run_update<T, F>(&self, method: &'static str, update: F) -> Result<T, Status>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static, {
+        self.submit(method, None, false, update)?
+            .await
+            .map_err(|_| Status::unavailable("The node is no longer processing queries."))
+    }
+
+    /// Queues a call, which is skipped once its deadline expires or, if it is
+    /// `abandonable`, once nobody is waiting for its result anymore.
+    fn submit<T, F>(
+        &self,
+        method: &'static str,
+        deadline: Option<Instant>,
+        abandonable: bool,
+        call: F,
+    ) -> Result<oneshot::Receiver<T>, Status>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static, {
+        let (result_sender, result_receiver) = oneshot::channel();
+        let stats = Arc::clone(&self.stats);
+        let enqueued = Instant::now();
+        let job: Job = Box::new(move || {
+            let started = Instant::now();
+            stats.rpc_query_queue_time_observe(method, started.duration_since(enqueued));
+            // nobody is waiting for the result anymore
+            if deadline.map_or(false, |deadline| started >= deadline)
+                || (abandonable && result_sender.is_closed())
+            {
+                return;
+            }
+            let result = call();
+            stats.rpc_query_execution_time_observe(method, started.elapsed());
+            // the receiver is gone if the deadline expired during the execution
+            let _ = result_sender.send(result);
+        });
+
+        self.sender.try_send(job).map_err(|e| match e {
+            TrySendError::Full(_) => {
+                warn!("Can't respond to a {} request due to a full query queue", method);
+                Status::resource_exhausted("The node is too busy to process the query.")
+            }
+            TrySendError::Disconnected(_) => {
+                Status::unavailable("The node is no longer processing queries.")
+            }
+        })?;
+        Ok(result_receiver)
+    }
+}
+
+/// Returns the deadline of a request, as given by its `grpc-timeout` header.
+pub fn request_deadline<T>(req: &Request<T>) -> Option<Instant> {
+    let timeout = req.metadata().get("grpc-timeout")?.to_str().ok()?;
+    parse_grpc_timeout(timeout).map(|timeout| Instant::now() + timeout)
+}
+
+/// Parses a timeout in the format of the `grpc-timeout` header, i.e., at most 8
+/// digits followed by a unit.
+fn parse_grpc_timeout(timeout: &str) -> Option<Duration> {
+    if timeout.len() < 2 || timeout.len() > 9 || !timeout.is_ascii() {
+        return None;
+    }
+    let (value, unit) = timeout.split_at(timeout.len() - 1);
+    let value = value.parse::<u64>().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(value * 60 * 60)),
+        "M" => Some(Duration::from_secs(value * 60)),
+        "S" => Some(Duration::from_secs(value)),
+        "m" => Some(Duration::from_millis(value)),
+        "u" => Some(Duration::from_micros(value)),
+        "n" => Some(Duration::from_nanos(value)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    #[test]
+    fn test_parse_grpc_timeout() {
+        assert_eq!(parse_grpc_timeout("5S"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_grpc_timeout("250m"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_grpc_timeout("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_grpc_timeout("S"), None);
+        assert_eq!(parse_grpc_timeout("123456789S"), None);
+        assert_eq!(parse_grpc_timeout("10x"), None);
+    }
+
+    #[tokio::test]
+    async fn test_query_deadline() {
+        let pool = QueryPool::new(1, 1, Arc::new(StatsExportService::new().unwrap()));
+        let deadline = Some(Instant::now() + Duration::from_millis(10));
+        let result =
+            pool.run("Test", deadline, || std::thread::sleep(Duration::from_millis(200))).await;
+        match result {
+            Err(status) => assert_eq!(status.code(), Code::DeadlineExceeded),
+            Ok(_) => panic!("The deadline was not honored"),
+        }
+        assert_eq!(pool.run("Test", None, || 42).await.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn test_update_not_abandoned() {
+        let pool = QueryPool::new(1, 2, Arc::new(StatsExportService::new().unwrap()));
+        let (done_sender, done_receiver) = crossbeam_channel::bounded(1);
+        let (blocker_sender, blocker_receiver) = crossbeam_channel::bounded::<()>(0);
+        // keep the only thread busy so the update is queued
+        let blocker = pool.run("Test", None, move || blocker_receiver.recv());
+        let update = pool.run_update("Test", move || done_sender.send(()).unwrap());
+        // the client gives up on both before they are started
+        let _ = tokio::time::timeout(Duration::from_millis(10), async {
+            tokio::join!(blocker, update)
+        })
+        .await;
+        // the blocking query may have been skipped as well
+        let _ = blocker_sender.send(());
+        assert!(done_receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+}
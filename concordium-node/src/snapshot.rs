@@ -0,0 +1,569 @@
+//! Snapshots of the data directory of a running node.
+//!
+//! The stores in the data directory are written continuously, so copying the
+//! directory while the node runs doesn't give a usable backup. A snapshot
+//! instead copies every store consistently without stopping the node:
+//! consensus writes a copy of its database, the persisted ban list is copied in
+//! a single read transaction, and the preferences (e.g., the node's id) are
+//! copied as they are. The copies are packed into a gzip-compressed tar archive
+//! which starts with a description of the snapshot, so that a restore can check
+//! that the snapshot belongs to the node's chain and database version before
+//! it unpacks anything.
+//!
+//! A restore unpacks the snapshot to a staging directory and checks the version
+//! stored in its tree state before it touches the data and config directories.
+//! The files it replaces are backed up first, and they are put back if the
+//! restore fails halfway.
+
+use crate::{
+    common::get_current_stamp,
+    configuration::DATABASE_SUB_DIRECTORY_NAME,
+    consensus_ffi::{blockchain_types::BlockHash, consensus::Consensus, helpers::TreeStateVersion},
+    p2p::bans::BAN_STORE_NAME,
+    read_or_die,
+};
+use anyhow::{bail, ensure, Context};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use rkv::{
+    backend::{Lmdb, LmdbEnvironment},
+    Rkv, StoreOptions,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+/// The version of the snapshot format.
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+/// The name of the description of a snapshot, which is the first entry of the
+/// archive.
+pub const SNAPSHOT_METADATA_FILE_NAME: &str = "snapshot.json";
+
+/// The directory of the archive holding the files of the config directory.
+const CONFIG_DIRECTORY_NAME: &str = "config";
+
+/// The data file of the node's key-value store in the data directory.
+const KVS_FILE_NAME: &str = "data.mdb";
+
+/// The stores of the node's key-value store that are part of a snapshot.
+const KVS_STORE_NAMES: &[&str] = &[BAN_STORE_NAME];
+
+/// The description of a snapshot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotMetadata {
+    pub format_version:     u32,
+    /// The version of the node that made the snapshot.
+    pub node_version:       String,
+    /// The name of the database directory, which changes with the format of
+    /// the database.
+    pub database_version:   String,
+    /// The version stored in the tree state of the database.
+    pub tree_state_version: String,
+    /// The genesis block of the chain of the node.
+    pub genesis_block:      BlockHash,
+    /// When the snapshot was made, in milliseconds since the Unix epoch.
+    pub timestamp:          u64,
+}
+
+/// Appends a suffix to a path, e.g., to get the name of a partial file.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// The tree state directories of a database directory, e.g., `treestate-0`.
+fn tree_state_dirs(database_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(database_dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir()
+            && entry.file_name().to_string_lossy().starts_with("treestate-")
+        {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Writes a snapshot of the node's stores to the file `output`, which must not
+/// exist yet. The files are copied to a staging directory next to it before
+/// they are packed. Once `cancelled` returns true, the snapshot stops at the
+/// next step and nothing is left behind.
+pub fn create_snapshot(
+    consensus: &dyn Consensus,
+    kvs: &RwLock<Rkv<LmdbEnvironment>>,
+    genesis_block: BlockHash,
+    preferences_file: &Path,
+    output: &Path,
+    cancelled: &dyn Fn() -> bool,
+) -> anyhow::Result<SnapshotMetadata> {
+    ensure!(!output.exists(), "{:?} already exists", output);
+    let staging = with_suffix(output, ".staging");
+    if staging.exists() {
+        // left over from an interrupted snapshot
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)
+        .with_context(|| format!("Can't create the staging directory {:?}", staging))?;
+    let result = write_snapshot(
+        consensus,
+        kvs,
+        genesis_block,
+        preferences_file,
+        &staging,
+        output,
+        cancelled,
+    );
+    if let Err(e) = fs::remove_dir_all(&staging) {
+        warn!("Can't remove the staging directory {:?}: {}", staging, e);
+    }
+    let partial_path = with_suffix(output, ".partial");
+    if result.is_err() && partial_path.exists() {
+        if let Err(e) = fs::remove_file(&partial_path) {
+            warn!("Can't remove {:?}: {}", partial_path, e);
+        }
+    }
+    result
+}
+
+fn write_snapshot(
+    consensus: &dyn Consensus,
+    kvs: &RwLock<Rkv<LmdbEnvironment>>,
+    genesis_block: BlockHash,
+    preferences_file: &Path,
+    staging: &Path,
+    output: &Path,
+    cancelled: &dyn Fn() -> bool,
+) -> anyhow::Result<SnapshotMetadata> {
+    let database_dir = staging.join(DATABASE_SUB_DIRECTORY_NAME);
+    ensure!(
+        consensus.snapshot_database(database_dir.to_string_lossy().as_bytes()),
+        "Consensus couldn't write a copy of its database"
+    );
+    let tree_state_version = match tree_state_dirs(&database_dir)?.as_slice() {
+        [tree_state_dir] => consensus.tree_state_version(tree_state_dir)?.version,
+        _ => bail!("The copy of the database doesn't have exactly one tree state"),
+    };
+    ensure!(!cancelled(), "The snapshot was cancelled");
+    copy_kvs(&read_or_die!(kvs), staging).context("Can't copy the key-value store")?;
+    if let Some(name) = preferences_file.file_name().filter(|_| preferences_file.exists()) {
+        let config_dir = staging.join(CONFIG_DIRECTORY_NAME);
+        fs::create_dir_all(&config_dir)?;
+        fs::copy(preferences_file, config_dir.join(name))
+            .with_context(|| format!("Can't copy {:?}", preferences_file))?;
+    }
+    let metadata = SnapshotMetadata {
+        format_version: SNAPSHOT_FORMAT_VERSION,
+        node_version: crate::VERSION.to_owned(),
+        database_version: DATABASE_SUB_DIRECTORY_NAME.to_owned(),
+        tree_state_version,
+        genesis_block,
+        timestamp: get_current_stamp(),
+    };
+    let metadata_path = staging.join(SNAPSHOT_METADATA_FILE_NAME);
+    fs::write(&metadata_path, serde_json::to_vec_pretty(&metadata)?)?;
+
+    let partial_path = with_suffix(output, ".partial");
+    let file =
+        File::create(&partial_path).with_context(|| format!("Can't create {:?}", partial_path))?;
+    let mut archive =
+        tar::Builder::new(GzEncoder::new(BufWriter::new(file), Compression::default()));
+    // the description comes first, so it can be checked before anything is unpacked
+    archive.append_path_with_name(&metadata_path, SNAPSHOT_METADATA_FILE_NAME)?;
+    for entry in fs::read_dir(staging)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == SNAPSHOT_METADATA_FILE_NAME {
+            continue;
+        }
+        ensure!(!cancelled(), "The snapshot was cancelled");
+        if entry.file_type()?.is_dir() {
+            archive.append_dir_all(&name, entry.path())?;
+        } else {
+            archive.append_path_with_name(entry.path(), &name)?;
+        }
+    }
+    archive.into_inner()?.finish()?.into_inner()?.sync_all()?;
+    fs::rename(&partial_path, output)?;
+    info!("Wrote a snapshot of the data directory to {:?}", output);
+    Ok(metadata)
+}
+
+/// Copies the persisted stores of the node's key-value store to a new
+/// environment in `directory`.
+fn copy_kvs(source: &Rkv<LmdbEnvironment>, directory: &Path) -> anyhow::Result<()> {
+    let target = Rkv::new::<Lmdb>(directory)?;
+    let mut stores = Vec::with_capacity(KVS_STORE_NAMES.len());
+    for name in KVS_STORE_NAMES {
+        stores.push((
+            source.open_single(*name, StoreOptions::create())?,
+            target.open_single(*name, StoreOptions::create())?,
+        ));
+    }
+    let reader = source.read()?;
+    let mut writer = target.write()?;
+    for (source_store, target_store) in stores {
+        for entry in source_store.iter_start(&reader)? {
+            let (key, value) = entry?;
+            target_store.put(&mut writer, key, &value)?;
+        }
+    }
+    writer.commit()?;
+    Ok(())
+}
+
+/// Checks that a snapshot can be restored by a node with the given genesis
+/// block.
+fn check_metadata(metadata: &SnapshotMetadata, genesis_block: &BlockHash) -> anyhow::Result<()> {
+    ensure!(
+        metadata.format_version == SNAPSHOT_FORMAT_VERSION,
+        "The snapshot has the unsupported format version {}",
+        metadata.format_version
+    );
+    ensure!(
+        metadata.database_version == DATABASE_SUB_DIRECTORY_NAME,
+        "The snapshot has database version {}, but this node uses {}",
+        metadata.database_version,
+        DATABASE_SUB_DIRECTORY_NAME
+    );
+    ensure!(
+        &metadata.genesis_block == genesis_block,
+        "The snapshot belongs to the chain with genesis block {}, but the genesis block of this \
+         node is {}",
+        metadata.genesis_block,
+        genesis_block
+    );
+    Ok(())
+}
+
+/// The files replaced by a restore, along with backups of the originals, so
+/// that the restore can be rolled back.
+struct Replacement {
+    backup_dir: PathBuf,
+    /// The replaced files and their backups, if they existed before.
+    replaced:   Vec<(PathBuf, Option<PathBuf>)>,
+}
+
+impl Replacement {
+    /// Moves `source` to `target`, backing up the latter if it exists.
+    fn replace(&mut self, source: &Path, target: &Path) -> anyhow::Result<()> {
+        let backup = if target.exists() {
+            fs::create_dir_all(&self.backup_dir)?;
+            let name = target.file_name().context("The file has no name")?.to_string_lossy();
+            let backup = self.backup_dir.join(format!("{}-{}", self.replaced.len(), name));
+            fs::copy(target, &backup)
+                .with_context(|| format!("Can't back up {:?} to {:?}", target, backup))?;
+            Some(backup)
+        } else {
+            None
+        };
+        // recorded before it is replaced, as a failed replacement may leave a partial
+        // file
+        self.replaced.push((target.to_owned(), backup));
+        // the config directory can be on another file system than the staging directory
+        fs::rename(source, target)
+            .or_else(|_| fs::copy(source, target).map(|_| ()))
+            .with_context(|| format!("Can't replace {:?}", target))
+    }
+
+    /// Puts the original files back; the backups are kept if that fails.
+    fn roll_back(self) {
+        let mut rolled_back = true;
+        for (target, backup) in self.replaced.into_iter().rev() {
+            let result = match backup {
+                Some(backup) => fs::copy(&backup, &target).map(|_| ()),
+                None if target.is_dir() => fs::remove_dir_all(&target),
+                None if target.exists() => fs::remove_file(&target),
+                None => Ok(()),
+            };
+            if let Err(e) = result {
+                error!("Can't roll back {:?}: {}", target, e);
+                rolled_back = false;
+            }
+        }
+        if rolled_back && self.backup_dir.exists() {
+            let _ = fs::remove_dir_all(&self.backup_dir);
+        }
+    }
+}
+
+/// Restores a snapshot to the given data and config directories after checking
+/// that it belongs to the chain with the given genesis block and that its tree
+/// state, whose stored version is read with `tree_state_version`, can be
+/// opened by this node. An existing database is never overwritten; the other
+/// files that are replaced are backed up to a directory in the data directory.
+pub fn restore_snapshot(
+    snapshot: &Path,
+    genesis_block: &BlockHash,
+    data_dir: &Path,
+    config_dir: &Path,
+    tree_state_version: &dyn Fn(&Path) -> anyhow::Result<TreeStateVersion>,
+) -> anyhow::Result<SnapshotMetadata> {
+    let database_dir = data_dir.join(DATABASE_SUB_DIRECTORY_NAME);
+    ensure!(
+        !database_dir.exists(),
+        "The database {:?} already exists; move it away to restore a snapshot",
+        database_dir
+    );
+    let file = File::open(snapshot).with_context(|| format!("Can't open {:?}", snapshot))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(BufReader::new(file)));
+    let mut entries = archive.entries()?;
+    let metadata: SnapshotMetadata = match entries.next() {
+        Some(entry) => {
+            let entry = entry?;
+            ensure!(
+                entry.path()? == Path::new(SNAPSHOT_METADATA_FILE_NAME),
+                "The snapshot doesn't start with {}",
+                SNAPSHOT_METADATA_FILE_NAME
+            );
+            serde_json::from_reader(entry).context("Can't parse the description of the snapshot")?
+        }
+        None => bail!("The snapshot is empty"),
+    };
+    check_metadata(&metadata, genesis_block)?;
+
+    let staging = data_dir.join("snapshot-restore.partial");
+    if staging.exists() {
+        // left over from an interrupted restore
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    let result = (|| {
+        for entry in entries {
+            // refuses entries outside the staging directory
+            entry?.unpack_in(&staging)?;
+        }
+        check_staged_database(
+            &staging.join(DATABASE_SUB_DIRECTORY_NAME),
+            &metadata,
+            tree_state_version,
+        )?;
+        install_snapshot(&staging, data_dir, config_dir)
+    })();
+    if let Err(e) = fs::remove_dir_all(&staging) {
+        warn!("Can't remove the staging directory {:?}: {}", staging, e);
+    }
+    result?;
+    info!(
+        "Restored the snapshot made by node version {} at {}",
+        metadata.node_version, metadata.timestamp
+    );
+    Ok(metadata)
+}
+
+/// Checks that the unpacked database has a tree state with the version given
+/// in the description of the snapshot and that this node can open it.
+fn check_staged_database(
+    database_dir: &Path,
+    metadata: &SnapshotMetadata,
+    tree_state_version: &dyn Fn(&Path) -> anyhow::Result<TreeStateVersion>,
+) -> anyhow::Result<()> {
+    ensure!(database_dir.is_dir(), "The snapshot doesn't contain a database");
+    let tree_state_dirs = tree_state_dirs(database_dir)?;
+    ensure!(!tree_state_dirs.is_empty(), "The database of the snapshot has no tree state");
+    for tree_state_dir in tree_state_dirs {
+        let version = tree_state_version(&tree_state_dir)
+            .with_context(|| format!("Can't read the version of {:?}", tree_state_dir))?;
+        ensure!(
+            version.version == metadata.tree_state_version,
+            "The tree state has version {}, but the snapshot says {}",
+            version.version,
+            metadata.tree_state_version
+        );
+        ensure!(
+            version.supported,
+            "The tree state has version {}, which this node can't open",
+            version.version
+        );
+    }
+    Ok(())
+}
+
+/// Moves the unpacked files to the data and config directories, rolling back
+/// if any of them can't be moved.
+fn install_snapshot(staging: &Path, data_dir: &Path, config_dir: &Path) -> anyhow::Result<()> {
+    let mut replacement = Replacement {
+        backup_dir: data_dir.join(format!("snapshot-backup-{}", get_current_stamp())),
+        replaced:   Vec::new(),
+    };
+    let result = (|| {
+        let config_files = staging.join(CONFIG_DIRECTORY_NAME);
+        if config_files.is_dir() {
+            fs::create_dir_all(config_dir)?;
+            for entry in fs::read_dir(&config_files)? {
+                let entry = entry?;
+                replacement.replace(&entry.path(), &config_dir.join(entry.file_name()))?;
+            }
+        }
+        let kvs_file = staging.join(KVS_FILE_NAME);
+        if kvs_file.exists() {
+            replacement.replace(&kvs_file, &data_dir.join(KVS_FILE_NAME))?;
+        }
+        // the database is moved last, as it marks a complete restore
+        replacement.replace(
+            &staging.join(DATABASE_SUB_DIRECTORY_NAME),
+            &data_dir.join(DATABASE_SUB_DIRECTORY_NAME),
+        )
+    })();
+    match result {
+        Ok(()) => {
+            if replacement.backup_dir.exists() {
+                info!("The replaced files were backed up to {:?}", replacement.backup_dir);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            replacement.roll_back();
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus_ffi::mock::MockConsensus;
+
+    fn tree_state_version(supported: bool) -> impl Fn(&Path) -> anyhow::Result<TreeStateVersion> {
+        move |_| {
+            Ok(TreeStateVersion {
+                version: "mock".to_owned(),
+                supported,
+            })
+        }
+    }
+
+    /// The backup directories in a data directory.
+    fn backups(data_dir: &Path) -> Vec<PathBuf> {
+        fs::read_dir(data_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.file_name().unwrap().to_string_lossy().starts_with("snapshot-backup")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let node_dir = tempfile::tempdir().unwrap();
+        let kvs = RwLock::new(Rkv::new::<Lmdb>(node_dir.path()).unwrap());
+        {
+            let env = read_or_die!(kvs);
+            let bans = env.open_single(BAN_STORE_NAME, StoreOptions::create()).unwrap();
+            let mut writer = env.write().unwrap();
+            bans.put(&mut writer, b"peer", &rkv::Value::U64(0)).unwrap();
+            writer.commit().unwrap();
+        }
+        let preferences_file = node_dir.path().join("main.config.json");
+        fs::write(&preferences_file, b"{}").unwrap();
+
+        let consensus = MockConsensus::default();
+        let genesis_block = BlockHash::new(&[1; 32]).unwrap();
+        let snapshot = |output: &Path, cancelled: bool| {
+            create_snapshot(
+                &consensus,
+                &kvs,
+                genesis_block.clone(),
+                &preferences_file,
+                output,
+                &|| cancelled,
+            )
+        };
+        let output = node_dir.path().join("snapshot.tar.gz");
+        let metadata = snapshot(&output, false).unwrap();
+        assert_eq!(metadata.genesis_block, genesis_block);
+        assert_eq!(metadata.tree_state_version, "mock");
+        assert!(!with_suffix(&output, ".staging").exists());
+        // an existing snapshot is not overwritten
+        assert!(snapshot(&output, false).is_err());
+        // a cancelled snapshot leaves nothing behind
+        let cancelled_output = node_dir.path().join("cancelled.tar.gz");
+        assert!(snapshot(&cancelled_output, true).is_err());
+        assert!(!cancelled_output.exists());
+        assert!(!with_suffix(&cancelled_output, ".partial").exists());
+        assert!(!with_suffix(&cancelled_output, ".staging").exists());
+
+        // another chain
+        let data_dir = tempfile::tempdir().unwrap();
+        let config_dir = tempfile::tempdir().unwrap();
+        let database_dir = data_dir.path().join(DATABASE_SUB_DIRECTORY_NAME);
+        let restore = |genesis_block: &BlockHash, supported: bool| {
+            restore_snapshot(
+                &output,
+                genesis_block,
+                data_dir.path(),
+                config_dir.path(),
+                &tree_state_version(supported),
+            )
+        };
+        let other_genesis = BlockHash::new(&[2; 32]).unwrap();
+        assert!(restore(&other_genesis, true).is_err());
+        assert!(!database_dir.exists());
+        // a tree state this node can't open
+        assert!(restore(&genesis_block, false).is_err());
+        assert!(!database_dir.exists());
+        assert!(!data_dir.path().join("snapshot-restore.partial").exists());
+
+        // the replaced preferences are backed up
+        fs::write(config_dir.path().join("main.config.json"), b"old").unwrap();
+        let restored = restore(&genesis_block, true).unwrap();
+        assert_eq!(restored, metadata);
+        assert_eq!(fs::read(database_dir.join("blockstate-0.dat")).unwrap(), b"mock");
+        assert_eq!(fs::read(config_dir.path().join("main.config.json")).unwrap(), b"{}");
+        let env = Rkv::new::<Lmdb>(data_dir.path()).unwrap();
+        let bans = env.open_single(BAN_STORE_NAME, StoreOptions::create()).unwrap();
+        assert!(bans.get(&env.read().unwrap(), b"peer").unwrap().is_some());
+        let backups = backups(data_dir.path());
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read(backups[0].join("0-main.config.json")).unwrap(), b"old");
+
+        // an existing database is not overwritten
+        assert!(restore(&genesis_block, true).is_err());
+    }
+
+    #[test]
+    fn test_failed_restore_is_rolled_back() {
+        let node_dir = tempfile::tempdir().unwrap();
+        let kvs = RwLock::new(Rkv::new::<Lmdb>(node_dir.path()).unwrap());
+        let preferences_file = node_dir.path().join("main.config.json");
+        fs::write(&preferences_file, b"{}").unwrap();
+        let genesis_block = BlockHash::new(&[1; 32]).unwrap();
+        let output = node_dir.path().join("snapshot.tar.gz");
+        create_snapshot(
+            &MockConsensus::default(),
+            &kvs,
+            genesis_block.clone(),
+            &preferences_file,
+            &output,
+            &|| false,
+        )
+        .unwrap();
+
+        // the preferences are replaced before the key-value store, which can't be
+        let data_dir = tempfile::tempdir().unwrap();
+        let config_dir = tempfile::tempdir().unwrap();
+        fs::write(config_dir.path().join("main.config.json"), b"old").unwrap();
+        fs::create_dir(data_dir.path().join(KVS_FILE_NAME)).unwrap();
+        fs::write(data_dir.path().join(KVS_FILE_NAME).join("file"), b"").unwrap();
+        assert!(restore_snapshot(
+            &output,
+            &genesis_block,
+            data_dir.path(),
+            config_dir.path(),
+            &tree_state_version(true)
+        )
+        .is_err());
+        assert_eq!(fs::read(config_dir.path().join("main.config.json")).unwrap(), b"old");
+        assert!(!data_dir.path().join(DATABASE_SUB_DIRECTORY_NAME).exists());
+        assert!(backups(data_dir.path()).is_empty());
+    }
+}
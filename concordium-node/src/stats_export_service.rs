@@ -2,7 +2,7 @@
 
 cfg_if! {
     if #[cfg(feature = "instrumentation")] {
-        use prometheus::{self, Encoder, core::{AtomicI64, AtomicU64, GenericGauge}, IntCounter, IntGauge, Opts, Registry, TextEncoder};
+        use prometheus::{self, Encoder, core::{AtomicI64, AtomicU64, GenericGauge}, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
         use crate::{common::p2p_node_id::P2PNodeId, spawn_or_die, read_or_die};
         use std::{net::SocketAddr, thread, time, sync::RwLock};
         use gotham::{
@@ -19,8 +19,11 @@ cfg_if! {
         use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
     }
 }
-use crate::configuration;
-use std::sync::Arc;
+use crate::{
+    configuration,
+    consensus_ffi::{blockchain_types::BlockHash, helpers::PacketType},
+};
+use std::{sync::Arc, time::Duration};
 
 cfg_if! {
     if #[cfg(feature = "instrumentation")] {
@@ -54,17 +57,33 @@ cfg_if! {
             connections_received: IntCounter,
             inbound_high_priority_consensus_drops_counter: IntCounter,
             inbound_low_priority_consensus_drops_counter: IntCounter,
+            inbound_catch_up_consensus_drops_counter: IntCounter,
             inbound_high_priority_consensus_counter: IntCounter,
             inbound_low_priority_consensus_counter: IntCounter,
             inbound_high_priority_consensus_size: IntGauge,
             inbound_low_priority_consensus_size: IntGauge,
+            inbound_catch_up_consensus_size: IntGauge,
             outbound_high_priority_consensus_size: IntGauge,
             outbound_low_priority_consensus_size: IntGauge,
+            inbound_consensus_read_throttle: IntGauge,
+            inbound_consensus_queue_time: HistogramVec,
+            inbound_consensus_processing_time: HistogramVec,
             last_throughput_measurement_timestamp: GenericGauge<AtomicI64>,
             bytes_received: GenericGauge<AtomicU64>,
             bytes_sent: GenericGauge<AtomicU64>,
             avg_bps_in: GenericGauge<AtomicU64>,
             avg_bps_out: GenericGauge<AtomicU64>,
+            rpc_rate_limit: IntGaugeVec,
+            rpc_max_in_flight: IntGaugeVec,
+            rpc_requests_in_flight: IntGaugeVec,
+            rpc_requests_rejected: IntCounterVec,
+            rpc_query_queue_time: HistogramVec,
+            rpc_query_execution_time: HistogramVec,
+            rpc_query_cache_hits: IntCounterVec,
+            rpc_query_cache_misses: IntCounterVec,
+            rpc_query_cache_size: IntGauge,
+            genesis_index: IntGauge,
+            genesis_block: IntGaugeVec,
         }
     }
 }
@@ -79,17 +98,26 @@ pub struct StatsExportService {
     connections_received: AtomicUsize,
     inbound_high_priority_consensus_drops_counter: AtomicUsize,
     inbound_low_priority_consensus_drops_counter: AtomicUsize,
+    inbound_catch_up_consensus_drops_counter: AtomicUsize,
     inbound_high_priority_consensus_counter: AtomicUsize,
     inbound_low_priority_consensus_counter: AtomicUsize,
     inbound_high_priority_consensus_size: AtomicUsize,
     inbound_low_priority_consensus_size: AtomicUsize,
+    inbound_catch_up_consensus_size: AtomicUsize,
     outbound_high_priority_consensus_size: AtomicUsize,
     outbound_low_priority_consensus_size: AtomicUsize,
+    inbound_consensus_read_throttle: AtomicUsize,
     last_throughput_measurement_timestamp: AtomicI64,
     bytes_received: AtomicU64,
     bytes_sent: AtomicU64,
     avg_bps_in: AtomicU64,
     avg_bps_out: AtomicU64,
+    rpc_requests_in_flight: AtomicUsize,
+    rpc_requests_rejected: AtomicUsize,
+    rpc_query_cache_hits: AtomicUsize,
+    rpc_query_cache_misses: AtomicUsize,
+    rpc_query_cache_size: AtomicUsize,
+    genesis_index: AtomicUsize,
 }
 
 impl StatsExportService {
@@ -158,6 +186,14 @@ impl StatsExportService {
             IntCounter::with_opts(inbound_low_priority_consensus_drops_opts)?;
         registry.register(Box::new(inbound_low_priority_consensus_drops_counter.clone()))?;
 
+        let inbound_catch_up_consensus_drops_opts = Opts::new(
+            "inbound_catch_up_consensus_drops",
+            "inbound catch-up consensus messages dropped",
+        );
+        let inbound_catch_up_consensus_drops_counter =
+            IntCounter::with_opts(inbound_catch_up_consensus_drops_opts)?;
+        registry.register(Box::new(inbound_catch_up_consensus_drops_counter.clone()))?;
+
         let inbound_high_priority_consensus_counter_opts = Opts::new(
             "inbound_high_priority_consensus_counter",
             "inbound high priority consensus messages received",
@@ -190,6 +226,12 @@ impl StatsExportService {
             IntGauge::with_opts(inbound_low_priority_consensus_size_opts)?;
         registry.register(Box::new(inbound_low_priority_consensus_size.clone()))?;
 
+        let inbound_catch_up_consensus_size_opts =
+            Opts::new("inbound_catch_up_consensus_size", "inbound catch-up consensus queue size");
+        let inbound_catch_up_consensus_size =
+            IntGauge::with_opts(inbound_catch_up_consensus_size_opts)?;
+        registry.register(Box::new(inbound_catch_up_consensus_size.clone()))?;
+
         let outbound_high_priority_consensus_size_opts = Opts::new(
             "outbound_high_priority_consensus_size",
             "outbound high priority consensus queue size",
@@ -206,6 +248,31 @@ impl StatsExportService {
             IntGauge::with_opts(outbound_low_priority_consensus_size_opts)?;
         registry.register(Box::new(outbound_low_priority_consensus_size.clone()))?;
 
+        let inbound_consensus_read_throttle_opts = Opts::new(
+            "inbound_consensus_read_throttle",
+            "reads deferred because of full inbound consensus queues (0: none, 1: from peers \
+             mostly sending transactions, 2: from all peers)",
+        );
+        let inbound_consensus_read_throttle =
+            IntGauge::with_opts(inbound_consensus_read_throttle_opts)?;
+        registry.register(Box::new(inbound_consensus_read_throttle.clone()))?;
+
+        let inbound_consensus_queue_time_opts = HistogramOpts::new(
+            "inbound_consensus_queue_time",
+            "time in seconds messages received from peers spend waiting to be processed",
+        );
+        let inbound_consensus_queue_time =
+            HistogramVec::new(inbound_consensus_queue_time_opts, &["packet_type"])?;
+        registry.register(Box::new(inbound_consensus_queue_time.clone()))?;
+
+        let inbound_consensus_processing_time_opts = HistogramOpts::new(
+            "inbound_consensus_processing_time",
+            "time in seconds spent processing messages received from peers",
+        );
+        let inbound_consensus_processing_time =
+            HistogramVec::new(inbound_consensus_processing_time_opts, &["packet_type"])?;
+        registry.register(Box::new(inbound_consensus_processing_time.clone()))?;
+
         let last_throughput_measurement_timestamp_opts = Opts::new(
             "last_throughput_measurement_timestamp",
             "last_throughput_measurement_timestamp",
@@ -229,6 +296,77 @@ impl StatsExportService {
         let avg_bps_out = GenericGauge::with_opts(avg_bps_out_opts)?;
         registry.register(Box::new(avg_bps_out.clone()))?;
 
+        let rpc_rate_limit_opts = Opts::new(
+            "rpc_rate_limit",
+            "configured limit of RPC requests per second for each client (0 means no limit)",
+        );
+        let rpc_rate_limit = IntGaugeVec::new(rpc_rate_limit_opts, &["class"])?;
+        registry.register(Box::new(rpc_rate_limit.clone()))?;
+
+        let rpc_max_in_flight_opts = Opts::new(
+            "rpc_max_in_flight",
+            "configured limit of concurrent RPC requests for each client (0 means no limit)",
+        );
+        let rpc_max_in_flight = IntGaugeVec::new(rpc_max_in_flight_opts, &["class"])?;
+        registry.register(Box::new(rpc_max_in_flight.clone()))?;
+
+        let rpc_requests_in_flight_opts =
+            Opts::new("rpc_requests_in_flight", "RPC requests currently being processed");
+        let rpc_requests_in_flight = IntGaugeVec::new(rpc_requests_in_flight_opts, &["class"])?;
+        registry.register(Box::new(rpc_requests_in_flight.clone()))?;
+
+        let rpc_requests_rejected_opts =
+            Opts::new("rpc_requests_rejected", "RPC requests rejected due to exceeded limits");
+        let rpc_requests_rejected =
+            IntCounterVec::new(rpc_requests_rejected_opts, &["class", "reason"])?;
+        registry.register(Box::new(rpc_requests_rejected.clone()))?;
+
+        let rpc_query_queue_time_opts = HistogramOpts::new(
+            "rpc_query_queue_time",
+            "time in seconds consensus queries spend waiting to be executed",
+        );
+        let rpc_query_queue_time = HistogramVec::new(rpc_query_queue_time_opts, &["method"])?;
+        registry.register(Box::new(rpc_query_queue_time.clone()))?;
+
+        let rpc_query_execution_time_opts = HistogramOpts::new(
+            "rpc_query_execution_time",
+            "time in seconds spent executing consensus queries",
+        );
+        let rpc_query_execution_time =
+            HistogramVec::new(rpc_query_execution_time_opts, &["method"])?;
+        registry.register(Box::new(rpc_query_execution_time.clone()))?;
+
+        let rpc_query_cache_hits_opts = Opts::new(
+            "rpc_query_cache_hits",
+            "consensus queries answered from the cache of finalized blocks",
+        );
+        let rpc_query_cache_hits = IntCounterVec::new(rpc_query_cache_hits_opts, &["method"])?;
+        registry.register(Box::new(rpc_query_cache_hits.clone()))?;
+
+        let rpc_query_cache_misses_opts = Opts::new(
+            "rpc_query_cache_misses",
+            "cacheable consensus queries that had to be executed",
+        );
+        let rpc_query_cache_misses = IntCounterVec::new(rpc_query_cache_misses_opts, &["method"])?;
+        registry.register(Box::new(rpc_query_cache_misses.clone()))?;
+
+        let rpc_query_cache_size_opts =
+            Opts::new("rpc_query_cache_size", "size in bytes of the cached query responses");
+        let rpc_query_cache_size = IntGauge::with_opts(rpc_query_cache_size_opts)?;
+        registry.register(Box::new(rpc_query_cache_size.clone()))?;
+
+        let genesis_index_opts = Opts::new(
+            "genesis_index",
+            "number of regenesis blocks since the original genesis block",
+        );
+        let genesis_index = IntGauge::with_opts(genesis_index_opts)?;
+        registry.register(Box::new(genesis_index.clone()))?;
+
+        let genesis_block_opts =
+            Opts::new("genesis_block", "current genesis block, given by the hash label");
+        let genesis_block = IntGaugeVec::new(genesis_block_opts, &["hash"])?;
+        registry.register(Box::new(genesis_block.clone()))?;
+
         Ok(StatsExportService {
             registry,
             pkts_received_counter: prc,
@@ -237,17 +375,33 @@ impl StatsExportService {
             connections_received: cr,
             inbound_high_priority_consensus_drops_counter,
             inbound_low_priority_consensus_drops_counter,
+            inbound_catch_up_consensus_drops_counter,
             inbound_high_priority_consensus_counter,
             inbound_low_priority_consensus_counter,
             inbound_high_priority_consensus_size,
             inbound_low_priority_consensus_size,
+            inbound_catch_up_consensus_size,
             outbound_high_priority_consensus_size,
             outbound_low_priority_consensus_size,
+            inbound_consensus_read_throttle,
+            inbound_consensus_queue_time,
+            inbound_consensus_processing_time,
             last_throughput_measurement_timestamp: ltm,
             bytes_received: brc,
             bytes_sent: bsc,
             avg_bps_in,
             avg_bps_out,
+            rpc_rate_limit,
+            rpc_max_in_flight,
+            rpc_requests_in_flight,
+            rpc_requests_rejected,
+            rpc_query_queue_time,
+            rpc_query_execution_time,
+            rpc_query_cache_hits,
+            rpc_query_cache_misses,
+            rpc_query_cache_size,
+            genesis_index,
+            genesis_block,
         })
     }
 
@@ -313,6 +467,15 @@ impl StatsExportService {
         self.inbound_low_priority_consensus_drops_counter.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Increases the number of catch-up messages dropped due to the catch-up
+    /// queue being full.
+    pub fn inbound_catch_up_consensus_drops_inc(&self) {
+        #[cfg(feature = "instrumentation")]
+        self.inbound_catch_up_consensus_drops_counter.inc();
+        #[cfg(not(feature = "instrumentation"))]
+        self.inbound_catch_up_consensus_drops_counter.fetch_add(1, Ordering::Relaxed);
+    }
+
     /// Increases the number of received high priority consensus messages.
     pub fn inbound_high_priority_consensus_inc(&self) {
         #[cfg(feature = "instrumentation")]
@@ -345,6 +508,14 @@ impl StatsExportService {
         self.inbound_low_priority_consensus_size.store(value as usize, Ordering::Relaxed);
     }
 
+    /// Sets the size value of the inbound catch-up queue.
+    pub fn set_inbound_catch_up_consensus_size(&self, value: i64) {
+        #[cfg(feature = "instrumentation")]
+        self.inbound_catch_up_consensus_size.set(value);
+        #[cfg(not(feature = "instrumentation"))]
+        self.inbound_catch_up_consensus_size.store(value as usize, Ordering::Relaxed);
+    }
+
     /// Sets the size value of the high priority outbound consensus queue.
     pub fn set_outbound_high_priority_consensus_size(&self, value: i64) {
         #[cfg(feature = "instrumentation")]
@@ -361,6 +532,36 @@ impl StatsExportService {
         self.outbound_low_priority_consensus_size.store(value as usize, Ordering::Relaxed);
     }
 
+    /// Sets which reads are deferred because of full inbound consensus queues.
+    pub fn set_inbound_consensus_read_throttle(&self, value: i64) {
+        #[cfg(feature = "instrumentation")]
+        self.inbound_consensus_read_throttle.set(value);
+        #[cfg(not(feature = "instrumentation"))]
+        self.inbound_consensus_read_throttle.store(value as usize, Ordering::Relaxed);
+    }
+
+    /// Records the time a message received from a peer spent in the queue.
+    #[cfg_attr(not(feature = "instrumentation"), allow(unused_variables))]
+    pub fn inbound_consensus_queue_time_observe(&self, packet_type: PacketType, time: Duration) {
+        #[cfg(feature = "instrumentation")]
+        self.inbound_consensus_queue_time
+            .with_label_values(&[&format!("{:?}", packet_type)])
+            .observe(time.as_secs_f64());
+    }
+
+    /// Records the time spent processing a message received from a peer.
+    #[cfg_attr(not(feature = "instrumentation"), allow(unused_variables))]
+    pub fn inbound_consensus_processing_time_observe(
+        &self,
+        packet_type: PacketType,
+        time: Duration,
+    ) {
+        #[cfg(feature = "instrumentation")]
+        self.inbound_consensus_processing_time
+            .with_label_values(&[&format!("{:?}", packet_type)])
+            .observe(time.as_secs_f64());
+    }
+
     /// Gets the timestamp for the last throughput check.
     pub fn get_last_throughput_measurement_timestamp(&self) -> i64 {
         #[cfg(feature = "instrumentation")]
@@ -457,6 +658,97 @@ impl StatsExportService {
         self.avg_bps_out.store(value, Ordering::Relaxed);
     }
 
+    /// Sets the configured RPC limits of a method class.
+    #[cfg_attr(not(feature = "instrumentation"), allow(unused_variables))]
+    pub fn set_rpc_limits(&self, class: &str, rate_limit: u32, max_in_flight: u32) {
+        #[cfg(feature = "instrumentation")]
+        {
+            self.rpc_rate_limit.with_label_values(&[class]).set(rate_limit.into());
+            self.rpc_max_in_flight.with_label_values(&[class]).set(max_in_flight.into());
+        }
+    }
+
+    /// Increases the number of RPC requests being processed.
+    #[cfg_attr(not(feature = "instrumentation"), allow(unused_variables))]
+    pub fn rpc_requests_in_flight_inc(&self, class: &str) {
+        #[cfg(feature = "instrumentation")]
+        self.rpc_requests_in_flight.with_label_values(&[class]).inc();
+        #[cfg(not(feature = "instrumentation"))]
+        self.rpc_requests_in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Decreases the number of RPC requests being processed.
+    #[cfg_attr(not(feature = "instrumentation"), allow(unused_variables))]
+    pub fn rpc_requests_in_flight_dec(&self, class: &str) {
+        #[cfg(feature = "instrumentation")]
+        self.rpc_requests_in_flight.with_label_values(&[class]).dec();
+        #[cfg(not(feature = "instrumentation"))]
+        self.rpc_requests_in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Increases the number of RPC requests rejected due to exceeded limits.
+    #[cfg_attr(not(feature = "instrumentation"), allow(unused_variables))]
+    pub fn rpc_requests_rejected_inc(&self, class: &str, reason: &str) {
+        #[cfg(feature = "instrumentation")]
+        self.rpc_requests_rejected.with_label_values(&[class, reason]).inc();
+        #[cfg(not(feature = "instrumentation"))]
+        self.rpc_requests_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the time a consensus query waited to be executed.
+    #[cfg_attr(not(feature = "instrumentation"), allow(unused_variables))]
+    pub fn rpc_query_queue_time_observe(&self, method: &str, time: Duration) {
+        #[cfg(feature = "instrumentation")]
+        self.rpc_query_queue_time.with_label_values(&[method]).observe(time.as_secs_f64());
+    }
+
+    /// Records the time spent executing a consensus query.
+    #[cfg_attr(not(feature = "instrumentation"), allow(unused_variables))]
+    pub fn rpc_query_execution_time_observe(&self, method: &str, time: Duration) {
+        #[cfg(feature = "instrumentation")]
+        self.rpc_query_execution_time.with_label_values(&[method]).observe(time.as_secs_f64());
+    }
+
+    /// Increases the number of consensus queries answered from the cache.
+    #[cfg_attr(not(feature = "instrumentation"), allow(unused_variables))]
+    pub fn rpc_query_cache_hits_inc(&self, method: &str) {
+        #[cfg(feature = "instrumentation")]
+        self.rpc_query_cache_hits.with_label_values(&[method]).inc();
+        #[cfg(not(feature = "instrumentation"))]
+        self.rpc_query_cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Increases the number of cacheable consensus queries that were executed.
+    #[cfg_attr(not(feature = "instrumentation"), allow(unused_variables))]
+    pub fn rpc_query_cache_misses_inc(&self, method: &str) {
+        #[cfg(feature = "instrumentation")]
+        self.rpc_query_cache_misses.with_label_values(&[method]).inc();
+        #[cfg(not(feature = "instrumentation"))]
+        self.rpc_query_cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Sets the size of the cached query responses.
+    pub fn set_rpc_query_cache_size(&self, value: usize) {
+        #[cfg(feature = "instrumentation")]
+        self.rpc_query_cache_size.set(value as i64);
+        #[cfg(not(feature = "instrumentation"))]
+        self.rpc_query_cache_size.store(value, Ordering::Relaxed);
+    }
+
+    /// Sets the index and the hash of the current genesis block.
+    #[cfg_attr(not(feature = "instrumentation"), allow(unused_variables))]
+    pub fn set_genesis_block(&self, index: usize, hash: &BlockHash) {
+        #[cfg(feature = "instrumentation")]
+        {
+            self.genesis_index.set(index as i64);
+            // only the current genesis block is reported
+            self.genesis_block.reset();
+            self.genesis_block.with_label_values(&[&hash.to_string()]).set(1);
+        }
+        #[cfg(not(feature = "instrumentation"))]
+        self.genesis_index.store(index, Ordering::Relaxed);
+    }
+
     #[cfg(feature = "instrumentation")]
     fn metrics(state: State) -> (State, String) {
         let state_data = PrometheusStateData::borrow_from(&state);
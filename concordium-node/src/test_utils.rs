@@ -17,6 +17,7 @@ use crate::{
     read_or_die,
     stats_export_service::StatsExportService,
 };
+use byteorder::{BigEndian, WriteBytesExt};
 use crypto_common::Serial;
 
 use std::{
@@ -102,6 +103,26 @@ pub fn dummy_regenesis_blocks() -> Vec<BlockHash> {
     ]
 }
 
+/// Creates an account transaction in the versioned format with a single dummy
+/// signature of the given length and a dummy payload of the given size that
+/// expires at the given time (in seconds since the Unix epoch).
+pub fn dummy_account_transaction(signature_length: u16, payload_size: u32, expiry: u64) -> Vec<u8> {
+    // the version and the block item type
+    let mut bytes = vec![0, 0];
+    // one credential with one signature
+    bytes.extend_from_slice(&[1, 0, 1, 0]);
+    bytes.write_u16::<BigEndian>(signature_length).unwrap();
+    bytes.extend(vec![0; signature_length as usize]);
+    // the header: sender, nonce, energy, payload size and expiry
+    bytes.extend_from_slice(&[7; 32]);
+    bytes.write_u64::<BigEndian>(1).unwrap();
+    bytes.write_u64::<BigEndian>(500).unwrap();
+    bytes.write_u32::<BigEndian>(payload_size).unwrap();
+    bytes.write_u64::<BigEndian>(expiry).unwrap();
+    bytes.extend(vec![3; payload_size as usize]);
+    bytes
+}
+
 /// Creates a `P2PNode` for test purposes
 /// This creates a temporary directory for the node's config and data
 /// directories. It is the responsibility of the test to delete the directory.
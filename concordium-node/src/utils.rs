@@ -487,23 +487,34 @@ fn read_peers_from_dns_entries(
 pub fn generate_ed25519_key() -> SecretKey { SecretKey::generate(&mut OsRng::default()) }
 
 pub fn get_config_and_logging_setup() -> anyhow::Result<(config::Config, config::AppPreferences)> {
-    // Get config and app preferences
-    let conf = config::parse_config()?;
+    get_logging_setup(config::parse_config()?)
+}
+
+/// Sets up logging and the app preferences for an already parsed config.
+pub fn get_logging_setup(
+    conf: config::Config,
+) -> anyhow::Result<(config::Config, config::AppPreferences)> {
+    // Get app preferences
     let app_prefs = config::AppPreferences::new(
         conf.common.config_dir.to_owned(),
         conf.common.data_dir.to_owned(),
     );
 
-    // Prepare the logger
-    let (env, log_lvl) = if conf.common.trace {
-        (Env::default().filter_or("LOG_LEVEL", "trace"), "trace")
+    // Prepare the logger. Unless a filter is given explicitly, the logger lets
+    // everything through and the level is controlled by the global maximum
+    // instead, so that it can be changed while the node is running.
+    let log_lvl = if conf.common.trace {
+        LevelFilter::Trace
     } else if conf.common.debug {
-        (Env::default().filter_or("LOG_LEVEL", "debug"), "debug")
+        LevelFilter::Debug
     } else {
-        (Env::default().filter_or("LOG_LEVEL", "info"), "info")
+        LevelFilter::Info
     };
 
-    setup_logger_env(env, conf.common.no_log_timestamp);
+    setup_logger_env(Env::default().filter_or("LOG_LEVEL", "trace"), conf.common.no_log_timestamp);
+    if std::env::var_os("LOG_LEVEL").is_none() {
+        log::set_max_level(log_lvl);
+    }
 
     if conf.common.print_config {
         info!("Config:{:?}\n", conf);